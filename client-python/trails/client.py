@@ -245,6 +245,8 @@ class TrailsClient:
                         if resp_data.get("type") == "error":
                             logger.error("registration rejected: %s", resp)
                             raise Exception("rejected")
+                        if resp_data.get("server_version"):
+                            logger.info("registered with trailsd %s", resp_data["server_version"])
                     except asyncio.TimeoutError:
                         logger.warning("registration timeout")
                         raise Exception("timeout")
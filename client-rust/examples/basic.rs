@@ -41,15 +41,13 @@ async fn main() {
     .await
     .unwrap();
 
-    // Business result — structured output.
-    g.result(json!({
+    // Business result — structured output. `finish` waits for the
+    // server's ack of it, then shuts down gracefully.
+    g.finish(json!({
         "rows_scanned": 100000,
         "pii_columns_found": 4,
         "duration_sec": 342,
     }))
     .await
     .unwrap();
-
-    // Graceful shutdown.
-    g.shutdown().await.unwrap();
 }
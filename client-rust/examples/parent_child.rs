@@ -0,0 +1,49 @@
+//! Parent + child example: the parent spawns this same binary as a child
+//! app, which reports to TRAILS under the parent.
+//!
+//! Run with TRAILS_INFO set for the parent:
+//! ```bash
+//! TRAILS_INFO=<base64> cargo run --example parent_child
+//! ```
+//! Without it both run as no-ops.
+
+use serde_json::json;
+use trails_client::{ChildSpec, Progress, TrailsClient};
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let g = TrailsClient::init().await;
+    if std::env::args().any(|arg| arg == "--child") {
+        child(g).await;
+    } else {
+        parent(g).await;
+    }
+}
+
+async fn parent(g: TrailsClient) {
+    g.status(Progress::new("spawning", 0.0)).await.unwrap();
+
+    let exe = std::env::current_exe().expect("current executable");
+    let mut cmd = tokio::process::Command::new(exe);
+    cmd.arg("--child");
+    let spec = ChildSpec::new("scan-shard-0")
+        .with_start_deadline(60)
+        .with_tags(json!({ "shard": 0 }));
+    let mut child = g.spawn_child(spec, &mut cmd).expect("spawn child");
+    let exit = child.wait().await.expect("wait for child");
+    println!("child exited: {exit}");
+
+    g.result(json!({ "children": 1, "child_ok": exit.success() }))
+        .await
+        .unwrap();
+    g.shutdown().await.unwrap();
+}
+
+async fn child(g: TrailsClient) {
+    println!("child TRAILS active: {}", g.is_active());
+    g.status(Progress::new("scanning", 0.5)).await.unwrap();
+    g.result(json!({ "rows_scanned": 5000 })).await.unwrap();
+    g.shutdown().await.unwrap();
+}
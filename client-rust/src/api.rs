@@ -0,0 +1,330 @@
+//! REST API types (`/api/v1`, spec §23), shared by the client and the
+//! `trailsctl` admin CLI.
+//!
+//! Bodies are camelCase JSON. Response types keep the fields they don't
+//! name in `other`, so re-serializing one gives back what the server sent.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use uuid::Uuid;
+
+/// `POST /api/v1/children` body, and an item of `children:batch`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChildRequest {
+    pub app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub app_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_deadline: Option<i32>,
+    #[serde(default)]
+    pub role_refs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<JsonValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_runtime_secs: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sec_level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Siblings that must be `done` before this child's start deadline runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<Uuid>,
+}
+
+/// `POST /api/v1/children:batch` response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResponse {
+    /// False when an atomic batch was rolled back.
+    pub committed: bool,
+    pub created: usize,
+    pub failed: usize,
+    pub elapsed_ms: u64,
+    pub results: Vec<BatchItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchItem {
+    pub index: usize,
+    pub app_id: Uuid,
+    /// `created`, `failed` or `aborted`.
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A soft and a hard limit in bytes; `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaLimits {
+    pub soft_bytes: Option<i64>,
+    pub hard_bytes: Option<i64>,
+}
+
+/// An app as returned by `GET /api/v1/apps` and `GET /api/v1/apps/{id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct App {
+    pub app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub app_name: String,
+    pub status: String,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: i16,
+    #[serde(default)]
+    pub paused: bool,
+    /// Flagged stalled: no Status progress for its threshold. Only
+    /// meaningful while the app is running.
+    #[serde(default)]
+    pub stalled_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub depends_on: Option<Vec<Uuid>>,
+    /// Set while the app waits for its dependencies.
+    #[serde(default)]
+    pub blocked_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub stored_bytes: i64,
+    #[serde(default)]
+    pub remaining_runtime_secs: Option<i64>,
+    #[serde(flatten)]
+    pub other: Map<String, JsonValue>,
+}
+
+/// Body of `POST /api/v1/apps/{id}/cancel`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelResponse {
+    /// The cancel control reached the app's socket.
+    pub delivered: bool,
+    /// `delivered`, `queued` until the app next registers, or `expired`.
+    #[serde(default)]
+    pub delivery: String,
+    /// The queue entry, when not delivered at once.
+    #[serde(default)]
+    pub control: Option<PendingControl>,
+    pub app: App,
+}
+
+/// A control queued for an app without a connection
+/// (`GET /api/v1/apps/{id}/controls`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingControl {
+    pub id: i64,
+    pub app_id: Uuid,
+    pub action: String,
+    pub payload: JsonValue,
+    /// `pending`, `delivered`, `acknowledged` or `expired`.
+    pub state: String,
+    pub requested_by: Option<String>,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub acked_at: Option<DateTime<Utc>>,
+    pub expired_at: Option<DateTime<Utc>>,
+}
+
+/// Body of `POST /api/v1/apps/{id}/force`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForceRequest {
+    /// Terminal status to set; the server defaults to `cancelled`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Body of `POST /api/v1/apps/{id}/adopt`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdoptRequest {
+    pub new_parent_id: Uuid,
+}
+
+/// Body of `POST /api/v1/apps/adopt`: moves every non-terminal child.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdoptChildrenRequest {
+    pub old_parent_id: Uuid,
+    pub new_parent_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdoptChildrenResponse {
+    pub adopted: Vec<App>,
+}
+
+/// Reference to a payload held in the server's object store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobRef {
+    pub url: String,
+    pub key: String,
+    pub size: i64,
+    pub sha256: String,
+    pub preview: String,
+}
+
+/// A stored message (`GET /api/v1/apps/{id}/messages`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: i64,
+    pub app_id: Uuid,
+    pub direction: String,
+    pub msg_type: String,
+    pub seq: i64,
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    /// Null for offloaded payloads unless resolved.
+    #[serde(default)]
+    pub payload: Option<JsonValue>,
+    #[serde(default)]
+    pub blob: Option<BlobRef>,
+    #[serde(default)]
+    pub sealed: bool,
+    /// Values masked or dropped by the server's redaction rules.
+    #[serde(default)]
+    pub redactions_applied: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Last line of `GET /api/v1/apps/{id}/messages/export`. An export
+/// without it, or with `complete: false`, was cut short.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportTrailer {
+    pub trailer: bool,
+    pub count: u64,
+    #[serde(default)]
+    pub last_seq: Option<i64>,
+    pub complete: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `GET /api/v1/apps/{id}/snapshots/latest`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub seq: i64,
+    pub snapshot: JsonValue,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One app of `GET /api/v1/snapshots/latest`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatestSnapshot {
+    pub app_id: Uuid,
+    pub app_name: String,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    pub status: String,
+    #[serde(default)]
+    pub last_seen: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub seq: Option<i64>,
+    /// Null if the app has not reported yet.
+    #[serde(default)]
+    pub snapshot: Option<JsonValue>,
+    #[serde(default)]
+    pub sealed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatestPage {
+    pub apps: Vec<LatestSnapshot>,
+    /// Pass as `after` for the next page; null on the last page.
+    pub next_after: Option<Uuid>,
+}
+
+/// An entry of `GET /api/v1/stats/storage`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageConsumer {
+    pub app_id: Uuid,
+    pub app_name: String,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    pub status: String,
+    pub stored_bytes: i64,
+    #[serde(default)]
+    pub quota_warned_at: Option<DateTime<Utc>>,
+    pub effective_storage_quota: QuotaLimits,
+}
+
+/// `GET /api/v1/version`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub version: String,
+    pub git_sha: String,
+    #[serde(default)]
+    pub build_timestamp: Option<DateTime<Utc>>,
+    pub server_version: String,
+    pub protocol: ProtocolRange,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ProtocolRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+/// Frames of the per-app live feed (`GET /ws/apps/{id}/feed`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedFrame {
+    Hello {
+        app: App,
+        snapshot: Option<JsonValue>,
+    },
+    Message {
+        message: Message,
+    },
+    /// Backfill done; everything after this is live.
+    Live {
+        #[serde(rename = "lastSeq")]
+        last_seq: Option<i64>,
+    },
+    /// A lifecycle event, with the app as of that event (null once the
+    /// app is purged, which also ends the feed).
+    Event {
+        event: JsonValue,
+        app: Option<App>,
+    },
+    /// Sent after the observer fell behind and was re-synced.
+    App {
+        app: App,
+    },
+    Error {
+        code: String,
+        message: String,
+    },
+}
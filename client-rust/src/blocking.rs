@@ -0,0 +1,369 @@
+//! Blocking client, for applications without a tokio runtime (plain
+//! threads and blocking I/O). Behind the `blocking` feature.
+//!
+//! It wraps the async [`crate::TrailsClient`]: the transport task runs on
+//! a current-thread runtime of its own, driven by a background thread
+//! that exits with the task. `status`, `result` and `error` only queue
+//! the message, as in the async client (unless
+//! [`TrailsClientBuilder::delivery`] says to wait for room); `shutdown`
+//! blocks until the disconnect is sent or the shutdown timeout passes.
+//!
+//! ```ignore
+//! let g = trails_client::blocking::TrailsClient::init();
+//! g.status(json!({"phase": "loading"}))?;
+//! g.result(json!({"rows": 5000}))?;
+//! g.shutdown()?;
+//! ```
+//!
+//! Not for use inside a tokio runtime, where its calls panic; use the
+//! async client there.
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tokio::runtime::Handle;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    ChildOutcome, ClientHandle, ClientStats, ConnectionState, DeadlineStatus, DisconnectReason,
+    ErrorReport, Level, PhaseGuard, TrailsClientBuilder, TrailsConfig, TrailsError,
+};
+
+/// Blocking TRAILS client. A no-op client, without a runtime or thread,
+/// if TRAILS_INFO is absent.
+pub struct TrailsClient {
+    inner: Option<Inner>,
+}
+
+struct Inner {
+    /// Dropped first: that queues the `dropped` disconnect, and the task
+    /// returns once it is sent, ending the thread.
+    client: crate::TrailsClient,
+    runtime: Handle,
+    thread: std::thread::JoinHandle<()>,
+}
+
+impl TrailsClient {
+    /// Read TRAILS_INFO and start the client; a no-op client if it is
+    /// absent or invalid.
+    pub fn init() -> Self {
+        crate::TrailsClient::builder().build_blocking()
+    }
+
+    /// Start with an explicit config (for non-env-var delivery, spec §5).
+    pub fn init_with(config: TrailsConfig) -> Self {
+        crate::TrailsClient::builder()
+            .config(config)
+            .build_blocking()
+    }
+
+    pub(crate) fn start(builder: TrailsClientBuilder) -> Self {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                warn!("can't start the client runtime: {e}, using no-op client");
+                return Self { inner: None };
+            }
+        };
+        let client = runtime.block_on(builder.build());
+        let Some(task_running) = client.inner.as_ref().map(|i| i.paused.clone()) else {
+            return Self { inner: None };
+        };
+        let handle = runtime.handle().clone();
+        let thread = std::thread::Builder::new()
+            .name("trails-client".into())
+            .spawn(move || {
+                // The task holds the pause watch's sender until it returns.
+                let mut task_running = task_running;
+                runtime.block_on(async { while task_running.changed().await.is_ok() {} });
+            });
+        match thread {
+            Ok(thread) => Self {
+                inner: Some(Inner {
+                    client,
+                    runtime: handle,
+                    thread,
+                }),
+            },
+            Err(e) => {
+                warn!("can't start the client thread: {e}, using no-op client");
+                Self { inner: None }
+            }
+        }
+    }
+
+    /// Whether this is a real client (not no-op).
+    pub fn is_active(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// The config the client was started with; `None` for the no-op
+    /// client.
+    pub fn config(&self) -> Option<&TrailsConfig> {
+        self.inner.as_ref().and_then(|i| i.client.config())
+    }
+
+    pub fn app_id(&self) -> Option<Uuid> {
+        self.config().map(|c| c.app_id)
+    }
+
+    pub fn parent_id(&self) -> Option<Uuid> {
+        self.config().and_then(|c| c.parent_id)
+    }
+
+    pub fn app_name(&self) -> Option<&str> {
+        self.config().map(|c| c.app_name.as_str())
+    }
+
+    pub fn tags(&self) -> Option<&JsonValue> {
+        self.config().and_then(|c| c.tags.as_ref())
+    }
+
+    pub fn role_refs(&self) -> Option<&[String]> {
+        self.config().map(|c| c.role_refs.as_slice())
+    }
+
+    /// Whether the client is connected; see
+    /// [`crate::TrailsClient::is_connected`].
+    pub fn is_connected(&self) -> bool {
+        self.inner.as_ref().is_some_and(|i| i.client.is_connected())
+    }
+
+    /// Follow the connection state; see
+    /// [`crate::TrailsClient::connection_events`]. Read it with `borrow()`,
+    /// or `has_changed()` to poll.
+    pub fn connection_events(&self) -> tokio::sync::watch::Receiver<ConnectionState> {
+        match &self.inner {
+            Some(i) => i.client.connection_events(),
+            None => tokio::sync::watch::channel(ConnectionState::Disabled).1,
+        }
+    }
+
+    /// Start over after the client gave up on the server; see
+    /// [`crate::TrailsClient::reconnect`].
+    pub fn reconnect(&self) -> bool {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.reconnect()),
+            None => false,
+        }
+    }
+
+    /// Whether the client started within the app's start deadline; see
+    /// [`crate::TrailsClient::deadline_status`].
+    pub fn deadline_status(&self) -> DeadlineStatus {
+        self.inner
+            .as_ref()
+            .map_or(DeadlineStatus::Unknown, |i| i.client.deadline_status())
+    }
+
+    /// Cancelled when the server cancels the app or the client shuts
+    /// down; poll `is_cancelled()`. See
+    /// [`crate::TrailsClient::cancellation_token`].
+    pub fn cancellation_token(&self) -> tokio_util::sync::CancellationToken {
+        match &self.inner {
+            Some(i) => i.client.cancellation_token(),
+            None => tokio_util::sync::CancellationToken::new(),
+        }
+    }
+
+    /// Report panics as an Error message; see
+    /// [`crate::TrailsClient::install_panic_hook`].
+    pub fn install_panic_hook(&self) {
+        if let Some(i) = &self.inner {
+            i.client.install_panic_hook();
+        }
+    }
+
+    /// Delivery counters; zeroed for the no-op client.
+    pub fn stats(&self) -> ClientStats {
+        self.inner
+            .as_ref()
+            .map(|i| i.client.stats())
+            .unwrap_or_default()
+    }
+
+    /// Send a status update (spec §9).
+    pub fn status<T: Serialize>(&self, payload: T) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.status(payload)),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a status with the fraction done and the current phase; see
+    /// [`crate::TrailsClient::progress`].
+    pub fn progress(&self, progress: f64) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.progress(progress)),
+            None => Ok(()),
+        }
+    }
+
+    /// Enter a phase, ended when the guard is dropped; see
+    /// [`crate::TrailsClient::phase`].
+    pub fn phase(&self, name: impl Into<String>) -> PhaseGuard {
+        match &self.inner {
+            Some(i) => i.client.phase(name),
+            None => PhaseGuard::enter(ClientHandle::default(), name.into()),
+        }
+    }
+
+    /// Send a business result (spec §9). Transitions app to 'done'.
+    pub fn result<T: Serialize>(&self, payload: T) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.result(payload)),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a business result and block until the server acks it, up to
+    /// `timeout`; see [`crate::TrailsClient::result_confirmed`].
+    pub fn result_confirmed<T: Serialize>(
+        &self,
+        payload: T,
+        timeout: std::time::Duration,
+    ) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i
+                .runtime
+                .block_on(i.client.result_confirmed(payload, timeout)),
+            None => Ok(()),
+        }
+    }
+
+    /// Send an error (spec §9). Transitions app to 'error'; see
+    /// [`crate::TrailsClient::error`].
+    pub fn error(&self, msg: &str, detail: Option<JsonValue>) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.error(msg, detail)),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a structured error; see [`crate::TrailsClient::error_report`].
+    pub fn error_report(&self, report: ErrorReport) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.error_report(report)),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a log line; see [`crate::TrailsClient::log`].
+    pub fn log(&self, level: Level, message: &str) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.log(level, message)),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a log line with structured fields; see
+    /// [`crate::TrailsClient::log_with_fields`].
+    pub fn log_with_fields(
+        &self,
+        level: Level,
+        message: &str,
+        fields: JsonValue,
+    ) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i
+                .runtime
+                .block_on(i.client.log_with_fields(level, message, fields)),
+            None => Ok(()),
+        }
+    }
+
+    /// Set a gauge; see [`crate::TrailsClient::metric`].
+    pub fn metric(&self, name: &str, value: f64) {
+        if let Some(i) = &self.inner {
+            i.client.metric(name, value);
+        }
+    }
+
+    /// Add to a counter; see [`crate::TrailsClient::counter`].
+    pub fn counter(&self, name: &str, delta: f64) {
+        if let Some(i) = &self.inner {
+            i.client.counter(name, delta);
+        }
+    }
+
+    /// Send a message of a type of the app's own; see
+    /// [`crate::TrailsClient::send_custom`].
+    pub fn send_custom<T: Serialize>(&self, msg_type: &str, payload: T) -> Result<(), TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.send_custom(msg_type, payload)),
+            None => Ok(()),
+        }
+    }
+
+    /// The newest snapshot the server holds for this app; see
+    /// [`crate::TrailsClient::last_snapshot`].
+    pub fn last_snapshot(&self) -> Result<Option<JsonValue>, TrailsError> {
+        match &self.inner {
+            Some(i) => i.runtime.block_on(i.client.last_snapshot()),
+            None => Ok(None),
+        }
+    }
+
+    /// Block until a child of this app ends, up to `timeout`; see
+    /// [`crate::TrailsClient::wait_for_child`].
+    pub fn wait_for_child(
+        &self,
+        child_id: Uuid,
+        timeout: std::time::Duration,
+    ) -> Result<ChildOutcome, TrailsError> {
+        match &self.inner {
+            Some(i) => i
+                .runtime
+                .block_on(i.client.wait_for_child(child_id, timeout)),
+            None => Err(TrailsError::NoConfig),
+        }
+    }
+
+    /// Send the result, wait for its ack, and shut down; see
+    /// [`crate::TrailsClient::finish`].
+    pub fn finish<T: Serialize>(self, payload: T) -> Result<(), TrailsError> {
+        let Some(inner) = self.inner else {
+            return Ok(());
+        };
+        let result = inner.runtime.block_on(inner.client.finish(payload));
+        let _ = inner.thread.join();
+        result
+    }
+
+    /// Send an error, wait for its ack, and shut down; see
+    /// [`crate::TrailsClient::fail`].
+    pub fn fail(self, msg: &str, detail: Option<JsonValue>) -> Result<(), TrailsError> {
+        let Some(inner) = self.inner else {
+            return Ok(());
+        };
+        let result = inner.runtime.block_on(inner.client.fail(msg, detail));
+        let _ = inner.thread.join();
+        result
+    }
+
+    /// Graceful shutdown: blocks until what is queued and the disconnect
+    /// are sent, up to the shutdown timeout; see
+    /// [`crate::TrailsClient::shutdown`].
+    pub fn shutdown(self) -> Result<(), TrailsError> {
+        let Some(inner) = self.inner else {
+            return Ok(());
+        };
+        let result = inner.runtime.block_on(inner.client.shutdown());
+        let _ = inner.thread.join();
+        result
+    }
+
+    /// Graceful shutdown, telling trailsd why; see
+    /// [`crate::TrailsClient::shutdown_with_reason`].
+    pub fn shutdown_with_reason(self, reason: DisconnectReason) -> Result<(), TrailsError> {
+        let Some(inner) = self.inner else {
+            return Ok(());
+        };
+        let result = inner.runtime.block_on(inner.client.shutdown_with_reason(reason));
+        let _ = inner.thread.join();
+        result
+    }
+}
@@ -0,0 +1,131 @@
+//! Child watches, for `TrailsClient::child_events` and `wait_for_child`:
+//! the WebSocket transport sends `subscribe_child` for each child
+//! watched, again after every reconnect, and hands the `child_*` frames
+//! the server pushes back to the watchers. The HTTP transport has no
+//! connection to push down; its watches end straight away.
+
+use std::collections::HashMap;
+
+use futures::channel::mpsc::UnboundedSender;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::ServerMessage;
+
+/// How a watched child ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChildOutcome {
+    /// Its terminal status: `done`, `error`, `cancelled`, `crashed`,
+    /// `start_failed` or `timed_out`.
+    pub status: String,
+    /// The child's Result payload, for `done`.
+    pub result: Option<JsonValue>,
+    /// For a crash, what kind: `connection_drop`, `never_started`, ...
+    pub crash_type: Option<String>,
+}
+
+/// What a watched child did, from [`crate::TrailsClient::child_events`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChildEvent {
+    /// The child connected, or already had when the watch began.
+    Started,
+    /// A Status the child stored.
+    Status {
+        seq: i64,
+        payload: Option<JsonValue>,
+    },
+    /// The child ended; the last event.
+    Ended(ChildOutcome),
+    /// The id isn't a child of this app; the last event.
+    Unknown,
+}
+
+#[derive(Serialize)]
+struct WireSubscribeChild {
+    r#type: &'static str,
+    child_id: Uuid,
+}
+
+/// The watchers of each watched child.
+#[derive(Default)]
+pub(crate) struct Watches {
+    by_child: HashMap<Uuid, Vec<UnboundedSender<ChildEvent>>>,
+}
+
+impl Watches {
+    /// Add a watcher. The server is asked again even for a child already
+    /// watched, so that the new watcher hears where the child is; the
+    /// others may hear it twice.
+    pub fn add(&mut self, child_id: Uuid, events: UnboundedSender<ChildEvent>) {
+        self.by_child.entry(child_id).or_default().push(events);
+    }
+
+    /// The `subscribe_child` frames of every child still watched, for a
+    /// new connection.
+    pub fn subscriptions(&mut self) -> Vec<String> {
+        self.by_child.retain(|_, watchers| {
+            watchers.retain(|events| !events.is_closed());
+            !watchers.is_empty()
+        });
+        self.by_child
+            .keys()
+            .map(|&child_id| subscribe_frame(child_id))
+            .collect()
+    }
+
+    /// Pass a `child_*` frame on to the child's watchers; others are
+    /// ignored. The last event of a child ends its watch.
+    pub fn dispatch(&mut self, msg: ServerMessage) {
+        let (child_id, event) = match msg {
+            ServerMessage::ChildStarted { child_id } => (child_id, ChildEvent::Started),
+            ServerMessage::ChildStatus {
+                child_id,
+                seq,
+                payload,
+            } => (child_id, ChildEvent::Status { seq, payload }),
+            ServerMessage::ChildTerminal {
+                child_id,
+                status,
+                result,
+            } => {
+                let outcome = ChildOutcome {
+                    status,
+                    result,
+                    crash_type: None,
+                };
+                (child_id, ChildEvent::Ended(outcome))
+            }
+            ServerMessage::ChildCrashed {
+                child_id,
+                status,
+                crash_type,
+            } => {
+                let outcome = ChildOutcome {
+                    status,
+                    result: None,
+                    crash_type: Some(crash_type),
+                };
+                (child_id, ChildEvent::Ended(outcome))
+            }
+            ServerMessage::ChildUnknown { child_id } => (child_id, ChildEvent::Unknown),
+            _ => return,
+        };
+        let last = matches!(event, ChildEvent::Ended(_) | ChildEvent::Unknown);
+        let Some(watchers) = self.by_child.get_mut(&child_id) else {
+            return;
+        };
+        watchers.retain(|events| events.unbounded_send(event.clone()).is_ok());
+        if last || watchers.is_empty() {
+            self.by_child.remove(&child_id);
+        }
+    }
+}
+
+pub(crate) fn subscribe_frame(child_id: Uuid) -> String {
+    let wire = WireSubscribeChild {
+        r#type: "subscribe_child",
+        child_id,
+    };
+    serde_json::to_string(&wire).unwrap()
+}
@@ -0,0 +1,62 @@
+//! Clock skew estimation. trailsd puts its clock in `registered` and in
+//! every `ack` (`server_time`, ms since the epoch); each such frame is a
+//! sample of how far the server's clock is ahead of ours. The estimate is
+//! the median of the latest samples, kept across reconnects, so a frame
+//! held up in the network (or a burst of them after a reconnect) moves it
+//! only once it is most of what there is.
+//!
+//! A sample is taken when the frame arrives, so it runs behind the server
+//! by the frame's trip: milliseconds, against the seconds of drift worth
+//! correcting.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Samples the median is taken over.
+const WINDOW: usize = 15;
+
+#[derive(Default)]
+pub(crate) struct ClockSkew {
+    /// Correct header timestamps by the estimate, with
+    /// `correct_clock_skew`.
+    correct: AtomicBool,
+    samples: Mutex<VecDeque<i64>>,
+}
+
+impl ClockSkew {
+    pub fn enable_correction(&self) {
+        self.correct.store(true, Ordering::Relaxed);
+    }
+
+    /// The server's clock read `server_time` as the frame left it.
+    pub fn sample(&self, server_time: i64) {
+        let offset = server_time - chrono::Utc::now().timestamp_millis();
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(offset);
+    }
+
+    /// How far, in ms, the server's clock is ahead of ours; `None` before
+    /// any sample.
+    pub fn offset(&self) -> Option<i64> {
+        let samples = self.samples.lock().unwrap();
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<i64> = samples.iter().copied().collect();
+        sorted.sort_unstable();
+        Some(sorted[sorted.len() / 2])
+    }
+
+    /// Now, in ms since the epoch, corrected when that is on.
+    pub fn now_ms(&self) -> i64 {
+        let now = chrono::Utc::now().timestamp_millis();
+        match self.correct.load(Ordering::Relaxed) {
+            true => now + self.offset().unwrap_or(0),
+            false => now,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Status coalescing, opted into with
+//! `TrailsClientBuilder::status_coalesce_interval`: at most one Status per
+//! interval goes to the channel, and it is always the newest. A Status
+//! sent too soon after the last one is held, replacing any held before
+//! it, until a background task lets it out when the interval is up. A
+//! Result, an Error, a correlated Status or the disconnect lets the held
+//! Status out first, so the newest one stays ahead of it.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::Value as JsonValue;
+use tokio::sync::Notify;
+
+pub(crate) struct Coalescer {
+    interval: Duration,
+    slot: Mutex<Slot>,
+    /// A Status was held, or the client closed.
+    wake: Notify,
+}
+
+#[derive(Default)]
+struct Slot {
+    held: Option<JsonValue>,
+    last_sent: Option<Instant>,
+    closed: bool,
+}
+
+impl Coalescer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            slot: Mutex::new(Slot::default()),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Pass a message's payload on its way to the channel. A Status that
+    /// can be coalesced comes back only if it may go now; otherwise it is
+    /// held in place of the one held before. Anything else first lets out
+    /// the held Status through `send_held`.
+    pub fn admit(
+        &self,
+        coalesce: bool,
+        payload: JsonValue,
+        send_held: impl FnOnce(JsonValue),
+    ) -> Option<JsonValue> {
+        if !coalesce {
+            if let Some(held) = self.take() {
+                send_held(held);
+            }
+            return Some(payload);
+        }
+        let mut slot = self.slot.lock().unwrap();
+        let now = Instant::now();
+        let due = slot
+            .last_sent
+            .is_none_or(|last| now.duration_since(last) >= self.interval);
+        if slot.closed || (slot.held.is_none() && due) {
+            slot.last_sent = Some(now);
+            return Some(payload);
+        }
+        if slot.held.replace(payload).is_none() {
+            self.wake.notify_one();
+        }
+        None
+    }
+
+    /// Take the held Status, to send now.
+    pub fn take(&self) -> Option<JsonValue> {
+        let mut slot = self.slot.lock().unwrap();
+        let held = slot.held.take();
+        if held.is_some() {
+            slot.last_sent = Some(Instant::now());
+        }
+        held
+    }
+
+    /// The client is going: stop `next_due` and hold nothing more.
+    pub fn close(&self) {
+        self.slot.lock().unwrap().closed = true;
+        self.wake.notify_one();
+    }
+
+    /// Wait until the held Status may go, and take it; `None` once
+    /// closed.
+    pub async fn next_due(&self) -> Option<JsonValue> {
+        loop {
+            let due = {
+                let slot = self.slot.lock().unwrap();
+                if slot.closed {
+                    return None;
+                }
+                slot.held
+                    .as_ref()
+                    .map(|_| slot.last_sent.map(|last| last + self.interval))
+            };
+            match due {
+                None => self.wake.notified().await,
+                Some(due) => {
+                    if let Some(due) = due {
+                        tokio::time::sleep_until(due.into()).await;
+                    }
+                    if let Some(held) = self.take() {
+                        return Some(held);
+                    }
+                }
+            }
+        }
+    }
+}
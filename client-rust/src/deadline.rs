@@ -0,0 +1,43 @@
+//! Whether the app started in time. A parent that schedules a child sets
+//! `scheduled_at` and `start_deadline` in its TRAILS_INFO; trailsd marks
+//! the child `start_failed` if it hasn't registered by then, and refuses
+//! its registration after. The client works out at start whether that
+//! has already happened, so a late start says so up front.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::TrailsConfig;
+
+/// Where the client's start stands against the app's start deadline,
+/// from [`TrailsClient::deadline_status`](crate::TrailsClient::deadline_status).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlineStatus {
+    /// Started before `scheduled_at + start_deadline`.
+    WithinDeadline,
+    /// Started this long after the deadline: trailsd has likely marked
+    /// the app `start_failed`, and will refuse its registration.
+    Late(Duration),
+    /// TRAILS_INFO has no `scheduled_at` or no `start_deadline`, or
+    /// this is the no-op client.
+    Unknown,
+}
+
+impl DeadlineStatus {
+    /// The status of an app with `config` starting at `now`.
+    pub(crate) fn of(config: &TrailsConfig, now: SystemTime) -> Self {
+        let (Some(scheduled_at), Some(deadline)) = (config.scheduled_at, config.start_deadline)
+        else {
+            return Self::Unknown;
+        };
+        let Ok(scheduled_at) = u64::try_from(scheduled_at) else {
+            return Self::Unknown;
+        };
+        let deadline = UNIX_EPOCH
+            + Duration::from_millis(scheduled_at)
+            + Duration::from_secs(u64::try_from(deadline).unwrap_or(0));
+        match now.duration_since(deadline) {
+            Ok(late) if !late.is_zero() => Self::Late(late),
+            _ => Self::WithinDeadline,
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! The process-wide client, for code that can't be handed one:
+//! [`TrailsClient::init_global`] starts it once, and the free functions
+//! here send through it, doing nothing until it is started.
+//!
+//! ```ignore
+//! trails_client::TrailsClient::init_global().await;
+//! // ... anywhere else:
+//! trails_client::status(json!({"rows": 1000})).await?;
+//! ```
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tokio::sync::OnceCell;
+
+use crate::{TrailsClient, TrailsError};
+
+static GLOBAL: OnceCell<TrailsClient> = OnceCell::const_new();
+
+/// The global client, started on the first call.
+pub(crate) async fn init() -> TrailsClient {
+    GLOBAL.get_or_init(TrailsClient::init).await.clone()
+}
+
+pub(crate) fn get() -> Option<&'static TrailsClient> {
+    GLOBAL.get()
+}
+
+/// [`TrailsClient::status`] on the global client; `Ok` without it.
+pub async fn status<T: Serialize>(payload: T) -> Result<(), TrailsError> {
+    match get() {
+        Some(client) => client.status(payload).await,
+        None => Ok(()),
+    }
+}
+
+/// [`TrailsClient::result`] on the global client; `Ok` without it.
+pub async fn result<T: Serialize>(payload: T) -> Result<(), TrailsError> {
+    match get() {
+        Some(client) => client.result(payload).await,
+        None => Ok(()),
+    }
+}
+
+/// [`TrailsClient::error`] on the global client; `Ok` without it.
+pub async fn error(msg: &str, detail: Option<JsonValue>) -> Result<(), TrailsError> {
+    match get() {
+        Some(client) => client.error(msg, detail).await,
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,387 @@
+//! HTTP transport: flushes queued messages as batches to
+//! `POST /api/v1/ingest`, for environments that can't hold a WebSocket.
+//!
+//! The first batch leads with the register (or, after a WebSocket
+//! session, re_register) frame; later ones name the app by the
+//! `X-Trails-App-Id` header. Every post is signed with the app key
+//! (`X-Trails-Timestamp`, `X-Trails-Signature`; see `ingest_message`). A
+//! failed post is re-signed and resent whole after a backoff: the server
+//! acks frames it already stored as
+//! duplicates. With nothing to send, an empty batch goes out every
+//! `HEARTBEAT` so the server's stale sweeper doesn't take the app for
+//! crashed. Controls, and the ack or nack of each frame, come back in
+//! the responses.
+
+use std::sync::mpsc::SyncSender;
+use std::time::Duration;
+
+use base64::Engine;
+use ed25519_dalek::SigningKey;
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::clock::ClockSkew;
+use crate::proxy::ProxySetting;
+use crate::replay::{AckWaiter, Backlog};
+use crate::stats::Stats;
+use crate::subtask::SubtaskFrame;
+use crate::{
+    apply_control, backoff_sleep, deliver_error, give_up, outbound_frame,
+    registration_frame, reject, rest_url, ControlSink, Outbound, ServerError, ServerMessage,
+    TrailsConfig, TrailsError, Tuning, FINAL_REJECTIONS,
+};
+
+/// How long messages are gathered into one batch.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Empty post interval while idle; well inside the server's default
+/// `INGEST_STALE_AFTER` of 300s.
+const HEARTBEAT: Duration = Duration::from_secs(30);
+/// Most frames per post; the server's default `INGEST_MAX_BATCH` is 500.
+const MAX_BATCH: usize = 100;
+
+/// Where the HTTP transport picks up.
+pub(crate) struct Start<'a> {
+    /// The app is already registered over HTTP; post with headers.
+    pub registered: bool,
+    /// Left over from the WebSocket transport: unacked frames, posted
+    /// first, and a held disconnect. Its `first_connect` picks `register`
+    /// or `re_register`.
+    pub backlog: &'a mut Backlog,
+}
+
+/// Frames gathered for one post.
+#[derive(Default)]
+struct Batch {
+    frames: Vec<String>,
+    /// Data messages among them.
+    messages: usize,
+    /// A Result, Error or disconnect ends the app.
+    terminal: bool,
+    /// The transport stops after this post: a disconnect, or the client
+    /// was dropped.
+    closed: bool,
+    /// Flush waiters, answered once the post goes through; the batch
+    /// goes out straight away.
+    flushes: Vec<SyncSender<()>>,
+    /// Ack waiters, by seq, answered by the frame's result.
+    waiters: Vec<(i64, AckWaiter)>,
+}
+
+#[derive(Deserialize)]
+struct IngestResponse {
+    results: Vec<FrameResult>,
+    #[serde(default)]
+    controls: Vec<JsonValue>,
+}
+
+#[derive(Deserialize)]
+struct FrameResult {
+    status: String,
+    seq: Option<i64>,
+    code: Option<String>,
+    message: Option<String>,
+}
+
+/// Run until shutdown (a disconnect), the client is dropped, or the
+/// backoff policy gives up on a post.
+pub(crate) async fn http_task(
+    config: &TrailsConfig,
+    signing_key: &SigningKey,
+    rx: &mut mpsc::Receiver<Outbound>,
+    stats: &Stats,
+    controls: &ControlSink,
+    tuning: &Tuning,
+    mut start: Start<'_>,
+) {
+    let url = rest_url(&config.server_ep, "/ingest");
+    let backoff = &tuning.backoff;
+    let clock = stats.clock();
+    let client = match client(tuning) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("{e}, not sending");
+            stats.error(&e);
+            return;
+        }
+    };
+    info!(url = %url, "sending over HTTP");
+    let mut finished = false;
+    let mut refused = false;
+
+    loop {
+        // ── Gather a batch ──────────────────────────────────
+        let mut batch = Batch::default();
+        for pending in start.backlog.take() {
+            batch.terminal |= pending.is_terminal();
+            batch.messages += 1;
+            if let Some(waiter) = pending.waiter {
+                batch.waiters.push((pending.seq, waiter));
+            }
+            batch.frames.push(pending.frame.into_text());
+        }
+        if let Some(disconnect) = start.backlog.closing.take() {
+            batch.frames.push(disconnect);
+            batch.terminal = true;
+            batch.closed = true;
+        }
+        // Register (and post any backlog) straight away; after that,
+        // post when there's news.
+        let idle = if (start.registered || refused) && batch.frames.is_empty() {
+            HEARTBEAT
+        } else {
+            Duration::ZERO
+        };
+        if !batch.closed {
+            match tokio::time::timeout(idle, rx.recv()).await {
+                Ok(Some(msg)) => push(config, signing_key, clock, &mut batch, &mut start, msg),
+                Ok(None) => batch.closed = true,
+                Err(_) if finished => continue,
+                Err(_) => {} // heartbeat
+            }
+        }
+        let deadline = Instant::now() + FLUSH_INTERVAL;
+        while !batch.closed
+            && !batch.terminal
+            && batch.flushes.is_empty()
+            && batch.frames.len() < MAX_BATCH
+        {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(msg)) => push(config, signing_key, clock, &mut batch, &mut start, msg),
+                Ok(None) => batch.closed = true,
+                Err(_) => break,
+            }
+        }
+        if batch.closed && batch.frames.is_empty() {
+            stats.set_connected(false);
+            return;
+        }
+        if !start.registered {
+            let reg = registration_frame(
+                config,
+                signing_key,
+                start.backlog.first_connect,
+                start.backlog.last_seq,
+                false,
+            );
+            batch.frames.insert(0, reg);
+        }
+
+        // ── Post it, retrying until it goes through ─────────
+        let body = format!("[{}]", batch.frames.join(","));
+        let mut attempt: u32 = 0;
+        loop {
+            match post(&client, &url, config, signing_key, clock, &start, body.clone()).await {
+                Ok(Some(resp)) => {
+                    stats.set_connected(true);
+                    if !start.registered {
+                        start.backlog.registered(None);
+                        start.registered = true;
+                    }
+                    let mut gone = None;
+                    for result in &resp.results {
+                        let mut acked = Ok(());
+                        if result.status == "nack" {
+                            let error = ServerError {
+                                code: result.code.clone().unwrap_or_default(),
+                                message: result.message.clone().unwrap_or_default(),
+                                app_id: None,
+                            };
+                            let ServerError { code, message, .. } = &error;
+                            acked = Err(TrailsError::ServerError(format!("{code}: {message}")));
+                            if FINAL_REJECTIONS.contains(&code.as_str()) {
+                                gone = Some(code.clone());
+                            }
+                            deliver_error(error, controls, stats);
+                        }
+                        let waiter = batch
+                            .waiters
+                            .iter()
+                            .position(|(seq, _)| Some(*seq) == result.seq);
+                        if let Some(at) = waiter {
+                            let _ = batch.waiters.swap_remove(at).1.send(acked);
+                        }
+                        // Only data frames carry a seq.
+                        if let Some(seq) = result.seq {
+                            stats.sent(1);
+                            if result.status != "nack" {
+                                stats.acked(1);
+                                start.backlog.ack(seq);
+                            }
+                        }
+                    }
+                    for control in &resp.controls {
+                        debug!("server: {control}");
+                        if let Ok(ServerMessage::Control(control)) =
+                            ServerMessage::deserialize(control)
+                        {
+                            apply_control(control, controls);
+                        }
+                    }
+                    for done in batch.flushes.drain(..) {
+                        let _ = done.send(());
+                    }
+                    if let Some(code) = gone {
+                        reject(rx, stats, 0, &code);
+                        return;
+                    }
+                    break;
+                }
+                // Refused outright: resending won't help.
+                Ok(None) => {
+                    stats.error("ingest refused");
+                    stats.dropped(batch.messages as u64);
+                    refused = !start.registered;
+                    break;
+                }
+                Err(e) => {
+                    warn!(attempt, "ingest post failed: {e}");
+                    stats.error(format_args!("ingest post failed: {e}"));
+                    if backoff.exhausted(attempt.saturating_add(1), stats.down_for()) {
+                        give_up(rx, stats, batch.messages, attempt.saturating_add(1));
+                        return;
+                    }
+                    stats.set_connected(false);
+                    stats.reconnecting(attempt.saturating_add(1));
+                    backoff_sleep(attempt, backoff).await;
+                    attempt = attempt.saturating_add(1);
+                }
+            }
+        }
+        finished |= batch.terminal;
+        if batch.closed {
+            stats.set_connected(false);
+            return;
+        }
+    }
+}
+
+/// Queue an outbound message's frame, noting whether it ends the app and
+/// whether it shuts the transport down; or a flush waiter. Child watches
+/// are refused.
+fn push(
+    config: &TrailsConfig,
+    signing_key: &SigningKey,
+    clock: &ClockSkew,
+    batch: &mut Batch,
+    start: &mut Start<'_>,
+    mut msg: Outbound,
+) {
+    match &mut msg {
+        Outbound::Data {
+            msg_type,
+            seq,
+            waiter,
+            ..
+        } => {
+            start.backlog.note_seq(*seq);
+            batch.messages += 1;
+            batch.terminal |= matches!(msg_type.as_str(), "Result" | "Error");
+            if let Some(waiter) = waiter.take() {
+                batch.waiters.push((*seq, waiter));
+            }
+        }
+        Outbound::Disconnect { .. } => {
+            batch.terminal = true;
+            batch.closed = true;
+        }
+        Outbound::Flush(done) => {
+            batch.flushes.push(done.clone());
+            return;
+        }
+        // Dropping the watcher ends its events.
+        Outbound::Watch { .. } => {
+            warn!("child events need the WebSocket transport");
+            return;
+        }
+        // Its messages are dropped with it.
+        Outbound::Subtask {
+            frame: SubtaskFrame::Register(_),
+            ..
+        } => {
+            warn!("subtasks need the WebSocket transport");
+            return;
+        }
+        Outbound::Subtask { .. } => {
+            debug!("subtask message dropped (no WebSocket)");
+            return;
+        }
+    }
+    batch
+        .frames
+        .push(outbound_frame(config, signing_key, msg, clock));
+}
+
+/// The client for the posts, with the builder's headers and proxy;
+/// reqwest follows the proxy variables by itself.
+fn client(tuning: &Tuning) -> Result<reqwest::Client, TrailsError> {
+    let builder = reqwest::Client::builder()
+        .default_headers(tuning.headers()?)
+        .connect_timeout(tuning.timeouts.connect);
+    let builder = match &tuning.proxy {
+        ProxySetting::Env => builder,
+        ProxySetting::Off => builder.no_proxy(),
+        ProxySetting::Url(url) => {
+            let proxy = reqwest::Proxy::all(url)
+                .map_err(|e| TrailsError::ConnectionFailed(format!("invalid proxy: {e}")))?;
+            builder.proxy(proxy)
+        }
+    };
+    // As `reqwest::Client::new()`, which panics on the same failure.
+    Ok(builder.build().expect("HTTP client"))
+}
+
+/// Bytes signed for an ingest post; must match the server. See
+/// `conformance/vectors/ingest_signature.json`.
+pub(crate) fn ingest_message(app_id: Uuid, timestamp_ms: i64, body: &[u8]) -> Vec<u8> {
+    let mut message = format!("trails-ingest:{app_id}:{timestamp_ms}:").into_bytes();
+    message.extend_from_slice(body);
+    message
+}
+
+pub(crate) fn sign_ingest(
+    key: &SigningKey,
+    app_id: Uuid,
+    timestamp_ms: i64,
+    body: &[u8],
+) -> String {
+    use ed25519_dalek::Signer;
+    let sig = key.sign(&ingest_message(app_id, timestamp_ms, body));
+    format!("ed25519:{}", base64::engine::general_purpose::STANDARD.encode(sig.to_bytes()))
+}
+
+/// One post. `Ok(None)` if the server refused it with a client error.
+async fn post(
+    client: &reqwest::Client,
+    url: &str,
+    config: &TrailsConfig,
+    signing_key: &SigningKey,
+    clock: &ClockSkew,
+    start: &Start<'_>,
+    body: String,
+) -> Result<Option<IngestResponse>, reqwest::Error> {
+    // The server checks the timestamp against its own clock.
+    let timestamp = chrono::Utc::now().timestamp_millis() + clock.offset().unwrap_or(0);
+    let sig = sign_ingest(signing_key, config.app_id, timestamp, body.as_bytes());
+    let mut req = client
+        .post(url)
+        .timeout(Duration::from_secs(30))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Trails-Timestamp", timestamp.to_string())
+        .header("X-Trails-Signature", sig)
+        .body(body);
+    if start.registered {
+        req = req.header("X-Trails-App-Id", config.app_id.to_string());
+    }
+    let resp = req.send().await?;
+    let status = resp.status();
+    if status.is_client_error() {
+        let body = resp.text().await.unwrap_or_default();
+        error!(%status, "ingest refused: {body}");
+        return Ok(None);
+    }
+    Ok(Some(resp.error_for_status()?.json().await?))
+}
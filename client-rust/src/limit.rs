@@ -0,0 +1,106 @@
+//! The payload size limit, set with
+//! `TrailsClientBuilder::max_payload_bytes`: a payload whose JSON is
+//! longer is refused as it is sent, with `TrailsError::PayloadTooLarge`,
+//! instead of being queued for the server to drop. The server's limit
+//! (`MAX_MESSAGE_BYTES`, 4 MiB by default) covers the whole frame; the
+//! default here leaves room for the rest of it.
+//!
+//! With `truncate_oversized_status`, an oversized Status goes out without
+//! its largest top-level fields instead, their names listed under
+//! `truncated`. Losing part of a progress update is fine; losing part of
+//! a Result or an Error isn't, so those are always refused.
+
+use std::io;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tracing::debug;
+
+use crate::TrailsError;
+
+/// The server's default `MAX_MESSAGE_BYTES`, less room for the header and
+/// signature.
+pub(crate) const DEFAULT_MAX_PAYLOAD_BYTES: usize = 4 * 1024 * 1024 - 4 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PayloadLimit {
+    pub max_bytes: usize,
+    /// Truncate an oversized Status rather than refuse it.
+    pub truncate_status: bool,
+}
+
+impl Default for PayloadLimit {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_PAYLOAD_BYTES,
+            truncate_status: false,
+        }
+    }
+}
+
+impl PayloadLimit {
+    /// The payload to send, truncated if it is an oversized Status that
+    /// may be; `PayloadTooLarge` if it can't be sent.
+    pub fn check(&self, msg_type: &str, payload: JsonValue) -> Result<JsonValue, TrailsError> {
+        let size = json_len(&payload);
+        if size <= self.max_bytes {
+            return Ok(payload);
+        }
+        let too_large = TrailsError::PayloadTooLarge {
+            size,
+            limit: self.max_bytes,
+        };
+        if !self.truncate_status || msg_type != "Status" {
+            return Err(too_large);
+        }
+        let truncated = truncate(payload, self.max_bytes).ok_or(too_large)?;
+        let dropped = &truncated["truncated"];
+        debug!(size, %dropped, "oversized status truncated");
+        Ok(truncated)
+    }
+}
+
+/// `payload` without its largest top-level fields, as few as it takes for
+/// its JSON to fit in `limit` bytes, their names under `truncated`; `None`
+/// if it isn't an object, or doesn't fit even so.
+pub(crate) fn truncate(payload: JsonValue, limit: usize) -> Option<JsonValue> {
+    let JsonValue::Object(mut fields) = payload else {
+        return None;
+    };
+    let mut sizes: Vec<(String, usize)> = fields
+        .iter()
+        .map(|(name, value)| (name.clone(), json_len(value)))
+        .collect();
+    sizes.sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+    let mut dropped = Vec::new();
+    for (name, _) in sizes {
+        fields.remove(&name);
+        dropped.push(name);
+        fields.insert("truncated".into(), dropped.clone().into());
+        if json_len(&fields) <= limit {
+            return Some(JsonValue::Object(fields));
+        }
+    }
+    None
+}
+
+/// The length of `value`'s JSON, without writing it out.
+pub(crate) fn json_len(value: &impl Serialize) -> usize {
+    struct Count(usize);
+
+    impl io::Write for Count {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut count = Count(0);
+    // Counting doesn't fail, and a Value always serializes.
+    let _ = serde_json::to_writer(&mut count, value);
+    count.0
+}
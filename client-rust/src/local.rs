@@ -0,0 +1,147 @@
+//! Local sink: with `TRAILS_LOCAL` set (`stderr`, or a file path) and no
+//! TRAILS_INFO, `init()` starts a client that writes each frame it would
+//! have sent, register first, as one line of NDJSON, in place of talking
+//! to a server. It is for running an app without trailsd during
+//! development and still seeing what it reports.
+//!
+//! The client is active but never connected. A message counts as sent
+//! once its line is written, and `result_confirmed` returns then, as no
+//! ack will come. Lines are flushed as they are written; a file is
+//! appended to.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use ed25519_dalek::SigningKey;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::stats::Stats;
+use crate::{
+    default_sec_level, outbound_frame, registration_frame, Outbound, TrailsConfig, Transport,
+};
+
+/// Where the frames go.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Sink {
+    Stderr,
+    File(PathBuf),
+}
+
+impl Sink {
+    /// The sink `TRAILS_LOCAL` names, if set.
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var_os("TRAILS_LOCAL")?;
+        if value.is_empty() {
+            return None;
+        }
+        Some(match value.to_str() {
+            Some("stderr") => Self::Stderr,
+            _ => Self::File(value.into()),
+        })
+    }
+
+    fn open(&self) -> Box<dyn Write + Send> {
+        let Self::File(path) = self else {
+            return Box::new(io::stderr());
+        };
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                warn!(path = %path.display(), "can't open TRAILS_LOCAL file ({e}), using stderr");
+                Box::new(io::stderr())
+            }
+        }
+    }
+}
+
+/// A config for an app that has none: a fresh id, named after the
+/// executable.
+pub(crate) fn config(sink: &Sink) -> TrailsConfig {
+    let app_name = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "local".into());
+    let server_ep = match sink {
+        Sink::Stderr => "local:stderr".into(),
+        Sink::File(path) => format!("local:{}", path.display()),
+    };
+    TrailsConfig {
+        v: 1,
+        app_id: Uuid::new_v4(),
+        parent_id: None,
+        app_name,
+        server_ep,
+        server_pub_key: None,
+        sec_level: default_sec_level(),
+        scheduled_at: None,
+        start_deadline: None,
+        originator: None,
+        role_refs: vec![],
+        tags: None,
+        priority: None,
+        transport: Transport::Ws,
+    }
+}
+
+/// Write the frames of queued messages to `sink` until the disconnect, or
+/// until the client is dropped.
+pub(crate) async fn local_task(
+    config: &TrailsConfig,
+    signing_key: &SigningKey,
+    rx: &mut mpsc::Receiver<Outbound>,
+    stats: &Stats,
+    sink: &Sink,
+) {
+    let mut out = sink.open();
+    let register = registration_frame(config, signing_key, true, 0, false);
+    write_line(&mut out, stats, register);
+    while let Some(mut msg) = rx.recv().await {
+        match msg {
+            Outbound::Data { .. } => {
+                let waiter = msg.take_waiter();
+                let frame = outbound_frame(config, signing_key, msg, stats.clock());
+                if write_line(&mut out, stats, frame) {
+                    stats.sent(1);
+                } else {
+                    stats.dropped(1);
+                }
+                if let Some(waiter) = waiter {
+                    let _ = waiter.send(Ok(()));
+                }
+            }
+            Outbound::Disconnect { .. } => {
+                let frame = outbound_frame(config, signing_key, msg, stats.clock());
+                write_line(&mut out, stats, frame);
+                return;
+            }
+            // Every line is flushed as it is written.
+            Outbound::Flush(done) => {
+                let _ = done.send(());
+            }
+            // Dropping the watcher ends its events.
+            Outbound::Watch { .. } => warn!("child events need a server"),
+            Outbound::Subtask { frame, .. } => {
+                let (frame, message) = frame.into_parts();
+                match write_line(&mut out, stats, frame) {
+                    true => stats.sent(u64::from(message)),
+                    false => stats.dropped(u64::from(message)),
+                }
+            }
+        }
+    }
+}
+
+/// Write `frame` as a line and flush it; false if that failed.
+fn write_line(out: &mut dyn Write, stats: &Stats, frame: String) -> bool {
+    match writeln!(out, "{frame}").and_then(|()| out.flush()) {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("local sink write failed: {e}");
+            stats.error(format_args!("local sink: {e}"));
+            false
+        }
+    }
+}
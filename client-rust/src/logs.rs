@@ -0,0 +1,71 @@
+//! Log lines, sent by `TrailsClient::log` as `Log` messages with payload
+//! `{"level": "warn", "message": "...", "fields": {...}}`, the time they
+//! were logged in the header. trailsd keeps them in a table of their own,
+//! beside the status history: they are neither progress nor terminal.
+//!
+//! They are for the lines worth keeping, not for all of stdout, so they
+//! are rate limited: a bucket of as many lines as the rate, refilled at
+//! the rate. A line that finds it empty is dropped and counted in
+//! `ClientStats::logs_dropped`.
+
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde_json::Value as JsonValue;
+use tracing::Level;
+
+pub(crate) const LOG: &str = "Log";
+
+/// Lines sent per second by default.
+pub(crate) const DEFAULT_LOG_RATE: u32 = 20;
+
+pub(crate) struct LogLimiter {
+    per_sec: f64,
+    /// Lines that may go now, and when that was worked out.
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl LogLimiter {
+    pub fn new(per_sec: u32) -> Self {
+        let per_sec = f64::from(per_sec.max(1));
+        Self {
+            per_sec,
+            bucket: Mutex::new((per_sec, Instant::now())),
+        }
+    }
+
+    /// Whether a line may go now; takes its place if so.
+    pub fn admit(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, since) = &mut *bucket;
+        let now = Instant::now();
+        let refill = now.duration_since(*since).as_secs_f64() * self.per_sec;
+        *tokens = (*tokens + refill).min(self.per_sec);
+        *since = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+/// The payload of a log line.
+pub(crate) fn entry(level: Level, message: &str, fields: Option<JsonValue>) -> JsonValue {
+    let mut entry = serde_json::json!({"level": level_name(level), "message": message});
+    if let Some(fields) = fields {
+        entry["fields"] = fields;
+    }
+    entry
+}
+
+/// The level as trailsd names it.
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::TRACE => "trace",
+        Level::DEBUG => "debug",
+        Level::INFO => "info",
+        Level::WARN => "warn",
+        Level::ERROR => "error",
+    }
+}
@@ -0,0 +1,136 @@
+//! Numeric metrics, set with `TrailsClient::metric` (a gauge: the latest
+//! value wins) and `TrailsClient::counter` (a running total the deltas
+//! add to). They are buffered, and a background task sends what changed
+//! at most once per interval, as one `Metric` message:
+//! `{"gauges": {"rows_per_sec": 1234.5}, "counters": {"bytes_read": 4096}}`.
+//! A counter goes as its total since the client started, so a flush
+//! that is lost costs nothing but a point. trailsd keeps each value as a
+//! point of the app's metric series.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::Value as JsonValue;
+use tokio::sync::Notify;
+
+pub(crate) const METRIC: &str = "Metric";
+
+/// The shortest time between two flushes.
+pub(crate) const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) struct MetricBuffer {
+    interval: Duration,
+    buffer: Mutex<Buffer>,
+    /// Something changed, or the client closed.
+    wake: Notify,
+}
+
+struct Buffer {
+    /// Gauges set since the last flush.
+    gauges: BTreeMap<String, f64>,
+    /// Counter totals, and whether each changed since the last flush.
+    counters: BTreeMap<String, (f64, bool)>,
+    changed: bool,
+    last_flush: Instant,
+    closed: bool,
+}
+
+impl MetricBuffer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            buffer: Mutex::new(Buffer {
+                gauges: BTreeMap::new(),
+                counters: BTreeMap::new(),
+                changed: false,
+                last_flush: Instant::now(),
+                closed: false,
+            }),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn gauge(&self, name: &str, value: f64) {
+        self.update(|buffer| {
+            buffer.gauges.insert(name.to_string(), value);
+        });
+    }
+
+    pub fn count(&self, name: &str, delta: f64) {
+        self.update(|buffer| {
+            let counter = buffer.counters.entry(name.to_string()).or_default();
+            *counter = (counter.0 + delta, true);
+        });
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Buffer)) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.closed {
+            return;
+        }
+        change(&mut buffer);
+        if !std::mem::replace(&mut buffer.changed, true) {
+            self.wake.notify_one();
+        }
+    }
+
+    /// The payload of what changed since the last flush, to send now.
+    pub fn take(&self) -> Option<JsonValue> {
+        let mut buffer = self.buffer.lock().unwrap();
+        if !std::mem::take(&mut buffer.changed) {
+            return None;
+        }
+        buffer.last_flush = Instant::now();
+        let gauges: serde_json::Map<_, _> = std::mem::take(&mut buffer.gauges)
+            .into_iter()
+            .map(|(name, value)| (name, value.into()))
+            .collect();
+        let counters: serde_json::Map<_, _> = buffer
+            .counters
+            .iter_mut()
+            .filter(|(_, (_, changed))| *changed)
+            .map(|(name, (total, changed))| {
+                *changed = false;
+                (name.clone(), (*total).into())
+            })
+            .collect();
+        let mut payload = serde_json::Map::new();
+        if !gauges.is_empty() {
+            payload.insert("gauges".into(), gauges.into());
+        }
+        if !counters.is_empty() {
+            payload.insert("counters".into(), counters.into());
+        }
+        Some(payload.into())
+    }
+
+    /// The client is going: stop `next_due` and buffer nothing more.
+    pub fn close(&self) {
+        self.buffer.lock().unwrap().closed = true;
+        self.wake.notify_one();
+    }
+
+    /// Wait until a change may be flushed, and take it; `None` once
+    /// closed.
+    pub async fn next_due(&self) -> Option<JsonValue> {
+        loop {
+            let due = {
+                let buffer = self.buffer.lock().unwrap();
+                if buffer.closed {
+                    return None;
+                }
+                buffer.changed.then_some(buffer.last_flush + self.interval)
+            };
+            match due {
+                None => self.wake.notified().await,
+                Some(due) => {
+                    tokio::time::sleep_until(due.into()).await;
+                    if let Some(payload) = self.take() {
+                        return Some(payload);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Read-only observers of another app: [`TrailsObserver`] opens its own
+//! WebSocket, sends `observe` in place of a register, and yields the
+//! Status, Result and Error messages the app stores from then on. It
+//! needs no TRAILS_INFO and never registers an app of its own.
+//!
+//! What the app stored before is read over REST: `last_seq` is where the
+//! stream starts. An observer doesn't reconnect; when the stream ends, a
+//! new one picks up again.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use crate::proxy::{self, ProxySetting};
+use crate::transport::{self, WsStream};
+use crate::{normalize_ws_url, TrailsError};
+
+/// How long the server has to answer the `observe`.
+const OBSERVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A message the observed app stored.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ObservedMessage {
+    pub seq: i64,
+    /// `Status`, `Result` or `Error`.
+    pub msg_type: String,
+    pub correlation_id: Option<String>,
+    /// Opaque sealed envelopes for sealed apps.
+    pub payload: Option<JsonValue>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct WireObserve {
+    r#type: &'static str,
+    app_id: Uuid,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ObserverFrame {
+    Observing {
+        status: String,
+        last_seq: Option<i64>,
+    },
+    Observed(ObservedMessage),
+    Error {
+        code: String,
+        message: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// A live stream of another app's stored messages.
+///
+/// ```ignore
+/// let mut observer = TrailsObserver::connect("wss://trails:8443", app_id).await?;
+/// while let Some(msg) = observer.next().await {
+///     println!("{} #{}: {:?}", msg.msg_type, msg.seq, msg.payload);
+/// }
+/// ```
+pub struct TrailsObserver {
+    app_id: Uuid,
+    status: String,
+    last_seq: Option<i64>,
+    ws: WsStream,
+}
+
+impl TrailsObserver {
+    /// Observe `app_id` on the server at `server_ep` (as in TRAILS_INFO).
+    /// `ServerError` if the server refuses, e.g. with `app_not_found`.
+    pub async fn connect(server_ep: &str, app_id: Uuid) -> Result<Self, TrailsError> {
+        Self::open(server_ep, app_id, None).await
+    }
+
+    /// `connect` to a server that requires the observer token
+    /// (`OBSERVER_TOKEN`), sent as `Authorization: Bearer <token>`.
+    pub async fn connect_with_token(
+        server_ep: &str,
+        app_id: Uuid,
+        token: &str,
+    ) -> Result<Self, TrailsError> {
+        Self::open(server_ep, app_id, Some(token)).await
+    }
+
+    async fn open(server_ep: &str, app_id: Uuid, token: Option<&str>) -> Result<Self, TrailsError> {
+        let url = normalize_ws_url(server_ep)?;
+        let headers = transport::headers(token, &[])?;
+        let proxy = proxy::for_url(&url, &ProxySetting::Env)?;
+        let timeouts = transport::Timeouts::default();
+        let mut ws = transport::connect(&url, None, &headers, proxy.as_ref(), timeouts)
+            .await
+            .map_err(|e| TrailsError::ConnectionFailed(e.to_string()))?;
+        let observe = WireObserve {
+            r#type: "observe",
+            app_id,
+        };
+        let observe = serde_json::to_string(&observe).unwrap();
+        ws.send(Message::Text(observe))
+            .await
+            .map_err(|e| TrailsError::ConnectionFailed(e.to_string()))?;
+        let answer = tokio::time::timeout(OBSERVE_TIMEOUT, next_frame(&mut ws))
+            .await
+            .map_err(|_| TrailsError::ConnectionFailed("no answer to observe".into()))?;
+        match answer {
+            Some(ObserverFrame::Observing { status, last_seq }) => Ok(Self {
+                app_id,
+                status,
+                last_seq,
+                ws,
+            }),
+            Some(ObserverFrame::Error { code, message }) => {
+                Err(TrailsError::ServerError(format!("{code}: {message}")))
+            }
+            _ => Err(TrailsError::ConnectionFailed(
+                "connection closed before observing".into(),
+            )),
+        }
+    }
+
+    /// The observed app.
+    pub fn app_id(&self) -> Uuid {
+        self.app_id
+    }
+
+    /// The app's status when observing began.
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// The highest seq the app had stored when observing began; the
+    /// stream brings what comes after.
+    pub fn last_seq(&self) -> Option<i64> {
+        self.last_seq
+    }
+}
+
+/// The next frame the observer cares about; `None` once the connection
+/// is gone.
+async fn next_frame(ws: &mut WsStream) -> Option<ObserverFrame> {
+    loop {
+        match ws.next().await? {
+            Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(ObserverFrame::Other) | Err(_) => {}
+                Ok(frame) => return Some(frame),
+            },
+            Ok(Message::Close(_)) | Err(_) => return None,
+            Ok(_) => {}
+        }
+    }
+}
+
+impl Stream for TrailsObserver {
+    type Item = ObservedMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let text = match self.ws.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(Message::Text(text)))) => text,
+                Poll::Ready(Some(Ok(Message::Close(_)) | Err(_)) | None) => {
+                    return Poll::Ready(None)
+                }
+                Poll::Ready(Some(Ok(_))) => continue,
+            };
+            match serde_json::from_str(&text) {
+                Ok(ObserverFrame::Observed(msg)) => return Poll::Ready(Some(msg)),
+                Ok(ObserverFrame::Error { .. }) => return Poll::Ready(None),
+                Ok(_) | Err(_) => {}
+            }
+        }
+    }
+}
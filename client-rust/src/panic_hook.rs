@@ -0,0 +1,71 @@
+//! Panic hook reporting a panic as an Error message before the process
+//! dies, installed by `TrailsClient::install_panic_hook`. Without it the
+//! server only sees the connection drop and marks the app crashed.
+//!
+//! The hook is installed once, in front of whatever hook was there; a
+//! later install only points it at another client. It queues the Error,
+//! then blocks the panicking thread for up to `FLUSH_TIMEOUT` while the
+//! background task writes it out. That needs the task to run on another
+//! thread: a panic on the only thread of a current-thread runtime still
+//! queues the Error, but it is only sent if the runtime carries on.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::panic::PanicHookInfo;
+use std::sync::{Mutex, Once, PoisonError};
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::{ClientHandle, ErrorCategory, ErrorReport};
+
+/// How long a panic waits for its Error to be sent.
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The client panics are reported to: the last to install the hook.
+static TARGET: Mutex<Option<ClientHandle>> = Mutex::new(None);
+static INSTALL: Once = Once::new();
+
+pub(crate) fn install(handle: ClientHandle) {
+    *TARGET.lock().unwrap_or_else(PoisonError::into_inner) = Some(handle);
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            report(info);
+            previous(info);
+        }));
+    });
+}
+
+fn report(info: &PanicHookInfo<'_>) {
+    let target = TARGET
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let Some(handle) = target else {
+        return;
+    };
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+    // Captured only when RUST_BACKTRACE (or RUST_LIB_BACKTRACE) asks.
+    let backtrace = Backtrace::capture();
+    let backtrace =
+        (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string());
+    let report = ErrorReport::new(
+        ErrorCategory::Internal,
+        "panic",
+        format!("panic: {message}"),
+    )
+    .with_detail(json!({
+        "location": info.location().map(ToString::to_string),
+        "thread": std::thread::current().name(),
+        "backtrace": backtrace,
+    }));
+    let payload = serde_json::to_value(report).unwrap_or_default();
+    if handle.send("Error", payload, false).is_ok() {
+        handle.flush(FLUSH_TIMEOUT);
+    }
+}
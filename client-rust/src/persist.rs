@@ -0,0 +1,113 @@
+//! Saved client state, for processes restarted with the same TRAILS_INFO
+//! (a pod restart, say): the app's signing key and last seq, kept in
+//! `<state dir>/trails-<app_id>.json` once the app has registered. A
+//! process that finds the file sends `re_register` with that key instead
+//! of a `register` the server would refuse. The server still has to take
+//! the app for live (reconnecting, lost contact, or on HTTP) for the
+//! re_register to succeed.
+//!
+//! The file is written with mode 0600 and replaced atomically. One that
+//! doesn't parse or names another app is ignored with a warning and the
+//! client registers afresh.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    app_id: Uuid,
+    /// Base64 of the 32-byte Ed25519 seed.
+    key: String,
+    last_seq: i64,
+}
+
+/// Where one app's state is saved.
+pub(crate) struct StateFile {
+    path: PathBuf,
+    app_id: Uuid,
+    key: String,
+}
+
+impl StateFile {
+    /// The signing key for `app_id` and, when a previous process
+    /// registered it, that process's last seq. Without usable saved state
+    /// the key is fresh.
+    pub fn open(dir: &Path, app_id: Uuid) -> (Self, SigningKey, Option<i64>) {
+        let path = dir.join(format!("trails-{app_id}.json"));
+        let restored = match fs::read(&path) {
+            Ok(bytes) => match decode(&bytes, app_id) {
+                Ok(restored) => Some(restored),
+                Err(e) => {
+                    warn!(path = %path.display(), "ignoring saved state: {e}");
+                    None
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!(path = %path.display(), "can't read saved state: {e}");
+                None
+            }
+        };
+        let (signing_key, last_seq) = match restored {
+            Some((key, last_seq)) => {
+                debug!(path = %path.display(), last_seq, "restored saved state");
+                (key, Some(last_seq))
+            }
+            None => (SigningKey::generate(&mut rand::thread_rng()), None),
+        };
+        let key = base64::engine::general_purpose::STANDARD.encode(signing_key.to_bytes());
+        let file = Self { path, app_id, key };
+        (file, signing_key, last_seq)
+    }
+
+    /// Record `last_seq`. Failures are logged: the app carries on, it just
+    /// can't be resumed after a restart.
+    pub fn save(&self, last_seq: i64) {
+        let saved = Saved {
+            app_id: self.app_id,
+            key: self.key.clone(),
+            last_seq,
+        };
+        if let Err(e) = self.write(&serde_json::to_vec(&saved).unwrap()) {
+            warn!(path = %self.path.display(), "can't save client state: {e}");
+        }
+    }
+
+    fn write(&self, bytes: &[u8]) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// The key and last seq saved for `app_id`.
+fn decode(bytes: &[u8], app_id: Uuid) -> Result<(SigningKey, i64), String> {
+    let saved: Saved = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+    if saved.app_id != app_id {
+        return Err(format!("saved for app {}", saved.app_id));
+    }
+    let seed = base64::engine::general_purpose::STANDARD
+        .decode(&saved.key)
+        .map_err(|e| format!("bad key: {e}"))?;
+    let seed: [u8; 32] = seed.try_into().map_err(|_| "bad key length".to_string())?;
+    Ok((SigningKey::from_bytes(&seed), saved.last_seq.max(0)))
+}
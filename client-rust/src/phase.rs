@@ -0,0 +1,96 @@
+//! Phases, for `TrailsClient::phase`: a guard per phase entered, on a
+//! stack kept by the client so that nested phases report their path,
+//! e.g. `etl/loading`.
+//!
+//! ```ignore
+//! let _etl = g.phase("etl");
+//! {
+//!     let _load = g.phase("loading");     // {"phase": "etl/loading"}
+//!     g.progress(0.5).await?;             // {"phase": "etl/loading", "progress": 0.5}
+//! }                                       // {"phase": "etl/loading", "phase_done": true,
+//!                                         //  "duration_ms": ...}
+//! ```
+//!
+//! Drop can't await, so the guard sends like [`ClientHandle`]: best
+//! effort, whatever the client's `Delivery`, and nothing once the client
+//! is gone. Phase Status messages aren't coalesced.
+
+use std::time::Instant;
+
+use serde_json::json;
+
+use crate::ClientHandle;
+
+/// The phases entered and not yet left, outermost first.
+#[derive(Default)]
+pub(crate) struct Phases {
+    next_id: u64,
+    stack: Vec<(u64, String)>,
+}
+
+impl Phases {
+    /// The path of the innermost phase, if any.
+    pub fn path(&self) -> Option<String> {
+        let names: Vec<&str> = self.stack.iter().map(|(_, name)| name.as_str()).collect();
+        (!names.is_empty()).then(|| names.join("/"))
+    }
+
+    fn enter(&mut self, name: String) -> (u64, String) {
+        self.next_id += 1;
+        self.stack.push((self.next_id, name));
+        (self.next_id, self.path().unwrap_or_default())
+    }
+
+    /// Guards dropped out of order (phases on concurrent tasks) only
+    /// take themselves off.
+    fn leave(&mut self, id: u64) {
+        if let Some(at) = self.stack.iter().rposition(|(entered, _)| *entered == id) {
+            self.stack.remove(at);
+        }
+    }
+}
+
+/// A phase of the app's work, from [`crate::TrailsClient::phase`]. Sends
+/// a Status with the phase's path when entered, and one marking it done,
+/// with its wall-clock duration, when dropped.
+#[must_use = "the phase ends when the guard is dropped"]
+pub struct PhaseGuard {
+    handle: ClientHandle,
+    id: u64,
+    path: String,
+    started: Instant,
+}
+
+impl PhaseGuard {
+    pub(crate) fn enter(handle: ClientHandle, name: String) -> Self {
+        let (id, path) = match handle.phases() {
+            Some(phases) => phases.lock().unwrap().enter(name),
+            None => (0, name),
+        };
+        let _ = handle.send_marker(json!({"phase": path}));
+        Self {
+            handle,
+            id,
+            path,
+            started: Instant::now(),
+        }
+    }
+
+    /// The phase's path, its name after those of the phases around it.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        if let Some(phases) = self.handle.phases() {
+            phases.lock().unwrap().leave(self.id);
+        }
+        let _ = self.handle.send_marker(json!({
+            "phase": self.path,
+            "phase_done": true,
+            "duration_ms": self.started.elapsed().as_millis() as u64,
+        }));
+    }
+}
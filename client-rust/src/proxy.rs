@@ -0,0 +1,268 @@
+//! HTTP proxies for the WebSocket: the connection to a `ws://` or
+//! `wss://` endpoint goes through a CONNECT tunnel when `http_proxy` (for
+//! `ws://`) or `https_proxy` (for `wss://`), lower or upper case, names a
+//! proxy and `no_proxy` doesn't exempt the host, or when the builder sets
+//! a proxy. Credentials in the proxy URL are sent as Basic
+//! `Proxy-Authorization`. Proxies are spoken to in plain HTTP; TLS to the
+//! server runs inside the tunnel.
+//!
+//! The HTTP transport and the REST helpers use reqwest, which honors the
+//! same variables by itself.
+
+use std::fmt;
+use std::io;
+
+use base64::Engine;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::TrailsError;
+
+/// The longest CONNECT answer read.
+const MAX_ANSWER: usize = 8 * 1024;
+
+/// Which proxy the WebSocket goes through, set through the builder.
+#[derive(Debug, Clone, Default)]
+pub(crate) enum ProxySetting {
+    /// As the environment says.
+    #[default]
+    Env,
+    /// None, whatever the environment says.
+    Off,
+    /// This one, for every endpoint.
+    Url(String),
+}
+
+/// An HTTP proxy to tunnel through.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Proxy {
+    host: String,
+    port: u16,
+    /// `Proxy-Authorization` value, from the URL's credentials.
+    authorization: Option<String>,
+}
+
+impl Proxy {
+    /// Parse `http://[user:password@]host[:port]`; the scheme may be left
+    /// out, and the port defaults to 80.
+    pub fn parse(url: &str) -> Result<Self, TrailsError> {
+        let invalid = |why: &str| TrailsError::ConnectionFailed(format!("invalid proxy: {why}"));
+        let with_scheme = if url.contains("://") {
+            url.to_string()
+        } else {
+            format!("http://{url}")
+        };
+        let parsed = url::Url::parse(&with_scheme).map_err(|e| invalid(&e.to_string()))?;
+        if parsed.scheme() != "http" {
+            return Err(invalid("only http:// proxies are supported"));
+        }
+        let host = parsed.host_str().ok_or_else(|| invalid("no host"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let authorization = match (parsed.username(), parsed.password()) {
+            ("", None) => None,
+            (user, password) => {
+                let credentials = format!(
+                    "{}:{}",
+                    percent_decode(user),
+                    percent_decode(password.unwrap_or(""))
+                );
+                let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+                Some(format!("Basic {encoded}"))
+            }
+        };
+        Ok(Self {
+            host: host.to_string(),
+            port: parsed.port().unwrap_or(80),
+            authorization,
+        })
+    }
+}
+
+/// Without the credentials, for logs.
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}:{}", self.host, self.port)
+    }
+}
+
+/// The proxy the WebSocket to `ws_url` goes through, if any.
+pub(crate) fn for_url(ws_url: &str, setting: &ProxySetting) -> Result<Option<Proxy>, TrailsError> {
+    match setting {
+        ProxySetting::Off => Ok(None),
+        ProxySetting::Url(url) => Proxy::parse(url).map(Some),
+        ProxySetting::Env => from_env(ws_url, |name| std::env::var(name).ok()),
+    }
+}
+
+/// The proxy the variables `var` looks up name for `ws_url`.
+pub(crate) fn from_env(
+    ws_url: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<Option<Proxy>, TrailsError> {
+    let Ok(url) = url::Url::parse(ws_url) else {
+        return Ok(None); // a Unix socket
+    };
+    let names = match url.scheme() {
+        "wss" => ["https_proxy", "HTTPS_PROXY"],
+        "ws" => ["http_proxy", "HTTP_PROXY"],
+        _ => return Ok(None),
+    };
+    let lookup = |names: [&str; 2]| {
+        names
+            .into_iter()
+            .find_map(|name| var(name).filter(|v| !v.trim().is_empty()))
+    };
+    let Some(proxy) = lookup(names) else {
+        return Ok(None);
+    };
+    let host = url.host_str().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or_default();
+    match lookup(["no_proxy", "NO_PROXY"]) {
+        Some(no_proxy) if bypassed(&no_proxy, host, port) => Ok(None),
+        _ => Proxy::parse(proxy.trim()).map(Some),
+    }
+}
+
+/// Whether `no_proxy` (comma-separated hosts, domain suffixes such as
+/// `.corp` or `corp`, either with an optional `:port`, or `*`) exempts
+/// `host`.
+pub(crate) fn bypassed(no_proxy: &str, host: &str, port: u16) -> bool {
+    let host = host.to_ascii_lowercase();
+    no_proxy.split(',').map(str::trim).any(|entry| {
+        if entry == "*" {
+            return true;
+        }
+        let entry = entry.to_ascii_lowercase();
+        let (name, entry_port) = match entry.rsplit_once(':') {
+            Some((name, p)) if !name.contains(':') => match p.parse::<u16>() {
+                Ok(p) => (name.to_string(), Some(p)),
+                Err(_) => return false,
+            },
+            _ => (entry, None),
+        };
+        let name = name.trim_start_matches('*').trim_start_matches('.');
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        !name.is_empty()
+            && entry_port.is_none_or(|p| p == port)
+            && (host == name || host.ends_with(&format!(".{name}")))
+    })
+}
+
+/// Why the tunnel couldn't be opened.
+#[derive(Debug)]
+pub(crate) enum ProxyError {
+    /// The proxy itself is out of reach.
+    Unreachable { proxy: String, source: io::Error },
+    /// The proxy turned the CONNECT down: 407 for credentials, 502 or
+    /// 504 when it can't reach the server.
+    Refused {
+        proxy: String,
+        target: String,
+        status: String,
+    },
+    /// The proxy hung up, or didn't answer in HTTP.
+    BadAnswer { proxy: String, why: String },
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreachable { proxy, source } => write!(f, "can't reach proxy {proxy}: {source}"),
+            Self::Refused {
+                proxy,
+                target,
+                status,
+            } => write!(f, "proxy {proxy} refused the tunnel to {target}: {status}"),
+            Self::BadAnswer { proxy, why } => write!(f, "proxy {proxy}: {why}"),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+/// Whether a connect failed at the proxy rather than past it.
+pub(crate) fn is_proxy_error(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<ProxyError>())
+}
+
+/// Open a tunnel through `proxy` to `host:port`.
+pub(crate) async fn tunnel(proxy: &Proxy, host: &str, port: u16) -> io::Result<TcpStream> {
+    let unreachable = |source| {
+        io::Error::other(ProxyError::Unreachable {
+            proxy: proxy.to_string(),
+            source,
+        })
+    };
+    let bad_answer = |why: &str| {
+        io::Error::other(ProxyError::BadAnswer {
+            proxy: proxy.to_string(),
+            why: why.into(),
+        })
+    };
+    let mut socket = TcpStream::connect((proxy.host.as_str(), proxy.port))
+        .await
+        .map_err(unreachable)?;
+    let target = format!("{host}:{port}");
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some(authorization) = &proxy.authorization {
+        request.push_str(&format!("Proxy-Authorization: {authorization}\r\n"));
+    }
+    request.push_str("\r\n");
+    socket
+        .write_all(request.as_bytes())
+        .await
+        .map_err(unreachable)?;
+
+    // Byte by byte, so nothing past the answer is taken from the tunnel.
+    let mut answer = Vec::new();
+    while !answer.ends_with(b"\r\n\r\n") {
+        if answer.len() >= MAX_ANSWER {
+            return Err(bad_answer("CONNECT answer too long"));
+        }
+        match socket.read_u8().await {
+            Ok(byte) => answer.push(byte),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(bad_answer("closed the connection during CONNECT"));
+            }
+            Err(e) => return Err(unreachable(e)),
+        }
+    }
+    let answer = String::from_utf8_lossy(&answer);
+    let status_line = answer.lines().next().unwrap_or_default();
+    let (version, status) = status_line.split_once(' ').unwrap_or((status_line, ""));
+    if !version.starts_with("HTTP/") {
+        return Err(bad_answer("not an HTTP answer to CONNECT"));
+    }
+    if !status.starts_with('2') {
+        return Err(io::Error::other(ProxyError::Refused {
+            proxy: proxy.to_string(),
+            target,
+            status: status.trim().to_string(),
+        }));
+    }
+    Ok(socket)
+}
+
+/// Decode `%XX` escapes, as in URL credentials.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
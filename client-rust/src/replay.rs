@@ -0,0 +1,255 @@
+//! Replay backlog: data frames the server hasn't acked, resent after a
+//! reconnect so a server restart doesn't leave a gap in the app's
+//! messages. A frame is kept from the moment it is queued, sent or not,
+//! until an `ack` covers its seq or the Registered ack of a re_register
+//! reports it stored.
+//!
+//! The backlog is bounded. Past capacity the oldest Status goes first,
+//! then the oldest other non-terminal message; Result and Error are never
+//! dropped, so those may take it over capacity.
+//!
+//! A frame is kept as it was sent: MessagePack if the connection took
+//! that, else JSON text. Replay to a connection that doesn't take
+//! MessagePack sends it as JSON.
+//!
+//! A frame may carry a waiter, from `TrailsClient::result_confirmed`,
+//! told when the ack comes, when the server rejects the frame, or when
+//! a second connection is lost before either.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use serde_json::Value as JsonValue;
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, warn};
+
+use crate::persist::StateFile;
+use crate::subtask::Subtasks;
+use crate::TrailsError;
+
+/// Told whether the server stored a frame.
+pub(crate) type AckWaiter = oneshot::Sender<Result<(), TrailsError>>;
+
+/// A data frame, as encoded for the connection it was sent on.
+#[derive(Clone)]
+pub(crate) enum Frame {
+    Text(String),
+    /// MessagePack, for a server that took `msgpack_frames`.
+    Binary(Vec<u8>),
+}
+
+impl Frame {
+    /// The frame as a WebSocket message, on a connection that takes
+    /// MessagePack (`binary`) or not.
+    pub fn into_message(self, binary: bool) -> Message {
+        match self {
+            Self::Binary(bytes) if binary => Message::Binary(bytes),
+            frame => Message::Text(frame.into_text()),
+        }
+    }
+
+    /// The frame as JSON text.
+    pub fn into_text(self) -> String {
+        match self {
+            Self::Text(text) => text,
+            Self::Binary(bytes) => rmp_serde::from_slice::<JsonValue>(&bytes)
+                .expect("a frame encoded by the client")
+                .to_string(),
+        }
+    }
+}
+
+/// A data frame awaiting its ack.
+pub(crate) struct Pending {
+    pub seq: i64,
+    pub msg_type: String,
+    pub frame: Frame,
+    pub waiter: Option<AckWaiter>,
+    /// A connection was lost with the frame unacked.
+    lost: bool,
+}
+
+impl Pending {
+    pub fn new(seq: i64, msg_type: String, frame: Frame, waiter: Option<AckWaiter>) -> Self {
+        Self {
+            seq,
+            msg_type,
+            frame,
+            waiter,
+            lost: false,
+        }
+    }
+
+    /// Result or Error: ends the app.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self.msg_type.as_str(), "Result" | "Error")
+    }
+}
+
+/// What the transport carries across reconnects.
+pub(crate) struct Backlog {
+    capacity: usize,
+    /// Unacked frames, in seq order.
+    pending: VecDeque<Pending>,
+    /// Highest seq the server acked, or reported stored; re_register
+    /// reports it.
+    pub last_seq: i64,
+    /// Highest seq numbered so far, acked or not: the state file keeps
+    /// it, so a restarted process doesn't number from an earlier seq.
+    numbered: i64,
+    /// The disconnect frame, when shutdown was asked for while offline.
+    pub closing: Option<String>,
+    /// Never registered: the next connect sends `register`, not
+    /// `re_register`.
+    pub first_connect: bool,
+    /// Subtasks to register again on each new connection.
+    pub subtasks: Subtasks,
+    /// The client's seq counter, moved on past what the server stored.
+    seq: Arc<AtomicI64>,
+    /// Keeps `numbered` for a restarted process, once registered.
+    state: Option<StateFile>,
+}
+
+impl Backlog {
+    pub fn new(capacity: usize, seq: Arc<AtomicI64>) -> Self {
+        Self {
+            capacity,
+            pending: VecDeque::new(),
+            last_seq: 0,
+            numbered: 0,
+            closing: None,
+            first_connect: true,
+            subtasks: Subtasks::default(),
+            seq,
+            state: None,
+        }
+    }
+
+    /// Save state to `state`, carrying on from `restored`, the last seq
+    /// of a previous process that registered the app. Whether the server
+    /// acked it is unknown: the `registered` answer settles it.
+    pub fn with_state(mut self, state: StateFile, restored: Option<i64>) -> Self {
+        if let Some(last_seq) = restored {
+            self.last_seq = last_seq;
+            self.numbered = last_seq;
+            self.first_connect = false;
+        }
+        self.state = Some(state);
+        self
+    }
+
+    /// The server took the register/re_register; `stored` is the last seq
+    /// it reports stored. New messages must not reuse a seq it has.
+    pub fn registered(&mut self, stored: Option<i64>) {
+        self.first_connect = false;
+        if let Some(stored) = stored {
+            self.ack(stored);
+            let taken = self.seq.fetch_max(stored, Ordering::Relaxed);
+            if stored > taken {
+                warn!(
+                    stored,
+                    taken, "server is ahead of the client's seq, skipping ahead"
+                );
+            }
+            self.numbered = self.numbered.max(stored);
+        }
+        self.save();
+    }
+
+    /// Note a seq sent without going through the backlog (over HTTP).
+    pub fn note_seq(&mut self, seq: i64) {
+        if seq > self.numbered {
+            self.numbered = seq;
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        if let Some(state) = &self.state {
+            if !self.first_connect {
+                state.save(self.numbered);
+            }
+        }
+    }
+
+    /// Keep a data frame until it is acked, dropping older ones if over
+    /// capacity. Returns how many were dropped.
+    pub fn push(&mut self, pending: Pending) -> usize {
+        self.note_seq(pending.seq);
+        // Senders race between taking a seq and queueing; keep seq order.
+        let at = self
+            .pending
+            .iter()
+            .rposition(|p| p.seq < pending.seq)
+            .map_or(0, |i| i + 1);
+        self.pending.insert(at, pending);
+        let mut dropped = 0;
+        while self.pending.len() > self.capacity {
+            let victim = self
+                .pending
+                .iter()
+                .position(|p| p.msg_type == "Status")
+                .or_else(|| self.pending.iter().position(|p| !p.is_terminal()));
+            let Some(victim) = victim.and_then(|i| self.pending.remove(i)) else {
+                break;
+            };
+            debug!(
+                seq = victim.seq,
+                msg_type = victim.msg_type,
+                "replay buffer full, message dropped"
+            );
+            dropped += 1;
+        }
+        dropped
+    }
+
+    /// The server has handled everything up to `seq`: acks come in order
+    /// on a connection, so one covers the frames before it.
+    pub fn ack(&mut self, seq: i64) {
+        while self.pending.front().is_some_and(|p| p.seq <= seq) {
+            if let Some(waiter) = self.pending.pop_front().and_then(|p| p.waiter) {
+                let _ = waiter.send(Ok(()));
+            }
+        }
+        self.last_seq = self.last_seq.max(seq);
+    }
+
+    /// The server answered with an error where the ack of the oldest
+    /// unacked frame was due. Its waiter hears of it; the frame stays
+    /// until a later ack covers it.
+    pub fn rejected(&mut self, error: String) {
+        if let Some(waiter) = self.pending.front_mut().and_then(|p| p.waiter.take()) {
+            let _ = waiter.send(Err(TrailsError::ServerError(error)));
+        }
+    }
+
+    /// The connection was lost. A waiter rides out one loss, its frame
+    /// replayed on the next connection, and fails on the second.
+    pub fn connection_lost(&mut self) {
+        for pending in &mut self.pending {
+            if pending.lost {
+                if let Some(waiter) = pending.waiter.take() {
+                    let e = "connection lost twice before the ack".to_string();
+                    let _ = waiter.send(Err(TrailsError::ConnectionFailed(e)));
+                }
+            }
+            pending.lost = true;
+        }
+    }
+
+    /// Frames to resend, in seq order.
+    pub fn iter(&self) -> impl Iterator<Item = &Pending> {
+        self.pending.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Take the unacked frames, e.g. to post them over HTTP.
+    pub fn take(&mut self) -> Vec<Pending> {
+        self.pending.drain(..).collect()
+    }
+}
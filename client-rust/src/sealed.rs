@@ -0,0 +1,156 @@
+//! Sealed payloads for `secLevel: "sealed"` (feature `sealed`).
+//!
+//! Each data payload is encrypted to a recipient X25519 public key taken
+//! from the config tags (`sealedRecipient`, optional `sealedKid`), so
+//! trailsd only ever stores ciphertext. The envelope sent as the payload:
+//!
+//! ```json
+//! { "v": 1, "alg": "x25519-hkdf-sha256-chacha20poly1305",
+//!   "kid": "team-a-2026", "epk": "<b64>", "nonce": "<b64>", "ct": "<b64>" }
+//! ```
+//!
+//! - shared = X25519(ephemeral secret, recipient public key)
+//! - key = HKDF-SHA256(ikm = shared, salt = epk ‖ recipient public key,
+//!   info = "trails-sealed-v1"), 32 bytes
+//! - ct = ChaCha20-Poly1305(key, nonce, payload JSON,
+//!   aad = "trails-sealed-v1:<app_id>:<msg_type>:<seq>")
+//!
+//! The AAD ties an envelope to its message, so stored ciphertext can't be
+//! moved to another app or seq. Consumers decrypt what the messages API
+//! returns with [`open`]. Test vectors:
+//! `conformance/vectors/sealed_envelope.json`.
+
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand::RngCore;
+use serde_json::{json, Value as JsonValue};
+use sha2::Sha256;
+use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::TrailsError;
+
+pub const ENVELOPE_VERSION: u64 = 1;
+pub const ALG: &str = "x25519-hkdf-sha256-chacha20poly1305";
+const INFO: &[u8] = b"trails-sealed-v1";
+
+/// Encrypt `payload` to `recipient` ("x25519:<base64>").
+pub fn seal(
+    recipient: &str,
+    kid: Option<&str>,
+    app_id: Uuid,
+    msg_type: &str,
+    seq: i64,
+    payload: &JsonValue,
+) -> Result<JsonValue, TrailsError> {
+    let recipient = parse_public_key(recipient)?;
+    let mut ephemeral = [0u8; 32];
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut ephemeral);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    seal_with(&recipient, ephemeral, nonce, kid, app_id, msg_type, seq, payload)
+}
+
+/// Decrypt an envelope with the recipient's 32-byte X25519 secret.
+/// `app_id`, `msg_type` and `seq` are those of the stored message.
+pub fn open(
+    recipient_secret: &[u8; 32],
+    app_id: Uuid,
+    msg_type: &str,
+    seq: i64,
+    envelope: &JsonValue,
+) -> Result<JsonValue, TrailsError> {
+    if envelope["v"].as_u64() != Some(ENVELOPE_VERSION) || envelope["alg"] != ALG {
+        return Err(TrailsError::Sealed("unsupported envelope".into()));
+    }
+    let field = |name: &str| {
+        envelope[name]
+            .as_str()
+            .and_then(|s| base64::engine::general_purpose::STANDARD.decode(s).ok())
+            .ok_or_else(|| TrailsError::Sealed(format!("envelope field '{name}' missing")))
+    };
+    let epk: [u8; 32] = field("epk")?
+        .try_into()
+        .map_err(|_| TrailsError::Sealed("epk must be 32 bytes".into()))?;
+    let nonce: [u8; 12] = field("nonce")?
+        .try_into()
+        .map_err(|_| TrailsError::Sealed("nonce must be 12 bytes".into()))?;
+    let ct = field("ct")?;
+
+    let secret = StaticSecret::from(*recipient_secret);
+    let recipient = PublicKey::from(&secret);
+    let shared = secret.diffie_hellman(&PublicKey::from(epk));
+    let cipher = cipher(shared.as_bytes(), &epk, recipient.as_bytes());
+    let aad = aad(app_id, msg_type, seq);
+    let plain = cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ct, aad: aad.as_bytes() })
+        .map_err(|_| TrailsError::Sealed("decryption failed".into()))?;
+    serde_json::from_slice(&plain).map_err(|e| TrailsError::Sealed(e.to_string()))
+}
+
+/// Public key for a recipient secret, as "x25519:<base64>" — the value
+/// for the `sealedRecipient` tag.
+pub fn public_key(recipient_secret: &[u8; 32]) -> String {
+    let public = PublicKey::from(&StaticSecret::from(*recipient_secret));
+    let b64 = base64::engine::general_purpose::STANDARD.encode(public.as_bytes());
+    format!("x25519:{b64}")
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn seal_with(
+    recipient: &PublicKey,
+    ephemeral: [u8; 32],
+    nonce: [u8; 12],
+    kid: Option<&str>,
+    app_id: Uuid,
+    msg_type: &str,
+    seq: i64,
+    payload: &JsonValue,
+) -> Result<JsonValue, TrailsError> {
+    let ephemeral = StaticSecret::from(ephemeral);
+    let epk = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(recipient);
+    let cipher = cipher(shared.as_bytes(), epk.as_bytes(), recipient.as_bytes());
+    let plain = serde_json::to_vec(payload).map_err(|e| TrailsError::Serialize(e.to_string()))?;
+    let aad = aad(app_id, msg_type, seq);
+    let ct = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plain, aad: aad.as_bytes() })
+        .map_err(|_| TrailsError::Sealed("encryption failed".into()))?;
+
+    let b64 = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+    let mut envelope = json!({
+        "v": ENVELOPE_VERSION,
+        "alg": ALG,
+        "epk": b64(epk.as_bytes()),
+        "nonce": b64(&nonce),
+        "ct": b64(&ct),
+    });
+    if let Some(kid) = kid {
+        envelope["kid"] = kid.into();
+    }
+    Ok(envelope)
+}
+
+fn cipher(shared: &[u8; 32], epk: &[u8; 32], recipient: &[u8; 32]) -> ChaCha20Poly1305 {
+    let salt = [epk.as_slice(), recipient.as_slice()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+fn aad(app_id: Uuid, msg_type: &str, seq: i64) -> String {
+    format!("trails-sealed-v1:{app_id}:{msg_type}:{seq}")
+}
+
+pub(crate) fn parse_public_key(s: &str) -> Result<PublicKey, TrailsError> {
+    let bytes: [u8; 32] = s
+        .strip_prefix("x25519:")
+        .and_then(|b64| base64::engine::general_purpose::STANDARD.decode(b64).ok())
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| TrailsError::Sealed(format!("invalid recipient key '{s}'")))?;
+    Ok(PublicKey::from(bytes))
+}
@@ -0,0 +1,311 @@
+//! Delivery counters, kept by the transport task and read through
+//! `TrailsClient::stats()`, so an app can tell how lossy its telemetry
+//! is. The task also reports the connection state here: this owns the
+//! `connected` watch behind `is_connected()`, and the `ConnectionState`
+//! one behind `connection_events()`, and the clock skew estimate.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::watch;
+
+use crate::clock::ClockSkew;
+
+/// A snapshot of the client's delivery counters; all zero for the no-op
+/// client. Counts are since the client started.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    /// Data messages written to the server, replays included. Over HTTP,
+    /// those in batches the server answered.
+    pub messages_sent: u64,
+    /// Messages lost before reaching the server: sends that found the
+    /// channel full or the task stopped, those pushed out of a full
+    /// replay buffer, and batches the server refused over HTTP.
+    pub messages_dropped: u64,
+    /// Messages the server acked, duplicates included.
+    pub acks_received: u64,
+    /// Connections made after the first.
+    pub reconnect_count: u64,
+    /// The last connection or delivery failure.
+    pub last_error: Option<String>,
+    /// When the current connection was registered; `None` while
+    /// disconnected.
+    pub connected_since: Option<SystemTime>,
+    /// The client stopped trying to reach the server, as its
+    /// `BackoffPolicy` allows; everything sent since is dropped.
+    pub gave_up: bool,
+    /// With `compress_payloads`, the payloads queued, as JSON, and the
+    /// same payloads as sent, gzipped or not; both zero otherwise.
+    pub payload_bytes_uncompressed: u64,
+    pub payload_bytes_compressed: u64,
+    /// Log lines dropped by the client's log rate limit, not sent.
+    pub logs_dropped: u64,
+    /// The server refused the app for good, with this code, e.g.
+    /// `already_terminal` at registration for an app it marked
+    /// `start_failed` past its start deadline; everything sent since is
+    /// dropped.
+    pub rejected: Option<String>,
+    /// Errors the server sent after registration, as also delivered by
+    /// `TrailsClient::server_errors`.
+    pub server_errors: u64,
+    /// How far the server's clock is ahead of this host's, in ms,
+    /// estimated from the server's acks; `None` before the first. Header
+    /// timestamps are corrected by it with `correct_clock_skew`.
+    pub clock_offset_ms: Option<i64>,
+}
+
+/// Where the client's connection to the server stands, as followed
+/// through `TrailsClient::connection_events()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Registered with the server: the WebSocket is up, or over HTTP,
+    /// the last post went through.
+    Connected,
+    /// Not connected, and trying to be, since `since`: when the connection
+    /// was lost, or the client started. `attempt` numbers the retry under
+    /// way, from 1; 0 is the client's first connect.
+    Reconnecting { attempt: u32, since: SystemTime },
+    /// A client with `lazy_connect` that hasn't sent anything yet, and
+    /// won't connect until it does.
+    Idle,
+    /// The background task has stopped, after shutdown or for good.
+    ShutDown,
+    /// The client stopped trying to reach the server after the failures
+    /// its `BackoffPolicy` allows; messages are dropped from then on, until
+    /// `TrailsClient::reconnect`.
+    GaveUp,
+    /// The server refused the registration in a way retrying won't fix,
+    /// e.g. the app already finished or missed its start deadline; the
+    /// code is in `ClientStats::rejected`. Messages are dropped from then
+    /// on.
+    Rejected,
+    /// A no-op client, which never connects.
+    Disabled,
+}
+
+impl ConnectionState {
+    /// Shut down, given up or rejected: the state changes no more.
+    fn is_final(&self) -> bool {
+        matches!(self, Self::ShutDown | Self::GaveUp | Self::Rejected)
+    }
+}
+
+pub(crate) struct Stats {
+    connected: watch::Sender<bool>,
+    state: watch::Sender<ConnectionState>,
+    ever_connected: AtomicBool,
+    gave_up: AtomicBool,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    acks: AtomicU64,
+    reconnects: AtomicU64,
+    payload_bytes: AtomicU64,
+    payload_bytes_sent: AtomicU64,
+    logs_dropped: AtomicU64,
+    server_errors: AtomicU64,
+    rejected: Mutex<Option<String>>,
+    clock: ClockSkew,
+    last_error: Mutex<Option<String>>,
+    connected_since: Mutex<Option<SystemTime>>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            connected: watch::channel(false).0,
+            state: watch::channel(ConnectionState::Reconnecting {
+                attempt: 0,
+                since: SystemTime::now(),
+            })
+            .0,
+            ever_connected: AtomicBool::new(false),
+            gave_up: AtomicBool::new(false),
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            acks: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            payload_bytes: AtomicU64::new(0),
+            payload_bytes_sent: AtomicU64::new(0),
+            logs_dropped: AtomicU64::new(0),
+            server_errors: AtomicU64::new(0),
+            rejected: Mutex::new(None),
+            clock: ClockSkew::default(),
+            last_error: Mutex::new(None),
+            connected_since: Mutex::new(None),
+        }
+    }
+
+    /// Follows the connection state.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.connected.subscribe()
+    }
+
+    /// Follows the connection state, with reconnect attempts.
+    pub fn connection_events(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Record a connection going up or down.
+    pub fn set_connected(&self, up: bool) {
+        let changed = self
+            .connected
+            .send_if_modified(|connected| std::mem::replace(connected, up) != up);
+        if !changed {
+            return;
+        }
+        let now = SystemTime::now();
+        *self.connected_since.lock().unwrap() = up.then_some(now);
+        if up && self.ever_connected.swap(true, Ordering::Relaxed) {
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        let state = match up {
+            true => ConnectionState::Connected,
+            false => ConnectionState::Reconnecting {
+                attempt: 0,
+                since: now,
+            },
+        };
+        self.state.send_if_modified(|current| {
+            !current.is_final() && std::mem::replace(current, state) != state
+        });
+    }
+
+    /// Record a retry to connect, the `attempt`th since the connection
+    /// was lost.
+    pub fn reconnecting(&self, attempt: u32) {
+        self.state.send_if_modified(|state| match state {
+            ConnectionState::Reconnecting {
+                attempt: failed, ..
+            } if *failed != attempt => {
+                *failed = attempt;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Lazy connect: nothing is sent, so nothing is tried, until the
+    /// first message.
+    pub fn idle(&self) {
+        self.state.send_replace(ConnectionState::Idle);
+    }
+
+    /// The first message of a lazy client: connecting starts.
+    pub fn wake(&self) {
+        self.state.send_if_modified(|state| {
+            let idle = *state == ConnectionState::Idle;
+            if idle {
+                *state = ConnectionState::Reconnecting {
+                    attempt: 0,
+                    since: SystemTime::now(),
+                };
+            }
+            idle
+        });
+    }
+
+    /// The transport task has stopped; nothing changes the state after.
+    pub fn shut_down(&self) {
+        self.state.send_if_modified(|state| {
+            let stopping = !state.is_final();
+            if stopping {
+                *state = ConnectionState::ShutDown;
+            }
+            stopping
+        });
+        self.set_connected(false);
+    }
+
+    /// The transport task gave up on the server and is stopping.
+    pub fn give_up(&self) {
+        self.gave_up.store(true, Ordering::Relaxed);
+        self.state.send_replace(ConnectionState::GaveUp);
+        self.set_connected(false);
+    }
+
+    pub fn has_given_up(&self) -> bool {
+        self.gave_up.load(Ordering::Relaxed)
+    }
+
+    /// `reconnect` after giving up: trying again, from the first attempt.
+    pub fn restart(&self) {
+        self.gave_up.store(false, Ordering::Relaxed);
+        self.state.send_replace(ConnectionState::Reconnecting {
+            attempt: 0,
+            since: SystemTime::now(),
+        });
+    }
+
+    /// How long the client has been trying to connect: since the
+    /// connection was lost, or the client started. Zero while connected.
+    pub fn down_for(&self) -> Duration {
+        match *self.state.borrow() {
+            ConnectionState::Reconnecting { since, .. } => since.elapsed().unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// The server refused the registration for good, with `code`; the
+    /// transport task is stopping.
+    pub fn reject(&self, code: &str) {
+        *self.rejected.lock().unwrap() = Some(code.to_string());
+        self.state.send_replace(ConnectionState::Rejected);
+        self.set_connected(false);
+    }
+
+    pub fn sent(&self, messages: u64) {
+        self.sent.fetch_add(messages, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self, messages: u64) {
+        self.dropped.fetch_add(messages, Ordering::Relaxed);
+    }
+
+    pub fn acked(&self, messages: u64) {
+        self.acks.fetch_add(messages, Ordering::Relaxed);
+    }
+
+    /// A payload of `original` bytes goes out as `sent` bytes.
+    pub fn payload_bytes(&self, original: u64, sent: u64) {
+        self.payload_bytes.fetch_add(original, Ordering::Relaxed);
+        self.payload_bytes_sent.fetch_add(sent, Ordering::Relaxed);
+    }
+
+    pub fn log_dropped(&self) {
+        self.logs_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The clock skew estimate, sampled by the transport task.
+    pub fn clock(&self) -> &ClockSkew {
+        &self.clock
+    }
+
+    pub fn error(&self, error: impl std::fmt::Display) {
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+    }
+
+    /// An error frame from the server, after registration.
+    pub fn server_error(&self, error: impl std::fmt::Display) {
+        self.server_errors.fetch_add(1, Ordering::Relaxed);
+        self.error(error);
+    }
+
+    pub fn snapshot(&self) -> ClientStats {
+        ClientStats {
+            messages_sent: self.sent.load(Ordering::Relaxed),
+            messages_dropped: self.dropped.load(Ordering::Relaxed),
+            acks_received: self.acks.load(Ordering::Relaxed),
+            reconnect_count: self.reconnects.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().unwrap().clone(),
+            connected_since: *self.connected_since.lock().unwrap(),
+            gave_up: self.gave_up.load(Ordering::Relaxed),
+            payload_bytes_uncompressed: self.payload_bytes.load(Ordering::Relaxed),
+            payload_bytes_compressed: self.payload_bytes_sent.load(Ordering::Relaxed),
+            logs_dropped: self.logs_dropped.load(Ordering::Relaxed),
+            rejected: self.rejected.lock().unwrap().clone(),
+            server_errors: self.server_errors.load(Ordering::Relaxed),
+            clock_offset_ms: self.clock.offset(),
+        }
+    }
+}
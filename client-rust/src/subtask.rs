@@ -0,0 +1,217 @@
+//! Subtasks, for `TrailsClient::subtask`: apps of their own, children of
+//! the client's app, that send over the client's WebSocket rather than
+//! opening one each, for a coordinator running many small tasks.
+//!
+//! The transport sends a subtask's `register_sub` when it is created, and
+//! again after every reconnect until the subtask ends; the server names
+//! the subtask (`app_id`) in the acks, controls and errors it sends for
+//! it. A subtask's messages are best effort: sent if the client is
+//! connected, not kept for replay, and their acks are not waited for.
+//! The HTTP transport has no connection to share; it drops them.
+//!
+//! ```ignore
+//! let part = g.subtask("part-1").await;
+//! part.status(json!({"rows": 100})).await?;
+//! part.result(json!({"rows": 5000})).await?;
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    data_message, outbound_frame, register_frame, ClientInner, Delivery, ErrorReport, Outbound,
+    TrailsConfig, TrailsError, WireDisconnect,
+};
+
+/// A frame of a subtask, for the transport.
+pub(crate) enum SubtaskFrame {
+    /// Its `register_sub`.
+    Register(String),
+    /// A Status or other message.
+    Message(String),
+    /// Its Result, Error or disconnect, which end it.
+    Last(String),
+}
+
+impl SubtaskFrame {
+    /// The frame, and whether it is a message.
+    pub fn into_parts(self) -> (String, bool) {
+        match self {
+            Self::Register(frame) => (frame, false),
+            Self::Message(frame) | Self::Last(frame) => (frame, true),
+        }
+    }
+}
+
+/// The `register_sub` frames of the subtasks still open, kept across
+/// reconnects.
+#[derive(Default)]
+pub(crate) struct Subtasks {
+    registers: HashMap<Uuid, String>,
+}
+
+impl Subtasks {
+    /// Note a subtask's frame on its way out: a `register_sub` is kept
+    /// for new connections, and the last frame forgets it. Returns the
+    /// frame, and whether it is a message.
+    pub fn note(&mut self, app_id: Uuid, frame: SubtaskFrame) -> (String, bool) {
+        match &frame {
+            SubtaskFrame::Register(register) => {
+                self.registers.insert(app_id, register.clone());
+            }
+            SubtaskFrame::Message(_) => {}
+            SubtaskFrame::Last(_) => {
+                self.registers.remove(&app_id);
+            }
+        }
+        frame.into_parts()
+    }
+
+    /// The server refused the subtask for good.
+    pub fn forget(&mut self, app_id: Uuid) {
+        self.registers.remove(&app_id);
+    }
+
+    /// The `register_sub` frames, for a new connection.
+    pub fn registrations(&self) -> Vec<String> {
+        self.registers.values().cloned().collect()
+    }
+}
+
+/// A subtask of a client, from
+/// [`TrailsClient::subtask`](crate::TrailsClient::subtask). It keeps the
+/// client alive; dropping it before a Result or Error disconnects it as
+/// `dropped`. A no-op subtask, of a no-op client, sends nothing.
+#[derive(Default)]
+pub struct TrailsSubtask {
+    inner: Option<SubtaskInner>,
+}
+
+struct SubtaskInner {
+    client: Arc<ClientInner>,
+    config: TrailsConfig,
+    /// The subtask's own seq.
+    seq: AtomicI64,
+    /// A Result or Error was sent: dropping it says nothing more.
+    finished: AtomicBool,
+}
+
+impl TrailsSubtask {
+    /// Register the subtask `config` describes: queue its `register_sub`,
+    /// waiting for room.
+    pub(crate) async fn start(client: &Arc<ClientInner>, config: TrailsConfig) -> Self {
+        let register = register_frame(&config, &client.signing_key, "register_sub", None);
+        let msg = Outbound::Subtask {
+            app_id: config.app_id,
+            frame: SubtaskFrame::Register(register),
+        };
+        if client.tx.sender().send(msg).await.is_err() {
+            debug!(app_id = %config.app_id, "subtask not registered (client stopped)");
+        }
+        Self {
+            inner: Some(SubtaskInner {
+                client: Arc::clone(client),
+                config,
+                seq: AtomicI64::new(0),
+                finished: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Whether this is a real subtask (not no-op).
+    pub fn is_active(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// The subtask's own app id; `None` for the no-op subtask.
+    pub fn app_id(&self) -> Option<Uuid> {
+        self.inner.as_ref().map(|i| i.config.app_id)
+    }
+
+    /// Send a status update for the subtask (spec §9).
+    pub async fn status<T: Serialize>(&self, payload: T) -> Result<(), TrailsError> {
+        self.send("Status", payload).await
+    }
+
+    /// Send the subtask's result. Transitions it to 'done', and ends it.
+    pub async fn result<T: Serialize>(&self, payload: T) -> Result<(), TrailsError> {
+        self.send("Result", payload).await
+    }
+
+    /// Send an error for the subtask, as
+    /// [`TrailsClient::error`](crate::TrailsClient::error) does.
+    /// Transitions it to 'error', and ends it.
+    pub async fn error(&self, msg: &str, detail: Option<JsonValue>) -> Result<(), TrailsError> {
+        self.send("Error", ErrorReport::internal(msg, detail)).await
+    }
+
+    /// Queue a message of the subtask, as the client's `Delivery` says.
+    async fn send<T: Serialize>(&self, msg_type: &str, payload: T) -> Result<(), TrailsError> {
+        let Some(inner) = &self.inner else {
+            return Ok(());
+        };
+        let client = &inner.client;
+        let payload =
+            serde_json::to_value(payload).map_err(|e| TrailsError::Serialize(e.to_string()))?;
+        let payload = client.limit.check(msg_type, payload)?;
+        let last = matches!(msg_type, "Result" | "Error");
+        if last {
+            inner.finished.store(true, Ordering::Relaxed);
+        }
+        let compression = client.compression.as_ref();
+        let (config, seq) = (&inner.config, &inner.seq);
+        let msg = data_message(config, seq, compression, msg_type, payload, None)?;
+        let frame = outbound_frame(config, &client.signing_key, msg, client.stats.clock());
+        let msg = Outbound::Subtask {
+            app_id: config.app_id,
+            frame: match last {
+                true => SubtaskFrame::Last(frame),
+                false => SubtaskFrame::Message(frame),
+            },
+        };
+        let strict = match client.delivery {
+            Delivery::BestEffort => false,
+            Delivery::Terminal => last,
+            Delivery::All => true,
+        };
+        let tx = client.tx.sender();
+        if strict {
+            return tx.send(msg).await.map_err(|_| TrailsError::ChannelClosed);
+        }
+        if tx.try_send(msg).is_err() {
+            client.stats.dropped(1);
+            debug!("subtask message dropped (disconnected or channel full)");
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TrailsSubtask {
+    /// Disconnect the subtask as `dropped`, unless it already ended.
+    fn drop(&mut self) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        if inner.finished.load(Ordering::Relaxed) {
+            return;
+        }
+        let wire = WireDisconnect {
+            r#type: "disconnect",
+            app_id: inner.config.app_id,
+            reason: "dropped".into(),
+        };
+        let msg = Outbound::Subtask {
+            app_id: inner.config.app_id,
+            frame: SubtaskFrame::Last(serde_json::to_string(&wire).unwrap()),
+        };
+        if inner.client.tx.sender().try_send(msg).is_err() {
+            debug!("subtask disconnect on drop not queued (channel full or closed)");
+        }
+    }
+}
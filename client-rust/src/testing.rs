@@ -0,0 +1,431 @@
+//! An in-process stand-in for trailsd, for testing code that reports
+//! through the client (the `testing` feature). [`MockServer`] listens on
+//! an ephemeral port, answers the register or re_register, acks each data
+//! message, and records every frame the client sends, to be inspected
+//! once the code under test has run:
+//!
+//! ```ignore
+//! let mock = MockServer::start().await;
+//! let client = TrailsClient::init_with(mock.config()).await;
+//! run_worker(&client).await;
+//! assert_eq!(mock.received_statuses()[0]["phase"], "extract");
+//! assert!(mock.wait_for_result(Duration::from_secs(5)).await.is_some());
+//! ```
+//!
+//! It speaks the open security level only: it never sends a challenge.
+//! Gzipped payloads are recorded decoded, as the server would store them,
+//! and MessagePack frames as the JSON they encode.
+
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::Engine;
+use futures::{SinkExt, StreamExt};
+use serde_json::Value as JsonValue;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Notify};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use crate::{TrailsClient, TrailsConfig, Transport};
+
+/// A frame the client sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMessage {
+    /// The frame's `type`: `register`, `re_register`, `message`,
+    /// `heartbeat`, `disconnect`, `register_sub`, ...
+    pub frame_type: String,
+    /// `Status`, `Result`, `Error` or an app's own type, for a data
+    /// message.
+    pub msg_type: Option<String>,
+    /// The seq of a data message.
+    pub seq: Option<i64>,
+    pub correlation_id: Option<String>,
+    /// The payload of a data message, decoded if it was gzipped.
+    pub payload: Option<JsonValue>,
+    /// The whole frame, as sent.
+    pub frame: JsonValue,
+    /// It came as MessagePack, in a binary frame.
+    pub binary: bool,
+}
+
+impl RecordedMessage {
+    fn parse(frame: JsonValue, binary: bool) -> Self {
+        let text = |value: &JsonValue| value.as_str().map(str::to_string);
+        let header = &frame["header"];
+        let payload = match (frame.get("payload"), header["encoding"].as_str()) {
+            (Some(payload), Some("gzip")) => gunzip(payload).or_else(|| Some(payload.clone())),
+            (payload, _) => payload.cloned(),
+        };
+        Self {
+            frame_type: text(&frame["type"]).unwrap_or_default(),
+            msg_type: text(&header["msg_type"]),
+            seq: header["seq"].as_i64(),
+            correlation_id: text(&header["correlation_id"]),
+            payload,
+            frame,
+            binary,
+        }
+    }
+
+    fn is(&self, msg_type: &str) -> bool {
+        self.msg_type.as_deref() == Some(msg_type)
+    }
+}
+
+fn gunzip(payload: &JsonValue) -> Option<JsonValue> {
+    let gzip = base64::engine::general_purpose::STANDARD
+        .decode(payload.as_str()?)
+        .ok()?;
+    let mut json = Vec::new();
+    flate2::read::GzDecoder::new(gzip.as_slice())
+        .read_to_end(&mut json)
+        .ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[derive(Default)]
+struct Shared {
+    received: Mutex<Vec<RecordedMessage>>,
+    /// Something was received.
+    arrived: Notify,
+    /// Frames to send, one queue per open connection.
+    connections: Mutex<Vec<mpsc::UnboundedSender<Message>>>,
+    /// Data messages go unacked.
+    no_acks: AtomicBool,
+    /// Registers offering MessagePack frames aren't taken up on it.
+    no_msgpack: AtomicBool,
+    /// How far, in ms, the mock's clock runs ahead of this host's.
+    clock_offset: AtomicI64,
+}
+
+impl Shared {
+    fn record(&self, message: RecordedMessage) {
+        self.received.lock().unwrap().push(message);
+        self.arrived.notify_waiters();
+    }
+
+    /// The mock's clock, as sent in `server_time`.
+    fn server_time(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis() + self.clock_offset.load(Ordering::Relaxed)
+    }
+
+    /// The ack of data message `seq`.
+    fn ack(&self, seq: i64) -> JsonValue {
+        serde_json::json!({"type": "ack", "seq": seq, "server_time": self.server_time()})
+    }
+
+    /// Send `frame` on every open connection; false if there is none.
+    fn broadcast(&self, frame: &JsonValue) -> bool {
+        let mut connections = self.connections.lock().unwrap();
+        connections.retain(|tx| tx.send(Message::Text(frame.to_string())).is_ok());
+        !connections.is_empty()
+    }
+}
+
+/// A fake trailsd for one app, stopped when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    app_id: Uuid,
+    shared: Arc<Shared>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Listen on an ephemeral port of 127.0.0.1, for an app of its own id.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind the mock server");
+        let addr = listener.local_addr().expect("mock server address");
+        let app_id = Uuid::new_v4();
+        let shared = Arc::new(Shared::default());
+        let task = tokio::spawn(serve(listener, app_id, Arc::clone(&shared)));
+        Self {
+            addr,
+            app_id,
+            shared,
+            task,
+        }
+    }
+
+    /// The id of the app the mock expects.
+    pub fn app_id(&self) -> Uuid {
+        self.app_id
+    }
+
+    /// The endpoint, as in TRAILS_INFO's `serverEp`.
+    pub fn url(&self) -> String {
+        format!("ws://{}/ws", self.addr)
+    }
+
+    /// A config for a client of the mock, for [`TrailsClient::init_with`]
+    /// or the builder's `config`.
+    pub fn config(&self) -> TrailsConfig {
+        TrailsConfig {
+            v: 1,
+            app_id: self.app_id,
+            parent_id: None,
+            app_name: "mock".into(),
+            server_ep: self.url(),
+            server_pub_key: None,
+            sec_level: "open".into(),
+            scheduled_at: None,
+            start_deadline: None,
+            originator: None,
+            role_refs: vec![],
+            tags: None,
+            priority: None,
+            transport: Transport::Ws,
+        }
+    }
+
+    /// [`config`](Self::config) as TRAILS_INFO, e.g. for a child process.
+    pub fn trails_info(&self) -> String {
+        TrailsClient::encode_config(&self.config()).expect("a config always encodes")
+    }
+
+    /// Every frame received so far, in order.
+    pub fn received(&self) -> Vec<RecordedMessage> {
+        self.shared.received.lock().unwrap().clone()
+    }
+
+    /// The payloads of the Status messages received so far.
+    pub fn received_statuses(&self) -> Vec<JsonValue> {
+        self.received_payloads("Status")
+    }
+
+    /// The payloads of the Result messages received so far.
+    pub fn received_results(&self) -> Vec<JsonValue> {
+        self.received_payloads("Result")
+    }
+
+    /// The payloads of the Error messages received so far.
+    pub fn received_errors(&self) -> Vec<JsonValue> {
+        self.received_payloads("Error")
+    }
+
+    /// The payloads of the messages of `msg_type` received so far, e.g.
+    /// those of `send_custom`.
+    pub fn received_payloads(&self, msg_type: &str) -> Vec<JsonValue> {
+        let received = self.shared.received.lock().unwrap();
+        received
+            .iter()
+            .filter(|m| m.is(msg_type))
+            .filter_map(|m| m.payload.clone())
+            .collect()
+    }
+
+    /// Wait up to `timeout` for a frame `matches`, received before or
+    /// since; the first such.
+    pub async fn wait_for(
+        &self,
+        timeout: Duration,
+        matches: impl Fn(&RecordedMessage) -> bool,
+    ) -> Option<RecordedMessage> {
+        self.wait_until(timeout, |received| {
+            received.iter().find(|m| matches(m)).cloned()
+        })
+        .await
+    }
+
+    /// Wait up to `timeout` for `check` to find what it looks for in all
+    /// received so far.
+    async fn wait_until<T>(
+        &self,
+        timeout: Duration,
+        check: impl Fn(&[RecordedMessage]) -> Option<T>,
+    ) -> Option<T> {
+        let found = async {
+            loop {
+                let arrived = self.shared.arrived.notified();
+                tokio::pin!(arrived);
+                arrived.as_mut().enable();
+                if let Some(found) = check(&self.received()) {
+                    return found;
+                }
+                arrived.await;
+            }
+        };
+        tokio::time::timeout(timeout, found).await.ok()
+    }
+
+    /// Wait up to `timeout` for the app's Result; its payload.
+    pub async fn wait_for_result(&self, timeout: Duration) -> Option<JsonValue> {
+        let result = self.wait_for(timeout, |m| m.is("Result")).await?;
+        result.payload
+    }
+
+    /// Wait up to `timeout` for `n` Status messages in all; their payloads.
+    pub async fn wait_for_statuses(&self, n: usize, timeout: Duration) -> Option<Vec<JsonValue>> {
+        self.wait_until(timeout, |received| {
+            let statuses: Vec<_> = received
+                .iter()
+                .filter(|m| m.is("Status"))
+                .filter_map(|m| m.payload.clone())
+                .collect();
+            (statuses.len() >= n).then_some(statuses)
+        })
+        .await
+    }
+
+    /// Wait up to `timeout` for the client's disconnect; its reason.
+    pub async fn wait_for_disconnect(&self, timeout: Duration) -> Option<String> {
+        let disconnect = self
+            .wait_for(timeout, |m| m.frame_type == "disconnect")
+            .await?;
+        disconnect.frame["reason"].as_str().map(str::to_string)
+    }
+
+    /// Stop acking data messages (or start again), e.g. to test replay.
+    pub fn set_auto_ack(&self, on: bool) {
+        self.shared.no_acks.store(!on, Ordering::Relaxed);
+    }
+
+    /// Take up a register's offer of MessagePack frames (the default), or
+    /// answer as a server that predates them, e.g. to test the fallback
+    /// to JSON.
+    pub fn set_msgpack(&self, on: bool) {
+        self.shared.no_msgpack.store(!on, Ordering::Relaxed);
+    }
+
+    /// Run the mock's clock `ms` ahead of this host's (behind, if
+    /// negative), as reported in `server_time`, e.g. to test clock skew
+    /// correction.
+    pub fn set_clock_offset(&self, ms: i64) {
+        self.shared.clock_offset.store(ms, Ordering::Relaxed);
+    }
+
+    /// Ack `seq` by hand; false if no client is connected.
+    pub fn ack(&self, seq: i64) -> bool {
+        self.send_frame(self.shared.ack(seq))
+    }
+
+    /// Send a control message, as the REST API's `POST .../control` does;
+    /// false if no client is connected.
+    pub fn send_control(&self, action: &str, payload: JsonValue) -> bool {
+        let control = serde_json::json!({"type": "control", "action": action, "payload": payload});
+        self.send_frame(control)
+    }
+
+    /// Send an `error` frame, as the server does for a message it refuses;
+    /// false if no client is connected.
+    pub fn send_error(&self, code: &str, message: &str) -> bool {
+        self.send_frame(serde_json::json!({"type": "error", "code": code, "message": message}))
+    }
+
+    /// Send any frame; false if no client is connected.
+    pub fn send_frame(&self, frame: JsonValue) -> bool {
+        self.shared.broadcast(&frame)
+    }
+
+    /// Drop the open connections, as a server restart would; the client
+    /// reconnects and re_registers.
+    pub fn drop_connections(&self) {
+        self.shared.connections.lock().unwrap().clear();
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(listener: TcpListener, app_id: Uuid, shared: Arc<Shared>) {
+    let mut connections = tokio::task::JoinSet::new();
+    while let Ok((stream, _)) = listener.accept().await {
+        let shared = Arc::clone(&shared);
+        connections.spawn(async move {
+            if let Ok(ws) = tokio_tungstenite::accept_async(stream).await {
+                connection(ws, app_id, &shared).await;
+            }
+        });
+    }
+}
+
+/// Answer one client until it, or the mock, hangs up.
+async fn connection(
+    ws: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
+    app_id: Uuid,
+    shared: &Shared,
+) {
+    let (mut sink, mut stream) = ws.split();
+    let (tx, mut outbound) = mpsc::unbounded_channel();
+    shared.connections.lock().unwrap().push(tx);
+    loop {
+        tokio::select! {
+            frame = outbound.recv() => {
+                // None once `drop_connections` lets go of the sender.
+                let Some(frame) = frame else { return };
+                let _ = sink.send(frame).await;
+            }
+            msg = stream.next() => {
+                let (frame, binary) = match msg {
+                    Some(Ok(Message::Text(text))) => (serde_json::from_str(&text), false),
+                    Some(Ok(Message::Binary(bytes))) => {
+                        let frame = rmp_serde::from_slice(&bytes).map_err(serde::de::Error::custom);
+                        (frame, true)
+                    }
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                    Some(Ok(_)) => continue,
+                };
+                let Ok(frame) = frame else {
+                    continue;
+                };
+                let message = RecordedMessage::parse(frame, binary);
+                // A subtask's frames name it, and so do the answers.
+                let subtask = serde_json::from_value::<Uuid>(message.frame["app_id"].clone())
+                    .ok()
+                    .filter(|id| *id != app_id);
+                let answer = match (message.frame_type.as_str(), message.seq) {
+                    ("register" | "re_register", _) => {
+                        let mut registered = serde_json::json!({
+                            "type": "registered",
+                            "app_id": app_id,
+                            "server_version": "mock",
+                            "last_seq": last_seq(shared, app_id),
+                            "server_time": shared.server_time(),
+                        });
+                        let msgpack = !shared.no_msgpack.load(Ordering::Relaxed);
+                        if msgpack && message.frame["binary"] == "msgpack" {
+                            registered["binary"] = "msgpack".into();
+                        }
+                        Some(registered)
+                    }
+                    ("register_sub", _) => Some(serde_json::json!({
+                        "type": "registered",
+                        "app_id": subtask,
+                        "server_version": "mock",
+                        "last_seq": null,
+                        "server_time": shared.server_time(),
+                    })),
+                    ("message", Some(seq)) if !shared.no_acks.load(Ordering::Relaxed) => {
+                        let mut ack = shared.ack(seq);
+                        if let Some(subtask) = subtask {
+                            ack["app_id"] = serde_json::json!(subtask);
+                        }
+                        Some(ack)
+                    }
+                    _ => None,
+                };
+                shared.record(message);
+                // A client that has hung up may still have frames to read.
+                if let Some(answer) = answer {
+                    let _ = sink.send(Message::Text(answer.to_string())).await;
+                }
+            }
+        }
+    }
+}
+
+/// The highest seq received from the app, for a re_register's answer.
+fn last_seq(shared: &Shared, app_id: Uuid) -> Option<i64> {
+    let received = shared.received.lock().unwrap();
+    let app_id = serde_json::json!(app_id);
+    let own = received.iter().filter(|m| m.frame["app_id"] == app_id);
+    own.filter_map(|m| m.seq).max()
+}
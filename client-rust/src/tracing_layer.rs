@@ -0,0 +1,173 @@
+//! A `tracing` layer that sends events as Status messages, for apps that
+//! are already instrumented with `tracing`. Behind the `tracing-layer`
+//! feature.
+//!
+//! ```ignore
+//! use tracing_subscriber::prelude::*;
+//!
+//! let g = TrailsClient::init().await;
+//! tracing_subscriber::registry()
+//!     .with(tracing_subscriber::fmt::layer())
+//!     .with(trails_client::tracing_layer(g.handle()))
+//!     .init();
+//! let _load = tracing::info_span!("load", phase = "loading").entered();
+//! tracing::info!(progress = 0.2, "loading rows");
+//! ```
+//!
+//! An event at the layer's level or above becomes a Status payload of its
+//! fields on top of those of its spans, here `{"phase": "loading",
+//! "progress": 0.2, "message": "loading rows"}`. Events that follow the
+//! last one sent within the minimum interval are dropped, so a hot loop
+//! can't flood the client's channel. The client's own events are never
+//! sent, and with an inactive client the layer does nothing.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::{Map, Value as JsonValue};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::ClientHandle;
+
+/// A layer sending `tracing` events through `handle` as Status messages:
+/// INFO and above, all fields, at most one a second.
+pub fn tracing_layer(handle: ClientHandle) -> TrailsLayer {
+    TrailsLayer {
+        handle,
+        level: Level::INFO,
+        fields: None,
+        min_interval: Duration::from_secs(1),
+        last_sent: Mutex::new(None),
+    }
+}
+
+/// Sends `tracing` events as Status messages; see [`tracing_layer`].
+pub struct TrailsLayer {
+    handle: ClientHandle,
+    level: Level,
+    fields: Option<Vec<String>>,
+    min_interval: Duration,
+    last_sent: Mutex<Option<Instant>>,
+}
+
+impl TrailsLayer {
+    /// Send events at `level` and above (default INFO).
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Send only these fields (default all), e.g. `["phase", "progress"]`;
+    /// an event with none of them isn't sent. `message` is the event's
+    /// formatted message.
+    pub fn with_fields(mut self, fields: &[&str]) -> Self {
+        self.fields = Some(fields.iter().map(|field| field.to_string()).collect());
+        self
+    }
+
+    /// Send at most one event per `interval` (default 1s); zero sends
+    /// every event.
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Whether the rate limit lets an event go now.
+    fn take_slot(&self) -> bool {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        let now = Instant::now();
+        if last_sent.is_some_and(|last| now.duration_since(last) < self.min_interval) {
+            return false;
+        }
+        *last_sent = Some(now);
+        true
+    }
+}
+
+/// Event or span fields as JSON; kept in each span's extensions.
+#[derive(Default)]
+struct Fields(Map<String, JsonValue>);
+
+impl Visit for Fields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{value:?}").into());
+    }
+}
+
+impl<S> Layer<S> for TrailsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if !self.handle.is_active() {
+            return;
+        }
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+                values.record(fields);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        // More verbose levels compare greater.
+        if !self.handle.is_active()
+            || *meta.level() > self.level
+            || meta.target().starts_with(env!("CARGO_CRATE_NAME"))
+        {
+            return;
+        }
+        let mut payload = Fields::default();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<Fields>() {
+                    payload.0.extend(fields.0.clone());
+                }
+            }
+        }
+        event.record(&mut payload);
+        if let Some(keep) = &self.fields {
+            payload.0.retain(|name, _| keep.contains(name));
+            if payload.0.is_empty() {
+                return;
+            }
+        }
+        if self.take_slot() {
+            let _ = self.handle.status(JsonValue::Object(payload.0));
+        }
+    }
+}
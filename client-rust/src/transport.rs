@@ -0,0 +1,398 @@
+//! WebSocket transports: TCP (TLS for `wss://`) and, for sidecar
+//! deployments without a TCP port, a Unix domain socket.
+//!
+//! A `ws+unix://` endpoint names the socket path, optionally followed by
+//! `:` and the request path, `/ws` by default:
+//! `ws+unix:///var/run/trails.sock` or `ws+unix:///var/run/trails.sock:/ws`.
+//! The REST helpers (`create_child` etc.) still need an `http(s)://`
+//! endpoint.
+//!
+//! TLS comes from the `native-tls` (default) or `rustls` feature; with
+//! both, native-tls is used, as tokio-tungstenite does. Extra root
+//! certificates and relaxed hostname checks ([`TlsSettings`]) apply to
+//! the WebSocket only.
+//!
+//! The handshake request carries any [`headers`]: a bearer token and
+//! others, for an ingress or proxy in front of trailsd that wants them.
+//! TCP connections may go through an HTTP proxy (see [`crate::proxy`]).
+//!
+//! Both phases of a connect are bounded ([`Timeouts`]): the dial, proxy
+//! tunnel included, and the TLS and WebSocket handshake after it. A
+//! black-holed address would otherwise hold the first attempt for the
+//! platform's TCP timeout, minutes, with no backoff or shutdown until
+//! it ends. Either limit fails the attempt with a `TimedOut` I/O error.
+
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::error::UrlError;
+use tokio_tungstenite::tungstenite::http::header::{HeaderName, AUTHORIZATION};
+use tokio_tungstenite::tungstenite::http::{HeaderMap, HeaderValue};
+use tokio_tungstenite::tungstenite::Error;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+
+use crate::proxy::{self, Proxy};
+use crate::TrailsError;
+
+/// Scheme of Unix domain socket endpoints.
+pub(crate) const UNIX_SCHEME: &str = "ws+unix://";
+
+pub(crate) type WsStream = WebSocketStream<MaybeTlsStream<Stream>>;
+
+/// The socket under the WebSocket.
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// A root certificate to trust on top of the built-in ones.
+#[derive(Debug, Clone)]
+pub(crate) enum RootCert {
+    Pem(Vec<u8>),
+    File(PathBuf),
+}
+
+/// TLS settings for `wss://` endpoints, set through the builder.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TlsSettings {
+    pub root_certs: Vec<RootCert>,
+    /// Accept a certificate issued for another host. For dev only.
+    pub accept_invalid_hostnames: bool,
+}
+
+/// How long each phase of a connect may take, set through the builder.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timeouts {
+    /// The TCP (or Unix socket) connect, and any proxy tunnel.
+    pub connect: Duration,
+    /// The TLS and WebSocket handshake on the connection.
+    pub handshake: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            handshake: Duration::from_secs(10),
+        }
+    }
+}
+
+/// `phase`, failed with `TimedOut` if it takes longer than `limit`.
+async fn within<T, E: From<io::Error>>(
+    limit: Duration,
+    what: &str,
+    phase: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    match tokio::time::timeout(limit, phase).await {
+        Ok(done) => done,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("{what} timed out after {limit:?}"),
+        )
+        .into()),
+    }
+}
+
+/// The TLS connector for `settings`; `None` for the backend's defaults.
+pub(crate) fn connector(settings: &TlsSettings) -> Result<Option<Connector>, TrailsError> {
+    if settings.root_certs.is_empty() && !settings.accept_invalid_hostnames {
+        return Ok(None);
+    }
+    let mut roots = Vec::new();
+    for cert in &settings.root_certs {
+        let pem = match cert {
+            RootCert::Pem(pem) => pem.clone(),
+            RootCert::File(path) => std::fs::read(path).map_err(|e| {
+                TrailsError::Tls(format!("reading root certificate {}: {e}", path.display()))
+            })?,
+        };
+        roots.extend(pem_certs(&pem)?);
+    }
+    tls_connector(roots, settings.accept_invalid_hostnames).map(Some)
+}
+
+/// The DER of each CERTIFICATE block in a PEM bundle.
+fn pem_certs(pem: &[u8]) -> Result<Vec<Vec<u8>>, TrailsError> {
+    use base64::Engine;
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = std::str::from_utf8(pem)
+        .map_err(|_| TrailsError::Tls("root certificate is not PEM".into()))?;
+    let mut certs = Vec::new();
+    let mut rest = pem;
+    while let Some(start) = rest.find(BEGIN) {
+        let body = &rest[start + BEGIN.len()..];
+        let end = body
+            .find(END)
+            .ok_or_else(|| TrailsError::Tls("unterminated PEM certificate".into()))?;
+        let b64: String = body[..end].split_whitespace().collect();
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(b64)
+            .map_err(|e| TrailsError::Tls(format!("bad PEM certificate: {e}")))?;
+        certs.push(der);
+        rest = &body[end + END.len()..];
+    }
+    if certs.is_empty() {
+        return Err(TrailsError::Tls(
+            "no certificate in root certificate PEM".into(),
+        ));
+    }
+    Ok(certs)
+}
+
+#[cfg(feature = "native-tls")]
+fn tls_connector(roots: Vec<Vec<u8>>, any_hostname: bool) -> Result<Connector, TrailsError> {
+    let mut builder = native_tls::TlsConnector::builder();
+    for der in roots {
+        let cert = native_tls::Certificate::from_der(&der)
+            .map_err(|e| TrailsError::Tls(format!("bad root certificate: {e}")))?;
+        builder.add_root_certificate(cert);
+    }
+    builder.danger_accept_invalid_hostnames(any_hostname);
+    let connector = builder
+        .build()
+        .map_err(|e| TrailsError::Tls(e.to_string()))?;
+    Ok(Connector::NativeTls(connector))
+}
+
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+fn tls_connector(roots: Vec<Vec<u8>>, any_hostname: bool) -> Result<Connector, TrailsError> {
+    use std::sync::Arc;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut store = rustls::RootCertStore::empty();
+    store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for der in roots {
+        store
+            .add(der.into())
+            .map_err(|e| TrailsError::Tls(format!("bad root certificate: {e}")))?;
+    }
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| TrailsError::Tls(e.to_string()))?;
+    let builder = if any_hostname {
+        let verifier =
+            rustls::client::WebPkiServerVerifier::builder_with_provider(Arc::new(store), provider)
+                .build()
+                .map_err(|e| TrailsError::Tls(e.to_string()))?;
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AnyHostname(verifier)))
+    } else {
+        builder.with_root_certificates(store)
+    };
+    Ok(Connector::Rustls(Arc::new(builder.with_no_client_auth())))
+}
+
+/// Verifies the chain as usual but takes a certificate for any host.
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+#[derive(Debug)]
+struct AnyHostname(std::sync::Arc<rustls::client::WebPkiServerVerifier>);
+
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+impl rustls::client::danger::ServerCertVerifier for AnyHostname {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::pki_types::CertificateDer<'_>,
+        intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        server_name: &rustls::pki_types::ServerName<'_>,
+        ocsp_response: &[u8],
+        now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        use rustls::CertificateError::{NotValidForName, NotValidForNameContext};
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(rustls::Error::InvalidCertificate(
+                NotValidForName | NotValidForNameContext { .. },
+            )) => Ok(rustls::client::danger::ServerCertVerified::assertion()),
+            verified => verified,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+/// Whether a connect failed in the TLS handshake rather than reaching the
+/// server: a certificate the client doesn't trust, say.
+pub(crate) fn is_tls_error(e: &Error) -> bool {
+    match e {
+        Error::Tls(_) => true,
+        Error::Url(UrlError::TlsFeatureNotEnabled) => true,
+        // tokio-rustls reports handshake failures as I/O errors.
+        #[cfg(feature = "rustls")]
+        Error::Io(e) => e.get_ref().is_some_and(|inner| inner.is::<rustls::Error>()),
+        _ => false,
+    }
+}
+
+/// The extra handshake headers: `Authorization: Bearer <token>` if
+/// there's a `bearer` token, then `extra` in order.
+pub(crate) fn headers(
+    bearer: Option<&str>,
+    extra: &[(String, String)],
+) -> Result<HeaderMap, TrailsError> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = bearer {
+        let value = HeaderValue::from_str(&format!("Bearer {token}"))
+            .map_err(|_| TrailsError::ConnectionFailed("invalid auth token".into()))?;
+        headers.insert(AUTHORIZATION, value);
+    }
+    for (name, value) in extra {
+        let invalid = |e: &dyn std::fmt::Display| {
+            TrailsError::ConnectionFailed(format!("invalid header {name}: {e}"))
+        };
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?;
+        let value = HeaderValue::from_str(value).map_err(|e| invalid(&e))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// Dial `url`, through `proxy` if set, and run the WebSocket handshake
+/// over it, with `connector` for `wss://` and `headers` added to the
+/// request; each within its `timeouts`.
+pub(crate) async fn connect(
+    url: &str,
+    connector: Option<Connector>,
+    headers: &HeaderMap,
+    proxy: Option<&Proxy>,
+    timeouts: Timeouts,
+) -> Result<WsStream, Error> {
+    if let Some(rest) = url.strip_prefix(UNIX_SCHEME) {
+        return connect_unix(rest, headers, timeouts).await;
+    }
+    let mut request = url.into_client_request()?;
+    request.headers_mut().extend(headers.clone());
+    let host = request
+        .uri()
+        .host()
+        .ok_or(Error::Url(UrlError::NoHostName))?
+        .to_string();
+    let port = match (request.uri().port_u16(), request.uri().scheme_str()) {
+        (Some(port), _) => port,
+        (None, Some("wss")) => 443,
+        (None, Some("ws")) => 80,
+        _ => return Err(Error::Url(UrlError::UnsupportedUrlScheme)),
+    };
+    let dial = async {
+        match proxy {
+            Some(proxy) => proxy::tunnel(proxy, &host, port).await,
+            None => TcpStream::connect(format!("{host}:{port}")).await,
+        }
+    };
+    let socket = within(timeouts.connect, "connect", dial).await?;
+    let handshake = tokio_tungstenite::client_async_tls_with_config(
+        request,
+        Stream::Tcp(socket),
+        None,
+        connector,
+    );
+    let (stream, _) = within(timeouts.handshake, "handshake", handshake).await?;
+    Ok(stream)
+}
+
+#[cfg(unix)]
+async fn connect_unix(
+    rest: &str,
+    headers: &HeaderMap,
+    timeouts: Timeouts,
+) -> Result<WsStream, Error> {
+    let (path, request_path) = rest.split_once(':').unwrap_or((rest, "/ws"));
+    if path.is_empty() {
+        return Err(Error::Url(UrlError::EmptyHostName));
+    }
+    // The host only fills the Host header; nothing resolves it.
+    let mut request = format!("ws://localhost{request_path}").into_client_request()?;
+    request.headers_mut().extend(headers.clone());
+    let socket = within(timeouts.connect, "connect", UnixStream::connect(path)).await?;
+    let handshake =
+        tokio_tungstenite::client_async_tls_with_config(request, Stream::Unix(socket), None, None);
+    let (stream, _) = within(timeouts.handshake, "handshake", handshake).await?;
+    Ok(stream)
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(
+    _rest: &str,
+    _headers: &HeaderMap,
+    _timeouts: Timeouts,
+) -> Result<WsStream, Error> {
+    Err(Error::Url(UrlError::UnsupportedUrlScheme))
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
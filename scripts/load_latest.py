@@ -0,0 +1,106 @@
+"""Load test for GET /api/v1/snapshots/latest.
+
+Seeds N running apps (default 10k), each with a few ~2 KB snapshots, straight
+into Postgres, then times full-page reads with and without field trimming.
+Start trailsd with LATEST_CACHE_MS=0 to time the query rather than the cache.
+
+    python3 scripts/load_latest.py --psql "psql -h localhost -U trails trails"
+    python3 scripts/load_latest.py --cleanup   # remove the seeded apps
+
+Only the standard library and the psql CLI are needed.
+"""
+
+import argparse
+import json
+import shlex
+import statistics
+import subprocess
+import time
+import urllib.request
+
+APP_NAME = "load-latest"
+
+SEED_SQL = """
+INSERT INTO apps (app_id, app_name, namespace, status, connected_at, start_time)
+SELECT gen_random_uuid(), '{name}', 'load-' || (i % 10), 'running', NOW(), NOW()
+FROM generate_series(1, {apps}) i;
+
+INSERT INTO snapshots (app_id, namespace, seq, snapshot_json, created_at)
+SELECT a.app_id, a.namespace, n,
+       jsonb_build_object(
+           'progress', n / {snapshots}.0,
+           'phase', 'phase-' || n,
+           'counters', (SELECT jsonb_object_agg('c' || k, k * n)
+                        FROM generate_series(1, 40) k),
+           'log', repeat('x', 1200)),
+       NOW() - make_interval(secs => {snapshots} - n)
+FROM apps a, generate_series(1, {snapshots}) n
+WHERE a.app_name = '{name}';
+"""
+
+CLEANUP_SQL = """
+DELETE FROM snapshots WHERE app_id IN (SELECT app_id FROM apps WHERE app_name = '{name}');
+DELETE FROM apps WHERE app_name = '{name}';
+"""
+
+
+def psql(cmd, sql):
+    subprocess.run(shlex.split(cmd) + ["-q", "-v", "ON_ERROR_STOP=1"],
+                   input=sql, text=True, check=True)
+
+
+def fetch_all(base, params):
+    """Read every page; returns (seconds, apps, bytes)."""
+    started = time.perf_counter()
+    apps = size = 0
+    after = None
+    while True:
+        query = dict(params, limit=5000, **({"after": after} if after else {}))
+        url = base + "?" + "&".join(f"{k}={v}" for k, v in query.items())
+        with urllib.request.urlopen(url) as resp:
+            body = resp.read()
+        page = json.loads(body)
+        apps += len(page["apps"])
+        size += len(body)
+        after = page["nextAfter"]
+        if not after:
+            return time.perf_counter() - started, apps, size
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--url", default="http://localhost:8443/api/v1/snapshots/latest")
+    parser.add_argument("--psql", default="psql -h localhost -U trails trails")
+    parser.add_argument("--apps", type=int, default=10_000)
+    parser.add_argument("--snapshots", type=int, default=3)
+    parser.add_argument("--runs", type=int, default=5)
+    parser.add_argument("--no-seed", action="store_true")
+    parser.add_argument("--cleanup", action="store_true")
+    args = parser.parse_args()
+
+    if args.cleanup:
+        psql(args.psql, CLEANUP_SQL.format(name=APP_NAME))
+        return
+    if not args.no_seed:
+        psql(args.psql, CLEANUP_SQL.format(name=APP_NAME))
+        started = time.perf_counter()
+        psql(args.psql, SEED_SQL.format(name=APP_NAME, apps=args.apps,
+                                        snapshots=args.snapshots))
+        psql(args.psql, "ANALYZE apps; ANALYZE snapshots;")
+        print(f"seeded {args.apps} apps in {time.perf_counter() - started:.1f}s")
+
+    for label, params in [
+        ("full snapshots", {"status": "running"}),
+        ("fields=/progress,/phase", {"status": "running", "fields": "/progress,/phase"}),
+    ]:
+        times = []
+        for _ in range(args.runs):
+            secs, apps, size = fetch_all(args.url, params)
+            times.append(secs)
+        print(f"{label:26} {apps} apps, {size / 1e6:.1f} MB: "
+              f"median {statistics.median(times) * 1000:.0f} ms, "
+              f"max {max(times) * 1000:.0f} ms")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,30 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Transactional outbox
+-- Lifecycle transitions insert an outbox row in the same transaction
+-- as the state change. The dispatcher delivers rows to durable sinks
+-- (webhooks, exporters) and tracks per-sink attempts.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS outbox (
+    id                  BIGSERIAL PRIMARY KEY,
+    event_type          TEXT NOT NULL,
+    app_id              UUID,
+    payload_json        JSONB NOT NULL,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at        TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_outbox_pending ON outbox(id) WHERE completed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_outbox_completed ON outbox(completed_at) WHERE completed_at IS NOT NULL;
+
+-- One row per (outbox entry, sink) once delivery has been attempted.
+CREATE TABLE IF NOT EXISTS outbox_deliveries (
+    outbox_id           BIGINT NOT NULL REFERENCES outbox(id) ON DELETE CASCADE,
+    sink                TEXT NOT NULL,
+    attempts            INTEGER NOT NULL DEFAULT 0,
+    last_error          TEXT,
+    next_attempt_at     TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at        TIMESTAMPTZ,
+    dead_lettered_at    TIMESTAMPTZ,
+    PRIMARY KEY (outbox_id, sink)
+);
@@ -0,0 +1,33 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Payload schema registry
+-- Versioned JSON Schemas for Status and Result payloads, keyed by
+-- app_name and applied at ingest to every app with that name.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS payload_schemas (
+    app_name            TEXT NOT NULL,
+    version             INTEGER NOT NULL,
+    status_schema       JSONB,
+    result_schema       JSONB,
+    mode                TEXT NOT NULL DEFAULT 'warn'
+        CHECK (mode IN ('warn', 'enforce')),
+    -- Set once a message has been validated against this version;
+    -- referenced versions are immutable (a PUT creates a new version).
+    referenced          BOOLEAN NOT NULL DEFAULT false,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (app_name, version)
+);
+
+-- Conformance counters per schema version and message type.
+CREATE TABLE IF NOT EXISTS schema_validation_stats (
+    app_name            TEXT NOT NULL,
+    version             INTEGER NOT NULL,
+    msg_type            TEXT NOT NULL,
+    pass_count          BIGINT NOT NULL DEFAULT 0,
+    warn_count          BIGINT NOT NULL DEFAULT 0,
+    fail_count          BIGINT NOT NULL DEFAULT 0,
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (app_name, version, msg_type),
+    FOREIGN KEY (app_name, version) REFERENCES payload_schemas(app_name, version)
+);
@@ -0,0 +1,22 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Dead letters
+-- Inbound frames rejected by the WebSocket handler (invalid JSON,
+-- schema violations, oversized frames, protocol violations), kept
+-- for debugging producer bugs. Pruned by the retention task.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS dead_letters (
+    id                  BIGSERIAL PRIMARY KEY,
+    -- No FK: the frame may name an app that doesn't exist.
+    app_id              UUID,
+    reason              TEXT NOT NULL,
+    detail              TEXT,
+    raw_frame           TEXT NOT NULL,
+    raw_size            INTEGER NOT NULL,
+    truncated           BOOLEAN NOT NULL DEFAULT false,
+    received_at         TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_dead_letters_app ON dead_letters(app_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_dead_letters_reason ON dead_letters(reason, id DESC);
+CREATE INDEX IF NOT EXISTS idx_dead_letters_time ON dead_letters(received_at);
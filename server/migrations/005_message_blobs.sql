@@ -0,0 +1,12 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Offloaded message payloads
+-- Payloads above the blob threshold live in object storage under
+-- {app_id}/{seq}; the row keeps the reference and a short preview,
+-- and payload_json is NULL.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS blob_url TEXT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS blob_key TEXT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS blob_size BIGINT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS blob_sha256 TEXT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS payload_preview TEXT;
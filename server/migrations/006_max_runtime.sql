@@ -0,0 +1,46 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Max-runtime budget and the timed_out terminal status
+-- Running apps past max_runtime_secs get a cancel control message;
+-- after the grace period they are forced to 'timed_out'.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS max_runtime_secs INTEGER;
+-- Set when the timeout cancel was sent; the force happens one grace
+-- period later.
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS timeout_cancel_at TIMESTAMPTZ;
+
+-- Widening a CHECK locks the table and rescans it, so each one is
+-- replaced only while its current definition lacks 'timed_out'.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'apps'::regclass AND conname = 'apps_status_check'
+          AND pg_get_constraintdef(oid) LIKE '%''timed_out''%'
+    ) THEN
+        ALTER TABLE apps DROP CONSTRAINT IF EXISTS apps_status_check;
+        ALTER TABLE apps ADD CONSTRAINT apps_status_check
+            CHECK (status IN (
+                'scheduled', 'connected', 'running',
+                'done', 'error', 'crashed', 'cancelled',
+                'start_failed', 'reconnecting', 'lost_contact',
+                'timed_out'
+            ));
+    END IF;
+
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'crashes'::regclass AND conname = 'crashes_crash_type_check'
+          AND pg_get_constraintdef(oid) LIKE '%''timed_out''%'
+    ) THEN
+        ALTER TABLE crashes DROP CONSTRAINT IF EXISTS crashes_crash_type_check;
+        ALTER TABLE crashes ADD CONSTRAINT crashes_crash_type_check
+            CHECK (crash_type IN (
+                'connection_drop', 'heartbeat_timeout', 'never_started',
+                'timed_out'
+            ));
+    END IF;
+END $$;
+
+CREATE INDEX IF NOT EXISTS idx_apps_running_budget ON apps(start_time)
+    WHERE status = 'running';
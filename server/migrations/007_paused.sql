@@ -0,0 +1,9 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Advisory pause flag
+-- A sub-state of connected/running, not a lifecycle status: the state
+-- machine is unchanged, clients are asked to hold off via control
+-- messages and the flag is re-delivered on re_register.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS paused BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS paused_at TIMESTAMPTZ;
@@ -0,0 +1,28 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Crash-loop detection
+-- One row per job identity (app_name + parent) that crashed past the
+-- threshold within the detection window. Re-armed when the identity
+-- stays quiet for a full window.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS crash_loops (
+    id                  BIGSERIAL PRIMARY KEY,
+    app_name            TEXT NOT NULL,
+    parent_id           UUID,
+    namespace           TEXT,
+    -- Distinct runs that crashed within the window.
+    crash_count         INTEGER NOT NULL,
+    last_app_id         UUID NOT NULL,
+    detected_at         TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_crash_at       TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Auto-created registrations for the identity are refused until then.
+    cooldown_until      TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_crash_loops_identity ON crash_loops(
+    app_name, COALESCE(parent_id, '00000000-0000-0000-0000-000000000000'::uuid)
+);
+CREATE INDEX IF NOT EXISTS idx_crash_loops_last ON crash_loops(last_crash_at DESC);
+
+-- Set on each run counted into a detected loop.
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS crash_loop_at TIMESTAMPTZ;
@@ -0,0 +1,9 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — App priority
+-- Higher runs first in background scans (deadline checker, runtime
+-- checker, snapshot compaction). Default 0; range enforced by the API.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS priority SMALLINT NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_apps_priority ON apps(priority DESC, created_at DESC);
@@ -0,0 +1,8 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Intended launch time
+-- The start deadline counts from scheduled_at (falling back to
+-- created_at), so children pre-registered for a later cron launch
+-- don't expire before they were meant to start.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS scheduled_at TIMESTAMPTZ;
@@ -0,0 +1,20 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Per-app security level
+-- Apps in 'signed' or 'full' mode receive server-signed frames. Set at
+-- pre-registration or register, restored on re_register.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS sec_level TEXT NOT NULL DEFAULT 'open';
+
+-- Added once: 012 widens it with 'sealed', which re-adding this list
+-- on a later boot would reject (and every re-add rescans apps).
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'apps'::regclass AND conname = 'apps_sec_level_check'
+    ) THEN
+        ALTER TABLE apps ADD CONSTRAINT apps_sec_level_check
+            CHECK (sec_level IN ('open', 'signed', 'full'));
+    END IF;
+END $$;
@@ -0,0 +1,22 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Sealed (end-to-end encrypted) payloads
+-- Apps in 'sealed' mode send encrypted envelopes the server stores
+-- opaquely; messages.sealed flags them for API consumers.
+-- ═══════════════════════════════════════════════════════════════
+
+-- Replaced only while the check lacks 'sealed'; each replacement
+-- locks and rescans apps.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'apps'::regclass AND conname = 'apps_sec_level_check'
+          AND pg_get_constraintdef(oid) LIKE '%''sealed''%'
+    ) THEN
+        ALTER TABLE apps DROP CONSTRAINT IF EXISTS apps_sec_level_check;
+        ALTER TABLE apps ADD CONSTRAINT apps_sec_level_check
+            CHECK (sec_level IN ('open', 'signed', 'full', 'sealed'));
+    END IF;
+END $$;
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS sealed BOOLEAN NOT NULL DEFAULT false;
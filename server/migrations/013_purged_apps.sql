@@ -0,0 +1,26 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Data purges (right-to-erasure)
+-- One tombstone per purged app. Written before any data is deleted,
+-- so an interrupted purge can be resumed, and kept afterwards so the
+-- app_id cannot be registered again. purged_at stays NULL until the
+-- apps row itself is gone; removed holds per-table row counts.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS purged_apps (
+    app_id              UUID PRIMARY KEY,
+    -- The app (or cascade root) named in the purge request.
+    root_app_id         UUID NOT NULL,
+    parent_id           UUID,
+    -- Hex sha256 of app_name; the name itself is erased.
+    name_sha256         TEXT NOT NULL,
+    -- Distance from root_app_id; purges run deepest first.
+    depth               INTEGER NOT NULL DEFAULT 0,
+    purged_by           TEXT NOT NULL,
+    requested_at        TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    purged_at           TIMESTAMPTZ,
+    removed             JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS idx_purged_apps_root ON purged_apps(root_app_id);
+CREATE INDEX IF NOT EXISTS idx_purged_apps_pending ON purged_apps(requested_at)
+    WHERE purged_at IS NULL;
@@ -0,0 +1,6 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Snapshot lookup by seq
+-- Snapshot diffs fetch two snapshots of an app by seq.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE INDEX IF NOT EXISTS idx_snapshots_seq ON snapshots(app_id, seq);
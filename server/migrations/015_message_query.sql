@@ -0,0 +1,10 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Message queries
+-- Payload predicates compile to JSONB containment (@>), served by a
+-- jsonb_path_ops GIN index; correlation ids are looked up directly.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE INDEX IF NOT EXISTS idx_messages_payload_gin
+    ON messages USING GIN (payload_json jsonb_path_ops);
+CREATE INDEX IF NOT EXISTS idx_messages_correlation
+    ON messages(correlation_id) WHERE correlation_id IS NOT NULL;
@@ -0,0 +1,10 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Correlation traces
+-- Apps carry the correlation id they were launched under (inherited
+-- from the parent when not given). messages.correlation_id is already
+-- indexed by 015_message_query.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS correlation_id TEXT;
+CREATE INDEX IF NOT EXISTS idx_apps_correlation
+    ON apps(correlation_id) WHERE correlation_id IS NOT NULL;
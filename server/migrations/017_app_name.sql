@@ -0,0 +1,8 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Lookup by app name
+-- GET /apps/by-name/{name} lists runs of one name, newest first.
+-- Stored names are trimmed at startup (APP_NAME_TRIM), not here:
+-- migrations can't see configuration.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE INDEX IF NOT EXISTS idx_apps_name ON apps(app_name, created_at DESC);
@@ -0,0 +1,30 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Per-app storage quotas
+-- stored_bytes is maintained by every message/snapshot write and
+-- corrected by the reconciler; it counts the JSON text size of data
+-- stored since quota_reset_at. The quota_*_bytes columns are admin
+-- overrides of the configured limits (NULL inherits).
+-- ═══════════════════════════════════════════════════════════════
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_name = 'apps' AND column_name = 'stored_bytes'
+    ) THEN
+        ALTER TABLE apps ADD COLUMN stored_bytes BIGINT NOT NULL DEFAULT 0;
+        -- One-time backfill; the reconciler only revisits live apps.
+        UPDATE apps a SET stored_bytes =
+            COALESCE((SELECT SUM(COALESCE(octet_length(m.payload_json::text), 0)
+                                 + COALESCE(octet_length(m.payload_preview), 0))
+                      FROM messages m WHERE m.app_id = a.app_id), 0)
+          + COALESCE((SELECT SUM(octet_length(s.snapshot_json::text))
+                      FROM snapshots s WHERE s.app_id = a.app_id), 0);
+    END IF;
+END $$;
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS quota_soft_bytes BIGINT;
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS quota_hard_bytes BIGINT;
+-- Set when the soft limit was first crossed (QuotaWarning sent).
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS quota_warned_at TIMESTAMPTZ;
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS quota_reset_at TIMESTAMPTZ;
@@ -0,0 +1,8 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Build attribution
+-- The trailsd build (`<version>+<git sha>`) that wrote the row; NULL
+-- for rows written before this migration.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE crashes ADD COLUMN IF NOT EXISTS server_version TEXT;
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS server_version TEXT;
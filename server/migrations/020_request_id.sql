@@ -0,0 +1,8 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Request ids
+-- The X-Request-Id of the HTTP request, or the connection id of the
+-- WebSocket, that wrote the row; NULL for background work.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS request_id TEXT;
+ALTER TABLE dead_letters ADD COLUMN IF NOT EXISTS request_id TEXT;
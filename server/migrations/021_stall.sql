@@ -0,0 +1,12 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Stall detection
+-- progress_at is the last Status whose content differed from the
+-- previous snapshot. stalled_at is an advisory marker set by the stall
+-- sweeper and cleared by the next such Status; the lifecycle status is
+-- untouched.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS progress_at TIMESTAMPTZ;
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS stalled_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_apps_stalled ON apps (stalled_at) WHERE stalled_at IS NOT NULL;
@@ -0,0 +1,7 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Message export
+-- Exports and message pages walk an app's messages in (seq, id) order;
+-- without this index every chunk re-sorts the whole app.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE INDEX IF NOT EXISTS idx_messages_app_seq ON messages(app_id, seq, id);
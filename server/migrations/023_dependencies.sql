@@ -0,0 +1,13 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Dependencies between sibling apps
+-- depends_on lists the siblings a scheduled app waits for. While any
+-- of them is unfinished the app is blocked (blocked_at set) and its
+-- start deadline doesn't run; the dependency resolver clears the flag
+-- once they are all done, or applies the failure policy.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS depends_on UUID[];
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS blocked_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_apps_depends_on ON apps USING GIN (depends_on);
+CREATE INDEX IF NOT EXISTS idx_apps_blocked ON apps (blocked_at) WHERE blocked_at IS NOT NULL;
@@ -0,0 +1,27 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Control messages queued for disconnected apps
+-- A cancel/pause/resume for a non-terminal app without a socket here
+-- waits until its next register/re_register, which delivers pending
+-- rows in id order before anything else. Rows past expires_at are
+-- marked expired_at instead and audited as control_expired. acked_at is
+-- for client control replies.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS pending_controls (
+    id                  BIGSERIAL PRIMARY KEY,
+    app_id              UUID NOT NULL REFERENCES apps(app_id),
+    action              TEXT NOT NULL,
+    payload_json        JSONB NOT NULL DEFAULT '{}',
+    requested_by        TEXT,
+    request_id          TEXT,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at          TIMESTAMPTZ NOT NULL,
+    delivered_at        TIMESTAMPTZ,
+    acked_at            TIMESTAMPTZ,
+    expired_at          TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_controls_app ON pending_controls (app_id, id)
+    WHERE delivered_at IS NULL AND expired_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_pending_controls_expiry ON pending_controls (expires_at)
+    WHERE delivered_at IS NULL AND expired_at IS NULL;
@@ -0,0 +1,7 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Payload redaction at ingest
+-- Number of values masked or dropped from a message's payload by the
+-- redaction rules before it was stored; 0 when it is stored as sent.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS redactions_applied INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,18 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Numeric metrics extracted from Status payloads
+-- One row per metric per stored Status, read at the JSON pointers of
+-- the app's extraction rules. seq is the Status message's seq. Rows
+-- older than METRICS_RETENTION are pruned by the retention task.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS app_metrics (
+    id                  BIGSERIAL PRIMARY KEY,
+    app_id              UUID NOT NULL REFERENCES apps(app_id),
+    metric              TEXT NOT NULL,
+    value               DOUBLE PRECISION NOT NULL,
+    seq                 BIGINT NOT NULL,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_app_metrics_series ON app_metrics (app_id, metric, created_at);
+CREATE INDEX IF NOT EXISTS idx_app_metrics_created ON app_metrics (created_at);
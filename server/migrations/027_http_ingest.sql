@@ -0,0 +1,26 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — HTTP ingestion
+-- transport is how the app last reached the server: 'ws' holds a
+-- socket whose drop is a crash; 'http' apps POST batches to
+-- /api/v1/ingest, move last_seen_at with each one, and are marked
+-- crashed (heartbeat_timeout) by the stale sweeper once silent for
+-- INGEST_STALE_AFTER.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS transport TEXT NOT NULL DEFAULT 'ws';
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS last_seen_at TIMESTAMPTZ;
+
+-- Added once, not re-added (locking and rescanning apps) every boot.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'apps'::regclass AND conname = 'apps_transport_check'
+    ) THEN
+        ALTER TABLE apps ADD CONSTRAINT apps_transport_check
+            CHECK (transport IN ('ws', 'http'));
+    END IF;
+END $$;
+
+CREATE INDEX IF NOT EXISTS idx_apps_http_last_seen ON apps (last_seen_at)
+    WHERE transport = 'http' AND status IN ('connected', 'running');
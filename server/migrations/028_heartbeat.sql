@@ -0,0 +1,8 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Client heartbeats
+-- Apps may opt in to a seq-less `heartbeat` frame on an interval, so
+-- one that is alive but has nothing to report can be told apart from a
+-- hung one. last_heartbeat is when the latest arrived.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS last_heartbeat TIMESTAMPTZ;
@@ -0,0 +1,20 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — App log lines
+-- Lines an app sends as `Log` messages, kept apart from the message
+-- history: level, message and structured fields. ts is when the app
+-- logged the line (its header timestamp); seq is the message's seq.
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE IF NOT EXISTS logs (
+    id                  BIGSERIAL PRIMARY KEY,
+    app_id              UUID NOT NULL REFERENCES apps(app_id),
+    seq                 BIGINT NOT NULL,
+    level               TEXT NOT NULL,
+    message             TEXT NOT NULL,
+    fields_json         JSONB,
+    ts                  TIMESTAMPTZ NOT NULL,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_logs_app_ts ON logs (app_id, ts);
+CREATE INDEX IF NOT EXISTS idx_logs_app_seq ON logs (app_id, seq);
@@ -0,0 +1,8 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — App-defined message types
+-- Messages of a type of the app's own (send_custom) and the client's
+-- Metric messages are stored next to Status, Result, Error and
+-- Control, so msg_type is free text; the server validates it.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_msg_type_check;
@@ -0,0 +1,23 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — One stored message per seq
+-- A client resends what the server hasn't acked, so the same
+-- (app_id, seq) may arrive twice, even at once over two connections.
+-- The index makes the second insert a no-op. Duplicates stored
+-- before it existed go first, keeping the earliest; once the index
+-- exists there are none, so later boots skip the scan.
+-- ═══════════════════════════════════════════════════════════════
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_indexes WHERE indexname = 'idx_messages_app_seq_in'
+    ) THEN
+        DELETE FROM messages m
+        USING messages d
+        WHERE m.direction = 'in' AND d.direction = 'in'
+          AND m.app_id = d.app_id AND m.seq = d.seq AND m.id > d.id;
+
+        CREATE UNIQUE INDEX idx_messages_app_seq_in
+            ON messages (app_id, seq) WHERE direction = 'in';
+    END IF;
+END $$;
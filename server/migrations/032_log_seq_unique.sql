@@ -0,0 +1,21 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — One stored log line per seq
+-- Like messages (031): a resent Log message, even one racing the
+-- first copy over another connection, must not store its line twice.
+-- Duplicates stored before go first, keeping the earliest; guarded
+-- on the unique index like 031, so it runs once.
+-- ═══════════════════════════════════════════════════════════════
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_indexes WHERE indexname = 'idx_logs_app_seq_unique'
+    ) THEN
+        DELETE FROM logs l
+        USING logs d
+        WHERE l.app_id = d.app_id AND l.seq = d.seq AND l.id > d.id;
+
+        DROP INDEX IF EXISTS idx_logs_app_seq;
+        CREATE UNIQUE INDEX idx_logs_app_seq_unique ON logs (app_id, seq);
+    END IF;
+END $$;
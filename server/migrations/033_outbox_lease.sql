@@ -0,0 +1,9 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Outbox leases
+-- A dispatcher claims a batch by setting locked_until, in a short
+-- statement of its own, and delivers with no transaction open. Other
+-- dispatchers skip the batch until the lease runs out, so one that
+-- dies mid-batch hands its rows on instead of holding row locks.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
@@ -0,0 +1,11 @@
+-- ═══════════════════════════════════════════════════════════════
+-- TRAILS — Outbox retry times
+-- The earliest retry across a row's undelivered sinks, so rows
+-- backing off are not claimed again until one of their sinks is due
+-- and newer events get through meanwhile. NULL means due now.
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS next_attempt_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_outbox_due ON outbox(next_attempt_at, id)
+    WHERE completed_at IS NULL;
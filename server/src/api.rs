@@ -0,0 +1,1967 @@
+//! REST API — mounted at /api/v1 (spec §23).
+//!
+//! Request and response bodies are camelCase JSON; query parameters are
+//! snake_case. Errors map through `TrailsError::into_response`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, patch, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tower_http::compression::CompressionLayer;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::app_metrics;
+use crate::blob::{self, BlobRef};
+use crate::config::{Config, QuotaLimits};
+use crate::controls::{self, Delivery};
+use crate::db::{
+    self, AppRow, Blocked, CrashLoopRow, DeadLetterRow, LogRow, MessageRow, PendingControl,
+    SchemaRow, SchemaStatsRow, TraceAppRow,
+};
+use crate::dependencies;
+use crate::diff;
+use crate::error::TrailsError;
+use crate::export;
+use crate::latest;
+use crate::lifecycle;
+use crate::observe;
+use crate::purge::{self, PurgeReport};
+use crate::query::{self, MessagePage, MessageQueryRequest};
+use crate::quota;
+use crate::request_id;
+use crate::rollup::{self, Rollup};
+use crate::schema;
+use crate::signing;
+use crate::state::AppState;
+use crate::types::{normalize_app_name, AppStatus, Event, PRIORITY_RANGE};
+use crate::version::{self, VersionInfo};
+use crate::ws;
+
+/// Routes under /api/v1.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/schemas", get(list_schemas))
+        .route("/schemas/{app_name}", get(list_schema_versions).put(put_schema))
+        .route(
+            "/schemas/{app_name}/versions/{version}",
+            get(get_schema_version).patch(patch_schema_version),
+        )
+        .route("/schemas/{app_name}/stats", get(get_schema_stats))
+        .route("/children", post(register_child))
+        .route(
+            "/children:batch",
+            post(register_children).layer(DefaultBodyLimit::max(BATCH_BODY_LIMIT)),
+        )
+        .route("/apps", get(list_apps))
+        .route("/apps/by-name/{name}", get(list_apps_by_name))
+        .route("/apps/adopt", post(adopt_children))
+        .route("/apps/{id}", get(get_app).patch(patch_app))
+        .route("/apps/{id}/children", get(list_children))
+        .route("/apps/{id}/rollup", get(get_rollup))
+        .route("/apps/{id}/pause", post(pause_app))
+        .route("/apps/{id}/resume", post(resume_app))
+        .route("/apps/{id}/cancel", post(cancel_app))
+        .route("/apps/{id}/controls", get(list_controls))
+        .route("/apps/{id}/metrics", get(get_app_metrics))
+        .route("/apps/{id}/logs", get(list_logs))
+        .route("/apps/{id}/force", post(force_app))
+        .route("/apps/{id}/adopt", post(adopt_app))
+        .route("/apps/{id}/messages", get(list_messages))
+        .route(
+            "/apps/{id}/messages/export",
+            get(export_messages).layer(CompressionLayer::new()),
+        )
+        .route("/apps/{id}/snapshots/latest", get(get_latest_snapshot))
+        .route("/apps/{id}/snapshots/diff", get(diff_snapshots))
+        .route("/apps/{id}/dead-letters", get(list_app_dead_letters))
+        .route("/apps/{id}/data", delete(purge_app_data))
+        .route("/apps/{id}/storage-quota", patch(patch_storage_quota))
+        .route("/messages/query", post(query_messages))
+        .route("/traces/{correlation_id}", get(get_trace))
+        .route("/traces/{correlation_id}/messages", get(list_trace_messages))
+        .route("/dead-letters", get(list_dead_letters))
+        .route("/snapshots/latest", get(latest_snapshots))
+        .route("/crash-loops", get(list_crash_loops))
+        .route("/stats/storage", get(storage_stats))
+        .route("/version", get(get_version))
+        .route(
+            "/admin/drain",
+            get(drain_status).post(start_drain).delete(stop_drain),
+        )
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Apps
+// ═══════════════════════════════════════════════════════════════
+
+/// POST /api/v1/children body — Phase A intent (spec §7).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterChildRequest {
+    parent_id: Option<Uuid>,
+    app_id: Uuid,
+    app_name: String,
+    #[serde(default)]
+    start_deadline: Option<i32>,
+    #[serde(default)]
+    role_refs: Vec<String>,
+    #[serde(default)]
+    tags: Option<JsonValue>,
+    #[serde(default)]
+    max_runtime_secs: Option<i32>,
+    #[serde(default)]
+    priority: Option<i16>,
+    /// Intended launch time; the start deadline counts from here.
+    #[serde(default)]
+    scheduled_at: Option<DateTime<Utc>>,
+    /// `open` (default), `signed`, `full` or `sealed`.
+    #[serde(default)]
+    sec_level: Option<String>,
+    /// Trace this run belongs to; defaults to the parent's.
+    #[serde(default)]
+    correlation_id: Option<String>,
+    /// Siblings that must be `done` first; the app waits blocked, without
+    /// a start deadline, until then.
+    #[serde(default)]
+    depends_on: Vec<Uuid>,
+}
+
+impl RegisterChildRequest {
+    fn new_app<'a>(&'a self, config: &Config) -> db::NewApp<'a> {
+        db::NewApp {
+            app_id: self.app_id,
+            parent_id: self.parent_id,
+            app_name: &self.app_name,
+            start_deadline: self.start_deadline.unwrap_or(config.default_start_deadline),
+            role_refs: &self.role_refs,
+            metadata: self.tags.as_ref(),
+            max_runtime_secs: self.max_runtime_secs,
+            priority: self.priority.unwrap_or(0),
+            scheduled_at: self.scheduled_at,
+            sec_level: self.sec_level.as_deref().unwrap_or("open"),
+            correlation_id: self.correlation_id.as_deref(),
+            depends_on: &self.depends_on,
+        }
+    }
+
+    /// Drop repeated dependencies; an app can't wait for itself.
+    fn normalize_depends_on(&mut self) -> Result<(), TrailsError> {
+        if self.depends_on.contains(&self.app_id) {
+            return Err(TrailsError::InvalidRequest("an app cannot depend on itself".into()));
+        }
+        let mut seen = HashSet::new();
+        self.depends_on.retain(|d| seen.insert(*d));
+        Ok(())
+    }
+
+    /// Why a dependency can't be used: unknown, or not a sibling. `parents`
+    /// maps known app ids to their parents.
+    fn dependency_error(&self, parents: &HashMap<Uuid, Option<Uuid>>) -> Option<String> {
+        self.depends_on.iter().find_map(|dep| match parents.get(dep) {
+            None => Some(format!("dependency {dep} not found")),
+            Some(parent) if *parent != self.parent_id => {
+                Some(format!("dependency {dep} is not a sibling"))
+            }
+            Some(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PatchAppRequest {
+    /// `null` clears the app's own budget (config default applies).
+    #[serde(default, deserialize_with = "present")]
+    max_runtime_secs: Option<Option<i32>>,
+    #[serde(default)]
+    priority: Option<i16>,
+    /// Delay a scheduled app's launch; audited.
+    #[serde(default)]
+    reschedule: Option<Reschedule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reschedule {
+    /// New launch time; must not be earlier than the current one.
+    scheduled_at: DateTime<Utc>,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Distinguishes an explicit `null` (Some(None)) from an absent field (None).
+pub(crate) fn present<'de, D, T>(d: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(d).map(Some)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AppDetail {
+    #[serde(flatten)]
+    app: AppRow,
+    /// Budget in force: the app's own or the namespace/global default.
+    effective_max_runtime_secs: Option<i32>,
+    /// Seconds left before the timeout cancel; running apps only.
+    remaining_runtime_secs: Option<i64>,
+    /// Storage quota in force: the app's overrides or the configured limits.
+    effective_storage_quota: QuotaLimits,
+    /// Payloads are sealed envelopes (`secLevel: "sealed"`).
+    sealed: bool,
+    /// Active crash loop of this app's identity; single-app GET only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crash_loop: Option<CrashLoopRow>,
+}
+
+impl AppDetail {
+    fn new(state: &AppState, app: AppRow) -> Self {
+        let config = state.config();
+        let budget = lifecycle::max_runtime(&config, &app);
+        let storage_quota = quota::limits(&config, &app);
+        let remaining = match (budget, app.start_time) {
+            (Some(budget), Some(start)) if app.status == "running" => {
+                let elapsed = (chrono::Utc::now() - start).num_seconds();
+                Some((i64::from(budget) - elapsed).max(0))
+            }
+            _ => None,
+        };
+        Self {
+            sealed: app.sec_level == "sealed",
+            app,
+            effective_max_runtime_secs: budget,
+            remaining_runtime_secs: remaining,
+            effective_storage_quota: storage_quota,
+            crash_loop: None,
+        }
+    }
+}
+
+fn validate_max_runtime(secs: Option<i32>) -> Result<(), TrailsError> {
+    match secs {
+        Some(s) if s <= 0 => Err(TrailsError::InvalidRequest(
+            "maxRuntimeSecs must be positive".into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn validate_sec_level(sec_level: Option<&str>) -> Result<(), TrailsError> {
+    match sec_level {
+        Some(level) if !signing::SEC_LEVELS.contains(&level) => Err(TrailsError::InvalidRequest(
+            format!("unknown secLevel '{level}' (expected one of {:?})", signing::SEC_LEVELS),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Normalize `name` in place per the app_name rules.
+fn normalize_name(name: &mut String, trim: bool) -> Result<(), TrailsError> {
+    let normalized = normalize_app_name(name, trim).map_err(TrailsError::InvalidRequest)?;
+    if normalized.len() != name.len() {
+        *name = normalized.to_string();
+    }
+    Ok(())
+}
+
+fn validate_priority(priority: Option<i16>) -> Result<(), TrailsError> {
+    match priority {
+        Some(p) if !PRIORITY_RANGE.contains(&p) => Err(TrailsError::InvalidRequest(format!(
+            "priority must be within {PRIORITY_RANGE:?}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// POST /api/v1/children — parent declares intent before the child starts.
+async fn register_child(
+    State(state): State<Arc<AppState>>,
+    Json(mut req): Json<RegisterChildRequest>,
+) -> Result<(StatusCode, Json<AppDetail>), TrailsError> {
+    normalize_name(&mut req.app_name, state.config().app_name_trim)?;
+    validate_max_runtime(req.max_runtime_secs)?;
+    validate_priority(req.priority)?;
+    validate_sec_level(req.sec_level.as_deref())?;
+    req.normalize_depends_on()?;
+    if !req.depends_on.is_empty() {
+        let parents = db::app_parents(&state.db, &req.depends_on).await?;
+        if let Some(error) = req.dependency_error(&parents) {
+            return Err(TrailsError::InvalidRequest(error));
+        }
+    }
+    let created = db::create_scheduled_app(&state.db, &req.new_app(&state.config())).await?;
+    if !created {
+        let ids: Vec<Uuid> = std::iter::once(req.app_id).chain(req.parent_id).collect();
+        if let Some(&purged) = db::purged_app_ids(&state.db, &ids).await?.first() {
+            return Err(TrailsError::Purged(purged));
+        }
+    }
+    if created && !req.depends_on.is_empty() {
+        // Dependencies may have ended already.
+        dependencies::resolve(&state, Blocked::Apps(&[req.app_id])).await?;
+    }
+    let app = db::get_app(&state.db, req.app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(req.app_id))?;
+    if !created {
+        return Err(TrailsError::InvalidTransition {
+            from: app.status,
+            to: "scheduled".into(),
+        });
+    }
+    Ok((StatusCode::CREATED, Json(AppDetail::new(&state, app))))
+}
+
+/// Request body cap for `children:batch`; the default 2 MiB is too small
+/// for batches of thousands.
+const BATCH_BODY_LIMIT: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchRegisterRequest {
+    children: Vec<RegisterChildRequest>,
+    /// Roll the whole batch back if any item fails (default: best-effort).
+    #[serde(default)]
+    atomic: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchRegisterResponse {
+    /// False when `atomic` and an item failed: nothing was created.
+    committed: bool,
+    created: usize,
+    failed: usize,
+    elapsed_ms: u64,
+    results: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchItemResult {
+    index: usize,
+    app_id: Uuid,
+    status: BatchItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BatchItemStatus {
+    Created,
+    Failed,
+    /// Valid, but not created because an atomic batch was rolled back.
+    Aborted,
+}
+
+/// POST /api/v1/children:batch — pre-register many children in one
+/// transaction. Per-item results; see `BatchRegisterRequest::atomic`.
+async fn register_children(
+    State(state): State<Arc<AppState>>,
+    Json(mut req): Json<BatchRegisterRequest>,
+) -> Result<Json<BatchRegisterResponse>, TrailsError> {
+    let started = Instant::now();
+    let max = state.config().children_batch_max;
+    if req.children.is_empty() || req.children.len() > max {
+        return Err(TrailsError::InvalidRequest(format!(
+            "batch must hold 1 to {max} children, got {}",
+            req.children.len()
+        )));
+    }
+
+    // Per-item validation. Parents may be created by the same batch.
+    let batch_ids: HashSet<Uuid> = req.children.iter().map(|c| c.app_id).collect();
+    let mut lookup: Vec<Uuid> = batch_ids.iter().copied().collect();
+    lookup.extend(req.children.iter().filter_map(|c| c.parent_id));
+    let existing: HashSet<Uuid> = db::existing_app_ids(&state.db, &lookup)
+        .await?
+        .into_iter()
+        .collect();
+    // Parents of dependencies, batch items overridden by existing apps.
+    let mut parents: HashMap<Uuid, Option<Uuid>> = HashMap::new();
+    for child in req.children.iter().rev() {
+        parents.insert(child.app_id, child.parent_id);
+    }
+    let outside: Vec<Uuid> = req
+        .children
+        .iter()
+        .flat_map(|c| &c.depends_on)
+        .filter(|d| !batch_ids.contains(d))
+        .copied()
+        .collect();
+    let known: Vec<Uuid> = existing.iter().copied().chain(outside).collect();
+    parents.extend(db::app_parents(&state.db, &known).await?);
+    let purged: HashSet<Uuid> = db::purged_app_ids(&state.db, &lookup)
+        .await?
+        .into_iter()
+        .collect();
+
+    let config = state.config();
+    let trim = config.app_name_trim;
+    let names: Vec<Result<(), TrailsError>> = req
+        .children
+        .iter_mut()
+        .map(|c| normalize_name(&mut c.app_name, trim).and_then(|_| c.normalize_depends_on()))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut errors: Vec<Option<String>> = req
+        .children
+        .iter()
+        .zip(names)
+        .map(|(c, name)| {
+            let invalid = name
+                .and(validate_max_runtime(c.max_runtime_secs))
+                .and(validate_priority(c.priority))
+                .and(validate_sec_level(c.sec_level.as_deref()))
+                .err()
+                .map(|e| match e {
+                    TrailsError::InvalidRequest(msg) => msg,
+                    other => other.to_string(),
+                });
+            invalid.or_else(|| {
+                if !seen.insert(c.app_id) {
+                    Some("duplicate appId in batch".into())
+                } else if purged.contains(&c.app_id) {
+                    Some("app was purged".into())
+                } else if existing.contains(&c.app_id) {
+                    Some("app already exists".into())
+                } else {
+                    match c.parent_id {
+                        Some(p) if purged.contains(&p) => Some(format!("parent {p} was purged")),
+                        Some(p) if !existing.contains(&p) && !batch_ids.contains(&p) => {
+                            Some(format!("parent {p} not found"))
+                        }
+                        _ => None,
+                    }
+                }
+            })
+            .or_else(|| c.dependency_error(&parents))
+        })
+        .collect();
+    check_batch_dependencies(&req.children, &mut errors, &existing);
+
+    let any_invalid = errors.iter().any(Option::is_some);
+    let (inserted, committed) = if req.atomic && any_invalid {
+        (HashSet::new(), false)
+    } else {
+        let apps: Vec<db::NewApp<'_>> = req
+            .children
+            .iter()
+            .zip(&errors)
+            .filter(|(_, err)| err.is_none())
+            .map(|(c, _)| c.new_app(&config))
+            .collect();
+        let (inserted, committed) = db::create_scheduled_apps(&state.db, &apps, req.atomic).await?;
+        let inserted: HashSet<Uuid> = inserted.into_iter().collect();
+        let blocked: Vec<Uuid> = apps
+            .iter()
+            .filter(|a| !a.depends_on.is_empty() && inserted.contains(&a.app_id))
+            .map(|a| a.app_id)
+            .collect();
+        if committed && !blocked.is_empty() {
+            // Dependencies outside the batch may have ended already.
+            dependencies::resolve(&state, Blocked::Apps(&blocked)).await?;
+        }
+        (inserted, committed)
+    };
+
+    let mut results = Vec::with_capacity(req.children.len());
+    for (index, (child, err)) in req.children.iter().zip(errors.iter_mut()).enumerate() {
+        // Valid but skipped by the insert: created concurrently.
+        if err.is_none() && !inserted.contains(&child.app_id) && (committed || !any_invalid) {
+            *err = Some("app already exists".into());
+        }
+        let status = match (&err, committed) {
+            (Some(_), _) => BatchItemStatus::Failed,
+            (None, true) => BatchItemStatus::Created,
+            (None, false) => BatchItemStatus::Aborted,
+        };
+        results.push(BatchItemResult {
+            index,
+            app_id: child.app_id,
+            status,
+            error: err.take(),
+        });
+    }
+    let created = results
+        .iter()
+        .filter(|r| r.status == BatchItemStatus::Created)
+        .count();
+    let failed = results
+        .iter()
+        .filter(|r| r.status == BatchItemStatus::Failed)
+        .count();
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    info!(created, failed, committed, elapsed_ms, "batch child pre-registration");
+
+    Ok(Json(BatchRegisterResponse {
+        committed,
+        created,
+        failed,
+        elapsed_ms,
+        results,
+    }))
+}
+
+/// Fail batch items on a dependency cycle, then those waiting for a
+/// failed item, transitively.
+fn check_batch_dependencies(
+    children: &[RegisterChildRequest],
+    errors: &mut [Option<String>],
+    existing: &HashSet<Uuid>,
+) {
+    let pending: HashMap<Uuid, Vec<Uuid>> = children
+        .iter()
+        .zip(errors.iter())
+        .filter(|(c, err)| err.is_none() && !c.depends_on.is_empty())
+        .map(|(c, _)| (c.app_id, c.depends_on.clone()))
+        .collect();
+    let stuck = dependencies::unresolvable(&pending);
+    for (child, err) in children.iter().zip(errors.iter_mut()) {
+        if err.is_none() && stuck.contains(&child.app_id) {
+            *err = Some("dependsOn forms a cycle".into());
+        }
+    }
+
+    let valid: HashSet<Uuid> = children
+        .iter()
+        .zip(errors.iter())
+        .filter(|(_, err)| err.is_none())
+        .map(|(c, _)| c.app_id)
+        .collect();
+    let mut failed: Vec<Uuid> = children
+        .iter()
+        .map(|c| c.app_id)
+        .filter(|id| !valid.contains(id) && !existing.contains(id))
+        .collect();
+    let mut waiting: HashMap<Uuid, Vec<usize>> = HashMap::new();
+    for (index, child) in children.iter().enumerate() {
+        for dep in &child.depends_on {
+            waiting.entry(*dep).or_default().push(index);
+        }
+    }
+    while let Some(id) = failed.pop() {
+        for &index in waiting.get(&id).into_iter().flatten() {
+            if errors[index].is_none() {
+                errors[index] = Some(format!("dependency {id} failed"));
+                failed.push(children[index].app_id);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AppListQuery {
+    #[serde(default)]
+    namespace: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    min_priority: Option<i16>,
+    /// `priority` sorts highest priority first; default is newest first.
+    #[serde(default)]
+    sort: Option<String>,
+    /// Continue after this app: the last `appId` of the previous page.
+    #[serde(default)]
+    after: Option<Uuid>,
+    /// `true`: only running apps flagged stalled; `false`: all others.
+    #[serde(default)]
+    stalled: Option<bool>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+/// GET /api/v1/apps — apps newest first. Filters: `namespace`, `status`,
+/// `min_priority`, `stalled`, `sort`, `after`, `limit`.
+async fn list_apps(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<AppListQuery>,
+) -> Result<Json<Vec<AppDetail>>, TrailsError> {
+    Ok(Json(list_filtered(&state, &q, None).await?))
+}
+
+/// GET /api/v1/apps/{id}/children — direct children, with the filters of
+/// `GET /apps`.
+async fn list_children(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<AppListQuery>,
+) -> Result<Json<Vec<AppDetail>>, TrailsError> {
+    if db::get_app(&state.db, app_id).await?.is_none() {
+        return Err(TrailsError::AppNotFound(app_id));
+    }
+    Ok(Json(list_filtered(&state, &q, Some(app_id)).await?))
+}
+
+async fn list_filtered(
+    state: &AppState,
+    q: &AppListQuery,
+    parent_id: Option<Uuid>,
+) -> Result<Vec<AppDetail>, TrailsError> {
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let by_priority = match q.sort.as_deref() {
+        None | Some("created") => false,
+        Some("priority") => true,
+        Some(other) => {
+            return Err(TrailsError::InvalidRequest(format!(
+                "unknown sort '{other}' (expected 'created' or 'priority')"
+            )))
+        }
+    };
+    let filter = db::AppListFilter {
+        namespace: q.namespace.as_deref(),
+        status: q.status.as_deref(),
+        min_priority: q.min_priority,
+        parent_id,
+        after: q.after,
+        stalled: q.stalled,
+    };
+    let rows = db::list_apps(&state.db, &filter, by_priority, limit).await?;
+    Ok(rows.into_iter().map(|app| AppDetail::new(state, app)).collect())
+}
+
+/// GET /api/v1/apps/{id} — single app state.
+async fn get_app(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+) -> Result<Json<AppDetail>, TrailsError> {
+    Ok(Json(app_detail(&state, app_id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct AppsByNameQuery {
+    /// Only the most recent run, as a single object.
+    #[serde(default)]
+    latest: bool,
+    /// Return runs created before this (pagination cursor).
+    #[serde(default)]
+    before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+/// GET /api/v1/apps/by-name/{name} — runs with exactly this app_name,
+/// newest first; `latest=true` returns just the newest (404 if none).
+/// Filters: `before`, `limit`.
+async fn list_apps_by_name(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(q): Query<AppsByNameQuery>,
+) -> Result<Response, TrailsError> {
+    let name = normalize_app_name(&name, state.config().app_name_trim)
+        .map_err(TrailsError::InvalidRequest)?;
+    let limit = if q.latest {
+        1
+    } else {
+        q.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
+    };
+    let rows = db::list_apps_by_name(&state.db, name, q.before, limit).await?;
+    let mut apps = rows.into_iter().map(|app| AppDetail::new(&state, app));
+    if q.latest {
+        let app = apps
+            .next()
+            .ok_or_else(|| TrailsError::AppNameNotFound(name.to_string()))?;
+        return Ok(Json(app).into_response());
+    }
+    Ok(Json(apps.collect::<Vec<_>>()).into_response())
+}
+
+/// Full detail of one app, crash-loop state included. Shared with the
+/// live feed (`feed`).
+pub(crate) async fn app_detail(state: &AppState, app_id: Uuid) -> Result<AppDetail, TrailsError> {
+    let app = db::get_app(&state.db, app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+    let crash_loop = db::get_crash_loop(
+        &state.db,
+        &app.app_name,
+        app.parent_id,
+        state.config().crash_loop_window,
+    )
+    .await?;
+    Ok(AppDetail {
+        crash_loop,
+        ..AppDetail::new(state, app)
+    })
+}
+
+/// PATCH /api/v1/apps/{id} — update `maxRuntimeSecs` and/or `priority`,
+/// or `reschedule` a scheduled app; all or nothing.
+async fn patch_app(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Json(req): Json<PatchAppRequest>,
+) -> Result<Json<AppDetail>, TrailsError> {
+    if req.max_runtime_secs.is_none() && req.priority.is_none() && req.reschedule.is_none() {
+        return Err(TrailsError::InvalidRequest("no updatable fields given".into()));
+    }
+    validate_max_runtime(req.max_runtime_secs.flatten())?;
+    validate_priority(req.priority)?;
+
+    let mut tx = state.db.begin().await?;
+    if let Some(r) = &req.reschedule {
+        let rescheduled = db::reschedule_app(
+            &mut tx,
+            app_id,
+            r.scheduled_at,
+            r.reason.as_deref(),
+            request_id::current().as_deref(),
+        )
+        .await?;
+        if rescheduled.is_none() {
+            return Err(reschedule_error(&state, app_id).await);
+        }
+    }
+    let app = db::update_app_settings(&mut tx, app_id, req.max_runtime_secs, req.priority)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+    tx.commit().await?;
+    if let Some(r) = &req.reschedule {
+        info!(app_id = %app_id, scheduled_at = %r.scheduled_at, "app rescheduled");
+    }
+    Ok(Json(AppDetail::new(&state, app)))
+}
+
+/// Why `reschedule_app` refused: unknown app, not scheduled, or moving
+/// the launch time backwards.
+async fn reschedule_error(state: &AppState, app_id: Uuid) -> TrailsError {
+    match db::get_app(&state.db, app_id).await {
+        Err(e) => e,
+        Ok(None) => TrailsError::AppNotFound(app_id),
+        Ok(Some(app)) if app.status != "scheduled" => TrailsError::Conflict(format!(
+            "app is '{}'; only scheduled apps can be rescheduled",
+            app.status
+        )),
+        Ok(Some(app)) => TrailsError::InvalidRequest(format!(
+            "scheduledAt must not be earlier than the current launch time {}",
+            app.scheduled_at.unwrap_or(app.created_at)
+        )),
+    }
+}
+
+/// GET /api/v1/apps/{id}/rollup — aggregate over direct children.
+async fn get_rollup(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+) -> Result<Json<Rollup>, TrailsError> {
+    if db::get_app(&state.db, app_id).await?.is_none() {
+        return Err(TrailsError::AppNotFound(app_id));
+    }
+    let rollup = rollup::get(&state, app_id).await?;
+    Ok(Json((*rollup).clone()))
+}
+
+/// POST /api/v1/apps/{id}/pause — ask the client to hold off.
+async fn pause_app(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+) -> Result<Json<AppDetail>, TrailsError> {
+    set_paused(&state, app_id, true).await
+}
+
+/// POST /api/v1/apps/{id}/resume
+async fn resume_app(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+) -> Result<Json<AppDetail>, TrailsError> {
+    set_paused(&state, app_id, false).await
+}
+
+/// Flip the pause flag and deliver the matching control message, or
+/// queue it until the app next registers. The app must not be terminal.
+async fn set_paused(
+    state: &Arc<AppState>,
+    app_id: Uuid,
+    paused: bool,
+) -> Result<Json<AppDetail>, TrailsError> {
+    let action = if paused { "pause" } else { "resume" };
+    let Some(app) = db::set_paused(&state.db, app_id, paused).await? else {
+        let app = db::get_app(&state.db, app_id)
+            .await?
+            .ok_or(TrailsError::AppNotFound(app_id))?;
+        return Err(TrailsError::Conflict(format!(
+            "cannot {action} app {app_id}: status is '{}'",
+            app.status
+        )));
+    };
+    let outcome =
+        controls::send_or_queue(state, app_id, action, serde_json::json!({}), None).await?;
+    let delivery = outcome.map_or(Delivery::Expired, |o| o.delivery);
+    info!(app_id = %app_id, action, delivery = delivery.as_str(), "pause state changed");
+    Ok(Json(AppDetail::new(state, app)))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct CancelRequest {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CancelResponse {
+    /// The cancel control reached the app's socket on this server.
+    delivered: bool,
+    /// `delivered`; `queued` until the app next registers; or `expired`,
+    /// when controls aren't queued (`CONTROL_QUEUE_TTL=0`).
+    delivery: Delivery,
+    /// The queue entry, when not delivered at once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    control: Option<PendingControl>,
+    app: AppDetail,
+}
+
+/// POST /api/v1/apps/{id}/cancel — ask a non-terminal app to stop. The
+/// client decides how; its exit is reported as usual. Without a
+/// connection here the cancel waits for its next registration. Audited
+/// under the `x-trails-actor`.
+async fn cancel_app(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(req): Json<CancelRequest>,
+) -> Result<Json<CancelResponse>, TrailsError> {
+    let actor = actor(&headers)?;
+    let app = db::get_app(&state.db, app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+    let conflict = || {
+        TrailsError::Conflict(format!(
+            "cannot cancel app {app_id}: status is '{}'",
+            app.status
+        ))
+    };
+    if app.status.parse::<AppStatus>().is_ok_and(|s| s.is_terminal()) {
+        return Err(conflict());
+    }
+    let payload = serde_json::json!({
+        "reason": req.reason.as_deref().unwrap_or("admin_request"),
+        "initiated_by": actor,
+    });
+    let outcome = controls::send_or_queue(&state, app_id, "cancel", payload, Some(actor))
+        .await?
+        .ok_or_else(conflict)?;
+    let control_id = outcome.control.as_ref().map(|c| c.id);
+    db::record_cancel(
+        &state.db,
+        app_id,
+        req.reason.as_deref(),
+        outcome.delivery.as_str(),
+        control_id,
+        actor,
+        request_id::current().as_deref(),
+    )
+    .await?;
+    info!(
+        app_id = %app_id,
+        cancelled_by = actor,
+        delivery = outcome.delivery.as_str(),
+        "cancel requested"
+    );
+    Ok(Json(CancelResponse {
+        delivered: outcome.delivery == Delivery::Delivered,
+        delivery: outcome.delivery,
+        control: outcome.control,
+        app: AppDetail::new(&state, app),
+    }))
+}
+
+/// GET /api/v1/apps/{id}/controls — controls queued for the app while it
+/// had no connection, newest first, with what became of them.
+async fn list_controls(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+) -> Result<Json<Vec<PendingControl>>, TrailsError> {
+    db::get_app(&state.db, app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+    let rows = db::list_controls(&state.db, app_id, MAX_PAGE_LIMIT).await?;
+    Ok(Json(rows))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ForceRequest {
+    /// Terminal status to set; default `cancelled`.
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// POST /api/v1/apps/{id}/force — move a stuck app straight to a
+/// terminal status without the client's cooperation, closing its socket
+/// if it is connected here. Audited under the `x-trails-actor`.
+async fn force_app(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(req): Json<ForceRequest>,
+) -> Result<Json<AppDetail>, TrailsError> {
+    let actor = actor(&headers)?;
+    let status = req.status.as_deref().unwrap_or("cancelled");
+    if !status.parse::<AppStatus>().is_ok_and(|s| s.is_terminal()) {
+        return Err(TrailsError::InvalidRequest(format!(
+            "status '{status}' is not a terminal status"
+        )));
+    }
+    let forced = db::force_terminal(
+        &state.db,
+        app_id,
+        status,
+        req.reason.as_deref(),
+        actor,
+        request_id::current().as_deref(),
+    )
+    .await?;
+    let Some((app, previous)) = forced else {
+        return Err(match db::get_app(&state.db, app_id).await? {
+            None => TrailsError::AppNotFound(app_id),
+            Some(app) => TrailsError::InvalidTransition {
+                from: app.status,
+                to: status.into(),
+            },
+        });
+    };
+    state.publish(Event::AppTerminal {
+        app_id,
+        parent_id: app.parent_id,
+        status: status.into(),
+    });
+    ws::close_connection(&state, app_id).await;
+    warn!(
+        app_id = %app_id,
+        forced_by = actor,
+        from = %previous,
+        to = status,
+        "app forced terminal"
+    );
+    Ok(Json(AppDetail::new(&state, app)))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct AdoptRequest {
+    new_parent_id: Uuid,
+}
+
+/// POST /api/v1/apps/{id}/adopt — move an app under another parent.
+/// The new parent must exist, not be terminal, and not be the app or one
+/// of its descendants. The old parent is kept in `adoptedFrom`. Audited
+/// under the `x-trails-actor`.
+async fn adopt_app(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(req): Json<AdoptRequest>,
+) -> Result<Json<AppDetail>, TrailsError> {
+    let actor = actor(&headers)?;
+    let adopted = adopt(&state, db::AdoptTarget::App(app_id), req.new_parent_id, actor).await?;
+    let app = match adopted.into_iter().next() {
+        Some(adoption) => adoption.app,
+        // Already a child of the new parent.
+        None => db::get_app(&state.db, app_id).await?.ok_or(TrailsError::AppNotFound(app_id))?,
+    };
+    Ok(Json(AppDetail::new(&state, app)))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct AdoptChildrenRequest {
+    old_parent_id: Uuid,
+    new_parent_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AdoptChildrenResponse {
+    adopted: Vec<AppDetail>,
+}
+
+/// POST /api/v1/apps/adopt — move every non-terminal child of
+/// `oldParentId` under `newParentId` in one transaction; terminal
+/// children stay where they are. Audited under the `x-trails-actor`.
+async fn adopt_children(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<AdoptChildrenRequest>,
+) -> Result<Json<AdoptChildrenResponse>, TrailsError> {
+    let actor = actor(&headers)?;
+    let target = db::AdoptTarget::ChildrenOf(req.old_parent_id);
+    let adopted = adopt(&state, target, req.new_parent_id, actor).await?;
+    Ok(Json(AdoptChildrenResponse {
+        adopted: adopted
+            .into_iter()
+            .map(|a| AppDetail::new(&state, a.app))
+            .collect(),
+    }))
+}
+
+async fn adopt(
+    state: &AppState,
+    target: db::AdoptTarget,
+    new_parent_id: Uuid,
+    actor: &str,
+) -> Result<Vec<db::Adoption>, TrailsError> {
+    let request_id = request_id::current();
+    let adopted =
+        db::adopt_apps(&state.db, target, new_parent_id, actor, request_id.as_deref()).await?;
+    for a in &adopted {
+        state.publish(Event::AppAdopted {
+            app_id: a.app.app_id,
+            parent_id: Some(new_parent_id),
+            previous_parent_id: a.previous_parent_id,
+        });
+        info!(
+            app_id = %a.app.app_id,
+            adopted_by = actor,
+            from = ?a.previous_parent_id,
+            to = %new_parent_id,
+            "app adopted"
+        );
+    }
+    Ok(adopted)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Schema registry
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PutSchemaRequest {
+    #[serde(default)]
+    status_schema: Option<JsonValue>,
+    #[serde(default)]
+    result_schema: Option<JsonValue>,
+    #[serde(default = "default_schema_mode")]
+    mode: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PatchSchemaRequest {
+    mode: String,
+}
+
+fn default_schema_mode() -> String {
+    "warn".into()
+}
+
+fn validate_mode(mode: &str) -> Result<(), TrailsError> {
+    match mode {
+        "warn" | "enforce" => Ok(()),
+        _ => Err(TrailsError::InvalidRequest(format!(
+            "mode must be 'warn' or 'enforce', got '{mode}'"
+        ))),
+    }
+}
+
+/// GET /api/v1/schemas — latest version of every registered app_name.
+async fn list_schemas(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SchemaRow>>, TrailsError> {
+    Ok(Json(db::list_latest_schemas(&state.db).await?))
+}
+
+/// GET /api/v1/schemas/{app_name} — all versions, newest first.
+async fn list_schema_versions(
+    State(state): State<Arc<AppState>>,
+    Path(app_name): Path<String>,
+) -> Result<Json<Vec<SchemaRow>>, TrailsError> {
+    let rows = db::list_schema_versions(&state.db, &app_name).await?;
+    if rows.is_empty() {
+        return Err(TrailsError::SchemaNotFound(app_name));
+    }
+    Ok(Json(rows))
+}
+
+/// PUT /api/v1/schemas/{app_name} — register or replace the schema.
+/// Replaces the latest version in place until it is referenced by a
+/// validated message; after that a new version is created.
+async fn put_schema(
+    State(state): State<Arc<AppState>>,
+    Path(app_name): Path<String>,
+    Json(req): Json<PutSchemaRequest>,
+) -> Result<Json<SchemaRow>, TrailsError> {
+    validate_mode(&req.mode)?;
+    if req.status_schema.is_none() && req.result_schema.is_none() {
+        return Err(TrailsError::InvalidRequest(
+            "at least one of statusSchema or resultSchema is required".into(),
+        ));
+    }
+    for s in [&req.status_schema, &req.result_schema].into_iter().flatten() {
+        schema::compile(s)?;
+    }
+
+    let row = db::put_schema(
+        &state.db,
+        &app_name,
+        req.status_schema.as_ref(),
+        req.result_schema.as_ref(),
+        &req.mode,
+    )
+    .await?;
+    state.schemas.invalidate(&app_name);
+    Ok(Json(row))
+}
+
+/// GET /api/v1/schemas/{app_name}/versions/{version}
+async fn get_schema_version(
+    State(state): State<Arc<AppState>>,
+    Path((app_name, version)): Path<(String, i32)>,
+) -> Result<Json<SchemaRow>, TrailsError> {
+    db::get_schema(&state.db, &app_name, version)
+        .await?
+        .map(Json)
+        .ok_or_else(|| TrailsError::SchemaNotFound(format!("{app_name} v{version}")))
+}
+
+/// PATCH /api/v1/schemas/{app_name}/versions/{version} — switch mode
+/// between 'warn' and 'enforce'.
+async fn patch_schema_version(
+    State(state): State<Arc<AppState>>,
+    Path((app_name, version)): Path<(String, i32)>,
+    Json(req): Json<PatchSchemaRequest>,
+) -> Result<Json<SchemaRow>, TrailsError> {
+    validate_mode(&req.mode)?;
+    let row = db::set_schema_mode(&state.db, &app_name, version, &req.mode)
+        .await?
+        .ok_or_else(|| TrailsError::SchemaNotFound(format!("{app_name} v{version}")))?;
+    state.schemas.invalidate(&app_name);
+    Ok(Json(row))
+}
+
+/// GET /api/v1/schemas/{app_name}/stats — pass/warn/fail counts per version.
+async fn get_schema_stats(
+    State(state): State<Arc<AppState>>,
+    Path(app_name): Path<String>,
+) -> Result<Json<Vec<SchemaStatsRow>>, TrailsError> {
+    Ok(Json(db::get_schema_stats(&state.db, &app_name).await?))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Messages
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+struct MessageQuery {
+    #[serde(default)]
+    msg_type: Option<String>,
+    /// Return messages with seq above this (pagination cursor).
+    #[serde(default)]
+    after_seq: Option<i64>,
+    #[serde(default)]
+    limit: Option<i64>,
+    /// Fetch offloaded payloads from the blob store and inline them.
+    #[serde(default)]
+    resolve: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MessageResponse {
+    id: i64,
+    app_id: Uuid,
+    direction: String,
+    msg_type: String,
+    seq: i64,
+    correlation_id: Option<String>,
+    /// Inline payload; null for offloaded payloads unless `resolve=true`.
+    payload: Option<JsonValue>,
+    /// Set when the payload lives in the blob store.
+    blob: Option<BlobRef>,
+    /// The payload is a sealed envelope; only the recipient can read it.
+    sealed: bool,
+    /// Values masked or dropped from the payload by redaction rules
+    /// before it was stored.
+    redactions_applied: i32,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<MessageRow> for MessageResponse {
+    fn from(row: MessageRow) -> Self {
+        let blob = match (row.blob_url, row.blob_key) {
+            (Some(url), Some(key)) => Some(BlobRef {
+                url,
+                key,
+                size: row.blob_size.unwrap_or_default(),
+                sha256: row.blob_sha256.unwrap_or_default(),
+                preview: row.payload_preview.unwrap_or_default(),
+            }),
+            _ => None,
+        };
+        Self {
+            id: row.id,
+            app_id: row.app_id,
+            direction: row.direction,
+            msg_type: row.msg_type,
+            seq: row.seq,
+            correlation_id: row.correlation_id,
+            payload: row.payload_json,
+            blob,
+            sealed: row.sealed,
+            redactions_applied: row.redactions_applied,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// GET /api/v1/apps/{id}/messages — messages in seq order. Filters:
+/// `msg_type`, `after_seq`, `limit`; `resolve=true` inlines offloaded
+/// payloads.
+async fn list_messages(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<MessageQuery>,
+) -> Result<Json<Vec<MessageResponse>>, TrailsError> {
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let rows = db::list_messages(&state.db, app_id, q.msg_type.as_deref(), q.after_seq, limit).await?;
+
+    let mut messages: Vec<MessageResponse> = rows.into_iter().map(Into::into).collect();
+    if q.resolve {
+        resolve_blobs(&state, &mut messages).await?;
+    }
+    Ok(Json(messages))
+}
+
+/// Inline offloaded payloads from the blob store.
+async fn resolve_blobs(
+    state: &AppState,
+    messages: &mut [MessageResponse],
+) -> Result<(), TrailsError> {
+    for msg in messages {
+        if let Some(b) = &msg.blob {
+            msg.payload = Some(blob::resolve(state, &b.key).await?);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    msg_type: Option<String>,
+    /// Messages stored at or after this time.
+    #[serde(default)]
+    from: Option<DateTime<Utc>>,
+    /// Messages stored before this time.
+    #[serde(default)]
+    to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    resolve: bool,
+}
+
+/// GET /api/v1/apps/{id}/messages/export — every stored message as
+/// NDJSON in seq order, streamed, ending with a trailer line (see
+/// `export`). Filters: `msg_type`, `from`, `to`; `resolve=true` inlines
+/// offloaded payloads. Gzipped for `Accept-Encoding: gzip`.
+async fn export_messages(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<ExportQuery>,
+) -> Result<Response, TrailsError> {
+    if let (Some(from), Some(to)) = (q.from, q.to) {
+        if from >= to {
+            return Err(TrailsError::InvalidRequest("'from' must be before 'to'".into()));
+        }
+    }
+    if db::get_app(&state.db, app_id).await?.is_none() {
+        return Err(TrailsError::AppNotFound(app_id));
+    }
+    let filter = db::ExportFilter {
+        msg_type: q.msg_type,
+        from: q.from,
+        to: q.to,
+    };
+    let resolve = q.resolve;
+    let fetch = move |after, limit| {
+        let state = Arc::clone(&state);
+        let filter = filter.clone();
+        async move {
+            let rows = db::export_messages(&state.db, app_id, &filter, after, limit).await?;
+            let mut messages: Vec<MessageResponse> = rows.into_iter().map(Into::into).collect();
+            if resolve {
+                resolve_blobs(&state, &mut messages).await?;
+            }
+            Ok(messages)
+        }
+    };
+    let body = export::ndjson(fetch, |m: &MessageResponse| (m.seq, m.id));
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        axum::body::Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// POST /api/v1/messages/query — messages across apps by time range,
+/// type, correlation id and payload predicates (grammar in `query`).
+async fn query_messages(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<MessageQueryRequest>,
+) -> Result<Json<MessagePage>, TrailsError> {
+    let filter = req.validate(state.config().message_query_max_span_secs)?;
+    Ok(Json(query::run(&state, &filter).await?))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Traces
+// ═══════════════════════════════════════════════════════════════
+
+/// Most apps summarized in one trace.
+const TRACE_MAX_APPS: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+struct TraceMessagesQuery {
+    /// Return messages with id above this (pagination cursor).
+    #[serde(default)]
+    after: Option<i64>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace {
+    correlation_id: String,
+    /// Apps in timeline order; `parentId` gives the relationships.
+    apps: Vec<TraceAppRow>,
+    /// More than `TRACE_MAX_APPS` apps carried the id.
+    apps_truncated: bool,
+    /// First page of the id's messages; continue with
+    /// `/traces/{id}/messages?after=nextAfter`.
+    #[serde(flatten)]
+    messages: MessagePage,
+}
+
+/// GET /api/v1/traces/{correlation_id} — every app that carried the id,
+/// summarized, then the first page of its messages as one timeline.
+async fn get_trace(
+    State(state): State<Arc<AppState>>,
+    Path(correlation_id): Path<String>,
+    Query(q): Query<TraceMessagesQuery>,
+) -> Result<Json<Trace>, TrailsError> {
+    let mut apps = db::trace_apps(&state.db, &correlation_id, TRACE_MAX_APPS + 1).await?;
+    if apps.is_empty() {
+        return Err(TrailsError::TraceNotFound(correlation_id));
+    }
+    let apps_truncated = apps.len() as i64 > TRACE_MAX_APPS;
+    apps.truncate(TRACE_MAX_APPS as usize);
+    let messages = trace_page(&state, &correlation_id, &q).await?;
+    Ok(Json(Trace {
+        correlation_id,
+        apps,
+        apps_truncated,
+        messages,
+    }))
+}
+
+/// GET /api/v1/traces/{correlation_id}/messages — further pages of a
+/// trace's messages. Filters: `after`, `limit`.
+async fn list_trace_messages(
+    State(state): State<Arc<AppState>>,
+    Path(correlation_id): Path<String>,
+    Query(q): Query<TraceMessagesQuery>,
+) -> Result<Json<MessagePage>, TrailsError> {
+    Ok(Json(trace_page(&state, &correlation_id, &q).await?))
+}
+
+async fn trace_page(
+    state: &AppState,
+    correlation_id: &str,
+    q: &TraceMessagesQuery,
+) -> Result<MessagePage, TrailsError> {
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let rows = db::trace_messages(&state.db, correlation_id, q.after, limit + 1).await?;
+    Ok(MessagePage::new(rows, limit))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// App metrics
+// ═══════════════════════════════════════════════════════════════
+
+/// Default metrics window, ending now.
+const METRICS_DEFAULT_WINDOW_SECS: i64 = 3600;
+/// Buckets per series when no step is given, and the most allowed.
+const METRICS_DEFAULT_POINTS: i64 = 300;
+const METRICS_MAX_POINTS: i64 = 10_000;
+
+#[derive(Debug, Deserialize)]
+struct MetricsQuery {
+    metric: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    /// Bucket width in seconds.
+    step: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MetricsResponse {
+    app_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    step: i64,
+    series: Vec<app_metrics::Series>,
+}
+
+/// GET /api/v1/apps/{id}/metrics?metric=&from=&to=&step= — the metrics
+/// extracted from the app's Status payloads, in `step`-second buckets.
+/// Defaults to the last hour in 300 buckets.
+async fn get_app_metrics(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<MetricsQuery>,
+) -> Result<Json<MetricsResponse>, TrailsError> {
+    let to = q.to.unwrap_or_else(Utc::now);
+    let from = q
+        .from
+        .unwrap_or(to - chrono::Duration::seconds(METRICS_DEFAULT_WINDOW_SECS));
+    if from >= to {
+        return Err(TrailsError::InvalidRequest("from must be before to".into()));
+    }
+    let span = (to - from).num_seconds().max(1);
+    let step = q.step.unwrap_or((span / METRICS_DEFAULT_POINTS).max(1));
+    if step < 1 {
+        return Err(TrailsError::InvalidRequest("step must be at least 1".into()));
+    }
+    if span / step + i64::from(span % step != 0) > METRICS_MAX_POINTS {
+        return Err(TrailsError::InvalidRequest(format!(
+            "at most {METRICS_MAX_POINTS} points per series; widen step"
+        )));
+    }
+    db::get_app(&state.db, app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+
+    let metric = q.metric.as_deref().filter(|m| !m.is_empty());
+    let points = db::metric_points(&state.db, app_id, metric, from, to, step).await?;
+    Ok(Json(MetricsResponse {
+        app_id,
+        from,
+        to,
+        step,
+        series: app_metrics::series(points),
+    }))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Logs
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+struct LogQuery {
+    /// Lines logged at or after this time.
+    #[serde(default)]
+    since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+/// GET /api/v1/apps/{id}/logs?since=&limit= — the log lines the app sent,
+/// oldest first.
+async fn list_logs(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<LogQuery>,
+) -> Result<Json<Vec<LogRow>>, TrailsError> {
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let rows = db::get_logs(&state.db, app_id, q.since, limit).await?;
+    Ok(Json(rows))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Snapshots
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+struct SnapshotDiffQuery {
+    from_seq: i64,
+    to_seq: i64,
+    /// `json-patch` (RFC 6902, default) or `merge-patch` (RFC 7386).
+    #[serde(default)]
+    format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotDiff {
+    app_id: Uuid,
+    from_seq: i64,
+    to_seq: i64,
+    format: &'static str,
+    /// A JSON Patch operation array, or a merge patch document.
+    patch: JsonValue,
+}
+
+/// GET /api/v1/apps/{id}/snapshots/latest — the app's newest snapshot,
+/// e.g. for a restarted worker to resume from; 404 if it has none.
+async fn get_latest_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+) -> Result<Json<db::SnapshotRow>, TrailsError> {
+    if db::get_app(&state.db, app_id).await?.is_none() {
+        return Err(TrailsError::AppNotFound(app_id));
+    }
+    let snapshot = db::get_latest_snapshot(&state.db, app_id).await?;
+    snapshot
+        .map(Json)
+        .ok_or_else(|| TrailsError::SnapshotNotFound(format!("no snapshot of {app_id} yet")))
+}
+
+/// GET /api/v1/apps/{id}/snapshots/diff?from_seq=&to_seq= — what changed
+/// between two stored snapshots, computed server-side.
+async fn diff_snapshots(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<SnapshotDiffQuery>,
+) -> Result<Json<SnapshotDiff>, TrailsError> {
+    let format = match q.format.as_deref().unwrap_or("json-patch") {
+        "json-patch" => "json-patch",
+        "merge-patch" => "merge-patch",
+        other => {
+            return Err(TrailsError::InvalidRequest(format!(
+                "format must be 'json-patch' or 'merge-patch', got '{other}'"
+            )))
+        }
+    };
+    let app = db::get_app(&state.db, app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+    if app.sec_level == "sealed" {
+        return Err(TrailsError::InvalidRequest(
+            "snapshots of sealed apps are opaque envelopes and can't be diffed".into(),
+        ));
+    }
+
+    let from = db::get_snapshot(&state.db, app_id, q.from_seq).await?;
+    let to = db::get_snapshot(&state.db, app_id, q.to_seq).await?;
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) => (from, to),
+        (from, to) => {
+            let missing: Vec<String> = [("from_seq", q.from_seq, from), ("to_seq", q.to_seq, to)]
+                .into_iter()
+                .filter(|(_, _, snapshot)| snapshot.is_none())
+                .map(|(name, seq, _)| format!("{name}={seq}"))
+                .collect();
+            return Err(TrailsError::SnapshotNotFound(missing.join(", ")));
+        }
+    };
+
+    let config = state.config();
+    let patch = if format == "merge-patch" {
+        diff::merge_patch(&from, &to).map_err(|path| {
+            TrailsError::InvalidRequest(format!(
+                "merge patch can't set {path} to null; use format=json-patch"
+            ))
+        })?
+    } else {
+        serde_json::to_value(diff::json_patch(&from, &to, config.snapshot_diff_array_max))
+            .map_err(|e| TrailsError::InvalidJson(e.to_string()))?
+    };
+
+    let size = serde_json::to_vec(&patch).map(|b| b.len()).unwrap_or(0);
+    let limit = config.snapshot_diff_max_bytes;
+    if size > limit {
+        return Err(TrailsError::DiffTooLarge { size, limit });
+    }
+    Ok(Json(SnapshotDiff {
+        app_id,
+        from_seq: q.from_seq,
+        to_seq: q.to_seq,
+        format,
+        patch,
+    }))
+}
+
+/// Default page for `/snapshots/latest`; wallboards usually want it all.
+const LATEST_DEFAULT_LIMIT: i64 = 1000;
+const LATEST_MAX_LIMIT: i64 = 5000;
+/// Most JSON Pointers accepted in `fields`.
+const LATEST_MAX_FIELDS: usize = 32;
+
+#[derive(Debug, Deserialize)]
+struct LatestSnapshotsQuery {
+    /// Comma-separated statuses; default `connected,running`.
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+    /// Comma-separated JSON Pointers to keep, e.g. `/progress,/phase`.
+    #[serde(default)]
+    fields: Option<String>,
+    /// Return apps with app_id above this (pagination cursor).
+    #[serde(default)]
+    after: Option<Uuid>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+/// GET /api/v1/snapshots/latest — latest snapshot of every matching app in
+/// one response. Filters: `status`, `namespace`; `fields` trims snapshots.
+async fn latest_snapshots(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<LatestSnapshotsQuery>,
+) -> Result<impl IntoResponse, TrailsError> {
+    let statuses: Vec<String> = q
+        .status
+        .as_deref()
+        .unwrap_or("connected,running")
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .collect();
+    for status in &statuses {
+        status
+            .parse::<AppStatus>()
+            .map_err(|_| TrailsError::InvalidRequest(format!("unknown status '{status}'")))?;
+    }
+    let fields = match q.fields.as_deref() {
+        None => None,
+        Some(fields) => {
+            let fields: Vec<String> = fields.split(',').map(|f| f.trim().to_string()).collect();
+            if fields.len() > LATEST_MAX_FIELDS {
+                return Err(TrailsError::InvalidRequest(format!(
+                    "at most {LATEST_MAX_FIELDS} fields"
+                )));
+            }
+            if let Some(bad) = fields.iter().find(|f| !f.starts_with('/')) {
+                return Err(TrailsError::InvalidRequest(format!(
+                    "field '{bad}' is not a JSON Pointer (e.g. /progress)"
+                )));
+            }
+            Some(fields)
+        }
+    };
+    let query = latest::LatestQuery {
+        statuses,
+        namespace: q.namespace,
+        fields,
+        after: q.after,
+        limit: q.limit.unwrap_or(LATEST_DEFAULT_LIMIT).clamp(1, LATEST_MAX_LIMIT),
+    };
+    let body = latest::page(&state, &query).await?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Dead letters
+// ═══════════════════════════════════════════════════════════════
+
+const DEFAULT_PAGE_LIMIT: i64 = 100;
+const MAX_PAGE_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+struct DeadLetterQuery {
+    #[serde(default)]
+    app_id: Option<Uuid>,
+    #[serde(default)]
+    reason: Option<String>,
+    /// Return entries with id below this (pagination cursor).
+    #[serde(default)]
+    before: Option<i64>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+impl DeadLetterQuery {
+    fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT)
+    }
+}
+
+/// GET /api/v1/apps/{id}/dead-letters — rejected frames for one app,
+/// newest first. Filters: `reason`, `before`, `limit`.
+async fn list_app_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<DeadLetterQuery>,
+) -> Result<Json<Vec<DeadLetterRow>>, TrailsError> {
+    let rows = db::list_dead_letters(
+        &state.db,
+        Some(app_id),
+        q.reason.as_deref(),
+        q.before,
+        q.limit(),
+    )
+    .await?;
+    Ok(Json(rows))
+}
+
+/// GET /api/v1/dead-letters — rejected frames across all apps, newest
+/// first. Filters: `app_id`, `reason`, `before`, `limit`.
+async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<DeadLetterQuery>,
+) -> Result<Json<Vec<DeadLetterRow>>, TrailsError> {
+    let rows = db::list_dead_letters(
+        &state.db,
+        q.app_id,
+        q.reason.as_deref(),
+        q.before,
+        q.limit(),
+    )
+    .await?;
+    Ok(Json(rows))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Crash loops
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+struct CrashLoopQuery {
+    #[serde(default)]
+    namespace: Option<String>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+/// GET /api/v1/crash-loops — active crash loops fleet-wide, most recent
+/// crash first. Filters: `namespace`, `limit`.
+async fn list_crash_loops(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<CrashLoopQuery>,
+) -> Result<Json<Vec<CrashLoopRow>>, TrailsError> {
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let rows = db::list_crash_loops(
+        &state.db,
+        state.config().crash_loop_window,
+        q.namespace.as_deref(),
+        limit,
+    )
+    .await?;
+    Ok(Json(rows))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Admin — data purge
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+struct PurgeQuery {
+    /// Also purge every descendant.
+    #[serde(default)]
+    cascade: bool,
+}
+
+/// Header naming who requested an admin change (purge, storage quota);
+/// recorded in the audit log. Mandatory until the API has authentication.
+const ACTOR_HEADER: &str = "x-trails-actor";
+
+/// Admin routes need the configured `ADMIN_TOKEN` as a bearer token, and
+/// are off while it is unset: `x-trails-actor` names the requester but
+/// anyone can send it.
+fn admin(state: &AppState, headers: &HeaderMap) -> Result<(), TrailsError> {
+    let Some(expected) = state.config().admin_token.clone() else {
+        return Err(TrailsError::Forbidden("admin API disabled; set ADMIN_TOKEN".into()));
+    };
+    let credentials = observe::Credentials::from_headers(headers);
+    match credentials.token() {
+        Some(token) if observe::constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        Some(_) => Err(TrailsError::Unauthorized("wrong admin token".into())),
+        None => Err(TrailsError::Unauthorized("admin token required".into())),
+    }
+}
+
+fn actor(headers: &HeaderMap) -> Result<&str, TrailsError> {
+    headers
+        .get(ACTOR_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| TrailsError::InvalidRequest(format!("{ACTOR_HEADER} header is required")))
+}
+
+/// DELETE /api/v1/apps/{id}/data — erase an app's stored data (and its
+/// descendants' with `?cascade=true`), keeping a tombstone. Repeating the
+/// request resumes an interrupted purge or returns the finished report.
+async fn purge_app_data(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<PurgeQuery>,
+    headers: HeaderMap,
+) -> Result<Json<PurgeReport>, TrailsError> {
+    admin(&state, &headers)?;
+    let actor = actor(&headers)?;
+    let report = purge::purge(&state, app_id, q.cascade, actor).await?;
+    info!(app_id = %app_id, purged_by = actor, complete = report.complete, "purge requested");
+    Ok(Json(report))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Admin — storage quotas
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct StorageQuotaPatch {
+    /// `null` clears the override (configured limit applies).
+    #[serde(default, deserialize_with = "present")]
+    soft_limit_bytes: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "present")]
+    hard_limit_bytes: Option<Option<i64>>,
+    /// Count usage from now on, and re-arm the quota warning.
+    #[serde(default)]
+    reset: bool,
+}
+
+/// PATCH /api/v1/apps/{id}/storage-quota — override an app's storage
+/// limits and/or reset its usage. Audited under the `x-trails-actor`.
+async fn patch_storage_quota(
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(req): Json<StorageQuotaPatch>,
+) -> Result<Json<AppDetail>, TrailsError> {
+    admin(&state, &headers)?;
+    let actor = actor(&headers)?;
+    if req.soft_limit_bytes.is_none() && req.hard_limit_bytes.is_none() && !req.reset {
+        return Err(TrailsError::InvalidRequest("no updatable fields given".into()));
+    }
+    for (field, value) in [
+        ("softLimitBytes", req.soft_limit_bytes.flatten()),
+        ("hardLimitBytes", req.hard_limit_bytes.flatten()),
+    ] {
+        if value.is_some_and(|v| v <= 0) {
+            return Err(TrailsError::InvalidRequest(format!("{field} must be positive")));
+        }
+    }
+    let app = db::set_storage_quota(
+        &state.db,
+        app_id,
+        req.soft_limit_bytes,
+        req.hard_limit_bytes,
+        req.reset,
+        actor,
+        request_id::current().as_deref(),
+    )
+    .await?
+    .ok_or(TrailsError::AppNotFound(app_id))?;
+    info!(
+        app_id = %app_id,
+        changed_by = actor,
+        soft = ?app.quota_soft_bytes,
+        hard = ?app.quota_hard_bytes,
+        reset = req.reset,
+        "storage quota changed"
+    );
+    Ok(Json(AppDetail::new(&state, app)))
+}
+
+#[derive(Debug, Deserialize)]
+struct StorageStatsQuery {
+    #[serde(default)]
+    namespace: Option<String>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageConsumer {
+    app_id: Uuid,
+    app_name: String,
+    namespace: Option<String>,
+    status: String,
+    stored_bytes: i64,
+    quota_warned_at: Option<DateTime<Utc>>,
+    effective_storage_quota: QuotaLimits,
+}
+
+/// GET /api/v1/stats/storage — apps storing the most data, largest
+/// first. Filters: `namespace`, `limit`.
+async fn storage_stats(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<StorageStatsQuery>,
+) -> Result<Json<Vec<StorageConsumer>>, TrailsError> {
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let rows = db::top_storage_consumers(&state.db, q.namespace.as_deref(), limit).await?;
+    let config = state.config();
+    let consumers = rows
+        .into_iter()
+        .map(|app| StorageConsumer {
+            effective_storage_quota: quota::limits(&config, &app),
+            app_id: app.app_id,
+            app_name: app.app_name,
+            namespace: app.namespace,
+            status: app.status,
+            stored_bytes: app.stored_bytes,
+            quota_warned_at: app.quota_warned_at,
+        })
+        .collect();
+    Ok(Json(consumers))
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Version
+// ═══════════════════════════════════════════════════════════════
+
+/// GET /api/v1/version — the build serving this request.
+async fn get_version() -> Json<VersionInfo> {
+    Json(version::info())
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Admin — drain mode
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DrainStatus {
+    draining: bool,
+    /// WebSocket connections still served by this instance.
+    live_connections: usize,
+}
+
+fn drain_status_of(state: &AppState) -> Json<DrainStatus> {
+    Json(DrainStatus {
+        draining: state.is_draining(),
+        live_connections: state.connections.len(),
+    })
+}
+
+/// GET /api/v1/admin/drain — deploy tooling polls this until
+/// `liveConnections` reaches zero.
+async fn drain_status(State(state): State<Arc<AppState>>) -> Json<DrainStatus> {
+    drain_status_of(&state)
+}
+
+/// POST /api/v1/admin/drain — stop accepting new registrations.
+async fn start_drain(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<DrainStatus>, TrailsError> {
+    admin(&state, &headers)?;
+    if !state.set_draining(true) {
+        info!(live = state.connections.len(), "drain requested via admin API");
+    }
+    Ok(drain_status_of(&state))
+}
+
+/// DELETE /api/v1/admin/drain — resume accepting registrations.
+async fn stop_drain(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<DrainStatus>, TrailsError> {
+    admin(&state, &headers)?;
+    if state.set_draining(false) {
+        info!("drain cancelled via admin API");
+    }
+    Ok(drain_status_of(&state))
+}
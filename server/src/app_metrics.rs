@@ -0,0 +1,270 @@
+//! Numeric series extracted from Status payloads, and reported in Metric
+//! messages.
+//!
+//! Rules name a metric and the JSON pointer its value is read from: the
+//! `METRIC_RULES` for the app's name, then the app's `metrics` tag, at
+//! most `METRICS_MAX_PER_APP` of them (config rules first, one rule per
+//! name). Every stored Status yields one `app_metrics` row per rule whose
+//! value is a JSON number; missing and non-numeric values are skipped.
+//! Values are read after redaction. Sealed payloads, and Statuses the
+//! storage quota only coalesces, yield none.
+//!
+//! A Metric message reports values itself, `{"gauges": {...}, "counters":
+//! {...}}`, counters as running totals; every number in either is a row,
+//! at most `METRICS_MAX_PER_APP` of them, gauges first.
+//!
+//! Cost model: an app without rules costs one hash lookup per Status. An
+//! app with rules adds one multi-row INSERT per Status that has values —
+//! at most `METRICS_MAX_PER_APP` rows of roughly 80 bytes, plus an index
+//! entry each. The tag is read once per connection, not per message. Rows
+//! older than `METRICS_RETENTION` are pruned by the retention task. A
+//! series query reads the app's rows in its window once and folds them
+//! into `step`-second buckets. With `METRICS_PROMETHEUS` every app and
+//! metric is one gauge until the app disconnects, so the exposition grows
+//! with connected apps × rules.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::config::{AppMetricsConfig, MetricRule};
+use crate::db::{self, MetricPoint};
+use crate::error::TrailsError;
+use crate::state::AppState;
+
+/// Prometheus gauge holding each app's latest values.
+const GAUGE: &str = "trails_app_metric";
+
+/// Rules from an app's `metrics` tag (`{"metric": "/pointer"}`); invalid
+/// entries are skipped.
+pub fn tag_rules(tag: Option<&JsonValue>) -> Vec<MetricRule> {
+    let Some(JsonValue::Object(map)) = tag else {
+        return Vec::new();
+    };
+    map.iter()
+        .filter_map(|(name, pointer)| {
+            let rule = pointer.as_str().and_then(|p| MetricRule::new(name, p));
+            if rule.is_none() {
+                debug!(metric = %name, "ignoring invalid metrics tag entry");
+            }
+            rule
+        })
+        .collect()
+}
+
+/// Load the rules of an app's `metrics` tag.
+pub async fn load_tag_rules(
+    state: &AppState,
+    app_id: Uuid,
+) -> Result<Arc<[MetricRule]>, TrailsError> {
+    let tag = db::app_metrics_tag(&state.db, app_id).await?;
+    Ok(tag_rules(tag.as_ref()).into())
+}
+
+/// The rules that apply to an app: its name's, then its tag's, capped.
+pub fn rules<'a>(
+    config: &'a AppMetricsConfig,
+    app_name: &str,
+    tag_rules: &'a [MetricRule],
+) -> Vec<&'a MetricRule> {
+    let mut seen = HashSet::new();
+    config
+        .app_rules
+        .get(app_name)
+        .into_iter()
+        .flatten()
+        .chain(tag_rules)
+        .filter(|rule| seen.insert(rule.name.as_str()))
+        .take(config.max_per_app)
+        .collect()
+}
+
+/// The numeric values `rules` point at in `payload`.
+pub fn extract<'a>(rules: &[&'a MetricRule], payload: &JsonValue) -> Vec<(&'a str, f64)> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let value = payload.pointer(&rule.pointer)?.as_f64()?;
+            Some((rule.name.as_str(), value))
+        })
+        .collect()
+}
+
+/// The numeric values a Metric message reports, at most `max` of them,
+/// one per name; invalid names are skipped, as in rules.
+pub fn reported(payload: &JsonValue, max: usize) -> Vec<(&str, f64)> {
+    let mut seen = HashSet::new();
+    ["gauges", "counters"]
+        .into_iter()
+        .filter_map(|kind| payload.get(kind)?.as_object())
+        .flatten()
+        .filter_map(|(name, value)| Some((name.as_str(), value.as_f64()?)))
+        .filter(|(name, _)| MetricRule::valid_name(name) && seen.insert(*name))
+        .take(max)
+        .collect()
+}
+
+/// Extract and store the metrics of one Status.
+pub async fn record(
+    state: &AppState,
+    app_id: Uuid,
+    app_name: &str,
+    tag_rules: &[MetricRule],
+    seq: i64,
+    payload: &JsonValue,
+) -> Result<(), TrailsError> {
+    let config = state.config();
+    let rules = rules(&config.app_metrics, app_name, tag_rules);
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let values = extract(&rules, payload);
+    store(state, app_id, app_name, seq, &values).await
+}
+
+/// Store the metrics a Metric message reports.
+pub async fn record_reported(
+    state: &AppState,
+    app_id: Uuid,
+    app_name: &str,
+    seq: i64,
+    payload: &JsonValue,
+) -> Result<(), TrailsError> {
+    let values = reported(payload, state.config().app_metrics.max_per_app);
+    store(state, app_id, app_name, seq, &values).await
+}
+
+/// Store the values of message `seq`, and set their gauges.
+async fn store(
+    state: &AppState,
+    app_id: Uuid,
+    app_name: &str,
+    seq: i64,
+    values: &[(&str, f64)],
+) -> Result<(), TrailsError> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let config = state.config();
+    db::store_metrics(&state.db, app_id, seq, values).await?;
+    if config.app_metrics.prometheus {
+        let app = app_id.to_string();
+        for (metric, value) in values {
+            let labels = [
+                ("app_id", app.as_str()),
+                ("app_name", app_name),
+                ("metric", metric),
+            ];
+            state.metrics.set(GAUGE, &labels, *value);
+        }
+    }
+    Ok(())
+}
+
+/// Drop an app's Prometheus gauges.
+pub fn forget(state: &AppState, app_id: Uuid) {
+    state
+        .metrics
+        .remove_labelled(GAUGE, "app_id", &app_id.to_string());
+}
+
+/// One metric's buckets.
+#[derive(Debug, Serialize)]
+pub struct Series {
+    pub metric: String,
+    pub points: Vec<MetricPoint>,
+}
+
+/// Group points ordered by metric into one series per metric.
+pub fn series(points: Vec<MetricPoint>) -> Vec<Series> {
+    let mut out: Vec<Series> = Vec::new();
+    for point in points {
+        match out.last_mut() {
+            Some(last) if last.metric == point.metric => last.points.push(point),
+            _ => out.push(Series {
+                metric: point.metric.clone(),
+                points: vec![point],
+            }),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn rule(name: &str, pointer: &str) -> MetricRule {
+        MetricRule::new(name, pointer).unwrap()
+    }
+
+    #[test]
+    fn test_extract_skips_missing_and_non_numeric() {
+        let rules = [
+            rule("rps", "/stats/rps"),
+            rule("depth", "/queues/0/depth"),
+            rule("label", "/stats/label"),
+            rule("quoted", "/stats/quoted"),
+            rule("absent", "/nope"),
+            rule("slash", "/a~1b"),
+        ];
+        let payload = json!({
+            "stats": {"rps": 1250.5, "label": "fast", "quoted": "12"},
+            "queues": [{"depth": 7}],
+            "a/b": -1,
+        });
+        let refs: Vec<&MetricRule> = rules.iter().collect();
+        assert_eq!(
+            extract(&refs, &payload),
+            vec![("rps", 1250.5), ("depth", 7.0), ("slash", -1.0)]
+        );
+    }
+
+    #[test]
+    fn test_rules_prefer_config_and_are_capped() {
+        let config = AppMetricsConfig {
+            app_rules: HashMap::from([(
+                "etl".to_string(),
+                vec![rule("rps", "/rps"), rule("depth", "/depth")],
+            )]),
+            max_per_app: 3,
+            retention: 86_400,
+            prometheus: false,
+        };
+        let tag = tag_rules(Some(&json!({
+            "rps": "/other",
+            "lag": "/lag",
+            "bad name": "/x",
+            "no_pointer": 3,
+            "extra": "/extra",
+        })));
+        let pointers: Vec<&str> = rules(&config, "etl", &tag)
+            .iter()
+            .map(|r| r.pointer.as_str())
+            .collect();
+        assert_eq!(pointers, ["/rps", "/depth", "/extra"]);
+        assert!(rules(&config, "other", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_reported_reads_gauges_then_counters() {
+        let payload = json!({
+            "gauges": {"rows_per_sec": 1234.5, "label": "fast", "bad name": 1},
+            "counters": {"bytes_read": 4096, "rows_per_sec": 7},
+        });
+        assert_eq!(
+            reported(&payload, 20),
+            vec![("rows_per_sec", 1234.5), ("bytes_read", 4096.0)]
+        );
+        assert_eq!(reported(&payload, 1), vec![("rows_per_sec", 1234.5)]);
+        assert!(reported(&json!({"rows_per_sec": 3}), 20).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+//! Large-payload offload to S3-compatible object storage.
+//!
+//! Payloads above `blob_threshold` bytes are written to the bucket under
+//! `{app_id}/{seq}` and the messages row keeps a reference (key, size,
+//! sha256) plus a short preview. If the store is unreachable the payload is
+//! stored inline instead, up to `blob_inline_cap` bytes.
+//!
+//! A blob lives as long as its row. Message rows only go away in a purge
+//! (see `purge`), which deletes their blobs first; retention prunes no
+//! messages and a quota reset only zeroes the counter. A blob whose row
+//! was never written is deleted again right away (`discard`).
+//!
+//! The S3 client is behind the `blob-store` cargo feature. Without it,
+//! `BlobStore` is uninhabited and every payload is stored inline.
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::db;
+use crate::error::TrailsError;
+use crate::state::AppState;
+
+/// Reference to a payload held in the object store.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobRef {
+    /// `s3://{bucket}/{key}`.
+    pub url: String,
+    pub key: String,
+    pub size: i64,
+    pub sha256: String,
+    /// First `blob_preview_bytes` of the serialized payload.
+    pub preview: String,
+}
+
+/// Offload `payload` if it exceeds the threshold and a store is configured.
+/// Returns `None` when the payload should be stored inline.
+pub async fn offload(
+    state: &AppState,
+    app_id: Uuid,
+    seq: i64,
+    payload: &JsonValue,
+) -> Result<Option<BlobRef>, TrailsError> {
+    let cfg = state.config();
+    let Some(store) = &state.blobs else {
+        return Ok(None);
+    };
+    let bytes = serde_json::to_vec(payload).map_err(|e| TrailsError::InvalidJson(e.to_string()))?;
+    if bytes.len() <= cfg.blob_threshold {
+        return Ok(None);
+    }
+
+    let key = format!("{app_id}/{seq}");
+    match store.put(&key, bytes.clone()).await {
+        Ok(()) => {
+            state.metrics.inc("trails_blob_offloaded_total", &[]);
+            Ok(Some(BlobRef {
+                url: store.url(&key),
+                key,
+                size: bytes.len() as i64,
+                sha256: format!("{:x}", Sha256::digest(&bytes)),
+                preview: preview(&bytes, cfg.blob_preview_bytes),
+            }))
+        }
+        Err(e) if bytes.len() <= cfg.blob_inline_cap => {
+            warn!(app_id = %app_id, seq, "blob put failed, storing inline: {e}");
+            state.metrics.inc("trails_blob_fallback_total", &[]);
+            Ok(None)
+        }
+        Err(e) => {
+            warn!(app_id = %app_id, seq, "blob put failed and payload exceeds inline cap: {e}");
+            Err(TrailsError::PayloadTooLarge {
+                size: bytes.len(),
+                limit: cfg.blob_inline_cap,
+            })
+        }
+    }
+}
+
+/// Delete a blob `offload` wrote for a message whose row then wasn't
+/// stored — unless a racing resend stored that seq, in which case its row
+/// refers to the same key. Best effort: on failure the blob stays.
+pub async fn discard(state: &AppState, app_id: Uuid, seq: i64, blob: &BlobRef) {
+    let Some(store) = &state.blobs else {
+        return;
+    };
+    match db::message_seq_exists(&state.db, app_id, seq).await {
+        Ok(false) => {}
+        Ok(true) => return,
+        Err(e) => {
+            warn!(app_id = %app_id, seq, key = %blob.key, "blob left behind: {e}");
+            return;
+        }
+    }
+    if let Err(e) = store.delete(&blob.key).await {
+        warn!(app_id = %app_id, seq, key = %blob.key, "blob left behind: {e}");
+    }
+}
+
+/// Fetch an offloaded payload and parse it back into JSON.
+pub async fn resolve(state: &AppState, key: &str) -> Result<JsonValue, TrailsError> {
+    let store = state
+        .blobs
+        .as_ref()
+        .ok_or_else(|| TrailsError::BlobStore("blob store not configured".into()))?;
+    let bytes = store.get(key).await.map_err(TrailsError::BlobStore)?;
+    serde_json::from_slice(&bytes).map_err(|e| TrailsError::BlobStore(format!("{key}: {e}")))
+}
+
+/// Leading bytes of the payload, cut on a char boundary.
+fn preview(bytes: &[u8], max: usize) -> String {
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(max)]);
+    text.trim_end_matches('\u{FFFD}').to_string()
+}
+
+// ═══════════════════════════════════════════════════════════════
+// S3 client
+// ═══════════════════════════════════════════════════════════════
+
+#[cfg(feature = "blob-store")]
+pub use s3::BlobStore;
+
+#[cfg(feature = "blob-store")]
+mod s3 {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use object_store::aws::AmazonS3Builder;
+    use object_store::path::Path;
+    use object_store::{BackoffConfig, ClientOptions, ObjectStore, PutPayload, RetryConfig};
+    use tracing::{info, warn};
+
+    use crate::config::BootConfig;
+
+    pub struct BlobStore {
+        store: Arc<dyn ObjectStore>,
+        bucket: String,
+    }
+
+    impl BlobStore {
+        /// Build from config; credentials come from the standard `AWS_*`
+        /// environment variables. `None` if no bucket is configured.
+        pub fn from_config(cfg: &BootConfig) -> Option<Self> {
+            let bucket = cfg.blob_bucket.clone()?;
+            // Puts happen on the ingest path: fail fast and fall back to
+            // inline storage rather than stall the connection.
+            let retry = RetryConfig {
+                backoff: BackoffConfig::default(),
+                max_retries: 2,
+                retry_timeout: Duration::from_secs(10),
+            };
+            let mut builder = AmazonS3Builder::from_env()
+                .with_bucket_name(&bucket)
+                .with_retry(retry)
+                .with_client_options(ClientOptions::new().with_timeout(Duration::from_secs(10)));
+            if let Some(endpoint) = &cfg.blob_endpoint {
+                builder = builder
+                    .with_endpoint(endpoint)
+                    .with_allow_http(endpoint.starts_with("http://"));
+            }
+            match builder.build() {
+                Ok(store) => {
+                    info!(bucket = %bucket, "blob store enabled");
+                    Some(Self { store: Arc::new(store), bucket })
+                }
+                Err(e) => {
+                    warn!("blob store disabled: {e}");
+                    None
+                }
+            }
+        }
+
+        pub fn url(&self, key: &str) -> String {
+            format!("s3://{}/{key}", self.bucket)
+        }
+
+        pub async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+            self.store
+                .put(&Path::from(key), PutPayload::from(bytes))
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+
+        pub async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+            let result = self
+                .store
+                .get(&Path::from(key))
+                .await
+                .map_err(|e| e.to_string())?;
+            let bytes = result.bytes().await.map_err(|e| e.to_string())?;
+            Ok(bytes.to_vec())
+        }
+
+        /// Delete an object. A missing object is not an error, so purges
+        /// can be retried.
+        pub async fn delete(&self, key: &str) -> Result<(), String> {
+            match self.store.delete(&Path::from(key)).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Stand-in when built without the `blob-store` feature: uninhabited, so
+/// `AppState::blobs` is always `None`.
+#[cfg(not(feature = "blob-store"))]
+pub enum BlobStore {}
+
+#[cfg(not(feature = "blob-store"))]
+impl BlobStore {
+    pub fn from_config(cfg: &crate::config::BootConfig) -> Option<Self> {
+        if cfg.blob_bucket.is_some() || cfg.blob_endpoint.is_some() {
+            warn!("BLOB_BUCKET is set but trailsd was built without the blob-store feature");
+        }
+        None
+    }
+
+    pub fn url(&self, _key: &str) -> String {
+        match *self {}
+    }
+
+    pub async fn put(&self, _key: &str, _bytes: Vec<u8>) -> Result<(), String> {
+        match *self {}
+    }
+
+    pub async fn get(&self, _key: &str) -> Result<Vec<u8>, String> {
+        match *self {}
+    }
+
+    pub async fn delete(&self, _key: &str) -> Result<(), String> {
+        match *self {}
+    }
+}
@@ -0,0 +1,178 @@
+//! Child watches: a parent connected over WebSocket sends
+//! `subscribe_child` for one of its children and is told, down its own
+//! connection, how the child gets on: `child_started` when it connects,
+//! `child_status` for each Status it stores, and `child_terminal` or
+//! `child_crashed` (a crash, or a start deadline missed) when it ends,
+//! which also ends the watch.
+//!
+//! The answer to the subscribe is the child's state as it stands
+//! (`child_started` if it has connected, `child_terminal` if it already
+//! ended, `child_unknown` if it isn't the parent's child), so a child
+//! that got there first isn't missed; an event racing the subscribe may
+//! come twice. Watches are kept on the parent's connection entry, so
+//! they end with the connection and a reconnecting parent subscribes
+//! again. Only children on this instance are seen.
+
+use std::sync::Arc;
+
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::blob;
+use crate::db;
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::{
+    AppStatus, ChildCrashedMsg, ChildMsg, ChildStatusMsg, ChildTerminalMsg, Event, MsgType,
+    ServerMessage,
+};
+use crate::ws::{self, Sender};
+
+/// Subscribe to the event bus and pass child events on to watching
+/// parents.
+pub fn spawn_child_watcher(state: Arc<AppState>) {
+    let mut rx = state.event_tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!(skipped = n, "child watcher lagged, child events lost");
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            if let Err(e) = forward(&state, event).await {
+                debug!("child event not forwarded: {e}");
+            }
+        }
+    });
+}
+
+/// Watch `child_id` for `parent_id`, answering with where the child is.
+pub async fn subscribe(
+    state: &AppState,
+    parent_id: Uuid,
+    child_id: Uuid,
+    sender: &Sender,
+) -> Result<(), TrailsError> {
+    // Watch first, so nothing that happens while the row is read is lost.
+    if let Some(mut conn) = state.connections.get_mut(&parent_id) {
+        conn.watched_children.insert(child_id);
+    }
+    let row = db::get_app(&state.db, child_id)
+        .await?
+        .filter(|row| row.parent_id == Some(parent_id));
+    let Some(row) = row else {
+        unwatch(state, parent_id, child_id);
+        return ws::send_msg(sender, &ServerMessage::ChildUnknown(ChildMsg { child_id })).await;
+    };
+    let status = row.status.parse::<AppStatus>().ok();
+    let msg = match status {
+        Some(status) if status.is_terminal() => {
+            unwatch(state, parent_id, child_id);
+            terminal_msg(state, child_id, row.status).await?
+        }
+        Some(AppStatus::Scheduled) | None => return Ok(()),
+        Some(_) => ServerMessage::ChildStarted(ChildMsg { child_id }),
+    };
+    ws::send_msg(sender, &msg).await
+}
+
+/// Send a parent the event of a child it watches.
+async fn forward(state: &AppState, event: Event) -> Result<(), TrailsError> {
+    let (child_id, parent_id) = match &event {
+        Event::AppConnected { app_id, parent_id }
+        | Event::MessageStored {
+            app_id, parent_id, ..
+        }
+        | Event::AppTerminal {
+            app_id, parent_id, ..
+        }
+        | Event::CrashDetected {
+            app_id, parent_id, ..
+        } => (*app_id, *parent_id),
+        _ => return Ok(()),
+    };
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+    let sender = state
+        .connections
+        .get(&parent_id)
+        .filter(|conn| conn.watched_children.contains(&child_id))
+        .map(|conn| Arc::clone(&conn.sender));
+    let Some(sender) = sender else {
+        return Ok(());
+    };
+    let msg = match event {
+        Event::AppConnected { .. } => ServerMessage::ChildStarted(ChildMsg { child_id }),
+        Event::MessageStored {
+            msg_type: MsgType::Status,
+            seq,
+            ..
+        } => ServerMessage::ChildStatus(ChildStatusMsg {
+            child_id,
+            seq,
+            payload: stored_payload(state, child_id, "Status", Some(seq - 1)).await?,
+        }),
+        Event::AppTerminal { status, .. } => {
+            unwatch(state, parent_id, child_id);
+            terminal_msg(state, child_id, status).await?
+        }
+        Event::CrashDetected { crash_type, .. } => {
+            unwatch(state, parent_id, child_id);
+            let status = db::get_app(&state.db, child_id).await?.map(|row| row.status);
+            ServerMessage::ChildCrashed(ChildCrashedMsg {
+                child_id,
+                status: status.unwrap_or_else(|| AppStatus::Crashed.as_str().into()),
+                crash_type,
+            })
+        }
+        _ => return Ok(()),
+    };
+    ws::send_msg(&sender, &msg).await
+}
+
+fn unwatch(state: &AppState, parent_id: Uuid, child_id: Uuid) {
+    if let Some(mut conn) = state.connections.get_mut(&parent_id) {
+        conn.watched_children.remove(&child_id);
+    }
+}
+
+/// `child_terminal`, with the Result payload for `done`.
+async fn terminal_msg(
+    state: &AppState,
+    child_id: Uuid,
+    status: String,
+) -> Result<ServerMessage, TrailsError> {
+    let result = if status == AppStatus::Done.as_str() {
+        stored_payload(state, child_id, "Result", None).await?
+    } else {
+        None
+    };
+    Ok(ServerMessage::ChildTerminal(ChildTerminalMsg {
+        child_id,
+        status,
+        result,
+    }))
+}
+
+/// Payload of the child's first `msg_type` message after `after_seq`,
+/// read back from its blob if offloaded.
+async fn stored_payload(
+    state: &AppState,
+    child_id: Uuid,
+    msg_type: &str,
+    after_seq: Option<i64>,
+) -> Result<Option<serde_json::Value>, TrailsError> {
+    let rows = db::list_messages(&state.db, child_id, Some(msg_type), after_seq, 1).await?;
+    let Some(row) = rows.into_iter().next() else {
+        return Ok(None);
+    };
+    match row.blob_key.as_deref() {
+        Some(key) => blob::resolve(state, key).await.map(Some),
+        None => Ok(row.payload_json),
+    }
+}
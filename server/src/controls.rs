@@ -0,0 +1,125 @@
+//! Control messages for apps without a connection here.
+//!
+//! A cancel, pause or resume goes straight down the app's socket when it
+//! is connected to this instance. Otherwise it is queued in
+//! `pending_controls` for `CONTROL_QUEUE_TTL` seconds, and the app's next
+//! register/re_register delivers its queue, oldest first, right after the
+//! Registered ack. Controls that outlive their TTL, or whose app ends
+//! first, are expired and audited as `control_expired`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::error::TrailsError;
+use crate::request_id;
+use crate::state::AppState;
+use crate::ws;
+
+/// What became of a control request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Delivery {
+    /// Sent down the app's socket.
+    Delivered,
+    /// Waiting for the app's next registration.
+    Queued,
+    /// Not delivered and no longer queued.
+    Expired,
+}
+
+impl Delivery {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Delivered => "delivered",
+            Self::Queued => "queued",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+/// A control request's delivery, and its queue row if it was queued.
+pub struct Outcome {
+    pub delivery: Delivery,
+    pub control: Option<db::PendingControl>,
+}
+
+/// Send `action` to the app if it is connected here, else queue it. None
+/// if the app doesn't exist or is terminal.
+pub async fn send_or_queue(
+    state: &AppState,
+    app_id: Uuid,
+    action: &str,
+    payload: serde_json::Value,
+    actor: Option<&str>,
+) -> Result<Option<Outcome>, TrailsError> {
+    let outcome = if ws::send_control(state, app_id, action, payload.clone()).await? {
+        Outcome {
+            delivery: Delivery::Delivered,
+            control: None,
+        }
+    } else {
+        let ttl = state.config().control_queue_ttl;
+        let request_id = request_id::current();
+        let request_id = request_id.as_deref();
+        let queued = db::queue_control(&state.db, app_id, action, &payload, ttl, actor, request_id);
+        let Some(control) = queued.await? else {
+            return Ok(None);
+        };
+        let mut delivery = match control.state.as_str() {
+            "expired" => Delivery::Expired,
+            _ => Delivery::Queued,
+        };
+        // The app may have registered since, its queue already drained.
+        if delivery == Delivery::Queued && state.connections.contains_key(&app_id) {
+            let sent = ws::deliver_pending_controls(state, app_id).await?;
+            if sent.iter().any(|c| c.id == control.id) {
+                delivery = Delivery::Delivered;
+            }
+        }
+        Outcome {
+            delivery,
+            control: Some(control),
+        }
+    };
+    state.metrics.inc(
+        "trails_controls_total",
+        &[("action", action), ("delivery", outcome.delivery.as_str())],
+    );
+    Ok(Some(outcome))
+}
+
+/// Spawn the queue expirer. Runs every `deadline_check_interval` seconds.
+pub fn spawn_control_expirer(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = expire(&state).await {
+                warn!("control expirer error: {e}");
+            }
+            let every = state.config().deadline_check_interval;
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+async fn expire(state: &AppState) -> Result<(), TrailsError> {
+    let mut total = 0;
+    loop {
+        let expired = db::expire_controls(&state.db).await?;
+        total += expired;
+        if expired == 0 {
+            break;
+        }
+    }
+    if total > 0 {
+        info!(count = total, "queued controls expired undelivered");
+        state
+            .metrics
+            .add("trails_queued_controls_expired_total", &[], total as f64);
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Crash-loop detection across retried runs.
+//!
+//! A job identity is `app_name` + parent. When `crash_loop_threshold`
+//! distinct runs of one identity crash within `crash_loop_window`
+//! seconds, the loop is recorded in `crash_loops`, the crashing run is
+//! tagged, and `CrashLoopDetected` goes to the bus and the outbox. With
+//! `crash_loop_cooldown` set, auto-created registrations for the identity
+//! are refused until the cool-down ends.
+
+use std::sync::Arc;
+
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::Event;
+
+/// Subscribe to the event bus and check every `CrashDetected`.
+pub fn spawn_crash_loop_detector(state: Arc<AppState>) {
+    let mut rx = state.event_tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let app_id = match rx.recv().await {
+                Ok(Event::CrashDetected { app_id, .. }) => app_id,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(n)) => {
+                    debug!(skipped = n, "crash-loop detector lagged");
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            if let Err(e) = check(&state, app_id).await {
+                warn!(app_id = %app_id, "crash-loop check failed: {e}");
+            }
+        }
+    });
+}
+
+async fn check(state: &AppState, app_id: Uuid) -> Result<(), TrailsError> {
+    let cfg = state.config();
+    let Some((lp, new_loop)) = db::check_crash_loop(
+        &state.db,
+        app_id,
+        cfg.crash_loop_threshold,
+        cfg.crash_loop_window,
+        cfg.crash_loop_cooldown,
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+    if !new_loop {
+        return Ok(());
+    }
+
+    warn!(
+        app_id = %app_id,
+        app_name = %lp.app_name,
+        parent_id = ?lp.parent_id,
+        crashes = lp.crash_count,
+        window_secs = cfg.crash_loop_window,
+        "crash loop detected"
+    );
+    state.metrics.inc(
+        "trails_crash_loops_detected_total",
+        &[("namespace", lp.namespace.as_deref().unwrap_or_default())],
+    );
+    state.publish(Event::CrashLoopDetected {
+        app_id,
+        parent_id: lp.parent_id,
+        app_name: lp.app_name,
+        crash_count: lp.crash_count,
+        window_secs: cfg.crash_loop_window,
+    });
+    Ok(())
+}
+
+/// Refuse an auto-created registration while the identity's cool-down runs.
+pub async fn check_registration(
+    state: &AppState,
+    app_name: &str,
+    parent_id: Option<Uuid>,
+) -> Result<(), TrailsError> {
+    if state.config().crash_loop_cooldown <= 0 {
+        return Ok(());
+    }
+    match db::crash_loop_cooldown(&state.db, app_name, parent_id).await? {
+        Some(until) => Err(TrailsError::CrashLoop {
+            app_name: app_name.to_string(),
+            until,
+        }),
+        None => Ok(()),
+    }
+}
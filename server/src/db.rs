@@ -4,19 +4,29 @@
 //! Uses sqlx with compile-time-unchecked queries (runtime-checked)
 //! to avoid needing a live DB at compile time.
 
+use std::collections::{HashMap, HashSet};
+
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use serde_json::Value as JsonValue;
-use sqlx::PgPool;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
 use uuid::Uuid;
 
+use crate::blob::BlobRef;
 use crate::error::TrailsError;
+use crate::logs::LogLine;
+use crate::query::{MessageFilter, Predicate, Scope};
+use crate::types::{AppStatus, Event};
+use crate::version::SERVER_VERSION;
 
 // ═══════════════════════════════════════════════════════════════
 // App lifecycle
 // ═══════════════════════════════════════════════════════════════
 
 /// Row returned from apps table queries.
-#[derive(Debug, sqlx::FromRow)]
+#[derive(Debug, sqlx::FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AppRow {
     pub app_id: Uuid,
     pub parent_id: Option<Uuid>,
@@ -28,40 +38,716 @@ pub struct AppRow {
     pub namespace: Option<String>,
     pub connected_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    /// Set on the first Status message (connected → running).
+    pub start_time: Option<DateTime<Utc>>,
+    /// Per-app runtime budget; `None` falls back to the config default.
+    pub max_runtime_secs: Option<i32>,
+    /// When the max-runtime cancel was sent.
+    pub timeout_cancel_at: Option<DateTime<Utc>>,
+    /// Advisory pause (sub-state of connected/running).
+    pub paused: bool,
+    pub paused_at: Option<DateTime<Utc>>,
+    /// Set when this run was counted into a detected crash loop.
+    pub crash_loop_at: Option<DateTime<Utc>>,
+    /// Higher is processed first by background scans.
+    pub priority: i16,
+    /// Intended launch time; the start deadline counts from here.
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// `open`, `signed` or `full`; signed levels get server-signed frames.
+    pub sec_level: String,
+    /// Correlation id the app was launched under; see `trace_apps`.
+    pub correlation_id: Option<String>,
+    pub role_refs: Option<Vec<String>>,
+    /// JSON bytes of messages and snapshots stored since `quota_reset_at`.
+    pub stored_bytes: i64,
+    /// Admin overrides of the configured storage quota; `None` inherits.
+    pub quota_soft_bytes: Option<i64>,
+    pub quota_hard_bytes: Option<i64>,
+    /// When the soft quota was crossed and `QuotaWarning` sent.
+    pub quota_warned_at: Option<DateTime<Utc>>,
+    pub quota_reset_at: Option<DateTime<Utc>>,
+    /// Earlier parents of an adopted app, oldest first:
+    /// `[{parentId, adoptedAt, adoptedBy}]`.
+    pub adopted_from: Option<JsonValue>,
+    /// Last Status whose content changed.
+    pub progress_at: Option<DateTime<Utc>>,
+    /// Advisory: flagged by the stall sweeper, cleared by progress.
+    pub stalled_at: Option<DateTime<Utc>>,
+    /// Siblings this app waits for before its start deadline runs.
+    pub depends_on: Option<Vec<Uuid>>,
+    /// Set while a scheduled app waits for its dependencies.
+    pub blocked_at: Option<DateTime<Utc>>,
+    /// Dependencies that ended in anything but `done`, recorded when the
+    /// failure policy cancelled or released this app.
+    pub failed_dependencies: Option<JsonValue>,
+    /// `ws` or `http`: how the app last reached the server.
+    pub transport: String,
+    /// Last HTTP ingest batch; the stale sweeper crashes silent `http` apps.
+    pub last_seen_at: Option<DateTime<Utc>>,
+    /// Last client heartbeat, from apps that opted in to sending them.
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    /// Tags from TRAILS_INFO or the children request: `metadata_json`
+    /// without the keys the server keeps there itself.
+    pub tags: Option<JsonValue>,
+}
+
+/// Column list matching `AppRow`.
+const APP_COLUMNS: &str = "app_id, parent_id, app_name, status, pub_key, \
+    server_instance, start_deadline, namespace, connected_at, created_at, \
+    start_time, max_runtime_secs, timeout_cancel_at, paused, paused_at, crash_loop_at, \
+    priority, scheduled_at, sec_level, correlation_id, role_refs, stored_bytes, \
+    quota_soft_bytes, quota_hard_bytes, quota_warned_at, quota_reset_at, \
+    metadata_json->'adoptedFrom' AS adopted_from, progress_at, stalled_at, depends_on, \
+    blocked_at, metadata_json->'failedDependencies' AS failed_dependencies, transport, \
+    last_seen_at, last_heartbeat, \
+    CASE WHEN jsonb_typeof(metadata_json) = 'object' \
+        THEN NULLIF(metadata_json - 'adoptedFrom' - 'failedDependencies', '{}') \
+        ELSE metadata_json END AS tags";
+
+/// A new scheduled app row; see `create_scheduled_app(s)`.
+pub struct NewApp<'a> {
+    pub app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub app_name: &'a str,
+    pub start_deadline: i32,
+    pub role_refs: &'a [String],
+    pub metadata: Option<&'a JsonValue>,
+    pub max_runtime_secs: Option<i32>,
+    pub priority: i16,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub sec_level: &'a str,
+    /// `None` inherits the parent's.
+    pub correlation_id: Option<&'a str>,
+    /// Sibling app ids; non-empty creates the app blocked.
+    pub depends_on: &'a [Uuid],
 }
 
 /// Create a "scheduled" app row — Phase A of two-phase lifecycle (spec §7).
 /// Called when parent registers intent via REST (Phase 2) or when
 /// a child registers directly and we auto-create the scheduled row.
-pub async fn create_scheduled_app(
+/// Returns false if the app already existed, or it or its parent was purged.
+pub async fn create_scheduled_app(pool: &PgPool, app: &NewApp<'_>) -> Result<bool, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO apps (app_id, parent_id, app_name, status, start_deadline, role_refs,
+                          metadata_json, max_runtime_secs, priority, scheduled_at, sec_level,
+                          correlation_id, depends_on, blocked_at)
+        SELECT $1, $2, $3, 'scheduled', $4, $5, $6, $7, $8, $9, $10,
+               COALESCE($11, (SELECT correlation_id FROM apps WHERE app_id = $2)),
+               NULLIF($12::UUID[], '{}'), CASE WHEN cardinality($12) > 0 THEN NOW() END
+        WHERE NOT EXISTS (SELECT 1 FROM purged_apps WHERE app_id IN ($1, $2))
+        ON CONFLICT (app_id) DO NOTHING
+        "#,
+    )
+    .bind(app.app_id)
+    .bind(app.parent_id)
+    .bind(app.app_name)
+    .bind(app.start_deadline)
+    .bind(app.role_refs)
+    .bind(app.metadata)
+    .bind(app.max_runtime_secs)
+    .bind(app.priority)
+    .bind(app.scheduled_at)
+    .bind(app.sec_level)
+    .bind(app.correlation_id)
+    .bind(app.depends_on)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Of `ids`, those that already exist as apps.
+pub async fn existing_app_ids(pool: &PgPool, ids: &[Uuid]) -> Result<Vec<Uuid>, TrailsError> {
+    let rows = sqlx::query_scalar("SELECT app_id FROM apps WHERE app_id = ANY($1)")
+        .bind(ids)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// Batch form of `create_scheduled_app`: one multi-row insert in one
+/// transaction. Returns the ids actually inserted (existing and purged ids
+/// are skipped) and whether the transaction committed — with `atomic`, any
+/// skipped row rolls the whole batch back.
+pub async fn create_scheduled_apps(
     pool: &PgPool,
+    apps: &[NewApp<'_>],
+    atomic: bool,
+) -> Result<(Vec<Uuid>, bool), TrailsError> {
+    let mut tx = pool.begin().await?;
+    // role_refs and depends_on go over as JSONB: UNNEST flattens arrays
+    // of arrays into one column.
+    let inserted: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        INSERT INTO apps (app_id, parent_id, app_name, status, start_deadline, role_refs,
+                          metadata_json, max_runtime_secs, priority, scheduled_at, sec_level,
+                          correlation_id, depends_on, blocked_at)
+        SELECT app_id, parent_id, app_name, 'scheduled', start_deadline,
+               ARRAY(SELECT jsonb_array_elements_text(role_refs)),
+               metadata_json, max_runtime_secs, priority, scheduled_at, sec_level,
+               COALESCE(correlation_id,
+                        (SELECT p.correlation_id FROM apps p WHERE p.app_id = t.parent_id)),
+               NULLIF(ARRAY(SELECT jsonb_array_elements_text(depends_on))::UUID[], '{}'),
+               CASE WHEN jsonb_array_length(depends_on) > 0 THEN NOW() END
+        FROM UNNEST($1::UUID[], $2::UUID[], $3::TEXT[], $4::INT[], $5::JSONB[],
+                    $6::JSONB[], $7::INT[], $8::SMALLINT[], $9::TIMESTAMPTZ[], $10::TEXT[],
+                    $11::TEXT[], $12::JSONB[])
+            AS t(app_id, parent_id, app_name, start_deadline, role_refs,
+                 metadata_json, max_runtime_secs, priority, scheduled_at, sec_level,
+                 correlation_id, depends_on)
+        WHERE NOT EXISTS (
+            SELECT 1 FROM purged_apps p WHERE p.app_id IN (t.app_id, t.parent_id)
+        )
+        ON CONFLICT (app_id) DO NOTHING
+        RETURNING app_id
+        "#,
+    )
+    .bind(apps.iter().map(|a| a.app_id).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.parent_id).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.app_name).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.start_deadline).collect::<Vec<_>>())
+    .bind(
+        apps.iter()
+            .map(|a| serde_json::json!(a.role_refs))
+            .collect::<Vec<_>>(),
+    )
+    .bind(apps.iter().map(|a| a.metadata.cloned()).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.max_runtime_secs).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.priority).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.scheduled_at).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.sec_level).collect::<Vec<_>>())
+    .bind(apps.iter().map(|a| a.correlation_id).collect::<Vec<_>>())
+    .bind(
+        apps.iter()
+            .map(|a| serde_json::json!(a.depends_on))
+            .collect::<Vec<_>>(),
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if atomic && inserted.len() != apps.len() {
+        tx.rollback().await?;
+        return Ok((inserted, false));
+    }
+    // The insert can't see parents created by the same statement; pass
+    // their correlation ids down one generation per round.
+    let ids: HashSet<Uuid> = apps.iter().map(|a| a.app_id).collect();
+    if apps.iter().any(|a| a.parent_id.is_some_and(|p| ids.contains(&p))) {
+        for _ in 0..apps.len() {
+            let inherited = sqlx::query(
+                r#"
+                UPDATE apps c SET correlation_id = p.correlation_id
+                FROM apps p
+                WHERE c.app_id = ANY($1) AND c.correlation_id IS NULL
+                  AND p.app_id = c.parent_id AND p.correlation_id IS NOT NULL
+                "#,
+            )
+            .bind(&inserted)
+            .execute(&mut *tx)
+            .await?;
+            if inherited.rows_affected() == 0 {
+                break;
+            }
+        }
+    }
+    tx.commit().await?;
+    Ok((inserted, true))
+}
+
+/// Apply a PATCH's settings in one statement: `max_runtime_secs` when
+/// given (`Some(None)` clears it) and `priority` when given. A new budget
+/// also resets a pending timeout cancel, so it starts a fresh
+/// cancel/grace cycle.
+pub async fn update_app_settings(
+    conn: &mut PgConnection,
     app_id: Uuid,
-    parent_id: Option<Uuid>,
-    app_name: &str,
-    start_deadline: i32,
-    role_refs: &[String],
-    metadata: Option<&JsonValue>,
+    max_runtime_secs: Option<Option<i32>>,
+    priority: Option<i16>,
+) -> Result<Option<AppRow>, TrailsError> {
+    let row: Option<AppRow> = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET
+            max_runtime_secs = CASE WHEN $2 THEN $3 ELSE max_runtime_secs END,
+            timeout_cancel_at = CASE WHEN $2 THEN NULL ELSE timeout_cancel_at END,
+            priority = COALESCE($4, priority),
+            updated_at = NOW()
+        WHERE app_id = $1
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(max_runtime_secs.is_some())
+    .bind(max_runtime_secs.flatten())
+    .bind(priority)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row)
+}
+
+/// Move a scheduled app's launch time forward and audit the change, in
+/// the caller's transaction. Returns None unless the app is still
+/// scheduled and `scheduled_at` is not earlier than its current launch
+/// time.
+pub async fn reschedule_app(
+    tx: &mut PgConnection,
+    app_id: Uuid,
+    scheduled_at: DateTime<Utc>,
+    reason: Option<&str>,
+    request_id: Option<&str>,
+) -> Result<Option<AppRow>, TrailsError> {
+    let previous: Option<DateTime<Utc>> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(scheduled_at, created_at) FROM apps
+        WHERE app_id = $1 AND status = 'scheduled'
+        FOR UPDATE
+        "#,
+    )
+    .bind(app_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(previous) = previous.filter(|p| scheduled_at >= *p) else {
+        return Ok(None);
+    };
+
+    let row: AppRow = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET scheduled_at = $2, updated_at = NOW()
+        WHERE app_id = $1
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(scheduled_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let payload = serde_json::json!({
+        "from": previous,
+        "to": scheduled_at,
+        "reason": reason,
+    });
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (action, target_app_id, payload_json, auth_domain, server_version,
+                               request_id)
+        VALUES ('reschedule', $1, $2, 'external', $3, $4)
+        "#,
+    )
+    .bind(app_id)
+    .bind(&payload)
+    .bind(SERVER_VERSION)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+    Ok(Some(row))
+}
+
+/// Set the advisory pause flag. Any non-terminal app can be paused or
+/// resumed; returns None otherwise.
+pub async fn set_paused(
+    pool: &PgPool,
+    app_id: Uuid,
+    paused: bool,
+) -> Result<Option<AppRow>, TrailsError> {
+    let row: Option<AppRow> = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET
+            paused = $2,
+            paused_at = CASE WHEN $2 THEN COALESCE(paused_at, NOW()) END,
+            updated_at = NOW()
+        WHERE app_id = $1
+          AND status NOT IN ('done', 'error', 'crashed', 'cancelled', 'start_failed',
+                             'timed_out')
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(paused)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Apps to move under a new parent.
+#[derive(Debug, Clone, Copy)]
+pub enum AdoptTarget {
+    App(Uuid),
+    /// Every non-terminal child of this app.
+    ChildrenOf(Uuid),
+}
+
+/// One reparented app.
+pub struct Adoption {
+    pub app: AppRow,
+    pub previous_parent_id: Option<Uuid>,
+}
+
+/// Reparent `target` under `new_parent_id` in one transaction: the new
+/// parent must exist and not be terminal, and may not be one of the
+/// adopted apps or their descendants. Each move appends the old parent
+/// to `metadata_json.adoptedFrom`, is audited and queues `AppAdopted`.
+/// Apps already under the new parent are left out.
+pub async fn adopt_apps(
+    pool: &PgPool,
+    target: AdoptTarget,
+    new_parent_id: Uuid,
+    actor: &str,
+    request_id: Option<&str>,
+) -> Result<Vec<Adoption>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    // Shared lock: the parent can't finish while it gains children.
+    let parent_status: Option<String> =
+        sqlx::query_scalar("SELECT status FROM apps WHERE app_id = $1 FOR SHARE")
+            .bind(new_parent_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    match parent_status.as_deref() {
+        None => {
+            return Err(TrailsError::InvalidRequest(format!(
+                "new parent {new_parent_id} not found"
+            )))
+        }
+        Some(status) if status.parse::<AppStatus>().is_ok_and(|s| s.is_terminal()) => {
+            return Err(TrailsError::Conflict(format!(
+                "new parent {new_parent_id} is '{status}'"
+            )))
+        }
+        Some(_) => {}
+    }
+
+    let moving: Vec<(Uuid, Option<Uuid>)> = match target {
+        AdoptTarget::App(app_id) => {
+            let row: Option<(Uuid, Option<Uuid>)> = sqlx::query_as(
+                "SELECT app_id, parent_id FROM apps WHERE app_id = $1 FOR UPDATE",
+            )
+            .bind(app_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            vec![row.ok_or(TrailsError::AppNotFound(app_id))?]
+        }
+        AdoptTarget::ChildrenOf(old_parent_id) => {
+            if old_parent_id == new_parent_id {
+                return Err(TrailsError::InvalidRequest(
+                    "oldParentId and newParentId are the same app".into(),
+                ));
+            }
+            let exists: Option<i32> = sqlx::query_scalar("SELECT 1 FROM apps WHERE app_id = $1")
+                .bind(old_parent_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            if exists.is_none() {
+                return Err(TrailsError::AppNotFound(old_parent_id));
+            }
+            sqlx::query_as(
+                r#"
+                SELECT app_id, parent_id FROM apps
+                WHERE parent_id = $1
+                  AND status NOT IN ('done', 'error', 'crashed', 'cancelled', 'start_failed',
+                                     'timed_out')
+                ORDER BY created_at, app_id
+                FOR UPDATE
+                "#,
+            )
+            .bind(old_parent_id)
+            .fetch_all(&mut *tx)
+            .await?
+        }
+    };
+    let moving: Vec<(Uuid, Option<Uuid>)> = moving
+        .into_iter()
+        .filter(|(_, parent)| *parent != Some(new_parent_id))
+        .collect();
+    if moving.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ids: Vec<Uuid> = moving.iter().map(|(id, _)| *id).collect();
+
+    // The new parent's ancestry, itself included, must not contain an
+    // adopted app. UNION stops on a cycle that already exists.
+    let cycle: Option<Uuid> = sqlx::query_scalar(
+        r#"
+        WITH RECURSIVE up AS (
+            SELECT app_id, parent_id FROM apps WHERE app_id = $1
+            UNION
+            SELECT a.app_id, a.parent_id FROM apps a JOIN up ON a.app_id = up.parent_id
+        )
+        SELECT app_id FROM up WHERE app_id = ANY($2) LIMIT 1
+        "#,
+    )
+    .bind(new_parent_id)
+    .bind(&ids)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(app_id) = cycle {
+        return Err(TrailsError::Conflict(format!(
+            "adopting {app_id} under {new_parent_id} would create a cycle"
+        )));
+    }
+
+    let rows: Vec<AppRow> = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET
+            parent_id = $2,
+            metadata_json = CASE
+                WHEN metadata_json IS NULL OR jsonb_typeof(metadata_json) = 'object' THEN
+                    jsonb_set(
+                        COALESCE(metadata_json, '{{}}'),
+                        '{{adoptedFrom}}',
+                        COALESCE(metadata_json->'adoptedFrom', '[]')
+                            || jsonb_build_array(jsonb_build_object(
+                                'parentId', parent_id, 'adoptedAt', NOW(), 'adoptedBy', $3::TEXT))
+                    )
+                ELSE metadata_json
+            END,
+            updated_at = NOW()
+        WHERE app_id = ANY($1)
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(&ids)
+    .bind(new_parent_id)
+    .bind(actor)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (action, target_app_id, payload_json, auth_domain, oauth_subject,
+                               server_version, request_id)
+        SELECT 'adopt', t.app_id, jsonb_build_object('from', t.parent_id, 'to', $3::UUID),
+               'external', $4, $5, $6
+        FROM UNNEST($1::UUID[], $2::UUID[]) AS t(app_id, parent_id)
+        "#,
+    )
+    .bind(&ids)
+    .bind(moving.iter().map(|(_, parent)| *parent).collect::<Vec<_>>())
+    .bind(new_parent_id)
+    .bind(actor)
+    .bind(SERVER_VERSION)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let previous: HashMap<Uuid, Option<Uuid>> = moving.into_iter().collect();
+    let mut adoptions = Vec::with_capacity(rows.len());
+    for app in rows {
+        let previous_parent_id = previous.get(&app.app_id).copied().flatten();
+        let event = Event::AppAdopted {
+            app_id: app.app_id,
+            parent_id: Some(new_parent_id),
+            previous_parent_id,
+        };
+        enqueue_event(&mut tx, &event).await?;
+        adoptions.push(Adoption {
+            app,
+            previous_parent_id,
+        });
+    }
+    tx.commit().await?;
+    Ok(adoptions)
+}
+
+/// Audit an admin cancel request. The app's state is left alone: the
+/// client decides how to stop, and reports it like any other exit.
+pub async fn record_cancel(
+    pool: &PgPool,
+    app_id: Uuid,
+    reason: Option<&str>,
+    delivery: &str,
+    control_id: Option<i64>,
+    actor: &str,
+    request_id: Option<&str>,
 ) -> Result<(), TrailsError> {
+    let payload = serde_json::json!({
+        "reason": reason,
+        "delivered": delivery == "delivered",
+        "delivery": delivery,
+        "controlId": control_id,
+    });
     sqlx::query(
         r#"
-        INSERT INTO apps (app_id, parent_id, app_name, status, start_deadline, role_refs, metadata_json)
-        VALUES ($1, $2, $3, 'scheduled', $4, $5, $6)
-        ON CONFLICT (app_id) DO NOTHING
+        INSERT INTO audit_log (action, target_app_id, payload_json, auth_domain, oauth_subject,
+                               server_version, request_id)
+        VALUES ('cancel', $1, $2, 'external', $3, $4, $5)
         "#,
     )
     .bind(app_id)
-    .bind(parent_id)
-    .bind(app_name)
-    .bind(start_deadline)
-    .bind(role_refs)
-    .bind(metadata)
+    .bind(&payload)
+    .bind(actor)
+    .bind(SERVER_VERSION)
+    .bind(request_id)
     .execute(pool)
     .await?;
     Ok(())
 }
 
+/// Force a non-terminal app into the terminal `status` by admin request,
+/// audited and with its `AppTerminal` in the outbox, all in one
+/// transaction. Returns the updated row and the status it left; None if
+/// the app doesn't exist or is already terminal.
+pub async fn force_terminal(
+    pool: &PgPool,
+    app_id: Uuid,
+    status: &str,
+    reason: Option<&str>,
+    actor: &str,
+    request_id: Option<&str>,
+) -> Result<Option<(AppRow, String)>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let previous: Option<String> = sqlx::query_scalar(
+        r#"
+        SELECT status FROM apps
+        WHERE app_id = $1
+          AND status NOT IN ('done', 'error', 'crashed', 'cancelled', 'start_failed',
+                             'timed_out')
+        FOR UPDATE
+        "#,
+    )
+    .bind(app_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(previous) = previous else {
+        return Ok(None);
+    };
+
+    let row: AppRow = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET status = $2, disconnected_at = COALESCE(disconnected_at, NOW()),
+                        updated_at = NOW()
+        WHERE app_id = $1
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(status)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let payload = serde_json::json!({ "from": previous, "to": status, "reason": reason });
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (action, target_app_id, payload_json, auth_domain, oauth_subject,
+                               server_version, request_id)
+        VALUES ('force_terminal', $1, $2, 'external', $3, $4, $5)
+        "#,
+    )
+    .bind(app_id)
+    .bind(&payload)
+    .bind(actor)
+    .bind(SERVER_VERSION)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+    let event = Event::AppTerminal {
+        app_id,
+        parent_id: row.parent_id,
+        status: status.into(),
+    };
+    enqueue_event(&mut tx, &event).await?;
+    tx.commit().await?;
+    Ok(Some((row, previous)))
+}
+
+/// Filters of `list_apps`; `None` matches everything.
+#[derive(Debug, Default)]
+pub struct AppListFilter<'a> {
+    pub namespace: Option<&'a str>,
+    pub status: Option<&'a str>,
+    pub min_priority: Option<i16>,
+    /// Direct children of this app only.
+    pub parent_id: Option<Uuid>,
+    /// Continue after this app in list order (pagination cursor).
+    pub after: Option<Uuid>,
+    /// Running apps flagged stalled (true) or not (false).
+    pub stalled: Option<bool>,
+}
+
+/// A running app flagged by the stall sweeper. The flag outlives the run
+/// but only counts while the app is running.
+const STALLED: &str = "(stalled_at IS NOT NULL AND status = 'running')";
+
+/// List apps, newest first (or highest priority first), filtered.
+pub async fn list_apps(
+    pool: &PgPool,
+    filter: &AppListFilter<'_>,
+    by_priority: bool,
+    limit: i64,
+) -> Result<Vec<AppRow>, TrailsError> {
+    // app_id breaks ties: batch-registered apps share one created_at.
+    let key = if by_priority {
+        "priority, created_at, app_id"
+    } else {
+        "created_at, app_id"
+    };
+    let order = key.replace(',', " DESC,") + " DESC";
+    let rows: Vec<AppRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT {APP_COLUMNS} FROM apps
+        WHERE ($1::TEXT IS NULL OR namespace = $1)
+          AND ($2::TEXT IS NULL OR status = $2)
+          AND ($3::SMALLINT IS NULL OR priority >= $3)
+          AND ($4::UUID IS NULL OR parent_id = $4)
+          AND ($5::UUID IS NULL OR ({key}) < (SELECT {key} FROM apps WHERE app_id = $5))
+          AND ($7::BOOL IS NULL OR {STALLED} = $7)
+        ORDER BY {order}
+        LIMIT $6
+        "#
+    ))
+    .bind(filter.namespace)
+    .bind(filter.status)
+    .bind(filter.min_priority)
+    .bind(filter.parent_id)
+    .bind(filter.after)
+    .bind(limit)
+    .bind(filter.stalled)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Runs of one app_name, newest first; `before` pages on created_at.
+pub async fn list_apps_by_name(
+    pool: &PgPool,
+    app_name: &str,
+    before: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<AppRow>, TrailsError> {
+    let rows: Vec<AppRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT {APP_COLUMNS} FROM apps
+        WHERE app_name = $1
+          AND ($2::TIMESTAMPTZ IS NULL OR created_at < $2)
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#
+    ))
+    .bind(app_name)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Trim surrounding whitespace from stored app names (`APP_NAME_TRIM`).
+/// Returns how many names changed.
+pub async fn trim_app_names(pool: &PgPool) -> Result<u64, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE apps SET app_name = btrim(app_name, E' \t\n\r\f\v')
+        WHERE app_name <> btrim(app_name, E' \t\n\r\f\v')
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
 /// Transition app to 'connected' and record process info + pub_key.
-/// Called on successful registration.
+/// Called on successful registration. The conditional update is the
+/// authority when registrations race: exactly one succeeds, the others
+/// get `already_registered` with the winner's status.
+#[allow(clippy::too_many_arguments)]
 pub async fn connect_app(
     pool: &PgPool,
     app_id: Uuid,
@@ -76,8 +762,11 @@ pub async fn connect_app(
     pod_ip: Option<&str>,
     namespace: Option<&str>,
     executable: Option<&str>,
+    sec_level: &str,
+    transport: &str,
 ) -> Result<(), TrailsError> {
-    let result = sqlx::query(
+    let mut tx = pool.begin().await?;
+    let parent_id: Option<Option<Uuid>> = sqlx::query_scalar(
         r#"
         UPDATE apps SET
             status = 'connected',
@@ -92,9 +781,15 @@ pub async fn connect_app(
             node_name = $9,
             pod_ip = $10::INET,
             namespace = $11,
-            executable = $12
+            executable = $12,
+            sec_level = $13,
+            blocked_at = NULL,
+            transport = $14,
+            last_seen_at = NOW()
         WHERE app_id = $1
           AND status IN ('scheduled', 'reconnecting')
+          AND NOT EXISTS (SELECT 1 FROM purged_apps WHERE app_id = $1)
+        RETURNING parent_id
         "#,
     )
     .bind(app_id)
@@ -109,15 +804,25 @@ pub async fn connect_app(
     .bind(pod_ip)
     .bind(namespace)
     .bind(executable)
-    .execute(pool)
+    .bind(sec_level)
+    .bind(transport)
+    .fetch_optional(&mut *tx)
     .await?;
 
-    if result.rows_affected() == 0 {
-        return Err(TrailsError::InvalidTransition {
-            from: "?".into(),
-            to: "connected".into(),
+    let Some(parent_id) = parent_id else {
+        // The update waited out any concurrent winner's row lock, so
+        // this read sees its committed status.
+        let status: Option<String> = sqlx::query_scalar("SELECT status FROM apps WHERE app_id = $1")
+            .bind(app_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        return Err(match status {
+            Some(status) => TrailsError::already_registered(app_id, status),
+            None => TrailsError::AppNotFound(app_id),
         });
-    }
+    };
+    enqueue_event(&mut tx, &Event::AppConnected { app_id, parent_id }).await?;
+    tx.commit().await?;
     Ok(())
 }
 
@@ -141,221 +846,2986 @@ pub async fn set_terminal(
     app_id: Uuid,
     status: &str,
 ) -> Result<(), TrailsError> {
-    sqlx::query(
+    let mut tx = pool.begin().await?;
+    let parent_id: Option<Option<Uuid>> = sqlx::query_scalar(
         r#"
         UPDATE apps SET status = $2, disconnected_at = NOW()
         WHERE app_id = $1 AND status IN ('connected', 'running')
+        RETURNING parent_id
         "#,
     )
     .bind(app_id)
     .bind(status)
-    .execute(pool)
+    .fetch_optional(&mut *tx)
     .await?;
+
+    if let Some(parent_id) = parent_id {
+        let event = Event::AppTerminal {
+            app_id,
+            parent_id,
+            status: status.into(),
+        };
+        enqueue_event(&mut tx, &event).await?;
+    }
+    tx.commit().await?;
     Ok(())
 }
 
-/// Mark app as crashed (connection drop).
-pub async fn set_crashed(pool: &PgPool, app_id: Uuid) -> Result<(), TrailsError> {
-    sqlx::query(
+/// Mark app as crashed (connection drop). Returns false if the app was
+/// not live (already terminal), or has moved to HTTP ingestion and is
+/// left to the stale sweeper; nothing changed then.
+pub async fn set_crashed(pool: &PgPool, app_id: Uuid) -> Result<bool, TrailsError> {
+    let result = sqlx::query(
         r#"
         UPDATE apps SET status = 'crashed', disconnected_at = NOW()
-        WHERE app_id = $1 AND status IN ('connected', 'running')
+        WHERE app_id = $1 AND status IN ('connected', 'running') AND transport = 'ws'
         "#,
     )
     .bind(app_id)
     .execute(pool)
     .await?;
-    Ok(())
+    Ok(result.rows_affected() > 0)
 }
 
-/// Mark app as start_failed (deadline expired, never connected).
-pub async fn set_start_failed(pool: &PgPool, app_id: Uuid) -> Result<(), TrailsError> {
-    sqlx::query(
+/// Running apps connected to `server_instance`; the max-runtime sweeper
+/// filters by budget. Each instance sweeps only the apps whose sockets
+/// it holds, so the cancel can actually be delivered. Highest priority first.
+pub async fn list_running_apps(
+    pool: &PgPool,
+    server_instance: &str,
+) -> Result<Vec<AppRow>, TrailsError> {
+    let rows: Vec<AppRow> = sqlx::query_as(&format!(
         r#"
-        UPDATE apps SET status = 'start_failed', disconnected_at = NOW()
-        WHERE app_id = $1 AND status = 'scheduled'
-        "#,
-    )
-    .bind(app_id)
-    .execute(pool)
+        SELECT {APP_COLUMNS} FROM apps
+        WHERE status = 'running' AND start_time IS NOT NULL AND server_instance = $1
+        ORDER BY priority DESC
+        "#
+    ))
+    .bind(server_instance)
+    .fetch_all(pool)
     .await?;
-    Ok(())
+    Ok(rows)
 }
 
-/// Mark apps on this server instance as 'reconnecting' after restart (spec §19).
-pub async fn mark_reconnecting(
+/// Note a Status about to be stored as a snapshot: unless its content
+/// equals the latest snapshot's, it is progress and clears any stall.
+/// True if a stall was cleared.
+pub async fn record_progress(
     pool: &PgPool,
-    server_instance: &str,
-) -> Result<u64, TrailsError> {
-    let result = sqlx::query(
+    app_id: Uuid,
+    snapshot: &JsonValue,
+) -> Result<bool, TrailsError> {
+    // The RETURNING subquery sees the row as it was before the update.
+    let cleared: Option<bool> = sqlx::query_scalar(
         r#"
-        UPDATE apps SET status = 'reconnecting'
-        WHERE server_instance = $1
-          AND status IN ('connected', 'running')
+        UPDATE apps SET progress_at = NOW(), stalled_at = NULL
+        WHERE app_id = $1
+          AND NOT EXISTS (
+              SELECT 1 FROM (
+                  SELECT snapshot_json FROM snapshots
+                  WHERE app_id = $1
+                  ORDER BY created_at DESC, id DESC
+                  LIMIT 1
+              ) latest
+              WHERE latest.snapshot_json = $2
+          )
+        RETURNING (SELECT stalled_at IS NOT NULL FROM apps WHERE app_id = $1)
         "#,
     )
-    .bind(server_instance)
-    .execute(pool)
+    .bind(app_id)
+    .bind(snapshot)
+    .fetch_optional(pool)
     .await?;
-    Ok(result.rows_affected())
+    Ok(cleared.unwrap_or(false))
 }
 
-/// Mark apps that failed to reconnect within window as 'lost_contact'.
-pub async fn mark_lost_contact(
+/// A running app the stall sweeper checks.
+#[derive(Debug, sqlx::FromRow)]
+pub struct StallCandidate {
+    pub app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub app_name: String,
+    /// Last progress, or the start of the run.
+    pub progress_at: DateTime<Utc>,
+    /// The app's `stall_after` tag, if any.
+    pub stall_after: Option<JsonValue>,
+}
+
+/// Running, unpaused, not yet stalled apps on this instance.
+pub async fn list_stall_candidates(
     pool: &PgPool,
     server_instance: &str,
-) -> Result<u64, TrailsError> {
-    let result = sqlx::query(
+) -> Result<Vec<StallCandidate>, TrailsError> {
+    let rows = sqlx::query_as(
         r#"
-        UPDATE apps SET status = 'lost_contact', disconnected_at = NOW()
-        WHERE server_instance = $1 AND status = 'reconnecting'
+        SELECT app_id, parent_id, app_name, COALESCE(progress_at, start_time) AS progress_at,
+               metadata_json->'stall_after' AS stall_after
+        FROM apps
+        WHERE status = 'running' AND NOT paused AND stalled_at IS NULL
+          AND COALESCE(progress_at, start_time) IS NOT NULL AND server_instance = $1
+        ORDER BY priority DESC
         "#,
     )
     .bind(server_instance)
-    .execute(pool)
+    .fetch_all(pool)
     .await?;
-    Ok(result.rows_affected())
+    Ok(rows)
 }
 
-/// Re-connect an app after server restart. Verifies pub_key matches.
-pub async fn reconnect_app(
+/// Flag an app stalled and queue its `StallDetected`, unless it made
+/// progress after `cutoff` or stopped running since the scan. False if
+/// nothing was flagged.
+pub async fn mark_stalled(
     pool: &PgPool,
-    app_id: Uuid,
-    pub_key: &str,
-    server_instance: &str,
-) -> Result<Option<AppRow>, TrailsError> {
-    let row: Option<AppRow> = sqlx::query_as(
+    event: &Event,
+    cutoff: DateTime<Utc>,
+) -> Result<bool, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let marked = sqlx::query(
         r#"
-        UPDATE apps SET
-            status = 'running',
-            server_instance = $3,
-            connected_at = NOW()
-        WHERE app_id = $1
-          AND pub_key = $2
-          AND status IN ('reconnecting', 'lost_contact')
-        RETURNING app_id, parent_id, app_name, status, pub_key,
-                  server_instance, start_deadline, namespace,
-                  connected_at, created_at
+        UPDATE apps SET stalled_at = NOW()
+        WHERE app_id = $1 AND status = 'running' AND NOT paused AND stalled_at IS NULL
+          AND COALESCE(progress_at, start_time) < $2
         "#,
     )
-    .bind(app_id)
-    .bind(pub_key)
-    .bind(server_instance)
-    .fetch_optional(pool)
-    .await?;
-    Ok(row)
+    .bind(event.app_id())
+    .bind(cutoff)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if marked {
+        enqueue_event(&mut tx, event).await?;
+    }
+    tx.commit().await?;
+    Ok(marked)
 }
 
-/// Lookup an app by id.
-pub async fn get_app(pool: &PgPool, app_id: Uuid) -> Result<Option<AppRow>, TrailsError> {
-    let row: Option<AppRow> = sqlx::query_as(
+/// Running apps currently flagged stalled.
+pub async fn count_stalled(pool: &PgPool) -> Result<i64, TrailsError> {
+    let count = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM apps WHERE {STALLED}"))
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+/// Record that the max-runtime cancel was sent. No-op unless still running.
+pub async fn mark_timeout_cancel(pool: &PgPool, app_id: Uuid) -> Result<(), TrailsError> {
+    sqlx::query(
         r#"
-        SELECT app_id, parent_id, app_name, status, pub_key,
-               server_instance, start_deadline, namespace,
-               connected_at, created_at
-        FROM apps WHERE app_id = $1
+        UPDATE apps SET timeout_cancel_at = NOW()
+        WHERE app_id = $1 AND status = 'running' AND timeout_cancel_at IS NULL
         "#,
     )
     .bind(app_id)
-    .fetch_optional(pool)
+    .execute(pool)
     .await?;
-    Ok(row)
+    Ok(())
 }
 
-/// Get all 'scheduled' apps past their start deadline.
-pub async fn get_expired_scheduled(pool: &PgPool) -> Result<Vec<AppRow>, TrailsError> {
-    let rows: Vec<AppRow> = sqlx::query_as(
+/// Force a running app to 'timed_out'. Returns false if it reached
+/// another state first (e.g. finished during the grace period).
+pub async fn set_timed_out(pool: &PgPool, app_id: Uuid) -> Result<bool, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let parent_id: Option<Option<Uuid>> = sqlx::query_scalar(
         r#"
-        SELECT app_id, parent_id, app_name, status, pub_key,
-               server_instance, start_deadline, namespace,
-               connected_at, created_at
-        FROM apps
-        WHERE status = 'scheduled'
-          AND created_at + (COALESCE(start_deadline, 300) || ' seconds')::INTERVAL < NOW()
+        UPDATE apps SET status = 'timed_out', disconnected_at = NOW()
+        WHERE app_id = $1 AND status = 'running'
+        RETURNING parent_id
         "#,
     )
-    .fetch_all(pool)
+    .bind(app_id)
+    .fetch_optional(&mut *tx)
     .await?;
-    Ok(rows)
+
+    let Some(parent_id) = parent_id else {
+        return Ok(false);
+    };
+    let event = Event::AppTerminal {
+        app_id,
+        parent_id,
+        status: "timed_out".into(),
+    };
+    enqueue_event(&mut tx, &event).await?;
+    tx.commit().await?;
+    Ok(true)
 }
 
+/// Scheduled apps whose start deadline has passed. The deadline counts
+/// from `scheduled_at` when set, so apps scheduled for the future are
+/// never expired before their launch time; blocked apps don't expire.
+const START_DEADLINE_EXPIRED: &str = "status = 'scheduled' AND blocked_at IS NULL \
+    AND COALESCE(scheduled_at, created_at) <= NOW() \
+    AND COALESCE(scheduled_at, created_at) \
+        + make_interval(secs => COALESCE(start_deadline, 300)) < NOW()";
+
+/// Mark app as start_failed (deadline expired, never connected). The
+/// deadline is re-checked so a connect or reschedule racing the checker
+/// wins; returns false then.
+pub async fn set_start_failed(pool: &PgPool, app_id: Uuid) -> Result<bool, TrailsError> {
+    let result = sqlx::query(&format!(
+        r#"
+        UPDATE apps SET status = 'start_failed', disconnected_at = NOW()
+        WHERE app_id = $1 AND {START_DEADLINE_EXPIRED}
+        "#
+    ))
+    .bind(app_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark apps on this server instance as 'reconnecting' after restart (spec §19).
+pub async fn mark_reconnecting(
+    pool: &PgPool,
+    server_instance: &str,
+) -> Result<u64, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE apps SET status = 'reconnecting'
+        WHERE server_instance = $1
+          AND status IN ('connected', 'running')
+          AND transport = 'ws'
+        "#,
+    )
+    .bind(server_instance)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Hold an app whose process said it will reconnect (`will_reconnect`)
+/// as 'reconnecting'. False if it wasn't live.
+pub async fn mark_app_reconnecting(pool: &PgPool, app_id: Uuid) -> Result<bool, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE apps SET status = 'reconnecting', disconnected_at = NOW()
+        WHERE app_id = $1 AND status IN ('connected', 'running')
+        "#,
+    )
+    .bind(app_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark an app 'lost_contact' if it is still 'reconnecting' after
+/// `window_secs` of it. False if it came back (or left again since).
+pub async fn mark_app_lost_contact(
+    pool: &PgPool,
+    app_id: Uuid,
+    window_secs: u64,
+) -> Result<bool, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE apps SET status = 'lost_contact', disconnected_at = NOW()
+        WHERE app_id = $1 AND status = 'reconnecting'
+          AND disconnected_at <= NOW() - make_interval(secs => $2)
+        "#,
+    )
+    .bind(app_id)
+    .bind(window_secs as f64)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark apps that failed to reconnect within window as 'lost_contact'.
+pub async fn mark_lost_contact(
+    pool: &PgPool,
+    server_instance: &str,
+) -> Result<u64, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE apps SET status = 'lost_contact', disconnected_at = NOW()
+        WHERE server_instance = $1 AND status = 'reconnecting'
+        "#,
+    )
+    .bind(server_instance)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Re-connect an app after server restart, or one switching transports
+/// after posting over HTTP. Verifies pub_key matches.
+pub async fn reconnect_app(
+    pool: &PgPool,
+    app_id: Uuid,
+    pub_key: &str,
+    server_instance: &str,
+    transport: &str,
+) -> Result<Option<AppRow>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let row: Option<AppRow> = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET
+            status = CASE WHEN status = 'connected' THEN status ELSE 'running' END,
+            server_instance = $3,
+            connected_at = NOW(),
+            transport = $4,
+            last_seen_at = NOW()
+        WHERE app_id = $1
+          AND pub_key = $2
+          AND (status IN ('reconnecting', 'lost_contact')
+               OR (transport = 'http' AND status IN ('connected', 'running')))
+          AND NOT EXISTS (SELECT 1 FROM purged_apps WHERE app_id = $1)
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(pub_key)
+    .bind(server_instance)
+    .bind(transport)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(row) = &row {
+        let event = Event::AppConnected {
+            app_id,
+            parent_id: row.parent_id,
+        };
+        enqueue_event(&mut tx, &event).await?;
+    }
+    tx.commit().await?;
+    Ok(row)
+}
+
+/// Note an HTTP ingest batch from a live app: the key must match, and the
+/// app switches to (or stays on) the `http` transport. `None` if the key
+/// is wrong or the app is not live.
+pub async fn touch_http_app(
+    pool: &PgPool,
+    app_id: Uuid,
+    pub_key: &str,
+    server_instance: &str,
+) -> Result<Option<AppRow>, TrailsError> {
+    let row: Option<AppRow> = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET
+            transport = 'http',
+            last_seen_at = NOW(),
+            server_instance = $3
+        WHERE app_id = $1
+          AND pub_key = $2
+          AND status IN ('connected', 'running')
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(pub_key)
+    .bind(server_instance)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Note a heartbeat from a live app.
+pub async fn record_heartbeat(pool: &PgPool, app_id: Uuid) -> Result<(), TrailsError> {
+    sqlx::query(
+        r#"
+        UPDATE apps SET last_heartbeat = NOW()
+        WHERE app_id = $1 AND status IN ('connected', 'running')
+        "#,
+    )
+    .bind(app_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Crash live `http` apps that have not posted for `stale_secs`. Returns
+/// `(app_id, parent_id)` of each.
+pub async fn crash_stale_http_apps(
+    pool: &PgPool,
+    stale_secs: i64,
+) -> Result<Vec<(Uuid, Option<Uuid>)>, TrailsError> {
+    let rows: Vec<(Uuid, Option<Uuid>)> = sqlx::query_as(
+        r#"
+        UPDATE apps SET status = 'crashed', disconnected_at = NOW()
+        WHERE transport = 'http'
+          AND status IN ('connected', 'running')
+          AND last_seen_at < NOW() - make_interval(secs => $1)
+        RETURNING app_id, parent_id
+        "#,
+    )
+    .bind(stale_secs as f64)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Lookup an app by id.
+pub async fn get_app(pool: &PgPool, app_id: Uuid) -> Result<Option<AppRow>, TrailsError> {
+    let row: Option<AppRow> = sqlx::query_as(&format!(
+        "SELECT {APP_COLUMNS} FROM apps WHERE app_id = $1"
+    ))
+    .bind(app_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Get all 'scheduled' apps past their start deadline, highest priority first.
+pub async fn get_expired_scheduled(pool: &PgPool) -> Result<Vec<AppRow>, TrailsError> {
+    let rows: Vec<AppRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT {APP_COLUMNS}
+        FROM apps
+        WHERE {START_DEADLINE_EXPIRED}
+        ORDER BY priority DESC, created_at
+        "#
+    ))
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Per-child inputs for a parent rollup.
+#[derive(Debug, sqlx::FromRow)]
+pub struct RollupChildRow {
+    pub app_id: Uuid,
+    pub app_name: String,
+    pub status: String,
+    pub start_time: Option<DateTime<Utc>>,
+    /// `weight` tag from metadata_json, if any.
+    pub weight: Option<JsonValue>,
+    /// `progress` field of the latest snapshot.
+    pub progress: Option<JsonValue>,
+    pub last_activity: Option<DateTime<Utc>>,
+    /// `message` of the last Error message.
+    pub error_message: Option<String>,
+    pub crash_type: Option<String>,
+}
+
+/// Direct children of `parent_id` with their latest progress, activity,
+/// error and crash — one round trip per rollup.
+pub async fn get_rollup_children(
+    pool: &PgPool,
+    parent_id: Uuid,
+) -> Result<Vec<RollupChildRow>, TrailsError> {
+    let rows: Vec<RollupChildRow> = sqlx::query_as(
+        r#"
+        SELECT a.app_id, a.app_name, a.status, a.start_time,
+               a.metadata_json->'weight' AS weight,
+               s.progress,
+               GREATEST(a.connected_at, a.disconnected_at, m.last_at) AS last_activity,
+               e.error_message,
+               c.crash_type
+        FROM apps a
+        LEFT JOIN LATERAL (
+            SELECT snapshot_json->'progress' AS progress FROM snapshots
+            WHERE app_id = a.app_id ORDER BY created_at DESC, id DESC LIMIT 1
+        ) s ON true
+        LEFT JOIN LATERAL (
+            SELECT MAX(created_at) AS last_at FROM messages WHERE app_id = a.app_id
+        ) m ON true
+        LEFT JOIN LATERAL (
+            SELECT COALESCE(payload_json->>'message', payload_preview) AS error_message
+            FROM messages
+            WHERE app_id = a.app_id AND msg_type = 'Error'
+            ORDER BY seq DESC, id DESC LIMIT 1
+        ) e ON true
+        LEFT JOIN LATERAL (
+            SELECT crash_type FROM crashes
+            WHERE app_id = a.app_id ORDER BY detected_at DESC LIMIT 1
+        ) c ON true
+        WHERE a.parent_id = $1
+        "#,
+    )
+    .bind(parent_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Dependencies
+// ═══════════════════════════════════════════════════════════════
+
+/// Parent of each of `ids` that exists.
+pub async fn app_parents(
+    pool: &PgPool,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, Option<Uuid>>, TrailsError> {
+    let rows: Vec<(Uuid, Option<Uuid>)> =
+        sqlx::query_as("SELECT app_id, parent_id FROM apps WHERE app_id = ANY($1)")
+            .bind(ids)
+            .fetch_all(pool)
+            .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Blocked apps to look at; see `list_blocked`.
+#[derive(Debug, Clone, Copy)]
+pub enum Blocked<'a> {
+    /// Those waiting for this app.
+    DependentsOf(Uuid),
+    Apps(&'a [Uuid]),
+    All,
+}
+
+/// A blocked app and where its dependencies stand.
+#[derive(Debug, sqlx::FromRow)]
+pub struct BlockedApp {
+    pub app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub app_name: String,
+    pub depends_on: Vec<Uuid>,
+    /// Dependencies that are `done`.
+    pub done: i64,
+    /// Dependencies that ended otherwise, or were purged.
+    pub failed: Vec<Uuid>,
+}
+
+/// Blocked scheduled apps in `scope`, highest priority first, with the
+/// state of their dependencies.
+pub async fn list_blocked(
+    pool: &PgPool,
+    scope: Blocked<'_>,
+) -> Result<Vec<BlockedApp>, TrailsError> {
+    let mut qb = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT b.app_id, b.parent_id, b.app_name, b.depends_on,
+               COUNT(*) FILTER (WHERE d.status = 'done') AS done,
+               COALESCE(ARRAY_AGG(dep) FILTER (
+                   WHERE d.app_id IS NULL
+                      OR d.status IN ('error', 'crashed', 'cancelled', 'start_failed',
+                                      'timed_out')
+               ), '{}') AS failed
+        FROM apps b
+        CROSS JOIN LATERAL UNNEST(b.depends_on) AS dep
+        LEFT JOIN apps d ON d.app_id = dep
+        WHERE b.status = 'scheduled' AND b.blocked_at IS NOT NULL
+        "#,
+    );
+    match scope {
+        Blocked::DependentsOf(app_id) => {
+            qb.push(" AND b.depends_on @> ARRAY[").push_bind(app_id).push("]");
+        }
+        Blocked::Apps(ids) => {
+            qb.push(" AND b.app_id = ANY(").push_bind(ids).push(")");
+        }
+        Blocked::All => {}
+    }
+    qb.push(" GROUP BY b.app_id ORDER BY b.priority DESC, b.created_at");
+    let rows = qb.build_query_as().fetch_all(pool).await?;
+    Ok(rows)
+}
+
+/// Records `$2`, the failed dependencies, in `metadata_json` (if any and
+/// the metadata is an object).
+const RECORD_FAILED_DEPENDENCIES: &str = "metadata_json = CASE \
+    WHEN cardinality($2::UUID[]) > 0 \
+         AND (metadata_json IS NULL OR jsonb_typeof(metadata_json) = 'object') \
+    THEN COALESCE(metadata_json, '{}') || jsonb_build_object('failedDependencies', $2::UUID[]) \
+    ELSE metadata_json END";
+
+/// Unblock the app of `event` (a `DependenciesSatisfied`): its start
+/// deadline counts from now, or from a later `scheduled_at`. Queues the
+/// event in the same transaction. False if it was no longer blocked.
+pub async fn release_blocked(
+    pool: &PgPool,
+    event: &Event,
+    failed: &[Uuid],
+) -> Result<bool, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let released = sqlx::query(&format!(
+        r#"
+        UPDATE apps SET
+            blocked_at = NULL,
+            scheduled_at = GREATEST(COALESCE(scheduled_at, created_at), NOW()),
+            {RECORD_FAILED_DEPENDENCIES},
+            updated_at = NOW()
+        WHERE app_id = $1 AND status = 'scheduled' AND blocked_at IS NOT NULL
+        "#
+    ))
+    .bind(event.app_id())
+    .bind(failed)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if released {
+        enqueue_event(&mut tx, event).await?;
+    }
+    tx.commit().await?;
+    Ok(released)
+}
+
+/// Cancel the blocked app of `event` (its `AppTerminal`) because
+/// `failed` dependencies didn't finish. False if it was no longer blocked.
+pub async fn cancel_blocked(
+    pool: &PgPool,
+    event: &Event,
+    failed: &[Uuid],
+) -> Result<bool, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let cancelled = sqlx::query(&format!(
+        r#"
+        UPDATE apps SET
+            status = 'cancelled',
+            blocked_at = NULL,
+            {RECORD_FAILED_DEPENDENCIES},
+            updated_at = NOW()
+        WHERE app_id = $1 AND status = 'scheduled' AND blocked_at IS NOT NULL
+        "#
+    ))
+    .bind(event.app_id())
+    .bind(failed)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if cancelled {
+        enqueue_event(&mut tx, event).await?;
+    }
+    tx.commit().await?;
+    Ok(cancelled)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Queued controls
+// ═══════════════════════════════════════════════════════════════
+
+/// A control message queued for an app that had no connection here.
+#[derive(Debug, sqlx::FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingControl {
+    pub id: i64,
+    pub app_id: Uuid,
+    pub action: String,
+    #[serde(rename = "payload")]
+    pub payload_json: JsonValue,
+    /// `pending`, `delivered`, `acknowledged` or `expired`.
+    pub state: String,
+    pub requested_by: Option<String>,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub acked_at: Option<DateTime<Utc>>,
+    pub expired_at: Option<DateTime<Utc>>,
+}
+
+const PENDING_CONTROL_COLUMNS: &str = r#"
+    id, app_id, action, payload_json,
+    CASE
+        WHEN acked_at IS NOT NULL THEN 'acknowledged'
+        WHEN delivered_at IS NOT NULL THEN 'delivered'
+        WHEN expired_at IS NOT NULL THEN 'expired'
+        ELSE 'pending'
+    END AS state,
+    requested_by, request_id, created_at, expires_at, delivered_at, acked_at, expired_at
+"#;
+
+/// Queue `action` for `app_id` until its next registration, at most
+/// `ttl_secs` from now; with no TTL it is expired at once. None if the
+/// app doesn't exist or is terminal.
+#[allow(clippy::too_many_arguments)]
+pub async fn queue_control(
+    pool: &PgPool,
+    app_id: Uuid,
+    action: &str,
+    payload: &JsonValue,
+    ttl_secs: i64,
+    actor: Option<&str>,
+    request_id: Option<&str>,
+) -> Result<Option<PendingControl>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let id: Option<i64> = sqlx::query_scalar(
+        r#"
+        INSERT INTO pending_controls (app_id, action, payload_json, requested_by, request_id,
+                                      expires_at)
+        SELECT app_id, $2, $3, $4, $5, NOW() + make_interval(secs => GREATEST($6, 0))
+        FROM apps
+        WHERE app_id = $1
+          AND status NOT IN ('done', 'error', 'crashed', 'cancelled', 'start_failed',
+                             'timed_out')
+        RETURNING id
+        "#,
+    )
+    .bind(app_id)
+    .bind(action)
+    .bind(payload)
+    .bind(actor)
+    .bind(request_id)
+    .bind(ttl_secs as f64)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    if ttl_secs <= 0 {
+        expire_controls_in(&mut tx, Some(app_id)).await?;
+    }
+    let row: PendingControl = sqlx::query_as(&format!(
+        "SELECT {PENDING_CONTROL_COLUMNS} FROM pending_controls WHERE id = $1"
+    ))
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(row))
+}
+
+/// Claim `app_id`'s pending controls for delivery, oldest first, marking
+/// them delivered. Those past their expiry are expired instead. Claimed
+/// rows aren't handed out twice.
+pub async fn take_pending_controls(
+    pool: &PgPool,
+    app_id: Uuid,
+) -> Result<Vec<PendingControl>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    expire_controls_in(&mut tx, Some(app_id)).await?;
+    let mut rows: Vec<PendingControl> = sqlx::query_as(&format!(
+        r#"
+        UPDATE pending_controls SET delivered_at = NOW()
+        WHERE id IN (
+            SELECT id FROM pending_controls
+            WHERE app_id = $1 AND delivered_at IS NULL AND expired_at IS NULL
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING {PENDING_CONTROL_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    rows.sort_by_key(|row| row.id);
+    Ok(rows)
+}
+
+/// Return claimed controls that couldn't be sent to the queue.
+pub async fn unclaim_controls(pool: &PgPool, ids: &[i64]) -> Result<(), TrailsError> {
+    sqlx::query("UPDATE pending_controls SET delivered_at = NULL WHERE id = ANY($1)")
+        .bind(ids)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Expire pending controls past their expiry or whose app has ended,
+/// auditing each as `control_expired`. Returns how many.
+pub async fn expire_controls(pool: &PgPool) -> Result<u64, TrailsError> {
+    let mut conn = pool.acquire().await?;
+    expire_controls_in(&mut conn, None).await
+}
+
+async fn expire_controls_in(
+    conn: &mut PgConnection,
+    app_id: Option<Uuid>,
+) -> Result<u64, TrailsError> {
+    let expired: i64 = sqlx::query_scalar(
+        r#"
+        WITH expired AS (
+            UPDATE pending_controls SET expired_at = NOW()
+            WHERE id IN (
+                SELECT pc.id FROM pending_controls pc JOIN apps a ON a.app_id = pc.app_id
+                WHERE pc.delivered_at IS NULL AND pc.expired_at IS NULL
+                  AND ($1::UUID IS NULL OR pc.app_id = $1)
+                  AND (pc.expires_at <= NOW()
+                       OR a.status IN ('done', 'error', 'crashed', 'cancelled',
+                                       'start_failed', 'timed_out'))
+                LIMIT 1000
+                FOR UPDATE OF pc SKIP LOCKED
+            )
+            RETURNING id, app_id, action, requested_by, request_id, created_at, expires_at
+        ), audited AS (
+            INSERT INTO audit_log (action, target_app_id, payload_json, auth_domain,
+                                   oauth_subject, server_version, request_id)
+            SELECT 'control_expired', app_id,
+                   jsonb_build_object('controlId', id, 'action', action,
+                                      'queuedAt', created_at, 'expiresAt', expires_at),
+                   'external', requested_by, $2, request_id
+            FROM expired
+        )
+        SELECT COUNT(*) FROM expired
+        "#,
+    )
+    .bind(app_id)
+    .bind(SERVER_VERSION)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(expired as u64)
+}
+
+/// Controls queued for `app_id`, newest first.
+pub async fn list_controls(
+    pool: &PgPool,
+    app_id: Uuid,
+    limit: i64,
+) -> Result<Vec<PendingControl>, TrailsError> {
+    let rows: Vec<PendingControl> = sqlx::query_as(&format!(
+        r#"
+        SELECT {PENDING_CONTROL_COLUMNS} FROM pending_controls
+        WHERE app_id = $1
+        ORDER BY id DESC
+        LIMIT $2
+        "#
+    ))
+    .bind(app_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Messages
+// ═══════════════════════════════════════════════════════════════
+
+/// Store a data message (Status, Result, Error). When `blob` is set the
+/// payload lives in object storage and only the reference is stored;
+/// `sealed` payloads are encrypted envelopes. Returns the app's
+/// `stored_bytes` after the write, or None if an inbound message with
+/// this seq was already stored (a replay): nothing is written then.
+#[allow(clippy::too_many_arguments)]
+pub async fn store_message(
+    pool: &PgPool,
+    app_id: Uuid,
+    direction: &str,
+    msg_type: &str,
+    seq: i64,
+    correlation_id: Option<&str>,
+    payload: &JsonValue,
+    blob: Option<&BlobRef>,
+    sealed: bool,
+    redactions_applied: i32,
+) -> Result<Option<i64>, TrailsError> {
+    let stored: Option<i64> = sqlx::query_scalar(&format!(
+        r#"
+        WITH m AS (
+            INSERT INTO messages (app_id, direction, msg_type, seq, correlation_id,
+                                  payload_json, blob_url, blob_key, blob_size, blob_sha256,
+                                  payload_preview, sealed, redactions_applied)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            ON CONFLICT (app_id, seq) WHERE direction = 'in' DO NOTHING
+            RETURNING {MESSAGE_BYTES} AS bytes
+        )
+        UPDATE apps SET stored_bytes = stored_bytes + m.bytes
+        FROM m
+        WHERE app_id = $1
+        RETURNING stored_bytes
+        "#
+    ))
+    .bind(app_id)
+    .bind(direction)
+    .bind(msg_type)
+    .bind(seq)
+    .bind(correlation_id)
+    .bind(if blob.is_some() { None } else { Some(payload) })
+    .bind(blob.map(|b| &b.url))
+    .bind(blob.map(|b| &b.key))
+    .bind(blob.map(|b| b.size))
+    .bind(blob.map(|b| &b.sha256))
+    .bind(blob.map(|b| &b.preview))
+    .bind(sealed)
+    .bind(redactions_applied)
+    .fetch_optional(pool)
+    .await?;
+    Ok(stored)
+}
+
+/// A stored message. Offloaded payloads have `payload: None` and the
+/// blob columns set.
+#[derive(Debug, sqlx::FromRow)]
+pub struct MessageRow {
+    pub id: i64,
+    pub app_id: Uuid,
+    pub direction: String,
+    pub msg_type: String,
+    pub seq: i64,
+    pub correlation_id: Option<String>,
+    pub payload_json: Option<JsonValue>,
+    pub blob_url: Option<String>,
+    pub blob_key: Option<String>,
+    pub blob_size: Option<i64>,
+    pub blob_sha256: Option<String>,
+    pub payload_preview: Option<String>,
+    pub sealed: bool,
+    /// Values the redaction rules masked or dropped before storage.
+    pub redactions_applied: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Messages of one app in seq order, optionally filtered by type.
+pub async fn list_messages(
+    pool: &PgPool,
+    app_id: Uuid,
+    msg_type: Option<&str>,
+    after_seq: Option<i64>,
+    limit: i64,
+) -> Result<Vec<MessageRow>, TrailsError> {
+    let rows: Vec<MessageRow> = sqlx::query_as(
+        r#"
+        SELECT id, app_id, direction, msg_type, seq, correlation_id, payload_json,
+               blob_url, blob_key, blob_size, blob_sha256, payload_preview, sealed,
+               redactions_applied, created_at
+        FROM messages
+        WHERE app_id = $1
+          AND ($2::TEXT IS NULL OR msg_type = $2)
+          AND ($3::BIGINT IS NULL OR seq > $3)
+        ORDER BY seq, id
+        LIMIT $4
+        "#,
+    )
+    .bind(app_id)
+    .bind(msg_type)
+    .bind(after_seq)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Filters of a message export.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub msg_type: Option<String>,
+    /// Stored at or after.
+    pub from: Option<DateTime<Utc>>,
+    /// Stored before.
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// One chunk of an export: messages after the `(seq, id)` key, in that
+/// order.
+pub async fn export_messages(
+    pool: &PgPool,
+    app_id: Uuid,
+    filter: &ExportFilter,
+    after: Option<(i64, i64)>,
+    limit: i64,
+) -> Result<Vec<MessageRow>, TrailsError> {
+    let rows: Vec<MessageRow> = sqlx::query_as(
+        r#"
+        SELECT id, app_id, direction, msg_type, seq, correlation_id, payload_json,
+               blob_url, blob_key, blob_size, blob_sha256, payload_preview, sealed,
+               redactions_applied, created_at
+        FROM messages
+        WHERE app_id = $1
+          AND ($2::TEXT IS NULL OR msg_type = $2)
+          AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+          AND (seq, id) > ($5, $6)
+        ORDER BY seq, id
+        LIMIT $7
+        "#,
+    )
+    .bind(app_id)
+    .bind(filter.msg_type.as_deref())
+    .bind(filter.from)
+    .bind(filter.to)
+    // A plain row comparison keeps the (app_id, seq, id) index usable
+    // under a generic plan.
+    .bind(after.map_or(i64::MIN, |(seq, _)| seq))
+    .bind(after.map_or(i64::MIN, |(_, id)| id))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Highest message seq stored for an app, log lines included.
+pub async fn max_message_seq(pool: &PgPool, app_id: Uuid) -> Result<Option<i64>, TrailsError> {
+    let seq = sqlx::query_scalar(
+        r#"
+        SELECT GREATEST((SELECT MAX(seq) FROM messages WHERE app_id = $1),
+                        (SELECT MAX(seq) FROM logs WHERE app_id = $1))
+        "#,
+    )
+        .bind(app_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(seq)
+}
+
+/// Whether an inbound message with this seq is already stored, as a
+/// message or a log line; HTTP retries resend whole batches.
+pub async fn message_seq_exists(
+    pool: &PgPool,
+    app_id: Uuid,
+    seq: i64,
+) -> Result<bool, TrailsError> {
+    let exists = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM messages WHERE app_id = $1 AND seq = $2 AND direction = 'in')
+            OR EXISTS (SELECT 1 FROM logs WHERE app_id = $1 AND seq = $2)
+        "#,
+    )
+    .bind(app_id)
+    .bind(seq)
+    .fetch_one(pool)
+    .await?;
+    Ok(exists)
+}
+
+/// Messages matching an ad-hoc query (`query::MessageFilter`), in id
+/// order, one row past `limit` so the caller can tell if more remain.
+/// Runs under a statement timeout of `timeout_ms`.
+pub async fn query_messages(
+    pool: &PgPool,
+    filter: &MessageFilter,
+    timeout_ms: u64,
+) -> Result<Vec<MessageRow>, TrailsError> {
+    let mut qb = QueryBuilder::<Postgres>::new("");
+    if let Scope::Tree(parent) = &filter.scope {
+        qb.push("WITH RECURSIVE scope AS (SELECT app_id FROM apps WHERE app_id = ")
+            .push_bind(*parent)
+            .push(
+                " UNION ALL SELECT a.app_id FROM apps a JOIN scope s ON a.parent_id = s.app_id) ",
+            );
+    }
+    qb.push(
+        "SELECT id, app_id, direction, msg_type, seq, correlation_id, payload_json, \
+         blob_url, blob_key, blob_size, blob_sha256, payload_preview, sealed, \
+         redactions_applied, created_at \
+         FROM messages WHERE ",
+    );
+    match &filter.scope {
+        Scope::Apps(ids) => qb.push("app_id = ANY(").push_bind(ids).push(")"),
+        Scope::Tree(_) => qb.push("app_id IN (SELECT app_id FROM scope)"),
+    };
+    qb.push(" AND created_at >= ").push_bind(filter.from);
+    qb.push(" AND created_at < ").push_bind(filter.to);
+    if !filter.msg_types.is_empty() {
+        let types: Vec<&str> = filter.msg_types.iter().map(|t| t.as_str()).collect();
+        qb.push(" AND msg_type = ANY(").push_bind(types).push(")");
+    }
+    if let Some(id) = &filter.correlation_id {
+        qb.push(" AND correlation_id = ").push_bind(id.clone());
+    }
+    if let Some(after) = filter.after {
+        qb.push(" AND id > ").push_bind(after);
+    }
+    if !filter.predicates.is_empty() {
+        qb.push(" AND (");
+        for (i, predicate) in filter.predicates.iter().enumerate() {
+            if i > 0 {
+                qb.push(if filter.any { " OR " } else { " AND " });
+            }
+            match predicate {
+                // Sealed payloads are envelopes; their fields mean nothing.
+                Predicate::Payload { doc, exact } => {
+                    qb.push("(NOT sealed AND payload_json @> ").push_bind(doc.clone());
+                    if let Some((path, value)) = exact {
+                        qb.push(" AND payload_json #> ")
+                            .push_bind(path.clone())
+                            .push(" = ")
+                            .push_bind(value.clone());
+                    }
+                    qb.push(")");
+                }
+                Predicate::MsgType(t) => {
+                    qb.push("msg_type = ").push_bind(t.as_str());
+                }
+                Predicate::CorrelationId(id) => {
+                    qb.push("correlation_id = ").push_bind(id.clone());
+                }
+            }
+        }
+        qb.push(")");
+    }
+    qb.push(" ORDER BY id LIMIT ").push_bind(filter.limit + 1);
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT set_config('statement_timeout', $1, true)")
+        .bind(timeout_ms.to_string())
+        .execute(&mut *tx)
+        .await?;
+    let rows = qb
+        .build_query_as::<MessageRow>()
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.code().as_deref() == Some("57014") => {
+                TrailsError::QueryTooExpensive { limit_ms: timeout_ms }
+            }
+            _ => TrailsError::Db(e),
+        })?;
+    tx.commit().await?;
+    Ok(rows)
+}
+
+/// One app in a correlation trace.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceAppRow {
+    pub app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub app_name: String,
+    pub status: String,
+    pub namespace: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Launched under the id (`apps.correlation_id`), not only seen on
+    /// its messages.
+    pub from_config: bool,
+    /// Messages bearing the id.
+    pub message_count: i64,
+    pub first_message_at: Option<DateTime<Utc>>,
+    pub last_message_at: Option<DateTime<Utc>>,
+}
+
+/// Apps that carried `correlation_id`, from config or on any message, in
+/// timeline order (first message, else creation).
+pub async fn trace_apps(
+    pool: &PgPool,
+    correlation_id: &str,
+    limit: i64,
+) -> Result<Vec<TraceAppRow>, TrailsError> {
+    let rows = sqlx::query_as(
+        r#"
+        WITH msgs AS (
+            SELECT app_id, COUNT(*) AS message_count,
+                   MIN(created_at) AS first_message_at, MAX(created_at) AS last_message_at
+            FROM messages
+            WHERE correlation_id = $1
+            GROUP BY app_id
+        ), ids AS (
+            SELECT app_id FROM apps WHERE correlation_id = $1
+            UNION
+            SELECT app_id FROM msgs
+        )
+        SELECT a.app_id, a.parent_id, a.app_name, a.status, a.namespace, a.created_at,
+               COALESCE(a.correlation_id = $1, FALSE) AS from_config,
+               COALESCE(m.message_count, 0) AS message_count,
+               m.first_message_at, m.last_message_at
+        FROM ids
+        JOIN apps a ON a.app_id = ids.app_id
+        LEFT JOIN msgs m ON m.app_id = a.app_id
+        ORDER BY COALESCE(m.first_message_at, a.created_at), a.app_id
+        LIMIT $2
+        "#,
+    )
+    .bind(correlation_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Messages bearing `correlation_id` across all apps, in id order.
+pub async fn trace_messages(
+    pool: &PgPool,
+    correlation_id: &str,
+    after_id: Option<i64>,
+    limit: i64,
+) -> Result<Vec<MessageRow>, TrailsError> {
+    let rows = sqlx::query_as(
+        r#"
+        SELECT id, app_id, direction, msg_type, seq, correlation_id, payload_json,
+               blob_url, blob_key, blob_size, blob_sha256, payload_preview, sealed,
+               redactions_applied, created_at
+        FROM messages
+        WHERE correlation_id = $1
+          AND ($2::BIGINT IS NULL OR id > $2)
+        ORDER BY id
+        LIMIT $3
+        "#,
+    )
+    .bind(correlation_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Store a snapshot (Status messages double as snapshots). Returns the
+/// app's `stored_bytes` after the write.
+pub async fn store_snapshot(
+    pool: &PgPool,
+    app_id: Uuid,
+    namespace: Option<&str>,
+    seq: i64,
+    snapshot: &JsonValue,
+) -> Result<i64, TrailsError> {
+    let stored: Option<i64> = sqlx::query_scalar(&format!(
+        r#"
+        WITH s AS (
+            INSERT INTO snapshots (app_id, namespace, seq, snapshot_json)
+            VALUES ($1, $2, $3, $4)
+            RETURNING {SNAPSHOT_BYTES} AS bytes
+        )
+        UPDATE apps SET stored_bytes = stored_bytes + (SELECT bytes FROM s)
+        WHERE app_id = $1
+        RETURNING stored_bytes
+        "#
+    ))
+    .bind(app_id)
+    .bind(namespace)
+    .bind(seq)
+    .bind(snapshot)
+    .fetch_optional(pool)
+    .await?;
+    Ok(stored.unwrap_or_default())
+}
+
+/// Overwrite the app's latest snapshot in place (storage-quota
+/// coalescing). Returns the app's `stored_bytes` after the write, or None
+/// if the app has no snapshot yet.
+pub async fn coalesce_snapshot(
+    pool: &PgPool,
+    app_id: Uuid,
+    seq: i64,
+    snapshot: &JsonValue,
+) -> Result<Option<i64>, TrailsError> {
+    let stored: Option<i64> = sqlx::query_scalar(&format!(
+        r#"
+        WITH latest AS (
+            SELECT id, {SNAPSHOT_BYTES} AS bytes FROM snapshots
+            WHERE app_id = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT 1
+            FOR UPDATE
+        ),
+        s AS (
+            UPDATE snapshots SET seq = $2, snapshot_json = $3, created_at = NOW()
+            FROM latest WHERE snapshots.id = latest.id
+            RETURNING {SNAPSHOT_BYTES} - latest.bytes AS delta
+        )
+        UPDATE apps SET stored_bytes = stored_bytes + (SELECT delta FROM s)
+        WHERE app_id = $1 AND EXISTS (SELECT 1 FROM s)
+        RETURNING stored_bytes
+        "#
+    ))
+    .bind(app_id)
+    .bind(seq)
+    .bind(snapshot)
+    .fetch_optional(pool)
+    .await?;
+    Ok(stored)
+}
+
+/// A stored snapshot.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotRow {
+    pub seq: i64,
+    pub snapshot: JsonValue,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Newest snapshot of an app.
+pub async fn get_latest_snapshot(
+    pool: &PgPool,
+    app_id: Uuid,
+) -> Result<Option<SnapshotRow>, TrailsError> {
+    let row = sqlx::query_as(
+        r#"
+        SELECT seq, snapshot_json AS snapshot, created_at FROM snapshots
+        WHERE app_id = $1
+        ORDER BY created_at DESC, id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(app_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Snapshot at `seq` — the latest one, if several share the seq.
+pub async fn get_snapshot(
+    pool: &PgPool,
+    app_id: Uuid,
+    seq: i64,
+) -> Result<Option<JsonValue>, TrailsError> {
+    let row = sqlx::query_scalar(
+        r#"
+        SELECT snapshot_json FROM snapshots
+        WHERE app_id = $1 AND seq = $2
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(app_id)
+    .bind(seq)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Non-terminal apps created more than `min_age_secs` ago — candidates
+/// for snapshot compaction, highest priority first.
+pub async fn list_compactable_apps(
+    pool: &PgPool,
+    min_age_secs: i64,
+) -> Result<Vec<(Uuid, Option<String>)>, TrailsError> {
+    let rows: Vec<(Uuid, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT app_id, namespace FROM apps
+        WHERE status NOT IN ('done', 'error', 'crashed', 'cancelled', 'start_failed')
+          AND created_at < NOW() - make_interval(secs => $1)
+        ORDER BY priority DESC, created_at
+        "#,
+    )
+    .bind(min_age_secs as f64)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Snapshot ids of app `$1` that compaction removes, given tiers as
+/// parallel arrays `$2` (min age, seconds) and `$3` (bucket, seconds).
+///
+/// Each snapshot falls in the oldest tier its age qualifies for and is
+/// kept only if it is the first in its time bucket, the app's first or
+/// latest snapshot, or a phase change (`phase`, else `state`, differs from
+/// the previous snapshot). Kept rows stay kept on later runs, so repeated
+/// compaction is idempotent.
+const SNAPSHOT_COMPACTION_CANDIDATES: &str = r#"
+    WITH ordered AS (
+        SELECT id, created_at,
+               COALESCE(snapshot_json->>'phase', snapshot_json->>'state') AS phase,
+               LAG(COALESCE(snapshot_json->>'phase', snapshot_json->>'state'))
+                   OVER (ORDER BY created_at, id) AS prev_phase,
+               ROW_NUMBER() OVER (ORDER BY created_at, id) AS from_first,
+               ROW_NUMBER() OVER (ORDER BY created_at DESC, id DESC) AS from_last
+        FROM snapshots
+        WHERE app_id = $1
+    ),
+    tiered AS (
+        SELECT o.*, t.bucket
+        FROM ordered o
+        CROSS JOIN LATERAL (
+            SELECT tier.bucket
+            FROM UNNEST($2::BIGINT[], $3::BIGINT[]) AS tier(min_age, bucket)
+            WHERE o.created_at < NOW() - make_interval(secs => tier.min_age::DOUBLE PRECISION)
+            ORDER BY tier.min_age DESC
+            LIMIT 1
+        ) t
+    ),
+    bucketed AS (
+        SELECT id, from_first, from_last, phase, prev_phase,
+               ROW_NUMBER() OVER (
+                   PARTITION BY bucket, FLOOR(EXTRACT(EPOCH FROM created_at) / bucket)
+                   ORDER BY created_at, id
+               ) AS in_bucket
+        FROM tiered
+    )
+    SELECT id FROM bucketed
+    WHERE in_bucket > 1
+      AND from_first > 1
+      AND from_last > 1
+      AND phase IS NOT DISTINCT FROM prev_phase
+"#;
+
+/// Count snapshots compaction would delete for an app (dry run).
+pub async fn count_compactable_snapshots(
+    pool: &PgPool,
+    app_id: Uuid,
+    ages: &[i64],
+    buckets: &[i64],
+) -> Result<i64, TrailsError> {
+    let count: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM ({SNAPSHOT_COMPACTION_CANDIDATES}) candidates"
+    ))
+    .bind(app_id)
+    .bind(ages)
+    .bind(buckets)
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
+
+/// Delete up to `batch_size` compactable snapshots for an app.
+pub async fn compact_snapshots(
+    pool: &PgPool,
+    app_id: Uuid,
+    ages: &[i64],
+    buckets: &[i64],
+    batch_size: i64,
+) -> Result<u64, TrailsError> {
+    let result = sqlx::query(&format!(
+        "DELETE FROM snapshots WHERE id IN (
+            SELECT id FROM ({SNAPSHOT_COMPACTION_CANDIDATES}) candidates LIMIT $4
+        )"
+    ))
+    .bind(app_id)
+    .bind(ages)
+    .bind(buckets)
+    .bind(batch_size)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Storage quotas
+// ═══════════════════════════════════════════════════════════════
+
+/// Bytes a message row counts against its app's quota: the JSON text of
+/// an inline payload, or the preview of an offloaded one.
+const MESSAGE_BYTES: &str =
+    "COALESCE(octet_length(payload_json::text), 0) + COALESCE(octet_length(payload_preview), 0)";
+
+/// Bytes a snapshot row counts against its app's quota.
+const SNAPSHOT_BYTES: &str = "octet_length(snapshot_json::text)";
+
+/// Stamp the first crossing of the soft quota and enqueue its
+/// `QuotaWarning`. False if the app was already warned.
+pub async fn mark_quota_warned(pool: &PgPool, event: &Event) -> Result<bool, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let marked = sqlx::query(
+        "UPDATE apps SET quota_warned_at = NOW() WHERE app_id = $1 AND quota_warned_at IS NULL",
+    )
+    .bind(event.app_id())
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if marked {
+        enqueue_event(&mut tx, event).await?;
+    }
+    tx.commit().await?;
+    Ok(marked)
+}
+
+/// Admin change to an app's storage quota, audited in the same
+/// transaction. `Some(None)` clears an override; `reset` restarts usage
+/// counting (and the warning) from now.
+pub async fn set_storage_quota(
+    pool: &PgPool,
+    app_id: Uuid,
+    soft_bytes: Option<Option<i64>>,
+    hard_bytes: Option<Option<i64>>,
+    reset: bool,
+    actor: &str,
+    request_id: Option<&str>,
+) -> Result<Option<AppRow>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let previous: Option<(Option<i64>, Option<i64>, i64)> = sqlx::query_as(
+        r#"
+        SELECT quota_soft_bytes, quota_hard_bytes, stored_bytes FROM apps
+        WHERE app_id = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(app_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((prev_soft, prev_hard, prev_stored)) = previous else {
+        return Ok(None);
+    };
+    let soft = soft_bytes.unwrap_or(prev_soft);
+    let hard = hard_bytes.unwrap_or(prev_hard);
+
+    let row: AppRow = sqlx::query_as(&format!(
+        r#"
+        UPDATE apps SET
+            quota_soft_bytes = $2,
+            quota_hard_bytes = $3,
+            stored_bytes = CASE WHEN $4 THEN 0 ELSE stored_bytes END,
+            quota_reset_at = CASE WHEN $4 THEN NOW() ELSE quota_reset_at END,
+            quota_warned_at = CASE WHEN $4 THEN NULL ELSE quota_warned_at END,
+            updated_at = NOW()
+        WHERE app_id = $1
+        RETURNING {APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(soft)
+    .bind(hard)
+    .bind(reset)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let payload = serde_json::json!({
+        "from": { "softBytes": prev_soft, "hardBytes": prev_hard, "storedBytes": prev_stored },
+        "to": { "softBytes": soft, "hardBytes": hard, "storedBytes": row.stored_bytes },
+        "reset": reset,
+    });
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (action, target_app_id, payload_json, auth_domain, oauth_subject,
+                               server_version, request_id)
+        VALUES ('storage_quota', $1, $2, 'external', $3, $4, $5)
+        "#,
+    )
+    .bind(app_id)
+    .bind(&payload)
+    .bind(actor)
+    .bind(SERVER_VERSION)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(row))
+}
+
+/// Recompute `stored_bytes` from the stored rows for up to `limit` live
+/// apps after `after` (keyset on app_id). Returns the last app id seen —
+/// None once the pass is complete — and how many counters were off.
+///
+/// A write landing between the sums and the update is overwritten; the
+/// next pass corrects it.
+pub async fn reconcile_stored_bytes(
+    pool: &PgPool,
+    after: Option<Uuid>,
+    limit: i64,
+) -> Result<(Option<Uuid>, u64), TrailsError> {
+    let (last, corrected): (Option<Uuid>, i64) = sqlx::query_as(&format!(
+        r#"
+        WITH batch AS (
+            SELECT app_id, COALESCE(quota_reset_at, '-infinity') AS since FROM apps
+            WHERE ($1::uuid IS NULL OR app_id > $1)
+              AND status NOT IN ('done', 'error', 'crashed', 'cancelled', 'start_failed',
+                                 'timed_out')
+            ORDER BY app_id
+            LIMIT $2
+        ),
+        actual AS (
+            SELECT b.app_id,
+                   COALESCE((SELECT SUM({MESSAGE_BYTES}) FROM messages m
+                             WHERE m.app_id = b.app_id AND m.created_at >= b.since), 0)
+                 + COALESCE((SELECT SUM({SNAPSHOT_BYTES}) FROM snapshots s
+                             WHERE s.app_id = b.app_id AND s.created_at >= b.since), 0)
+                   AS bytes
+            FROM batch b
+        ),
+        fixed AS (
+            UPDATE apps a SET stored_bytes = actual.bytes
+            FROM actual
+            WHERE a.app_id = actual.app_id AND a.stored_bytes <> actual.bytes
+            RETURNING a.app_id
+        )
+        SELECT (SELECT app_id FROM batch ORDER BY app_id DESC LIMIT 1),
+               (SELECT COUNT(*) FROM fixed)
+        "#
+    ))
+    .bind(after)
+    .bind(limit)
+    .fetch_one(pool)
+    .await?;
+    Ok((last, corrected as u64))
+}
+
+/// Apps storing the most data, optionally within one namespace.
+pub async fn top_storage_consumers(
+    pool: &PgPool,
+    namespace: Option<&str>,
+    limit: i64,
+) -> Result<Vec<AppRow>, TrailsError> {
+    let rows: Vec<AppRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT {APP_COLUMNS} FROM apps
+        WHERE stored_bytes > 0 AND ($1::text IS NULL OR namespace = $1)
+        ORDER BY stored_bytes DESC, app_id
+        LIMIT $2
+        "#
+    ))
+    .bind(namespace)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Crashes
+// ═══════════════════════════════════════════════════════════════
+
+/// Record a crash event.
+pub async fn record_crash(
+    pool: &PgPool,
+    app_id: Uuid,
+    crash_type: &str,
+    gap_seconds: Option<f32>,
+    metadata: Option<&JsonValue>,
+) -> Result<(), TrailsError> {
+    let mut tx = pool.begin().await?;
+    let parent_id: Option<Uuid> = sqlx::query_scalar(
+        r#"
+        INSERT INTO crashes (app_id, crash_type, gap_seconds, metadata_json, server_version)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING (SELECT parent_id FROM apps WHERE app_id = $1)
+        "#,
+    )
+    .bind(app_id)
+    .bind(crash_type)
+    .bind(gap_seconds)
+    .bind(metadata)
+    .bind(SERVER_VERSION)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let event = Event::CrashDetected {
+        app_id,
+        parent_id,
+        crash_type: crash_type.into(),
+    };
+    enqueue_event(&mut tx, &event).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A detected crash loop for one job identity (app_name + parent).
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashLoopRow {
+    pub app_name: String,
+    pub parent_id: Option<Uuid>,
+    pub namespace: Option<String>,
+    /// Distinct runs that crashed within the window.
+    pub crash_count: i32,
+    pub last_app_id: Uuid,
+    pub detected_at: DateTime<Utc>,
+    pub last_crash_at: DateTime<Utc>,
+    pub cooldown_until: Option<DateTime<Utc>>,
+}
+
+/// Column list matching `CrashLoopRow`.
+const CRASH_LOOP_COLUMNS: &str = "app_name, parent_id, namespace, crash_count, \
+    last_app_id, detected_at, last_crash_at, cooldown_until";
+
+/// A crash loop is active while its identity keeps crashing within the
+/// window, or while its cool-down runs.
+const CRASH_LOOP_ACTIVE: &str = "(last_crash_at > NOW() - make_interval(secs => $1) \
+    OR cooldown_until > NOW())";
+
+/// Count recent crashed runs of `app_id`'s identity and, at `threshold` or
+/// more, record the loop and tag the app row. Returns the loop and whether
+/// it was newly detected — only then is `CrashLoopDetected` enqueued; a
+/// loop re-arms once the identity stays quiet for a full window.
+pub async fn check_crash_loop(
+    pool: &PgPool,
+    app_id: Uuid,
+    threshold: i64,
+    window_secs: i64,
+    cooldown_secs: i64,
+) -> Result<Option<(CrashLoopRow, bool)>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let Some((app_name, parent_id, namespace)): Option<(String, Option<Uuid>, Option<String>)> =
+        sqlx::query_as("SELECT app_name, parent_id, namespace FROM apps WHERE app_id = $1")
+            .bind(app_id)
+            .fetch_optional(&mut *tx)
+            .await?
+    else {
+        return Ok(None);
+    };
+
+    // Serialize detection per identity so concurrent crashes of sibling
+    // runs emit one event.
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1 || '/' || COALESCE($2::text, ''), 0))")
+        .bind(&app_name)
+        .bind(parent_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let crashed: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(DISTINCT c.app_id) FROM crashes c
+        JOIN apps a ON a.app_id = c.app_id
+        WHERE a.app_name = $1
+          AND a.parent_id IS NOT DISTINCT FROM $2
+          AND c.detected_at > NOW() - make_interval(secs => $3)
+        "#,
+    )
+    .bind(&app_name)
+    .bind(parent_id)
+    .bind(window_secs as f64)
+    .fetch_one(&mut *tx)
+    .await?;
+    if crashed < threshold {
+        tx.commit().await?;
+        return Ok(None);
+    }
+
+    // NOW() is the transaction start, so `detected_at = NOW()` holds for
+    // both a fresh insert and a re-armed loop.
+    let row: CrashLoopRow = sqlx::query_as(&format!(
+        r#"
+        INSERT INTO crash_loops
+            (app_name, parent_id, namespace, crash_count, last_app_id, cooldown_until)
+        VALUES ($1, $2, $3, $4, $5,
+                CASE WHEN $6 > 0 THEN NOW() + make_interval(secs => $6) END)
+        ON CONFLICT (app_name, COALESCE(parent_id, '00000000-0000-0000-0000-000000000000'::uuid))
+        DO UPDATE SET
+            namespace = EXCLUDED.namespace,
+            crash_count = EXCLUDED.crash_count,
+            last_app_id = EXCLUDED.last_app_id,
+            detected_at = CASE
+                WHEN crash_loops.last_crash_at < NOW() - make_interval(secs => $7)
+                THEN NOW() ELSE crash_loops.detected_at END,
+            last_crash_at = NOW(),
+            cooldown_until = EXCLUDED.cooldown_until
+        RETURNING {CRASH_LOOP_COLUMNS}
+        "#
+    ))
+    .bind(&app_name)
+    .bind(parent_id)
+    .bind(&namespace)
+    .bind(crashed as i32)
+    .bind(app_id)
+    .bind(cooldown_secs as f64)
+    .bind(window_secs as f64)
+    .fetch_one(&mut *tx)
+    .await?;
+    let now: DateTime<Utc> = sqlx::query_scalar("SELECT NOW()")
+        .fetch_one(&mut *tx)
+        .await?;
+    let new_loop = row.detected_at == now;
+
+    sqlx::query("UPDATE apps SET crash_loop_at = NOW(), updated_at = NOW() WHERE app_id = $1")
+        .bind(app_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if new_loop {
+        let event = Event::CrashLoopDetected {
+            app_id,
+            parent_id,
+            app_name: row.app_name.clone(),
+            crash_count: row.crash_count,
+            window_secs,
+        };
+        enqueue_event(&mut tx, &event).await?;
+    }
+    tx.commit().await?;
+    Ok(Some((row, new_loop)))
+}
+
+/// End of the registration cool-down for an identity, if one is running.
+pub async fn crash_loop_cooldown(
+    pool: &PgPool,
+    app_name: &str,
+    parent_id: Option<Uuid>,
+) -> Result<Option<DateTime<Utc>>, TrailsError> {
+    let until = sqlx::query_scalar(
+        r#"
+        SELECT cooldown_until FROM crash_loops
+        WHERE app_name = $1 AND parent_id IS NOT DISTINCT FROM $2
+          AND cooldown_until > NOW()
+        "#,
+    )
+    .bind(app_name)
+    .bind(parent_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(until)
+}
+
+/// Active crash loop for an identity.
+pub async fn get_crash_loop(
+    pool: &PgPool,
+    app_name: &str,
+    parent_id: Option<Uuid>,
+    window_secs: i64,
+) -> Result<Option<CrashLoopRow>, TrailsError> {
+    let row = sqlx::query_as(&format!(
+        r#"
+        SELECT {CRASH_LOOP_COLUMNS} FROM crash_loops
+        WHERE app_name = $2 AND parent_id IS NOT DISTINCT FROM $3
+          AND {CRASH_LOOP_ACTIVE}
+        "#
+    ))
+    .bind(window_secs as f64)
+    .bind(app_name)
+    .bind(parent_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Active crash loops fleet-wide, most recent crash first.
+pub async fn list_crash_loops(
+    pool: &PgPool,
+    window_secs: i64,
+    namespace: Option<&str>,
+    limit: i64,
+) -> Result<Vec<CrashLoopRow>, TrailsError> {
+    let rows = sqlx::query_as(&format!(
+        r#"
+        SELECT {CRASH_LOOP_COLUMNS} FROM crash_loops
+        WHERE {CRASH_LOOP_ACTIVE}
+          AND ($2::TEXT IS NULL OR namespace = $2)
+        ORDER BY last_crash_at DESC
+        LIMIT $3
+        "#
+    ))
+    .bind(window_secs as f64)
+    .bind(namespace)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Outbox
+// ═══════════════════════════════════════════════════════════════
+
+/// Insert an outbox row for `event`. Must run inside the transaction that
+/// performed the state change so the event commits (or rolls back) with it.
+async fn enqueue_event(conn: &mut PgConnection, event: &Event) -> Result<(), TrailsError> {
+    let payload = serde_json::to_value(event).unwrap_or_default();
+    sqlx::query(
+        r#"
+        INSERT INTO outbox (event_type, app_id, payload_json)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(event.event_type())
+    .bind(event.app_id())
+    .bind(&payload)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Undelivered outbox entry.
+#[derive(Debug, sqlx::FromRow)]
+pub struct OutboxRow {
+    pub id: i64,
+    pub event_type: String,
+    pub app_id: Option<Uuid>,
+    pub payload_json: JsonValue,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Per-sink delivery state for an outbox entry.
+#[derive(Debug, sqlx::FromRow)]
+pub struct OutboxDeliveryRow {
+    pub outbox_id: i64,
+    pub sink: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub dead_lettered_at: Option<DateTime<Utc>>,
+}
+
+/// Claim a batch of undelivered outbox rows that are due for `lease_secs`,
+/// in id order. Rows backing off (`next_attempt_at` ahead) wait. Concurrent
+/// dispatchers (other trailsd instances) skip rows claimed and not yet
+/// released, until the lease runs out.
+pub async fn claim_pending_outbox(
+    pool: &PgPool,
+    limit: i64,
+    lease_secs: i64,
+) -> Result<Vec<OutboxRow>, TrailsError> {
+    let mut rows: Vec<OutboxRow> = sqlx::query_as(
+        r#"
+        UPDATE outbox SET locked_until = NOW() + make_interval(secs => $2)
+        WHERE id IN (
+            SELECT id FROM outbox
+            WHERE completed_at IS NULL
+              AND (next_attempt_at IS NULL OR next_attempt_at <= NOW())
+              AND (locked_until IS NULL OR locked_until < NOW())
+            ORDER BY id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, event_type, app_id, payload_json, created_at
+        "#,
+    )
+    .bind(limit)
+    .bind(lease_secs as f64)
+    .fetch_all(pool)
+    .await?;
+    rows.sort_by_key(|row| row.id);
+    Ok(rows)
+}
+
+/// Delivery state for a set of outbox rows, across all sinks.
+pub async fn get_outbox_deliveries(
+    pool: &PgPool,
+    outbox_ids: &[i64],
+) -> Result<Vec<OutboxDeliveryRow>, TrailsError> {
+    let rows: Vec<OutboxDeliveryRow> = sqlx::query_as(
+        r#"
+        SELECT outbox_id, sink, attempts, next_attempt_at, delivered_at, dead_lettered_at
+        FROM outbox_deliveries
+        WHERE outbox_id = ANY($1)
+        "#,
+    )
+    .bind(outbox_ids)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Record a successful delivery to `sink`.
+pub async fn mark_outbox_delivered(
+    conn: &mut PgConnection,
+    outbox_id: i64,
+    sink: &str,
+) -> Result<(), TrailsError> {
+    sqlx::query(
+        r#"
+        INSERT INTO outbox_deliveries (outbox_id, sink, attempts, delivered_at)
+        VALUES ($1, $2, 1, NOW())
+        ON CONFLICT (outbox_id, sink) DO UPDATE SET
+            attempts = outbox_deliveries.attempts + 1,
+            last_error = NULL,
+            delivered_at = NOW()
+        "#,
+    )
+    .bind(outbox_id)
+    .bind(sink)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Record a failed delivery to `sink`. Once `attempts` reaches
+/// `max_attempts` the delivery is dead-lettered and no longer retried.
+/// Returns true if this failure dead-lettered the delivery.
+pub async fn mark_outbox_failed(
+    conn: &mut PgConnection,
+    outbox_id: i64,
+    sink: &str,
+    error: &str,
+    retry_after_secs: i64,
+    max_attempts: i32,
+) -> Result<bool, TrailsError> {
+    let dead: bool = sqlx::query_scalar(
+        r#"
+        INSERT INTO outbox_deliveries (outbox_id, sink, attempts, last_error, next_attempt_at, dead_lettered_at)
+        VALUES ($1, $2, 1, $3, NOW() + make_interval(secs => $4),
+                CASE WHEN $5 <= 1 THEN NOW() END)
+        ON CONFLICT (outbox_id, sink) DO UPDATE SET
+            attempts = outbox_deliveries.attempts + 1,
+            last_error = $3,
+            next_attempt_at = NOW() + make_interval(secs => $4),
+            dead_lettered_at = CASE
+                WHEN outbox_deliveries.attempts + 1 >= $5 THEN NOW()
+            END
+        RETURNING dead_lettered_at IS NOT NULL
+        "#,
+    )
+    .bind(outbox_id)
+    .bind(sink)
+    .bind(error)
+    .bind(retry_after_secs as f64)
+    .bind(max_attempts)
+    .fetch_one(conn)
+    .await?;
+    Ok(dead)
+}
+
+/// Release an outbox row's lease. `retry_at` is the earliest next attempt
+/// across its undelivered sinks; None completes the row, as every sink
+/// delivered it or dead-lettered it.
+pub async fn release_outbox(
+    conn: &mut PgConnection,
+    outbox_id: i64,
+    retry_at: Option<DateTime<Utc>>,
+) -> Result<(), TrailsError> {
+    sqlx::query(
+        r#"
+        UPDATE outbox SET locked_until = NULL,
+                          next_attempt_at = $2,
+                          completed_at = CASE WHEN $2 IS NULL THEN NOW() END
+        WHERE id = $1
+        "#,
+    )
+    .bind(outbox_id)
+    .bind(retry_at)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Make every pending outbox row due now, e.g. for a sink just added,
+/// which shouldn't wait out the others' backoff.
+pub async fn reset_outbox_backoff(pool: &PgPool) -> Result<u64, TrailsError> {
+    let result = sqlx::query(
+        "UPDATE outbox SET next_attempt_at = NULL \
+         WHERE completed_at IS NULL AND next_attempt_at IS NOT NULL",
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Outbox depth and age of the oldest undelivered row in seconds.
+pub async fn outbox_backlog(pool: &PgPool) -> Result<(i64, Option<f64>), TrailsError> {
+    let row: (i64, Option<f64>) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*),
+               EXTRACT(EPOCH FROM NOW() - MIN(created_at))::FLOAT8
+        FROM outbox
+        WHERE completed_at IS NULL
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Delete completed outbox rows older than `retention_secs`, in a bounded batch.
+pub async fn prune_outbox(pool: &PgPool, retention_secs: i64) -> Result<u64, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM outbox WHERE id IN (
+            SELECT id FROM outbox
+            WHERE completed_at < NOW() - make_interval(secs => $1)
+            LIMIT 1000
+        )
+        "#,
+    )
+    .bind(retention_secs as f64)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Payload schema registry
 // ═══════════════════════════════════════════════════════════════
-// Messages
-// ═══════════════════════════════════════════════════════════════
 
-/// Store a data message (Status, Result, Error).
-pub async fn store_message(
+/// A registered schema version for an app_name.
+#[derive(Debug, sqlx::FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaRow {
+    pub app_name: String,
+    pub version: i32,
+    pub status_schema: Option<JsonValue>,
+    pub result_schema: Option<JsonValue>,
+    pub mode: String,
+    pub referenced: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const SCHEMA_COLUMNS: &str = "app_name, version, status_schema, result_schema, mode, \
+                              referenced, created_at, updated_at";
+
+/// Store a schema for `app_name`. The latest version is replaced in place
+/// while nothing has been validated against it; once referenced it is
+/// immutable and a new version is created instead.
+pub async fn put_schema(
+    pool: &PgPool,
+    app_name: &str,
+    status_schema: Option<&JsonValue>,
+    result_schema: Option<&JsonValue>,
+    mode: &str,
+) -> Result<SchemaRow, TrailsError> {
+    let mut tx = pool.begin().await?;
+
+    // Serialize concurrent writers for the same app_name.
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(app_name)
+        .execute(&mut *tx)
+        .await?;
+
+    let latest: Option<(i32, bool)> = sqlx::query_as(
+        r#"
+        SELECT version, referenced FROM payload_schemas
+        WHERE app_name = $1
+        ORDER BY version DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(app_name)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let sql = match latest {
+        Some((_, false)) => format!(
+            r#"
+            UPDATE payload_schemas SET
+                status_schema = $3, result_schema = $4, mode = $5, updated_at = NOW()
+            WHERE app_name = $1 AND version = $2
+            RETURNING {SCHEMA_COLUMNS}
+            "#
+        ),
+        _ => format!(
+            r#"
+            INSERT INTO payload_schemas (app_name, version, status_schema, result_schema, mode)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING {SCHEMA_COLUMNS}
+            "#
+        ),
+    };
+    let version = match latest {
+        Some((v, false)) => v,
+        Some((v, true)) => v + 1,
+        None => 1,
+    };
+
+    let row: SchemaRow = sqlx::query_as(&sql)
+        .bind(app_name)
+        .bind(version)
+        .bind(status_schema)
+        .bind(result_schema)
+        .bind(mode)
+        .fetch_one(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(row)
+}
+
+/// Latest schema version for `app_name` — the one applied at ingest.
+pub async fn get_latest_schema(
+    pool: &PgPool,
+    app_name: &str,
+) -> Result<Option<SchemaRow>, TrailsError> {
+    let row: Option<SchemaRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT {SCHEMA_COLUMNS} FROM payload_schemas
+        WHERE app_name = $1
+        ORDER BY version DESC
+        LIMIT 1
+        "#
+    ))
+    .bind(app_name)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// A specific schema version.
+pub async fn get_schema(
+    pool: &PgPool,
+    app_name: &str,
+    version: i32,
+) -> Result<Option<SchemaRow>, TrailsError> {
+    let row: Option<SchemaRow> = sqlx::query_as(&format!(
+        "SELECT {SCHEMA_COLUMNS} FROM payload_schemas WHERE app_name = $1 AND version = $2"
+    ))
+    .bind(app_name)
+    .bind(version)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// All versions for `app_name`, newest first.
+pub async fn list_schema_versions(
+    pool: &PgPool,
+    app_name: &str,
+) -> Result<Vec<SchemaRow>, TrailsError> {
+    let rows: Vec<SchemaRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT {SCHEMA_COLUMNS} FROM payload_schemas
+        WHERE app_name = $1
+        ORDER BY version DESC
+        "#
+    ))
+    .bind(app_name)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Latest version of every registered app_name.
+pub async fn list_latest_schemas(pool: &PgPool) -> Result<Vec<SchemaRow>, TrailsError> {
+    let rows: Vec<SchemaRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT DISTINCT ON (app_name) {SCHEMA_COLUMNS}
+        FROM payload_schemas
+        ORDER BY app_name, version DESC
+        "#
+    ))
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Switch a version between 'warn' and 'enforce'. Mode is policy, not
+/// schema content, so it may change on referenced versions.
+pub async fn set_schema_mode(
+    pool: &PgPool,
+    app_name: &str,
+    version: i32,
+    mode: &str,
+) -> Result<Option<SchemaRow>, TrailsError> {
+    let row: Option<SchemaRow> = sqlx::query_as(&format!(
+        r#"
+        UPDATE payload_schemas SET mode = $3, updated_at = NOW()
+        WHERE app_name = $1 AND version = $2
+        RETURNING {SCHEMA_COLUMNS}
+        "#
+    ))
+    .bind(app_name)
+    .bind(version)
+    .bind(mode)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Freeze a schema version once a message has been validated against it.
+pub async fn mark_schema_referenced(
     pool: &PgPool,
-    app_id: Uuid,
-    direction: &str,
+    app_name: &str,
+    version: i32,
+) -> Result<(), TrailsError> {
+    sqlx::query(
+        r#"
+        UPDATE payload_schemas SET referenced = true
+        WHERE app_name = $1 AND version = $2 AND NOT referenced
+        "#,
+    )
+    .bind(app_name)
+    .bind(version)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Validation counters for one (schema version, msg_type).
+#[derive(Debug, sqlx::FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaStatsRow {
+    pub version: i32,
+    pub msg_type: String,
+    pub pass_count: i64,
+    pub warn_count: i64,
+    pub fail_count: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Add to the validation counters for a schema version.
+pub async fn add_schema_stats(
+    pool: &PgPool,
+    app_name: &str,
+    version: i32,
     msg_type: &str,
-    seq: i64,
-    correlation_id: Option<&str>,
-    payload: &JsonValue,
+    pass: i64,
+    warn: i64,
+    fail: i64,
 ) -> Result<(), TrailsError> {
     sqlx::query(
         r#"
-        INSERT INTO messages (app_id, direction, msg_type, seq, correlation_id, payload_json)
+        INSERT INTO schema_validation_stats
+            (app_name, version, msg_type, pass_count, warn_count, fail_count)
         VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (app_name, version, msg_type) DO UPDATE SET
+            pass_count = schema_validation_stats.pass_count + $4,
+            warn_count = schema_validation_stats.warn_count + $5,
+            fail_count = schema_validation_stats.fail_count + $6,
+            updated_at = NOW()
         "#,
     )
-    .bind(app_id)
-    .bind(direction)
+    .bind(app_name)
+    .bind(version)
     .bind(msg_type)
-    .bind(seq)
-    .bind(correlation_id)
-    .bind(payload)
+    .bind(pass)
+    .bind(warn)
+    .bind(fail)
     .execute(pool)
     .await?;
     Ok(())
 }
 
-/// Store a snapshot (Status messages double as snapshots).
-pub async fn store_snapshot(
+/// Validation counters for every version of `app_name`'s schema.
+pub async fn get_schema_stats(
     pool: &PgPool,
-    app_id: Uuid,
+    app_name: &str,
+) -> Result<Vec<SchemaStatsRow>, TrailsError> {
+    let rows: Vec<SchemaStatsRow> = sqlx::query_as(
+        r#"
+        SELECT version, msg_type, pass_count, warn_count, fail_count, updated_at
+        FROM schema_validation_stats
+        WHERE app_name = $1
+        ORDER BY version DESC, msg_type
+        "#,
+    )
+    .bind(app_name)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// An app with its newest snapshot, for wallboards.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LatestSnapshotRow {
+    pub app_id: Uuid,
+    pub app_name: String,
+    pub namespace: Option<String>,
+    pub status: String,
+    /// Latest of connect, disconnect and snapshot time.
+    pub last_seen: Option<DateTime<Utc>>,
+    pub seq: Option<i64>,
+    /// The snapshot, or only the requested fields keyed by JSON Pointer.
+    /// Null if the app has not reported yet.
+    pub snapshot: Option<JsonValue>,
+    /// The snapshot is a sealed envelope; fields are not trimmed.
+    pub sealed: bool,
+}
+
+/// Apps in `statuses` ordered by app_id, each with its newest snapshot.
+/// `fields` is `[[pointer, [token, ...]], ...]`; when set, each snapshot
+/// is reduced in SQL to an object of the pointers present in it.
+pub async fn list_latest_snapshots(
+    pool: &PgPool,
+    statuses: &[String],
     namespace: Option<&str>,
+    fields: Option<&JsonValue>,
+    after: Option<Uuid>,
+    limit: i64,
+) -> Result<Vec<LatestSnapshotRow>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    // A page is thousands of cheap index probes; the planner's cost
+    // estimate trips JIT, whose compile time dwarfs the query itself.
+    sqlx::query("SET LOCAL jit = off").execute(&mut *tx).await?;
+    let rows = sqlx::query_as(
+        r#"
+        WITH fields AS (
+            SELECT f->>0 AS ptr, ARRAY(SELECT jsonb_array_elements_text(f->1)) AS path
+            FROM jsonb_array_elements($3) f
+        )
+        SELECT a.app_id, a.app_name, a.namespace, a.status,
+               GREATEST(a.connected_at, a.disconnected_at, s.created_at) AS last_seen,
+               s.seq,
+               CASE
+                   WHEN $3::JSONB IS NULL OR a.sec_level = 'sealed' THEN s.snapshot_json
+                   WHEN s.snapshot_json IS NULL THEN NULL
+                   ELSE (
+                       SELECT COALESCE(jsonb_object_agg(ptr, value), '{}'::JSONB)
+                       FROM (SELECT ptr, s.snapshot_json #> path AS value FROM fields) v
+                       WHERE value IS NOT NULL
+                   )
+               END AS snapshot,
+               a.sec_level = 'sealed' AS sealed
+        FROM apps a
+        LEFT JOIN LATERAL (
+            SELECT seq, snapshot_json, created_at FROM snapshots
+            WHERE app_id = a.app_id ORDER BY created_at DESC, id DESC LIMIT 1
+        ) s ON true
+        WHERE a.status = ANY($1)
+          AND ($2::TEXT IS NULL OR a.namespace = $2)
+          AND ($4::UUID IS NULL OR a.app_id > $4)
+        ORDER BY a.app_id
+        LIMIT $5
+        "#,
+    )
+    .bind(statuses)
+    .bind(namespace)
+    .bind(fields)
+    .bind(after)
+    .bind(limit)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(rows)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Dead letters
+// ═══════════════════════════════════════════════════════════════
+
+/// A rejected inbound frame.
+#[derive(Debug, sqlx::FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetterRow {
+    pub id: i64,
+    pub app_id: Option<Uuid>,
+    pub reason: String,
+    pub detail: Option<String>,
+    pub raw_frame: String,
+    pub raw_size: i32,
+    pub truncated: bool,
+    pub received_at: DateTime<Utc>,
+    /// Connection id of the WebSocket that sent the frame.
+    pub request_id: Option<String>,
+}
+
+/// Store a rejected frame. Skipped (returns false) once the app already
+/// has `max_per_app` dead letters, so a misbehaving producer can't fill
+/// the table.
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_dead_letter(
+    pool: &PgPool,
+    app_id: Option<Uuid>,
+    reason: &str,
+    detail: Option<&str>,
+    raw_frame: &str,
+    raw_size: i32,
+    truncated: bool,
+    max_per_app: i64,
+    request_id: Option<&str>,
+) -> Result<bool, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO dead_letters (app_id, reason, detail, raw_frame, raw_size, truncated,
+                                  request_id)
+        SELECT $1, $2, $3, $4, $5, $6, $8
+        WHERE $1::UUID IS NULL
+           OR (SELECT COUNT(*) FROM (
+                   SELECT 1 FROM dead_letters WHERE app_id = $1 LIMIT $7
+               ) capped) < $7
+        "#,
+    )
+    .bind(app_id)
+    .bind(reason)
+    .bind(detail)
+    .bind(raw_frame)
+    .bind(raw_size)
+    .bind(truncated)
+    .bind(max_per_app)
+    .bind(request_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// List dead letters newest first. `before_id` pages backwards.
+pub async fn list_dead_letters(
+    pool: &PgPool,
+    app_id: Option<Uuid>,
+    reason: Option<&str>,
+    before_id: Option<i64>,
+    limit: i64,
+) -> Result<Vec<DeadLetterRow>, TrailsError> {
+    let rows: Vec<DeadLetterRow> = sqlx::query_as(
+        r#"
+        SELECT id, app_id, reason, detail, raw_frame, raw_size, truncated, received_at,
+               request_id
+        FROM dead_letters
+        WHERE ($1::UUID IS NULL OR app_id = $1)
+          AND ($2::TEXT IS NULL OR reason = $2)
+          AND ($3::BIGINT IS NULL OR id < $3)
+        ORDER BY id DESC
+        LIMIT $4
+        "#,
+    )
+    .bind(app_id)
+    .bind(reason)
+    .bind(before_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Delete dead letters older than `retention_secs`, in a bounded batch.
+pub async fn prune_dead_letters(pool: &PgPool, retention_secs: i64) -> Result<u64, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM dead_letters WHERE id IN (
+            SELECT id FROM dead_letters
+            WHERE received_at < NOW() - make_interval(secs => $1)
+            LIMIT 1000
+        )
+        "#,
+    )
+    .bind(retention_secs as f64)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// ═══════════════════════════════════════════════════════════════
+// App metrics
+// ═══════════════════════════════════════════════════════════════
+
+/// One bucket of a metric series.
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub struct MetricPoint {
+    #[serde(skip)]
+    pub metric: String,
+    /// Start of the bucket.
+    pub t: DateTime<Utc>,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    /// The value of the bucket's highest seq.
+    pub last: f64,
+    pub count: i64,
+}
+
+/// The app's `metrics` tag, if it has one.
+pub async fn app_metrics_tag(
+    pool: &PgPool,
+    app_id: Uuid,
+) -> Result<Option<JsonValue>, TrailsError> {
+    let tag: Option<Option<JsonValue>> =
+        sqlx::query_scalar("SELECT metadata_json->'metrics' FROM apps WHERE app_id = $1")
+            .bind(app_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(tag.flatten())
+}
+
+/// Store the metrics extracted from the Status at `seq`.
+pub async fn store_metrics(
+    pool: &PgPool,
+    app_id: Uuid,
     seq: i64,
-    snapshot: &JsonValue,
+    values: &[(&str, f64)],
 ) -> Result<(), TrailsError> {
+    let (metrics, values): (Vec<&str>, Vec<f64>) = values.iter().copied().unzip();
     sqlx::query(
         r#"
-        INSERT INTO snapshots (app_id, namespace, seq, snapshot_json)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO app_metrics (app_id, metric, value, seq)
+        SELECT $1, metric, value, $4
+        FROM UNNEST($2::TEXT[], $3::FLOAT8[]) AS m (metric, value)
         "#,
     )
     .bind(app_id)
-    .bind(namespace)
+    .bind(metrics)
+    .bind(values)
     .bind(seq)
-    .bind(snapshot)
     .execute(pool)
     .await?;
     Ok(())
 }
 
+/// The app's metrics (or just `metric`) in `[from, to)`, folded into
+/// `step_secs` buckets counted from `from`, ordered by metric and time.
+pub async fn metric_points(
+    pool: &PgPool,
+    app_id: Uuid,
+    metric: Option<&str>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    step_secs: i64,
+) -> Result<Vec<MetricPoint>, TrailsError> {
+    let rows = sqlx::query_as::<_, MetricPoint>(
+        r#"
+        SELECT metric,
+               $3 + make_interval(secs => floor(
+                   EXTRACT(EPOCH FROM created_at - $3)::FLOAT8 / $5) * $5) AS t,
+               avg(value) AS avg, min(value) AS min, max(value) AS max,
+               (array_agg(value ORDER BY seq DESC, id DESC))[1] AS last,
+               count(*) AS count
+        FROM app_metrics
+        WHERE app_id = $1 AND ($2::TEXT IS NULL OR metric = $2)
+          AND created_at >= $3 AND created_at < $4
+        GROUP BY metric, t
+        ORDER BY metric, t
+        "#,
+    )
+    .bind(app_id)
+    .bind(metric)
+    .bind(from)
+    .bind(to)
+    .bind(step_secs as f64)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Delete metric rows older than `retention_secs`, in a bounded batch.
+pub async fn prune_app_metrics(pool: &PgPool, retention_secs: i64) -> Result<u64, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM app_metrics WHERE id IN (
+            SELECT id FROM app_metrics
+            WHERE created_at < NOW() - make_interval(secs => $1)
+            LIMIT 5000
+        )
+        "#,
+    )
+    .bind(retention_secs as f64)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
 // ═══════════════════════════════════════════════════════════════
-// Crashes
+// Logs
 // ═══════════════════════════════════════════════════════════════
 
-/// Record a crash event.
-pub async fn record_crash(
+/// A stored log line.
+#[derive(Debug, sqlx::FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogRow {
+    pub id: i64,
+    pub app_id: Uuid,
+    pub seq: i64,
+    pub level: String,
+    pub message: String,
+    pub fields: Option<JsonValue>,
+    /// When the app logged it.
+    pub ts: DateTime<Utc>,
+}
+
+/// Store the log line that came as message `seq`. False if a line with
+/// this seq was already stored (a replay).
+pub async fn store_log(
     pool: &PgPool,
     app_id: Uuid,
-    crash_type: &str,
-    gap_seconds: Option<f32>,
-    metadata: Option<&JsonValue>,
+    seq: i64,
+    line: &LogLine,
+    ts: DateTime<Utc>,
+) -> Result<bool, TrailsError> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO logs (app_id, seq, level, message, fields_json, ts)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (app_id, seq) DO NOTHING
+        "#,
+    )
+    .bind(app_id)
+    .bind(seq)
+    .bind(&line.level)
+    .bind(&line.message)
+    .bind(&line.fields)
+    .bind(ts)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The app's log lines logged at or after `since`, oldest first.
+pub async fn get_logs(
+    pool: &PgPool,
+    app_id: Uuid,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<LogRow>, TrailsError> {
+    let rows = sqlx::query_as::<_, LogRow>(
+        r#"
+        SELECT id, app_id, seq, level, message, fields_json AS fields, ts
+        FROM logs
+        WHERE app_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR ts >= $2)
+        ORDER BY ts, id
+        LIMIT $3
+        "#,
+    )
+    .bind(app_id)
+    .bind(since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Purges
+// ═══════════════════════════════════════════════════════════════
+
+/// Tombstone left by a data purge.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgedAppRow {
+    pub app_id: Uuid,
+    pub root_app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name_sha256: String,
+    pub depth: i32,
+    pub purged_by: String,
+    pub requested_at: DateTime<Utc>,
+    /// NULL while the purge is still in progress.
+    pub purged_at: Option<DateTime<Utc>>,
+    /// Rows (and blobs) removed so far, per table.
+    pub removed: JsonValue,
+}
+
+/// Column list matching `PurgedAppRow`.
+const PURGED_APP_COLUMNS: &str = "app_id, root_app_id, parent_id, name_sha256, depth, \
+    purged_by, requested_at, purged_at, removed";
+
+/// Per-app tables emptied by a purge, in order. The apps row goes last.
+/// `messages` is handled separately because of offloaded blobs.
+pub const PURGE_TABLES: &[&str] = &[
+    "snapshots",
+    "dead_letters",
+    "crashes",
+    "control_queue",
+    "pending_controls",
+    "app_metrics",
+    "logs",
+    "grants",
+];
+
+/// Statuses that still have (or are about to regain) a live connection.
+const LIVE_STATUSES: &[&str] = &["connected", "running", "reconnecting"];
+
+/// Start a purge of `app_id` (and, with `cascade`, its descendants):
+/// lock the subtree, refuse live apps, and write one tombstone per app in
+/// a single transaction. If `app_id` already has a tombstone, returns the
+/// tombstones of that purge instead so the caller can resume or report it.
+/// Tombstones come back deepest first.
+pub async fn begin_purge(
+    pool: &PgPool,
+    app_id: Uuid,
+    cascade: bool,
+    purged_by: &str,
+) -> Result<Vec<PurgedAppRow>, TrailsError> {
+    let mut tx = pool.begin().await?;
+
+    let root: Option<Uuid> =
+        sqlx::query_scalar("SELECT root_app_id FROM purged_apps WHERE app_id = $1")
+            .bind(app_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if let Some(root) = root {
+        tx.commit().await?;
+        return list_purge(pool, root).await;
+    }
+
+    let tree: Vec<(Uuid, Option<Uuid>, String, String, i32)> = sqlx::query_as(
+        r#"
+        WITH RECURSIVE tree AS (
+            SELECT app_id, 0 AS depth FROM apps WHERE app_id = $1
+            UNION ALL
+            SELECT a.app_id, t.depth + 1
+            FROM apps a JOIN tree t ON a.parent_id = t.app_id
+            WHERE $2
+        )
+        SELECT a.app_id, a.parent_id, a.app_name, a.status, t.depth
+        FROM apps a JOIN tree t USING (app_id)
+        ORDER BY t.depth DESC
+        FOR UPDATE OF a
+        "#,
+    )
+    .bind(app_id)
+    .bind(cascade)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if tree.is_empty() {
+        return Err(TrailsError::AppNotFound(app_id));
+    }
+    if let Some((live, _, _, status, _)) =
+        tree.iter().find(|(_, _, _, status, _)| LIVE_STATUSES.contains(&status.as_str()))
+    {
+        return Err(TrailsError::Conflict(format!(
+            "app {live} is {status}; cancel it and wait for a terminal state before purging"
+        )));
+    }
+    if !cascade {
+        let children: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM apps WHERE parent_id = $1")
+            .bind(app_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if children > 0 {
+            return Err(TrailsError::Conflict(format!(
+                "app {app_id} has {children} child app(s); purge them first or pass cascade=true"
+            )));
+        }
+    }
+
+    let rows: Vec<PurgedAppRow> = sqlx::query_as(&format!(
+        r#"
+        INSERT INTO purged_apps (app_id, root_app_id, parent_id, name_sha256, depth, purged_by)
+        SELECT app_id, $1, parent_id, name_sha256, depth, $6
+        FROM UNNEST($2::UUID[], $3::UUID[], $4::TEXT[], $5::INT[])
+            AS t(app_id, parent_id, name_sha256, depth)
+        RETURNING {PURGED_APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .bind(tree.iter().map(|t| t.0).collect::<Vec<_>>())
+    .bind(tree.iter().map(|t| t.1).collect::<Vec<_>>())
+    .bind(
+        tree.iter()
+            .map(|t| format!("{:x}", Sha256::digest(t.2.as_bytes())))
+            .collect::<Vec<_>>(),
+    )
+    .bind(tree.iter().map(|t| t.4).collect::<Vec<_>>())
+    .bind(purged_by)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let mut rows = rows;
+    rows.sort_by_key(|r| std::cmp::Reverse(r.depth));
+    Ok(rows)
+}
+
+/// Tombstones of the purge rooted at `root_app_id`, deepest first.
+pub async fn list_purge(
+    pool: &PgPool,
+    root_app_id: Uuid,
+) -> Result<Vec<PurgedAppRow>, TrailsError> {
+    let rows = sqlx::query_as(&format!(
+        r#"
+        SELECT {PURGED_APP_COLUMNS} FROM purged_apps
+        WHERE root_app_id = $1
+        ORDER BY depth DESC, app_id
+        "#
+    ))
+    .bind(root_app_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Roots of purges that were interrupted before finishing.
+pub async fn list_unfinished_purges(pool: &PgPool) -> Result<Vec<Uuid>, TrailsError> {
+    let rows = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT root_app_id FROM purged_apps WHERE purged_at IS NULL
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Of `ids`, those that have been purged (or are being purged).
+pub async fn purged_app_ids(pool: &PgPool, ids: &[Uuid]) -> Result<Vec<Uuid>, TrailsError> {
+    let rows = sqlx::query_scalar("SELECT app_id FROM purged_apps WHERE app_id = ANY($1)")
+        .bind(ids)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// Add `count` to a tombstone's per-table tally.
+async fn add_removed(
+    conn: &mut PgConnection,
+    app_id: Uuid,
+    key: &str,
+    count: u64,
 ) -> Result<(), TrailsError> {
     sqlx::query(
         r#"
-        INSERT INTO crashes (app_id, crash_type, gap_seconds, metadata_json)
-        VALUES ($1, $2, $3, $4)
+        UPDATE purged_apps SET removed = jsonb_set(
+            removed, ARRAY[$2], to_jsonb(COALESCE((removed->>$2)::BIGINT, 0) + $3)
+        )
+        WHERE app_id = $1
         "#,
     )
     .bind(app_id)
-    .bind(crash_type)
-    .bind(gap_seconds)
-    .bind(metadata)
-    .execute(pool)
+    .bind(key)
+    .bind(count as i64)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Next batch of a purging app's messages: (id, blob_key).
+pub async fn next_purge_messages(
+    pool: &PgPool,
+    app_id: Uuid,
+    limit: i64,
+) -> Result<Vec<(i64, Option<String>)>, TrailsError> {
+    let rows = sqlx::query_as(
+        "SELECT id, blob_key FROM messages WHERE app_id = $1 ORDER BY id LIMIT $2",
+    )
+    .bind(app_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Delete a batch of messages whose blobs (`blobs` of them) are already
+/// gone, recording both counts on the tombstone in the same transaction.
+pub async fn delete_purge_messages(
+    pool: &PgPool,
+    app_id: Uuid,
+    ids: &[i64],
+    blobs: u64,
+) -> Result<u64, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let deleted = sqlx::query("DELETE FROM messages WHERE app_id = $1 AND id = ANY($2)")
+        .bind(app_id)
+        .bind(ids)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    add_removed(&mut tx, app_id, "messages", deleted).await?;
+    if blobs > 0 {
+        add_removed(&mut tx, app_id, "blobs", blobs).await?;
+    }
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+/// Delete up to `limit` of a purging app's rows from `table` (one of
+/// `PURGE_TABLES`), recording the count on the tombstone in the same
+/// transaction.
+pub async fn delete_purge_batch(
+    pool: &PgPool,
+    app_id: Uuid,
+    table: &str,
+    limit: i64,
+) -> Result<u64, TrailsError> {
+    debug_assert!(PURGE_TABLES.contains(&table));
+    let mut tx = pool.begin().await?;
+    let deleted = sqlx::query(&format!(
+        r#"
+        DELETE FROM {table} WHERE id IN (
+            SELECT id FROM {table} WHERE app_id = $1 LIMIT $2
+        )
+        "#
+    ))
+    .bind(app_id)
+    .bind(limit)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if deleted > 0 {
+        add_removed(&mut tx, app_id, table, deleted).await?;
+    }
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+/// Final step of a purge: delete the apps row, stamp the tombstone, and
+/// write the audit record and outbox event in one transaction. Returns
+/// the tombstone, or None if it was already finished.
+pub async fn finish_purge(
+    pool: &PgPool,
+    app_id: Uuid,
+    request_id: Option<&str>,
+) -> Result<Option<PurgedAppRow>, TrailsError> {
+    let mut tx = pool.begin().await?;
+    let deleted = sqlx::query("DELETE FROM apps WHERE app_id = $1")
+        .bind(app_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    add_removed(&mut tx, app_id, "apps", deleted).await?;
+
+    let row: Option<PurgedAppRow> = sqlx::query_as(&format!(
+        r#"
+        UPDATE purged_apps SET purged_at = NOW()
+        WHERE app_id = $1 AND purged_at IS NULL
+        RETURNING {PURGED_APP_COLUMNS}
+        "#
+    ))
+    .bind(app_id)
+    .fetch_optional(&mut *tx)
     .await?;
+
+    if let Some(row) = &row {
+        let payload = serde_json::json!({
+            "rootAppId": row.root_app_id,
+            "nameSha256": row.name_sha256,
+            "removed": row.removed,
+        });
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (action, target_app_id, cascade, payload_json,
+                                   auth_domain, oauth_subject, server_version, request_id)
+            VALUES ('purge', $1, $2, $3, 'external', $4, $5, $6)
+            "#,
+        )
+        .bind(app_id)
+        .bind(row.app_id != row.root_app_id)
+        .bind(&payload)
+        .bind(&row.purged_by)
+        .bind(SERVER_VERSION)
+        .bind(request_id)
+        .execute(&mut *tx)
+        .await?;
+        let event = Event::AppPurged {
+            app_id,
+            parent_id: row.parent_id,
+        };
+        enqueue_event(&mut tx, &event).await?;
+    }
+    tx.commit().await?;
+    Ok(row)
+}
+
+/// Take the cross-instance lock serialising work on one purge. Session
+/// level: it must be released on the same connection.
+pub async fn try_lock_purge(conn: &mut PgConnection, root_app_id: Uuid) -> Result<bool, TrailsError> {
+    let locked = sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtext($1))")
+        .bind(format!("trails-purge:{root_app_id}"))
+        .fetch_one(conn)
+        .await?;
+    Ok(locked)
+}
+
+pub async fn unlock_purge(conn: &mut PgConnection, root_app_id: Uuid) -> Result<(), TrailsError> {
+    sqlx::query("SELECT pg_advisory_unlock(hashtext($1))")
+        .bind(format!("trails-purge:{root_app_id}"))
+        .execute(conn)
+        .await?;
     Ok(())
 }
@@ -0,0 +1,165 @@
+//! Dependencies between sibling apps.
+//!
+//! A child pre-registered with `dependsOn` is created blocked: it stays
+//! `scheduled` with `blocked_at` set, and its start deadline doesn't run.
+//! Whenever a dependency reaches a terminal state its dependents are
+//! re-evaluated. Once all their dependencies are `done` they are released
+//! — the deadline counts from then — and `DependenciesSatisfied` goes to
+//! the bus and the outbox. A dependency ending any other way, or purged,
+//! applies `DEPENDENCY_FAILURE_POLICY`: `cancel` the dependent (whose own
+//! dependents follow), or `release` it anyway.
+//!
+//! Dependencies are existing siblings or siblings created in the same
+//! batch, so only a batch can declare a cycle; `unresolvable` finds them.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::config::DependencyFailurePolicy;
+use crate::db::{self, Blocked};
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::Event;
+
+/// Subscribe to the event bus and re-evaluate the dependents of every app
+/// that ends. Starts with a full pass for events missed while down.
+pub fn spawn_dependency_resolver(state: Arc<AppState>) {
+    let mut rx = state.event_tx.subscribe();
+    tokio::spawn(async move {
+        let mut scope = Some(Blocked::All);
+        loop {
+            if let Some(scope) = scope.take() {
+                if let Err(e) = resolve(&state, scope).await {
+                    warn!("dependency resolver error: {e}");
+                }
+            }
+            scope = match rx.recv().await {
+                Ok(Event::AppTerminal { app_id, .. })
+                | Ok(Event::CrashDetected { app_id, .. })
+                | Ok(Event::AppPurged { app_id, .. }) => Some(Blocked::DependentsOf(app_id)),
+                Ok(_) => None,
+                Err(RecvError::Lagged(n)) => {
+                    debug!(skipped = n, "dependency resolver lagged, checking all");
+                    Some(Blocked::All)
+                }
+                Err(RecvError::Closed) => return,
+            };
+        }
+    });
+}
+
+/// Release or cancel the blocked apps in `scope` whose dependencies have
+/// all ended. Also run for new apps, whose dependencies may have ended
+/// already.
+pub async fn resolve(state: &AppState, scope: Blocked<'_>) -> Result<(), TrailsError> {
+    let policy = state.config().dependency_failure_policy;
+    for app in db::list_blocked(&state.db, scope).await? {
+        if !app.failed.is_empty() && policy == DependencyFailurePolicy::Cancel {
+            let event = Event::AppTerminal {
+                app_id: app.app_id,
+                parent_id: app.parent_id,
+                status: "cancelled".into(),
+            };
+            if db::cancel_blocked(&state.db, &event, &app.failed).await? {
+                warn!(
+                    app_id = %app.app_id,
+                    app_name = %app.app_name,
+                    failed = ?app.failed,
+                    "dependency failed → cancelled"
+                );
+                state.metrics.inc("trails_dependents_cancelled_total", &[]);
+                state.publish(event);
+            }
+            continue;
+        }
+        if (app.done as usize) + app.failed.len() < app.depends_on.len() {
+            continue;
+        }
+        let event = Event::DependenciesSatisfied {
+            app_id: app.app_id,
+            parent_id: app.parent_id,
+            depends_on: app.depends_on.clone(),
+            failed_dependencies: app.failed.clone(),
+        };
+        if db::release_blocked(&state.db, &event, &app.failed).await? {
+            info!(
+                app_id = %app.app_id,
+                app_name = %app.app_name,
+                failed = ?app.failed,
+                "dependencies finished → released"
+            );
+            state.metrics.inc("trails_dependents_released_total", &[]);
+            state.publish(event);
+        }
+    }
+    Ok(())
+}
+
+/// Apps of a batch (`app id → dependencies`) that can never be released:
+/// those on a dependency cycle and those waiting for one. Dependencies
+/// outside the batch are ignored.
+pub fn unresolvable(batch: &HashMap<Uuid, Vec<Uuid>>) -> HashSet<Uuid> {
+    // Peel off apps whose in-batch dependencies are all peeled already;
+    // what remains is stuck.
+    let mut waiting: HashMap<Uuid, usize> = batch
+        .iter()
+        .map(|(id, deps)| {
+            let in_batch: HashSet<&Uuid> = deps.iter().filter(|d| batch.contains_key(d)).collect();
+            (*id, in_batch.len())
+        })
+        .collect();
+    let mut dependents: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for (id, deps) in batch {
+        let unique: HashSet<&Uuid> = deps.iter().collect();
+        for dep in unique.into_iter().filter(|d| batch.contains_key(d)) {
+            dependents.entry(*dep).or_default().push(*id);
+        }
+    }
+    let mut ready: Vec<Uuid> = waiting
+        .iter()
+        .filter(|(_, n)| **n == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    while let Some(id) = ready.pop() {
+        waiting.remove(&id);
+        for dependent in dependents.get(&id).into_iter().flatten() {
+            if let Some(n) = waiting.get_mut(dependent) {
+                *n -= 1;
+                if *n == 0 {
+                    ready.push(*dependent);
+                }
+            }
+        }
+    }
+    waiting.into_keys().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unresolvable_finds_cycles_and_their_dependents() {
+        let ids: Vec<Uuid> = (0..6).map(|_| Uuid::new_v4()).collect();
+        let outside = Uuid::new_v4();
+        let batch = HashMap::from([
+            // 0 ← 1 ← 2: a chain, fine.
+            (ids[0], vec![outside]),
+            (ids[1], vec![ids[0], ids[0]]),
+            (ids[2], vec![ids[1], ids[0]]),
+            // 3 ⇄ 4, and 5 waits for the cycle.
+            (ids[3], vec![ids[4]]),
+            (ids[4], vec![ids[3], ids[0]]),
+            (ids[5], vec![ids[4]]),
+        ]);
+        let stuck = unresolvable(&batch);
+        assert_eq!(stuck, HashSet::from([ids[3], ids[4], ids[5]]));
+
+        let own = HashMap::from([(ids[0], vec![ids[0]])]);
+        assert_eq!(unresolvable(&own), HashSet::from([ids[0]]));
+    }
+}
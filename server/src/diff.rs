@@ -0,0 +1,360 @@
+//! JSON diffs between snapshots: RFC 6902 JSON Patch and RFC 7386 merge
+//! patch.
+//!
+//! JSON Patch output uses only `add`, `remove` and `replace`. Arrays are
+//! diffed element by element while both sides are at most `array_max`
+//! long; past that the whole array is replaced, which keeps the diff
+//! linear and the patch predictable for big lists.
+
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+
+/// One RFC 6902 operation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: JsonValue },
+    Remove { path: String },
+    Replace { path: String, value: JsonValue },
+}
+
+/// JSON Patch turning `from` into `to`. Arrays longer than `array_max`
+/// are replaced whole rather than diffed.
+pub fn json_patch(from: &JsonValue, to: &JsonValue, array_max: usize) -> Vec<PatchOp> {
+    let mut ops = Vec::new();
+    diff_into(from, to, "", array_max, &mut ops);
+    ops
+}
+
+fn diff_into(
+    from: &JsonValue,
+    to: &JsonValue,
+    path: &str,
+    array_max: usize,
+    ops: &mut Vec<PatchOp>,
+) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (JsonValue::Object(a), JsonValue::Object(b)) => {
+            for (key, old) in a {
+                let child = pointer(path, key);
+                match b.get(key) {
+                    Some(new) => diff_into(old, new, &child, array_max, ops),
+                    None => ops.push(PatchOp::Remove { path: child }),
+                }
+            }
+            for (key, new) in b {
+                if !a.contains_key(key) {
+                    ops.push(PatchOp::Add {
+                        path: pointer(path, key),
+                        value: new.clone(),
+                    });
+                }
+            }
+        }
+        (JsonValue::Array(a), JsonValue::Array(b)) if a.len().max(b.len()) <= array_max => {
+            let common = a.len().min(b.len());
+            for i in 0..common {
+                diff_into(&a[i], &b[i], &pointer(path, &i.to_string()), array_max, ops);
+            }
+            // Trailing removals go last-first so earlier indices stay valid.
+            for i in (common..a.len()).rev() {
+                ops.push(PatchOp::Remove {
+                    path: pointer(path, &i.to_string()),
+                });
+            }
+            for (i, new) in b.iter().enumerate().skip(common) {
+                ops.push(PatchOp::Add {
+                    path: pointer(path, &i.to_string()),
+                    value: new.clone(),
+                });
+            }
+        }
+        _ => ops.push(PatchOp::Replace {
+            path: path.to_string(),
+            value: to.clone(),
+        }),
+    }
+}
+
+/// Append one reference token to a JSON Pointer (RFC 6901 escaping).
+fn pointer(path: &str, token: &str) -> String {
+    format!("{path}/{}", token.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Merge patch turning `from` into `to`. Merge patches can't set a member
+/// to `null` (null means "remove"); `Err` carries the JSON Pointer of the
+/// first such member.
+pub fn merge_patch(from: &JsonValue, to: &JsonValue) -> Result<JsonValue, String> {
+    merge_into(from, to, "")
+}
+
+fn merge_into(from: &JsonValue, to: &JsonValue, path: &str) -> Result<JsonValue, String> {
+    let (JsonValue::Object(a), JsonValue::Object(b)) = (from, to) else {
+        // Replaced wholesale — but an object value is still applied as a
+        // merge, which would drop its null members.
+        check_nulls(to, path)?;
+        return Ok(to.clone());
+    };
+    let mut patch = Map::new();
+    for key in a.keys() {
+        if !b.contains_key(key) {
+            patch.insert(key.clone(), JsonValue::Null);
+        }
+    }
+    for (key, new) in b {
+        let child = pointer(path, key);
+        match a.get(key) {
+            Some(old) if old == new => {}
+            _ if new.is_null() => return Err(child),
+            Some(old) => {
+                patch.insert(key.clone(), merge_into(old, new, &child)?);
+            }
+            None => {
+                check_nulls(new, &child)?;
+                patch.insert(key.clone(), new.clone());
+            }
+        }
+    }
+    Ok(JsonValue::Object(patch))
+}
+
+/// Fail on any null object member outside arrays (arrays apply verbatim).
+fn check_nulls(value: &JsonValue, path: &str) -> Result<(), String> {
+    if let JsonValue::Object(map) = value {
+        for (key, member) in map {
+            let child = pointer(path, key);
+            if member.is_null() {
+                return Err(child);
+            }
+            check_nulls(member, &child)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Minimal RFC 6902 apply for the ops we emit.
+    fn apply(doc: &JsonValue, ops: &[PatchOp]) -> JsonValue {
+        let mut doc = doc.clone();
+        for op in ops {
+            let (path, value) = match op {
+                PatchOp::Add { path, value } | PatchOp::Replace { path, value } => {
+                    (path, Some(value.clone()))
+                }
+                PatchOp::Remove { path } => (path, None),
+            };
+            if path.is_empty() {
+                doc = value.expect("root remove");
+                continue;
+            }
+            let (parent, last) = path.rsplit_once('/').unwrap();
+            let last = last.replace("~1", "/").replace("~0", "~");
+            let target = doc.pointer_mut(parent).expect("parent exists");
+            match (target, matches!(op, PatchOp::Add { .. })) {
+                (JsonValue::Object(map), _) => match value {
+                    Some(v) => {
+                        map.insert(last, v);
+                    }
+                    None => {
+                        map.remove(&last).expect("member exists");
+                    }
+                },
+                (JsonValue::Array(arr), is_add) => {
+                    let i: usize = last.parse().unwrap();
+                    match value {
+                        Some(v) if is_add => arr.insert(i, v),
+                        Some(v) => arr[i] = v,
+                        None => {
+                            arr.remove(i);
+                        }
+                    }
+                }
+                _ => panic!("bad target for {path}"),
+            }
+        }
+        doc
+    }
+
+    /// RFC 7386 MergePatch.
+    fn apply_merge(target: &JsonValue, patch: &JsonValue) -> JsonValue {
+        let JsonValue::Object(patch) = patch else {
+            return patch.clone();
+        };
+        let mut out = match target {
+            JsonValue::Object(map) => map.clone(),
+            _ => Map::new(),
+        };
+        for (key, value) in patch {
+            if value.is_null() {
+                out.remove(key);
+            } else {
+                let merged = apply_merge(out.get(key).unwrap_or(&JsonValue::Null), value);
+                out.insert(key.clone(), merged);
+            }
+        }
+        JsonValue::Object(out)
+    }
+
+    fn round_trip(from: JsonValue, to: JsonValue) -> Vec<PatchOp> {
+        let ops = json_patch(&from, &to, 16);
+        assert_eq!(apply(&from, &ops), to, "json patch {ops:?}");
+        if let Ok(patch) = merge_patch(&from, &to) {
+            assert_eq!(apply_merge(&from, &patch), to, "merge patch {patch}");
+        }
+        ops
+    }
+
+    #[test]
+    fn test_equal_documents_produce_empty_patch() {
+        let doc = json!({"a": [1, {"b": null}], "c": "x"});
+        assert!(json_patch(&doc, &doc, 16).is_empty());
+        assert_eq!(merge_patch(&doc, &doc).unwrap(), json!({}));
+    }
+
+    #[test]
+    fn test_nested_object_changes() {
+        let ops = round_trip(
+            json!({"progress": {"pct": 10, "stage": "scan"}, "gone": true}),
+            json!({"progress": {"pct": 55, "stage": "scan", "eta": 30}, "new": [1]}),
+        );
+        assert_eq!(
+            ops,
+            vec![
+                PatchOp::Remove {
+                    path: "/gone".into()
+                },
+                PatchOp::Replace {
+                    path: "/progress/pct".into(),
+                    value: json!(55)
+                },
+                PatchOp::Add {
+                    path: "/progress/eta".into(),
+                    value: json!(30)
+                },
+                PatchOp::Add {
+                    path: "/new".into(),
+                    value: json!([1])
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_array_append_and_truncate() {
+        let ops = round_trip(json!({"xs": [1, 2]}), json!({"xs": [1, 2, 3, 4]}));
+        assert_eq!(
+            ops,
+            vec![
+                PatchOp::Add {
+                    path: "/xs/2".into(),
+                    value: json!(3)
+                },
+                PatchOp::Add {
+                    path: "/xs/3".into(),
+                    value: json!(4)
+                },
+            ]
+        );
+        let ops = round_trip(json!({"xs": [1, 2, 3, 4]}), json!({"xs": [1, 9]}));
+        assert_eq!(
+            ops,
+            vec![
+                PatchOp::Replace {
+                    path: "/xs/1".into(),
+                    value: json!(9)
+                },
+                PatchOp::Remove {
+                    path: "/xs/3".into()
+                },
+                PatchOp::Remove {
+                    path: "/xs/2".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_objects_inside_arrays() {
+        round_trip(
+            json!({"workers": [{"id": 1, "state": "idle"}, {"id": 2, "state": "busy"}]}),
+            json!({"workers": [{"id": 1, "state": "busy"}, {"id": 2}]}),
+        );
+    }
+
+    #[test]
+    fn test_large_arrays_replaced_whole() {
+        let from = json!({"rows": (0..20).collect::<Vec<_>>()});
+        let to = json!({"rows": (0..21).collect::<Vec<_>>()});
+        let ops = round_trip(from, to.clone());
+        assert_eq!(
+            ops,
+            vec![PatchOp::Replace {
+                path: "/rows".into(),
+                value: to["rows"].clone()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_type_changes_and_root_replace() {
+        round_trip(json!({"a": {"b": 1}}), json!({"a": [1]}));
+        round_trip(json!({"a": 1}), json!({"a": {"b": {"c": 2}}}));
+        let ops = round_trip(json!([1, 2]), json!({"a": 1}));
+        assert_eq!(
+            ops,
+            vec![PatchOp::Replace {
+                path: "".into(),
+                value: json!({"a": 1})
+            }]
+        );
+    }
+
+    #[test]
+    fn test_pointer_escaping() {
+        let ops = round_trip(json!({"a/b": 1, "m~n": 1}), json!({"a/b": 2, "m~n": 2}));
+        assert_eq!(
+            ops,
+            vec![
+                PatchOp::Replace {
+                    path: "/a~1b".into(),
+                    value: json!(2)
+                },
+                PatchOp::Replace {
+                    path: "/m~0n".into(),
+                    value: json!(2)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_patch_nested() {
+        let patch = merge_patch(
+            &json!({"a": {"b": 1, "c": 2}, "d": [1, 2], "e": 1}),
+            &json!({"a": {"b": 1, "c": 3}, "d": [1]}),
+        )
+        .unwrap();
+        assert_eq!(patch, json!({"a": {"c": 3}, "d": [1], "e": null}));
+    }
+
+    #[test]
+    fn test_merge_patch_rejects_nulls() {
+        assert_eq!(
+            merge_patch(&json!({"a": 1}), &json!({"a": null})),
+            Err("/a".into())
+        );
+        assert_eq!(
+            merge_patch(&json!({}), &json!({"a": {"b": {"c": null}}})),
+            Err("/a/b/c".into())
+        );
+        // Nulls inside arrays are carried verbatim.
+        round_trip(json!({"a": []}), json!({"a": [null, {"b": null}]}));
+    }
+}
@@ -0,0 +1,58 @@
+//! Drain mode and graceful shutdown for rolling upgrades.
+//!
+//! Drain is the first phase: the instance stops accepting new
+//! registrations (clients get the retryable `draining` code and land on
+//! another pod), /readyz turns 503, and existing connections keep being
+//! served. SIGTERM is the second phase: drain if not already draining,
+//! wait for connections to finish or `shutdown_grace` to elapse, then exit.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::state::AppState;
+
+/// Enter drain mode on SIGUSR1.
+pub fn spawn_drain_signal(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut usr1 = match signal(SignalKind::user_defined1()) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("cannot install SIGUSR1 handler: {e}");
+                return;
+            }
+        };
+        while usr1.recv().await.is_some() {
+            if !state.set_draining(true) {
+                info!(live = state.connections.len(), "SIGUSR1: draining");
+            }
+        }
+    });
+}
+
+/// Resolves once the server should stop: after SIGTERM or Ctrl-C, drain
+/// and wait for live connections to close, bounded by `shutdown_grace`.
+pub async fn shutdown_signal(state: Arc<AppState>) {
+    let mut term = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = term.recv() => info!("SIGTERM received"),
+        _ = tokio::signal::ctrl_c() => info!("interrupt received"),
+    }
+
+    state.set_draining(true);
+    let grace = Duration::from_secs(state.config().shutdown_grace);
+    info!(live = state.connections.len(), grace_secs = grace.as_secs(), "draining before shutdown");
+
+    let wait = async {
+        while !state.connections.is_empty() {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    };
+    if tokio::time::timeout(grace, wait).await.is_err() {
+        warn!(live = state.connections.len(), "grace period elapsed, shutting down");
+    } else {
+        info!("all connections closed, shutting down");
+    }
+}
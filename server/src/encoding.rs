@@ -0,0 +1,184 @@
+//! Payload encodings. A client may gzip a large payload and send the
+//! base64 of the gzip as a JSON string, marking the message header
+//! `"encoding": "gzip"`. The payload is decoded before anything else
+//! looks at it, so it is checked, stored and passed on as if sent plain.
+//!
+//! This stands in for permessage-deflate, which neither axum's nor the
+//! Rust client's WebSocket negotiates. The decoded payload is held to
+//! `MAX_MESSAGE_BYTES`, like the frame it came in.
+//!
+//! A client may also send whole frames as MessagePack, in binary
+//! WebSocket messages, once its register offered `"binary": "msgpack"`
+//! and `registered` took the offer. Those are turned into the JSON they
+//! encode on arrival, and handled as if sent as text.
+
+use std::io::Read;
+
+use base64::Engine;
+use flate2::read::GzDecoder;
+
+use crate::error::TrailsError;
+use crate::types::DataMsg;
+
+pub const GZIP: &str = "gzip";
+pub const MSGPACK: &str = "msgpack";
+
+/// The answer to a register's offer of a binary encoding: the one taken,
+/// if any.
+pub fn binary_taken(offer: Option<&str>) -> Option<&'static str> {
+    (offer == Some(MSGPACK)).then_some(MSGPACK)
+}
+
+/// The JSON text of a MessagePack frame. The frame is held to `limit`;
+/// the JSON is, by the caller, as a text frame is.
+pub fn msgpack_frame(bytes: &[u8], limit: usize) -> Result<String, TrailsError> {
+    if bytes.len() > limit {
+        return Err(TrailsError::PayloadTooLarge {
+            size: bytes.len(),
+            limit,
+        });
+    }
+    let frame: serde_json::Value = rmp_serde::from_slice(bytes)
+        .map_err(|e| TrailsError::Protocol(format!("MessagePack frame: {e}")))?;
+    Ok(frame.to_string())
+}
+
+/// Replace an encoded payload with the JSON it encodes; plain payloads
+/// are left alone.
+pub fn decode(data: &mut DataMsg, limit: usize) -> Result<(), TrailsError> {
+    let Some(encoding) = data.header.encoding.take() else {
+        return Ok(());
+    };
+    if encoding != GZIP {
+        return Err(TrailsError::Protocol(format!(
+            "unknown payload encoding '{encoding}'"
+        )));
+    }
+    let bad = |why: String| TrailsError::Protocol(format!("gzip payload: {why}"));
+    let b64 = data
+        .payload
+        .as_str()
+        .ok_or_else(|| bad("not a base64 string".into()))?;
+    let gzip = base64::engine::general_purpose::STANDARD
+        .decode(b64)
+        .map_err(|e| bad(e.to_string()))?;
+    // One byte past the limit tells a payload too large.
+    let mut json = Vec::new();
+    GzDecoder::new(gzip.as_slice())
+        .take(limit as u64 + 1)
+        .read_to_end(&mut json)
+        .map_err(|e| bad(e.to_string()))?;
+    if json.len() > limit {
+        return Err(TrailsError::PayloadTooLarge {
+            size: json.len(),
+            limit,
+        });
+    }
+    data.payload =
+        serde_json::from_slice(&json).map_err(|e| TrailsError::InvalidJson(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::{ClientMessage, MsgHeader, MsgType};
+
+    fn message(payload: serde_json::Value, encoding: Option<&str>) -> DataMsg {
+        DataMsg {
+            app_id: uuid::Uuid::new_v4(),
+            header: MsgHeader {
+                msg_type: MsgType::Status,
+                timestamp: 0,
+                seq: 1,
+                correlation_id: None,
+                encoding: encoding.map(Into::into),
+            },
+            payload,
+            sig: None,
+        }
+    }
+
+    fn gzip(json: &serde_json::Value) -> serde_json::Value {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json.to_string().as_bytes()).unwrap();
+        let gzip = encoder.finish().unwrap();
+        base64::engine::general_purpose::STANDARD
+            .encode(gzip)
+            .into()
+    }
+
+    #[test]
+    fn decodes_gzip_payloads() {
+        let payload = json!({"tables": ["a", "b"], "rows": 3});
+        let mut data = message(gzip(&payload), Some("gzip"));
+        decode(&mut data, 1024).unwrap();
+        assert_eq!(data.payload, payload);
+        assert_eq!(data.header.encoding, None);
+
+        let mut plain = message(payload.clone(), None);
+        decode(&mut plain, 1024).unwrap();
+        assert_eq!(plain.payload, payload);
+    }
+
+    #[test]
+    fn refuses_what_it_cant_decode() {
+        let payload = json!({"blob": "x".repeat(4096)});
+        let mut data = message(gzip(&payload), Some("gzip"));
+        let e = decode(&mut data, 1024).unwrap_err();
+        assert!(
+            matches!(e, TrailsError::PayloadTooLarge { size: 1025, .. }),
+            "{e}"
+        );
+
+        let mut data = message(gzip(&payload), Some("br"));
+        assert!(matches!(
+            decode(&mut data, 1 << 20),
+            Err(TrailsError::Protocol(_))
+        ));
+        let mut data = message(json!({"not": "a string"}), Some("gzip"));
+        assert!(matches!(
+            decode(&mut data, 1 << 20),
+            Err(TrailsError::Protocol(_))
+        ));
+        let mut data = message(json!("bm90IGd6aXA="), Some("gzip"));
+        assert!(matches!(
+            decode(&mut data, 1 << 20),
+            Err(TrailsError::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn reads_msgpack_frames() {
+        let frame = json!({
+            "type": "message",
+            "app_id": uuid::Uuid::new_v4(),
+            "header": {"msg_type": "Result", "timestamp": 1, "seq": 2},
+            "payload": {"readings": [0.8414709848078965, -3, 7]},
+            "sig": null,
+        });
+        let bytes = rmp_serde::to_vec(&frame).unwrap();
+        let json = msgpack_frame(&bytes, 1024).unwrap();
+        let Ok(ClientMessage::Message(data)) = serde_json::from_str(&json) else {
+            panic!("not a data message: {json}");
+        };
+        assert_eq!(data.payload, frame["payload"]);
+
+        assert!(matches!(
+            msgpack_frame(&bytes, 16),
+            Err(TrailsError::PayloadTooLarge { .. })
+        ));
+        assert!(matches!(
+            msgpack_frame(b"\xc1", 1024),
+            Err(TrailsError::Protocol(_))
+        ));
+        assert_eq!(binary_taken(Some("msgpack")), Some(MSGPACK));
+        assert_eq!(binary_taken(Some("cbor")), None);
+    }
+}
@@ -1,8 +1,11 @@
 //! Error types for trailsd.
 
-use axum::http::StatusCode;
+use axum::http::{HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 
+use crate::request_id;
+use crate::types::AppStatus;
+
 #[derive(Debug, thiserror::Error)]
 pub enum TrailsError {
     #[error("database error: {0}")]
@@ -14,11 +17,122 @@ pub enum TrailsError {
     #[error("invalid state transition: {from} → {to}")]
     InvalidTransition { from: String, to: String },
 
+    #[error("conflict: {0}")]
+    Conflict(String),
+
     #[error("registration failed: {0}")]
     RegistrationFailed(String),
 
     #[error("protocol error: {0}")]
     Protocol(String),
+
+    #[error("invalid JSON: {0}")]
+    InvalidJson(String),
+
+    #[error("message of {size} bytes exceeds the {limit} byte limit")]
+    PayloadTooLarge { size: usize, limit: usize },
+
+    #[error("snapshot diff of {size} bytes exceeds the {limit} byte limit")]
+    DiffTooLarge { size: usize, limit: usize },
+
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("schema not found: {0}")]
+    SchemaNotFound(String),
+
+    #[error("no app named '{0}'")]
+    AppNameNotFound(String),
+
+    #[error("trace not found: {0}")]
+    TraceNotFound(String),
+
+    #[error("snapshot not found: {0}")]
+    SnapshotNotFound(String),
+
+    #[error("schema violation: {0}")]
+    SchemaViolation(String),
+
+    #[error("server is draining; retry against another instance")]
+    Draining,
+
+    #[error("'{app_name}' is crash-looping; registrations refused until {until}")]
+    CrashLoop {
+        app_name: String,
+        until: chrono::DateTime<chrono::Utc>,
+    },
+
+    #[error("registration challenge expired")]
+    ChallengeExpired,
+
+    #[error("invalid registration proof: {0}")]
+    InvalidProof(String),
+
+    #[error("invalid sealed envelope: {0}")]
+    SealedEnvelope(String),
+
+    #[error("app {0} was purged")]
+    Purged(uuid::Uuid),
+
+    #[error("app {app_id} is already registered ({status})")]
+    AlreadyConnected { app_id: uuid::Uuid, status: String },
+
+    #[error("app {app_id} has already finished ({status})")]
+    AlreadyTerminal { app_id: uuid::Uuid, status: String },
+
+    #[error(
+        "stored data ({stored} bytes) plus this message would pass the {limit} byte storage \
+         quota; only a final Result or Error is accepted"
+    )]
+    StorageQuotaExceeded { stored: i64, limit: i64 },
+
+    #[error("blob store error: {0}")]
+    BlobStore(String),
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("query exceeded its {limit_ms} ms budget; narrow the time range, scope or predicates")]
+    QueryTooExpensive { limit_ms: u64 },
+}
+
+impl TrailsError {
+    /// Rejection for registering an app already past `scheduled`, e.g.
+    /// the loser of two simultaneous registrations; carries the status
+    /// the app actually has.
+    pub fn already_registered(app_id: uuid::Uuid, status: String) -> Self {
+        if status.parse::<AppStatus>().is_ok_and(|s| s.is_terminal()) {
+            TrailsError::AlreadyTerminal { app_id, status }
+        } else {
+            TrailsError::AlreadyConnected { app_id, status }
+        }
+    }
+
+    /// Machine-readable code sent in `ServerErrorMsg.code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TrailsError::SchemaViolation(_) => "schema_violation",
+            TrailsError::InvalidJson(_) => "invalid_json",
+            TrailsError::PayloadTooLarge { .. } => "payload_too_large",
+            TrailsError::RegistrationFailed(_) => "registration_failed",
+            TrailsError::Draining => "draining",
+            TrailsError::CrashLoop { .. } => "crash_loop",
+            TrailsError::ChallengeExpired => "challenge_expired",
+            TrailsError::InvalidProof(_) => "invalid_proof",
+            TrailsError::SealedEnvelope(_) => "invalid_sealed_envelope",
+            TrailsError::Purged(_) => "purged",
+            TrailsError::AlreadyConnected { .. } => "already_connected",
+            TrailsError::AlreadyTerminal { .. } => "already_terminal",
+            TrailsError::StorageQuotaExceeded { .. } => "storage_quota_exceeded",
+            TrailsError::AppNotFound(_) => "app_not_found",
+            TrailsError::Unauthorized(_) => "unauthorized",
+            TrailsError::Forbidden(_) => "forbidden",
+            _ => "message_error",
+        }
+    }
 }
 
 impl IntoResponse for TrailsError {
@@ -26,10 +140,37 @@ impl IntoResponse for TrailsError {
         let status = match &self {
             TrailsError::AppNotFound(_) => StatusCode::NOT_FOUND,
             TrailsError::InvalidTransition { .. } => StatusCode::CONFLICT,
+            TrailsError::Conflict(_) => StatusCode::CONFLICT,
             TrailsError::RegistrationFailed(_) => StatusCode::BAD_REQUEST,
             TrailsError::Protocol(_) => StatusCode::BAD_REQUEST,
+            TrailsError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            TrailsError::InvalidJson(_) => StatusCode::BAD_REQUEST,
+            TrailsError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            TrailsError::DiffTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            TrailsError::SchemaNotFound(_) => StatusCode::NOT_FOUND,
+            TrailsError::SnapshotNotFound(_) => StatusCode::NOT_FOUND,
+            TrailsError::TraceNotFound(_) => StatusCode::NOT_FOUND,
+            TrailsError::AppNameNotFound(_) => StatusCode::NOT_FOUND,
+            TrailsError::SchemaViolation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            TrailsError::Draining => StatusCode::SERVICE_UNAVAILABLE,
+            TrailsError::CrashLoop { .. } => StatusCode::TOO_MANY_REQUESTS,
+            TrailsError::ChallengeExpired => StatusCode::UNAUTHORIZED,
+            TrailsError::InvalidProof(_) => StatusCode::UNAUTHORIZED,
+            TrailsError::SealedEnvelope(_) => StatusCode::BAD_REQUEST,
+            TrailsError::Purged(_) => StatusCode::GONE,
+            TrailsError::AlreadyConnected { .. } => StatusCode::CONFLICT,
+            TrailsError::AlreadyTerminal { .. } => StatusCode::CONFLICT,
+            TrailsError::StorageQuotaExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            TrailsError::BlobStore(_) => StatusCode::BAD_GATEWAY,
+            TrailsError::QueryTooExpensive { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            TrailsError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            TrailsError::Forbidden(_) => StatusCode::FORBIDDEN,
             TrailsError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
-        (status, self.to_string()).into_response()
+        let mut response = (status, self.to_string()).into_response();
+        if let Some(id) = request_id::current().and_then(|id| HeaderValue::from_str(&id).ok()) {
+            response.headers_mut().insert(request_id::HEADER, id);
+        }
+        response
     }
 }
@@ -0,0 +1,208 @@
+//! Streaming NDJSON export (`GET /api/v1/apps/{id}/messages/export`).
+//!
+//! The body is produced chunk by chunk: each poll fetches the next
+//! `CHUNK_ROWS` messages after the last `(seq, id)` sent and writes them
+//! as one body chunk, so memory stays flat however many messages the app
+//! has and a client that stops reading stops the queries. The last line is
+//! always a trailer, `{"trailer":true,"count":N,"lastSeq":S,"complete":B}`;
+//! an export without one, or with `complete: false` (and an `error`), was
+//! cut short.
+
+use std::convert::Infallible;
+use std::future::Future;
+
+use axum::body::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::TrailsError;
+
+/// Messages fetched per query, and per body chunk.
+pub const CHUNK_ROWS: i64 = 2000;
+
+/// Final line of every export.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trailer {
+    trailer: bool,
+    count: u64,
+    last_seq: Option<i64>,
+    complete: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+enum Step<F> {
+    Next {
+        fetch: F,
+        after: Option<(i64, i64)>,
+        count: u64,
+    },
+    Done,
+}
+
+/// NDJSON lines of everything `fetch` returns, then the trailer. `fetch`
+/// gets the `(seq, id)` key of the last row sent and a row limit; `key`
+/// reads that key from a row. A short page ends the export.
+pub fn ndjson<T, F, Fut>(
+    fetch: F,
+    key: fn(&T) -> (i64, i64),
+) -> impl Stream<Item = Result<Bytes, Infallible>>
+where
+    T: Serialize,
+    F: FnMut(Option<(i64, i64)>, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, TrailsError>>,
+{
+    let first = Step::Next {
+        fetch,
+        after: None,
+        count: 0,
+    };
+    futures::stream::unfold(first, move |step| async move {
+        let Step::Next {
+            mut fetch,
+            after,
+            count,
+        } = step
+        else {
+            return None;
+        };
+        let last_seq = after.map(|(seq, _)| seq);
+        let rows = match fetch(after, CHUNK_ROWS).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("message export cut short after {count} messages: {e}");
+                return Some((trailer(count, last_seq, Some(e.to_string())), Step::Done));
+            }
+        };
+        let Some(last) = rows.last().map(key) else {
+            return Some((trailer(count, last_seq, None), Step::Done));
+        };
+        let mut chunk = Vec::new();
+        for row in &rows {
+            if let Err(e) = serde_json::to_writer(&mut chunk, row) {
+                let error = Some(format!("unserializable message: {e}"));
+                return Some((trailer(count, last_seq, error), Step::Done));
+            }
+            chunk.push(b'\n');
+        }
+        let count = count + rows.len() as u64;
+        let next = if (rows.len() as i64) < CHUNK_ROWS {
+            // Nothing further: finish in this chunk.
+            chunk.extend_from_slice(&trailer_line(count, Some(last.0), None));
+            Step::Done
+        } else {
+            Step::Next {
+                fetch,
+                after: Some(last),
+                count,
+            }
+        };
+        Some((Ok(Bytes::from(chunk)), next))
+    })
+    // Compressing bodies poll once more after the end.
+    .fuse()
+}
+
+fn trailer(count: u64, last_seq: Option<i64>, error: Option<String>) -> Result<Bytes, Infallible> {
+    Ok(Bytes::from(trailer_line(count, last_seq, error)))
+}
+
+fn trailer_line(count: u64, last_seq: Option<i64>, error: Option<String>) -> Vec<u8> {
+    let trailer = Trailer {
+        trailer: true,
+        count,
+        last_seq,
+        complete: error.is_none(),
+        error,
+    };
+    let mut line = serde_json::to_vec(&trailer).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use serde_json::Value;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        seq: i64,
+        id: i64,
+    }
+
+    const ROWS: i64 = 100_000;
+
+    /// Synthetic rows: two per seq, ids interleaved out of seq order.
+    fn synthetic(after: Option<(i64, i64)>, limit: i64) -> Vec<Row> {
+        let start = after.map_or(0, |(seq, id)| seq * 2 + (id % 2) + 1);
+        (start..ROWS.min(start + limit))
+            .map(|n| Row {
+                seq: n / 2,
+                id: (ROWS - n / 2) * 2 + n % 2,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_export_is_chunked_and_ordered() {
+        let largest_fetch = Cell::new(0);
+        let stream = ndjson(
+            |after, limit| {
+                largest_fetch.set(largest_fetch.get().max(limit));
+                std::future::ready(Ok(synthetic(after, limit)))
+            },
+            |row: &Row| (row.seq, row.id),
+        );
+        let chunks: Vec<Bytes> = stream.map(|c| c.unwrap()).collect().await;
+
+        // Bounded memory: one fetch of CHUNK_ROWS is all that is held.
+        assert_eq!(largest_fetch.get(), CHUNK_ROWS);
+        let biggest = chunks.iter().map(Bytes::len).max().unwrap();
+        assert!(biggest < CHUNK_ROWS as usize * 64, "chunk of {biggest} bytes");
+
+        let text: Vec<u8> = chunks.concat();
+        let lines: Vec<Value> = text
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        let (trailer, rows) = lines.split_last().unwrap();
+        assert_eq!(rows.len() as i64, ROWS);
+        let keys: Vec<(i64, i64)> = rows
+            .iter()
+            .map(|r| (r["seq"].as_i64().unwrap(), r["id"].as_i64().unwrap()))
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(trailer["trailer"], true);
+        assert_eq!(trailer["count"], ROWS);
+        assert_eq!(trailer["lastSeq"], ROWS / 2 - 1);
+        assert_eq!(trailer["complete"], true);
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_ends_with_incomplete_trailer() {
+        let calls = Cell::new(0);
+        let stream = ndjson(
+            |after, limit| {
+                calls.set(calls.get() + 1);
+                std::future::ready(match calls.get() {
+                    1 => Ok(synthetic(after, limit)),
+                    _ => Err(TrailsError::BlobStore("connection reset".into())),
+                })
+            },
+            |row: &Row| (row.seq, row.id),
+        );
+        let chunks: Vec<Bytes> = stream.map(|c| c.unwrap()).collect().await;
+        let last = chunks.last().unwrap();
+        let trailer: Value = serde_json::from_slice(last).unwrap();
+        assert_eq!(trailer["complete"], false);
+        assert_eq!(trailer["count"], CHUNK_ROWS);
+        assert!(trailer["error"].as_str().unwrap().contains("connection reset"));
+    }
+}
@@ -0,0 +1,247 @@
+//! Per-app live feed for dashboards (`GET /ws/apps/{id}/feed`).
+//!
+//! On connect the feed sends a `hello` with the app detail and its latest
+//! snapshot, backfills stored messages after `?since_seq=` (from the
+//! current end when omitted), sends `live`, then streams new messages and
+//! lifecycle events for that one app.
+//!
+//! Each feed holds its own receiver on the event bus, which is its
+//! bounded queue: a slow observer only lags itself, never the bus or the
+//! other observers. Bus events are treated as wake-ups, not as data —
+//! messages are always read from Postgres after the last seq sent. The
+//! receiver is subscribed before the backfill starts, so a message stored
+//! mid-backfill is either in the backfill or wakes the loop afterwards;
+//! either way the seq cursor sends it exactly once. A lagged receiver
+//! re-syncs the same way and gets a fresh `app` frame.
+//!
+//! Access matches `GET /api/v1/apps/{id}`: no auth or namespace scoping
+//! beyond the app id.
+
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket};
+use axum::extract::{Path, Query, State, WebSocketUpgrade};
+use axum::response::{IntoResponse, Response};
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info_span, warn, Instrument};
+use uuid::Uuid;
+
+use crate::api::{self, AppDetail, MessageResponse};
+use crate::blob;
+use crate::db::{self, MessageRow, SnapshotRow};
+use crate::error::TrailsError;
+use crate::request_id;
+use crate::state::AppState;
+use crate::types::Event;
+
+/// Messages read per backfill query.
+const BACKFILL_BATCH: i64 = 500;
+
+/// Message types whose offloaded payloads are inlined.
+const HYDRATED_TYPES: [&str; 3] = ["Status", "Result", "Error"];
+
+type Sink = SplitSink<WebSocket, Message>;
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+    /// Backfill stored messages with a greater seq before going live.
+    #[serde(default)]
+    since_seq: Option<i64>,
+}
+
+/// Frames sent to feed observers.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FeedFrame {
+    Hello {
+        app: AppDetail,
+        snapshot: Option<SnapshotRow>,
+    },
+    Message {
+        message: MessageResponse,
+    },
+    /// Backfill done; everything after this is live.
+    Live {
+        #[serde(rename = "lastSeq")]
+        last_seq: Option<i64>,
+    },
+    /// A lifecycle event, with the app detail as of that event (null once
+    /// the app is purged).
+    Event {
+        event: Event,
+        app: Option<AppDetail>,
+    },
+    /// Sent after the observer fell behind and was re-synced.
+    App {
+        app: AppDetail,
+    },
+    Error {
+        code: String,
+        message: String,
+    },
+}
+
+/// Axum handler for GET /ws/apps/{id}/feed — 404s before the upgrade if
+/// the app doesn't exist.
+pub async fn feed_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    Path(app_id): Path<Uuid>,
+    Query(q): Query<FeedQuery>,
+) -> Result<Response, TrailsError> {
+    if db::get_app(&state.db, app_id).await?.is_none() {
+        return Err(TrailsError::AppNotFound(app_id));
+    }
+    let conn_id = request_id::current().unwrap_or_else(|| Uuid::new_v4().to_string());
+    Ok(ws
+        .on_upgrade(move |socket| {
+            let span = info_span!("feed", conn_id = %conn_id);
+            request_id::scope(conn_id, handle_feed(socket, state, app_id, q.since_seq))
+                .instrument(span)
+        })
+        .into_response())
+}
+
+async fn handle_feed(socket: WebSocket, state: Arc<AppState>, app_id: Uuid, since: Option<i64>) {
+    let (mut sink, mut stream) = socket.split();
+    state.metrics.inc("trails_feed_connections_total", &[]);
+    debug!(app_id = %app_id, "feed observer connected");
+
+    if let Err(e) = run(&mut sink, &mut stream, &state, app_id, since).await {
+        debug!(app_id = %app_id, "feed closed: {e}");
+        let _ = send(&mut sink, &FeedFrame::Error {
+            code: e.code().into(),
+            message: e.to_string(),
+        })
+        .await;
+    }
+    let _ = sink.close().await;
+}
+
+async fn run(
+    sink: &mut Sink,
+    stream: &mut SplitStream<WebSocket>,
+    state: &AppState,
+    app_id: Uuid,
+    since: Option<i64>,
+) -> Result<(), TrailsError> {
+    // Subscribe first: anything stored from here on wakes the loop below.
+    let mut rx = state.event_tx.subscribe();
+
+    let mut last_seq = match since {
+        Some(seq) => Some(seq),
+        None => db::max_message_seq(&state.db, app_id).await?,
+    };
+    let app = api::app_detail(state, app_id).await?;
+    let snapshot = db::get_latest_snapshot(&state.db, app_id).await?;
+    send(sink, &FeedFrame::Hello { app, snapshot }).await?;
+    catch_up(sink, state, app_id, &mut last_seq).await?;
+    send(sink, &FeedFrame::Live { last_seq }).await?;
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    if !handle_event(sink, state, app_id, &mut last_seq, event).await? {
+                        return Ok(());
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    debug!(app_id = %app_id, skipped = n, "feed observer lagged, re-syncing");
+                    state.metrics.inc("trails_feed_resyncs_total", &[]);
+                    catch_up(sink, state, app_id, &mut last_seq).await?;
+                    let app = api::app_detail(state, app_id).await?;
+                    send(sink, &FeedFrame::App { app }).await?;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            msg = stream.next() => match msg {
+                // Observers only listen; anything but close is ignored.
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Ok(()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Forward one bus event if it concerns `app_id`. False ends the feed.
+async fn handle_event(
+    sink: &mut Sink,
+    state: &AppState,
+    app_id: Uuid,
+    last_seq: &mut Option<i64>,
+    event: Event,
+) -> Result<bool, TrailsError> {
+    match &event {
+        Event::MessageStored { app_id: id, seq, .. }
+            if *id == app_id && last_seq.is_none_or(|last| *seq > last) =>
+        {
+            catch_up(sink, state, app_id, last_seq).await?;
+        }
+        Event::AppTerminal { app_id: id, .. }
+        | Event::CrashDetected { app_id: id, .. }
+        | Event::QuotaWarning { app_id: id, .. }
+        | Event::StallDetected { app_id: id, .. }
+        | Event::AppAdopted { app_id: id, .. }
+        | Event::DependenciesSatisfied { app_id: id, .. }
+            if *id == app_id =>
+        {
+            // Messages stored before the event go out first.
+            catch_up(sink, state, app_id, last_seq).await?;
+            let app = api::app_detail(state, app_id).await?;
+            send(sink, &FeedFrame::Event { event, app: Some(app) }).await?;
+        }
+        Event::AppPurged { app_id: id, .. } if *id == app_id => {
+            send(sink, &FeedFrame::Event { event, app: None }).await?;
+            return Ok(false);
+        }
+        _ => {}
+    }
+    Ok(true)
+}
+
+/// Send every stored message after `last_seq`, advancing it.
+async fn catch_up(
+    sink: &mut Sink,
+    state: &AppState,
+    app_id: Uuid,
+    last_seq: &mut Option<i64>,
+) -> Result<(), TrailsError> {
+    loop {
+        let rows = db::list_messages(&state.db, app_id, None, *last_seq, BACKFILL_BATCH).await?;
+        let done = (rows.len() as i64) < BACKFILL_BATCH;
+        for row in rows {
+            *last_seq = Some(row.seq);
+            let message = hydrate(state, row).await;
+            send(sink, &FeedFrame::Message { message }).await?;
+        }
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Inline offloaded Status/Result/Error payloads. A blob that can't be
+/// read leaves the blob reference for the observer to fetch.
+async fn hydrate(state: &AppState, mut row: MessageRow) -> MessageResponse {
+    if let Some(key) = row.blob_key.as_deref() {
+        if HYDRATED_TYPES.contains(&row.msg_type.as_str()) {
+            match blob::resolve(state, key).await {
+                Ok(payload) => row.payload_json = Some(payload),
+                Err(e) => warn!(app_id = %row.app_id, seq = row.seq, "feed blob resolve: {e}"),
+            }
+        }
+    }
+    row.into()
+}
+
+async fn send(sink: &mut Sink, frame: &FeedFrame) -> Result<(), TrailsError> {
+    let json = serde_json::to_string(frame)
+        .map_err(|e| TrailsError::Protocol(format!("serialize error: {e}")))?;
+    sink.send(Message::Text(json.into()))
+        .await
+        .map_err(|e| TrailsError::Protocol(format!("send error: {e}")))
+}
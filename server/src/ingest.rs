@@ -0,0 +1,486 @@
+//! HTTP ingestion — `POST /api/v1/ingest`, for apps that can make plain
+//! HTTPS requests but can't hold a WebSocket open (locked-down CI
+//! runners, some serverless platforms).
+//!
+//! The body is a JSON array of the WebSocket `ClientMessage` frames. The
+//! app is identified by a `register` or `re_register` as the first
+//! element, or on later posts by the `X-Trails-App-Id` header. Every post
+//! is signed with the app key (the one being registered, or else the
+//! registered one): `X-Trails-Signature` over the body and
+//! `X-Trails-Timestamp`, which must be within `SIGNATURE_WINDOW_MS` of
+//! the server's clock (see `signing::ingest_message`). Frames run
+//! through the same registration and message handling as the WebSocket
+//! path, and the response carries a result per frame plus any controls
+//! queued for the app. Controls are handed over once: a response lost on
+//! the way is not redelivered.
+//!
+//! Retries are safe: a data frame whose seq is already stored is acked as
+//! a duplicate, and a `register` from an app already live over HTTP with
+//! the same key is answered as registered again. With no socket to drop,
+//! apps on this transport are crashed (`heartbeat_timeout`) by the stale
+//! sweeper once they haven't posted for `INGEST_STALE_AFTER` seconds; an
+//! empty array is a heartbeat.
+//!
+//! Signed security levels need the challenge round trip and are refused
+//! here.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::app_metrics;
+use crate::db;
+use crate::error::TrailsError;
+use crate::signing;
+use crate::state::AppState;
+use crate::types::*;
+use crate::version;
+use crate::ws::{self, Session};
+
+/// Header naming the app on posts after the registering one.
+pub const APP_ID_HEADER: &str = "x-trails-app-id";
+/// Header carrying the post's signing time, unix ms.
+pub const TIMESTAMP_HEADER: &str = "x-trails-timestamp";
+/// Header carrying the app key's signature over the post.
+pub const SIGNATURE_HEADER: &str = "x-trails-signature";
+
+/// How far a post's timestamp may be from the server's clock; a captured
+/// post can be replayed for about this long.
+const SIGNATURE_WINDOW_MS: i64 = 5 * 60 * 1000;
+
+/// Request body cap; the default 2 MiB is too small for batches of
+/// Status payloads.
+const INGEST_BODY_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Routes under /api/v1.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route(
+        "/ingest",
+        post(ingest).layer(DefaultBodyLimit::max(INGEST_BODY_LIMIT)),
+    )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IngestResponse {
+    app_id: Uuid,
+    server_pub_key: String,
+    server_version: &'static str,
+    results: Vec<FrameResult>,
+    /// `control` frames, as they'd arrive over the WebSocket.
+    controls: Vec<ServerMessage>,
+}
+
+/// Outcome of one frame of the batch.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameResult {
+    index: usize,
+    /// `registered`, `ack`, `duplicate` or `nack`.
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl FrameResult {
+    fn ok(index: usize, status: &'static str, seq: Option<i64>) -> Self {
+        Self {
+            index,
+            status,
+            seq,
+            code: None,
+            message: None,
+        }
+    }
+
+    fn nack(index: usize, seq: Option<i64>, e: &TrailsError) -> Self {
+        Self {
+            index,
+            status: "nack",
+            seq,
+            code: Some(e.code()),
+            message: Some(e.to_string()),
+        }
+    }
+}
+
+/// POST /api/v1/ingest
+async fn ingest(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<IngestResponse>, TrailsError> {
+    let batch: Vec<JsonValue> =
+        serde_json::from_slice(&body).map_err(|e| TrailsError::InvalidJson(e.to_string()))?;
+    let max_batch = state.config().ingest_max_batch;
+    if batch.len() > max_batch {
+        return Err(TrailsError::InvalidRequest(format!(
+            "{} frames exceed the batch limit of {max_batch}",
+            batch.len()
+        )));
+    }
+
+    let mut frames = batch.into_iter().enumerate().peekable();
+    let mut results = Vec::new();
+    let registration = frames.next_if(|(_, frame)| {
+        matches!(
+            frame.get("type").and_then(JsonValue::as_str),
+            Some("register" | "re_register")
+        )
+    });
+    // The app's terminal status once it has finished.
+    let (session, mut finished) = match registration {
+        Some((_, frame)) => {
+            let session = register(&state, frame, &headers, &body).await?;
+            results.push(FrameResult::ok(0, "registered", None));
+            (session, None)
+        }
+        None => from_headers(&state, &headers, &body).await?,
+    };
+    let app_id = session.app_id;
+
+    for (index, frame) in frames {
+        let seq = frame.pointer("/header/seq").and_then(JsonValue::as_i64);
+        if let Some(status) = &finished {
+            // Retries of what was stored before the app finished still ack,
+            // as does the disconnect that follows a Result or Error.
+            let disconnect = frame.get("type").and_then(JsonValue::as_str) == Some("disconnect");
+            let stored = match seq {
+                Some(seq) => db::message_seq_exists(&state.db, app_id, seq).await?,
+                None => false,
+            };
+            results.push(match (disconnect, stored) {
+                (true, _) => FrameResult::ok(index, "ack", None),
+                (false, true) => FrameResult::ok(index, "duplicate", seq),
+                (false, false) => FrameResult::nack(
+                    index,
+                    seq,
+                    &TrailsError::already_registered(app_id, status.clone()),
+                ),
+            });
+            continue;
+        }
+        let (status, terminal) = match handle_frame(&state, &session, frame).await {
+            Ok(handled) => handled,
+            Err(e) => {
+                warn!(app_id = %app_id, index, "ingest frame error: {e}");
+                results.push(FrameResult::nack(index, seq, &e));
+                state
+                    .metrics
+                    .inc("trails_ingest_frames_total", &[("status", "nack")]);
+                continue;
+            }
+        };
+        results.push(FrameResult::ok(index, status, seq));
+        state
+            .metrics
+            .inc("trails_ingest_frames_total", &[("status", status)]);
+        if terminal {
+            app_metrics::forget(&state, app_id);
+            let row = db::get_app(&state.db, app_id).await?;
+            finished = Some(row.map_or_else(|| "done".into(), |row| row.status));
+        }
+    }
+
+    let controls = match finished {
+        None => take_controls(&state, app_id).await?,
+        Some(_) => Vec::new(),
+    };
+
+    Ok(Json(IngestResponse {
+        app_id,
+        server_pub_key: state.server_pub_key_str(),
+        server_version: version::SERVER_VERSION,
+        results,
+        controls,
+    }))
+}
+
+/// Register (or re-register) the app from the batch's first frame, once
+/// the post is signed with the key it names.
+async fn register(
+    state: &Arc<AppState>,
+    frame: JsonValue,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Session, TrailsError> {
+    let raw = frame.to_string();
+    let msg: ClientMessage = match serde_json::from_value(frame) {
+        Ok(msg) => msg,
+        Err(e) => {
+            let e = TrailsError::InvalidJson(e.to_string());
+            ws::dead_letter(state, None, &e, &raw).await;
+            return Err(e);
+        }
+    };
+    let (app_id, pub_key) = match &msg {
+        ClientMessage::Register(reg) => (reg.app_id, reg.child_pub_key.as_str()),
+        ClientMessage::ReRegister(rereg) => (rereg.app_id, rereg.pub_key.as_str()),
+        _ => unreachable!("only registrations are peeked"),
+    };
+    check_signature(headers, body, app_id, pub_key)?;
+    let (app_id, result) = match msg {
+        ClientMessage::Register(reg) => (reg.app_id, register_app(state, *reg).await),
+        ClientMessage::ReRegister(rereg) => (rereg.app_id, re_register_app(state, rereg).await),
+        _ => unreachable!("only registrations are peeked"),
+    };
+    if let Err(e) = &result {
+        ws::dead_letter(state, Some(app_id), e, &raw).await;
+    }
+    result
+}
+
+async fn register_app(state: &Arc<AppState>, mut reg: RegisterMsg) -> Result<Session, TrailsError> {
+    // A retried register whose first response was lost.
+    let instance = &state.boot.server_instance;
+    let touched = db::touch_http_app(&state.db, reg.app_id, &reg.child_pub_key, instance).await?;
+    if let Some(row) = touched {
+        return Session::of_row(state, &row).await;
+    }
+    if state.is_draining() {
+        return Err(TrailsError::Draining);
+    }
+    let refuse_signed = async || {
+        Err(TrailsError::RegistrationFailed(
+            "signed security levels need the WebSocket transport".into(),
+        ))
+    };
+    let session = ws::register_app(&mut reg, state, "http", refuse_signed).await?;
+    state.publish(Event::AppConnected {
+        app_id: session.app_id,
+        parent_id: session.parent_id,
+    });
+    Ok(session)
+}
+
+async fn re_register_app(
+    state: &Arc<AppState>,
+    rereg: ReRegisterMsg,
+) -> Result<Session, TrailsError> {
+    let app_id = rereg.app_id;
+    let instance = &state.boot.server_instance;
+    if let Some(row) = db::touch_http_app(&state.db, app_id, &rereg.pub_key, instance).await? {
+        return Session::of_row(state, &row).await;
+    }
+    let signed = db::get_app(&state.db, app_id)
+        .await?
+        .is_some_and(|row| signing::is_signed_level(&row.sec_level));
+    if signed {
+        return Err(TrailsError::RegistrationFailed(
+            "signed security levels need the WebSocket transport".into(),
+        ));
+    }
+    let row = db::reconnect_app(&state.db, app_id, &rereg.pub_key, instance, "http").await?;
+    let Some(row) = row else {
+        return Err(TrailsError::RegistrationFailed(format!(
+            "re_register failed for {app_id}: not found, not live or pub_key mismatch"
+        )));
+    };
+    info!(app_id = %app_id, last_seq = rereg.last_seq, "re-registered over http");
+    state.publish(Event::AppConnected {
+        app_id,
+        parent_id: row.parent_id,
+    });
+    Session::of_row(state, &row).await
+}
+
+/// The app named by the headers, and its status if it has finished. A
+/// finished app is still answered so that retried batches get their
+/// duplicates acked.
+async fn from_headers(
+    state: &Arc<AppState>,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(Session, Option<String>), TrailsError> {
+    let Some(app_id) = headers.get(APP_ID_HEADER).and_then(|v| v.to_str().ok()) else {
+        return Err(TrailsError::RegistrationFailed(format!(
+            "first frame must be register or re_register, or send {APP_ID_HEADER}"
+        )));
+    };
+    let app_id: Uuid = app_id
+        .parse()
+        .map_err(|_| TrailsError::InvalidRequest(format!("{APP_ID_HEADER}: not a uuid")))?;
+
+    let row = db::get_app(&state.db, app_id).await?;
+    let Some((row, pub_key)) = row.and_then(|row| row.pub_key.clone().map(|key| (row, key))) else {
+        return Err(TrailsError::RegistrationFailed(format!("app {app_id} not found")));
+    };
+    check_signature(headers, body, app_id, &pub_key)?;
+
+    let instance = &state.boot.server_instance;
+    if let Some(row) = db::touch_http_app(&state.db, app_id, &pub_key, instance).await? {
+        return Ok((Session::of_row(state, &row).await?, None));
+    }
+    let status: AppStatus = row.status.parse().unwrap_or(AppStatus::Scheduled);
+    if !status.is_terminal() {
+        return Err(TrailsError::Conflict(format!(
+            "app {app_id} is {}; re_register first",
+            row.status
+        )));
+    }
+    let session = Session::of_row(state, &row).await?;
+    Ok((session, Some(row.status)))
+}
+
+/// Check the post's signature headers against `pub_key`.
+fn check_signature(
+    headers: &HeaderMap,
+    body: &[u8],
+    app_id: Uuid,
+    pub_key: &str,
+) -> Result<(), TrailsError> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(timestamp), Some(sig)) = (header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER)) else {
+        return Err(TrailsError::InvalidProof(format!(
+            "send {TIMESTAMP_HEADER} and {SIGNATURE_HEADER}"
+        )));
+    };
+    let timestamp: i64 = timestamp
+        .parse()
+        .map_err(|_| TrailsError::InvalidProof(format!("{TIMESTAMP_HEADER}: not unix ms")))?;
+    let skew = (chrono::Utc::now().timestamp_millis() - timestamp).abs();
+    if skew > SIGNATURE_WINDOW_MS {
+        return Err(TrailsError::InvalidProof(format!(
+            "{TIMESTAMP_HEADER} is {skew}ms from the server's clock"
+        )));
+    }
+    signing::verify_ingest(pub_key, app_id, timestamp, body, sig)
+}
+
+/// Handle one frame after registration; returns its result status and
+/// whether it ended the app.
+async fn handle_frame(
+    state: &Arc<AppState>,
+    session: &Session,
+    frame: JsonValue,
+) -> Result<(&'static str, bool), TrailsError> {
+    let app_id = session.app_id;
+    let raw = frame.to_string();
+    let limit = state.config().max_message_bytes;
+    let result = async {
+        if raw.len() > limit {
+            return Err(TrailsError::PayloadTooLarge {
+                size: raw.len(),
+                limit,
+            });
+        }
+        let msg: ClientMessage =
+            serde_json::from_value(frame).map_err(|e| TrailsError::InvalidJson(e.to_string()))?;
+        match msg {
+            ClientMessage::Message(data) => {
+                if data.app_id != app_id {
+                    return Err(TrailsError::Protocol(format!(
+                        "app_id mismatch: registered={app_id}, message={}",
+                        data.app_id
+                    )));
+                }
+                // A retried batch resends what is stored; the shared data
+                // path tells it apart, racing copies included.
+                match ws::handle_data_message(data, state, session).await? {
+                    ws::Handled::Stored => Ok(("ack", false)),
+                    ws::Handled::Terminal => Ok(("ack", true)),
+                    ws::Handled::Duplicate => Ok(("duplicate", false)),
+                }
+            }
+            ClientMessage::Heartbeat(hb) => {
+                if hb.app_id != app_id {
+                    return Err(TrailsError::Protocol(format!(
+                        "app_id mismatch: registered={app_id}, heartbeat={}",
+                        hb.app_id
+                    )));
+                }
+                db::record_heartbeat(&state.db, app_id).await?;
+                Ok(("ack", false))
+            }
+            ClientMessage::Disconnect(disc) => {
+                ws::handle_disconnect(disc, state, session.parent_id).await?;
+                Ok(("ack", true))
+            }
+            ClientMessage::Register(_) | ClientMessage::ReRegister(_) => Err(
+                TrailsError::Protocol("registration must be the first frame".into()),
+            ),
+            ClientMessage::RegisterProof(_) => Err(TrailsError::Protocol(
+                "register_proof is not accepted over http".into(),
+            )),
+            // Subtasks share a socket; each http app posts its own batches.
+            ClientMessage::RegisterSub(_) => Err(TrailsError::Protocol(
+                "register_sub is not accepted over http".into(),
+            )),
+            // Child events are pushed down a connection; there is none here.
+            ClientMessage::SubscribeChild(_) => Err(TrailsError::Protocol(
+                "subscribe_child is not accepted over http".into(),
+            )),
+            ClientMessage::Observe(_) => Err(TrailsError::Protocol(
+                "observe is not accepted over http".into(),
+            )),
+        }
+    }
+    .await;
+    if let Err(e) = &result {
+        ws::dead_letter(state, Some(app_id), e, &raw).await;
+    }
+    result
+}
+
+/// Hand over the app's queued controls.
+async fn take_controls(state: &AppState, app_id: Uuid) -> Result<Vec<ServerMessage>, TrailsError> {
+    let mut pending = db::take_pending_controls(&state.db, app_id).await?;
+    if pending.is_empty() {
+        return Ok(Vec::new());
+    }
+    state.metrics.add(
+        "trails_queued_controls_delivered_total",
+        &[],
+        pending.len() as f64,
+    );
+    pending
+        .iter_mut()
+        .for_each(|c| c.state = "delivered".into());
+    Ok(pending.iter().map(|c| ws::queued_control_msg(c, None)).collect())
+}
+
+/// Spawn the stale sweeper: crashes `http` apps silent for longer than
+/// `ingest_stale_after`. Runs every `deadline_check_interval` seconds.
+pub fn spawn_stale_sweeper(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = sweep_stale(&state).await {
+                warn!("ingest stale sweeper error: {e}");
+            }
+            let every = state.config().deadline_check_interval;
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+async fn sweep_stale(state: &AppState) -> Result<(), TrailsError> {
+    let stale_after = state.config().ingest_stale_after;
+    for (app_id, parent_id) in db::crash_stale_http_apps(&state.db, stale_after).await? {
+        info!(app_id = %app_id, stale_after, "http app went silent → crash");
+        let gap = Some(stale_after as f32);
+        if let Err(e) = db::record_crash(&state.db, app_id, "heartbeat_timeout", gap, None).await {
+            warn!(app_id = %app_id, "record_crash error: {e}");
+        }
+        app_metrics::forget(state, app_id);
+        state.metrics.inc("trails_ingest_stale_total", &[]);
+        state.publish(Event::CrashDetected {
+            app_id,
+            parent_id,
+            crash_type: "heartbeat_timeout".into(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Bulk latest-snapshot reads for wallboards (`GET /api/v1/snapshots/latest`).
+//!
+//! One query — a LATERAL join onto each app's newest snapshot — returns a
+//! page of apps with their latest snapshot, optionally trimmed in SQL to a
+//! few JSON Pointer paths so large snapshots never leave Postgres. The
+//! rendered page is cached for `latest_cache_ms`, keyed by the query, so a
+//! wall of dashboards polling the same view costs one query per interval.
+
+use std::time::{Duration, Instant};
+
+use axum::body::Bytes;
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::db::{self, LatestSnapshotRow};
+use crate::error::TrailsError;
+use crate::state::AppState;
+
+/// Cached pages kept before stale ones are swept.
+const CACHE_SWEEP_AT: usize = 256;
+
+/// A validated `GET /snapshots/latest` query.
+#[derive(Debug)]
+pub struct LatestQuery {
+    pub statuses: Vec<String>,
+    pub namespace: Option<String>,
+    /// JSON Pointers to keep; `None` returns whole snapshots.
+    pub fields: Option<Vec<String>>,
+    /// Keyset cursor: only apps with a greater app_id.
+    pub after: Option<Uuid>,
+    pub limit: i64,
+}
+
+impl LatestQuery {
+    fn cache_key(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}",
+            self.statuses.join(","),
+            self.namespace.as_deref().unwrap_or(""),
+            self.fields.as_ref().map(|f| f.join(",")).unwrap_or_default(),
+            self.after.map(|a| a.to_string()).unwrap_or_default(),
+            self.limit,
+        )
+    }
+
+    /// `fields` as `[[pointer, [token, ...]], ...]` for the query.
+    fn field_paths(&self) -> Option<JsonValue> {
+        let fields = self.fields.as_ref()?;
+        Some(JsonValue::Array(
+            fields
+                .iter()
+                .map(|ptr| {
+                    let tokens: Vec<String> = ptr
+                        .split('/')
+                        .skip(1)
+                        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+                        .collect();
+                    serde_json::json!([ptr, tokens])
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LatestPage {
+    apps: Vec<LatestSnapshotRow>,
+    /// Pass as `after` for the next page; null on the last page.
+    next_after: Option<Uuid>,
+}
+
+/// Short-lived cache of rendered pages held in `AppState`.
+#[derive(Default)]
+pub struct LatestCache {
+    entries: DashMap<String, (Instant, Bytes)>,
+}
+
+/// The rendered JSON page for `query`, from cache if fresh.
+pub async fn page(state: &AppState, query: &LatestQuery) -> Result<Bytes, TrailsError> {
+    let ttl = Duration::from_millis(state.config().latest_cache_ms);
+    let key = query.cache_key();
+    if let Some(entry) = state.latest.entries.get(&key) {
+        if entry.0.elapsed() < ttl {
+            state.metrics.inc("trails_latest_snapshots_cache_total", &[("result", "hit")]);
+            return Ok(entry.1.clone());
+        }
+    }
+    state.metrics.inc("trails_latest_snapshots_cache_total", &[("result", "miss")]);
+
+    let mut apps = db::list_latest_snapshots(
+        &state.db,
+        &query.statuses,
+        query.namespace.as_deref(),
+        query.field_paths().as_ref(),
+        query.after,
+        query.limit + 1,
+    )
+    .await?;
+    let next_after = if apps.len() as i64 > query.limit {
+        apps.truncate(query.limit as usize);
+        apps.last().map(|a| a.app_id)
+    } else {
+        None
+    };
+    let body = serde_json::to_vec(&LatestPage { apps, next_after })
+        .map_err(|e| TrailsError::InvalidJson(e.to_string()))?;
+    let body = Bytes::from(body);
+
+    if state.latest.entries.len() >= CACHE_SWEEP_AT {
+        state.latest.entries.retain(|_, (at, _)| at.elapsed() < ttl);
+    }
+    state.latest.entries.insert(key, (Instant::now(), body.clone()));
+    Ok(body)
+}
@@ -6,38 +6,56 @@
 //!
 //! 2. **Reconnection window** — after server startup, waits for clients
 //!    to re-register, then marks stragglers as 'lost_contact' (spec §19).
+//!    An app whose process disconnects with `will_reconnect` gets the
+//!    same window of its own.
+//!
+//! 3. **Max-runtime checker** — running apps past their runtime budget get
+//!    a `cancel` control message; if still running one grace period later
+//!    they are forced to 'timed_out' with a crash record of that type.
 
 use std::sync::Arc;
 use std::time::Duration;
 
+use chrono::Utc;
+use serde_json::json;
 use tracing::{info, warn};
+use uuid::Uuid;
 
-use crate::db;
+use crate::config::Config;
+use crate::controls::{self, Delivery};
+use crate::db::{self, AppRow};
 use crate::state::AppState;
 use crate::types::Event;
+use crate::ws;
 
-/// Spawn the start-deadline checker. Runs every 30 seconds.
+/// Spawn the start-deadline checker. Runs every `deadline_check_interval` seconds.
 pub fn spawn_deadline_checker(state: Arc<AppState>) {
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(30));
         loop {
-            interval.tick().await;
             if let Err(e) = check_deadlines(&state).await {
                 warn!("deadline checker error: {e}");
             }
+            let every = state.config().deadline_check_interval;
+            tokio::time::sleep(Duration::from_secs(every)).await;
         }
     });
 }
 
 async fn check_deadlines(state: &Arc<AppState>) -> Result<(), crate::error::TrailsError> {
     let expired = db::get_expired_scheduled(&state.db).await?;
+    let mut failed = 0;
     for app in &expired {
+        // Re-checked in the update: the app may have connected or been
+        // rescheduled since the scan.
+        if !db::set_start_failed(&state.db, app.app_id).await? {
+            continue;
+        }
+        failed += 1;
         info!(
             app_id = %app.app_id,
             app_name = %app.app_name,
             "start deadline expired → start_failed (never_started)"
         );
-        db::set_start_failed(&state.db, app.app_id).await?;
         db::record_crash(&state.db, app.app_id, "never_started", None, None).await?;
 
         state.publish(Event::CrashDetected {
@@ -46,8 +64,8 @@ async fn check_deadlines(state: &Arc<AppState>) -> Result<(), crate::error::Trai
             crash_type: "never_started".into(),
         });
     }
-    if !expired.is_empty() {
-        info!(count = expired.len(), "expired scheduled apps → start_failed");
+    if failed > 0 {
+        info!(count = failed, "expired scheduled apps → start_failed");
     }
     Ok(())
 }
@@ -55,8 +73,8 @@ async fn check_deadlines(state: &Arc<AppState>) -> Result<(), crate::error::Trai
 /// On server startup: mark previous connections as 'reconnecting',
 /// then after the window expires, mark stragglers as 'lost_contact' (spec §19).
 pub fn spawn_reconnection_window(state: Arc<AppState>) {
-    let window = state.config.reconnect_window;
-    let instance = state.config.server_instance.clone();
+    let window = state.boot.reconnect_window;
+    let instance = state.boot.server_instance.clone();
 
     tokio::spawn(async move {
         // Step 1: mark all apps that were connected to us as 'reconnecting'.
@@ -87,3 +105,134 @@ pub fn spawn_reconnection_window(state: Arc<AppState>) {
         }
     });
 }
+
+/// After an app's `will_reconnect` disconnect: mark it 'lost_contact' if
+/// it hasn't re-registered within the reconnection window.
+pub fn spawn_reconnect_deadline(state: Arc<AppState>, app_id: Uuid) {
+    let window = state.boot.reconnect_window;
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(window)).await;
+        match db::mark_app_lost_contact(&state.db, app_id, window).await {
+            Ok(true) => warn!(app_id = %app_id, "app failed to reconnect → lost_contact"),
+            Ok(false) => {}
+            Err(e) => warn!(app_id = %app_id, "mark_app_lost_contact error: {e}"),
+        }
+    });
+}
+
+/// Effective runtime budget in seconds: the app's own, else the
+/// namespace/global default. `None` means unlimited.
+pub fn max_runtime(config: &Config, app: &AppRow) -> Option<i32> {
+    app.max_runtime_secs
+        .or_else(|| config.max_runtime_for(app.namespace.as_deref()))
+}
+
+/// Spawn the max-runtime checker. Runs every `runtime_check_interval` seconds.
+pub fn spawn_runtime_checker(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = check_runtimes(&state).await {
+                warn!("runtime checker error: {e}");
+            }
+            let every = state.config().runtime_check_interval;
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+async fn check_runtimes(state: &Arc<AppState>) -> Result<(), crate::error::TrailsError> {
+    let config = state.config();
+    let grace = config.timeout_grace;
+    let running = db::list_running_apps(&state.db, &state.boot.server_instance).await?;
+    let now = Utc::now();
+
+    for app in &running {
+        let (Some(budget), Some(start_time)) = (max_runtime(&config, app), app.start_time)
+        else {
+            continue;
+        };
+        let elapsed = (now - start_time).num_seconds();
+        if elapsed < i64::from(budget) {
+            continue;
+        }
+
+        match app.timeout_cancel_at {
+            // Phase 1: ask the app to stop.
+            None => {
+                let payload = json!({
+                    "reason": "max_runtime_exceeded",
+                    "max_runtime_secs": budget,
+                    "grace_seconds": grace,
+                    "initiated_by": "trailsd",
+                });
+                // Apps on HTTP ingestion pick it up with their next post.
+                let sent = match app.transport.as_str() {
+                    "http" => {
+                        let queued = controls::send_or_queue(
+                            state,
+                            app.app_id,
+                            "cancel",
+                            payload,
+                            Some("trailsd"),
+                        );
+                        queued
+                            .await
+                            .map(|o| o.is_some_and(|o| o.delivery == Delivery::Delivered))
+                    }
+                    _ => ws::send_control(state, app.app_id, "cancel", payload).await,
+                };
+                let delivered = sent.unwrap_or_else(|e| {
+                    warn!(app_id = %app.app_id, "timeout cancel send failed: {e}");
+                    false
+                });
+                db::mark_timeout_cancel(&state.db, app.app_id).await?;
+                state.metrics.inc("trails_runtime_cancels_total", &[]);
+                info!(
+                    app_id = %app.app_id,
+                    app_name = %app.app_name,
+                    budget,
+                    elapsed,
+                    delivered,
+                    "max runtime exceeded → cancel sent"
+                );
+            }
+            // Phase 2: grace elapsed and still running — force it.
+            Some(cancel_at) if (now - cancel_at).num_seconds() >= grace => {
+                force_timeout(state, app, budget, elapsed).await?;
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+async fn force_timeout(
+    state: &Arc<AppState>,
+    app: &AppRow,
+    budget: i32,
+    elapsed: i64,
+) -> Result<(), crate::error::TrailsError> {
+    // Loses the race cleanly if the app finished during the grace period.
+    if !db::set_timed_out(&state.db, app.app_id).await? {
+        info!(app_id = %app.app_id, "app finished within timeout grace");
+        return Ok(());
+    }
+    let metadata = json!({ "max_runtime_secs": budget, "elapsed_secs": elapsed });
+    db::record_crash(&state.db, app.app_id, "timed_out", None, Some(&metadata)).await?;
+
+    state.publish(Event::AppTerminal {
+        app_id: app.app_id,
+        parent_id: app.parent_id,
+        status: "timed_out".into(),
+    });
+    state.publish(Event::CrashDetected {
+        app_id: app.app_id,
+        parent_id: app.parent_id,
+        crash_type: "timed_out".into(),
+    });
+    state.metrics.inc("trails_apps_timed_out_total", &[]);
+    warn!(app_id = %app.app_id, app_name = %app.app_name, budget, elapsed, "grace elapsed → timed_out");
+
+    ws::close_connection(state, app.app_id).await;
+    Ok(())
+}
@@ -0,0 +1,94 @@
+//! Listeners. The router is served on TCP (`LISTEN_ADDR`), on a Unix
+//! domain socket (`LISTEN_UDS`) for sidecars sharing the pod, or on both;
+//! WebSocket upgrades work the same on either. One shutdown signal stops
+//! them all.
+
+use std::fs::{self, Permissions};
+use std::future::IntoFuture;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::Router;
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::config::BootConfig;
+use crate::drain;
+use crate::state::AppState;
+
+/// Bind the configured listeners and serve `app` until shutdown. Panics
+/// if one can't be bound, like the TCP bind always has.
+pub async fn serve(boot: &BootConfig, app: Router, state: Arc<AppState>) {
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let stopped = |mut rx: watch::Receiver<bool>| async move {
+        let _ = rx.wait_for(|stop| *stop).await;
+    };
+    let mut servers = JoinSet::new();
+
+    if let Some(addr) = &boot.listen_addr {
+        let listener = TcpListener::bind(addr).await.expect("failed to bind");
+        info!(addr = %addr, "trailsd listening");
+        let server =
+            axum::serve(listener, app.clone()).with_graceful_shutdown(stopped(stop_rx.clone()));
+        servers.spawn(server.into_future());
+    }
+    if let Some(path) = &boot.listen_uds {
+        let listener = bind_uds(Path::new(path), boot.listen_uds_mode, boot.listen_uds_owner)
+            .unwrap_or_else(|e| panic!("failed to bind {path}: {e}"));
+        info!(path = %path, mode = format!("{:o}", boot.listen_uds_mode), "trailsd listening");
+        let server = axum::serve(listener, app).with_graceful_shutdown(stopped(stop_rx));
+        servers.spawn(server.into_future());
+    }
+
+    tokio::spawn(async move {
+        drain::shutdown_signal(state).await;
+        let _ = stop_tx.send(true);
+    });
+    while let Some(result) = servers.join_next().await {
+        result.expect("server task panicked").expect("server error");
+    }
+
+    if let Some(path) = &boot.listen_uds {
+        if let Err(e) = fs::remove_file(path) {
+            warn!(path = %path, "removing socket file failed: {e}");
+        }
+    }
+}
+
+/// Bind a socket at `path`, replacing a stale one left by an unclean exit,
+/// then set its mode and owner.
+fn bind_uds(
+    path: &Path,
+    mode: u32,
+    owner: Option<(Option<u32>, Option<u32>)>,
+) -> io::Result<UnixListener> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "another server is listening there",
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "path exists and is not a socket",
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, Permissions::from_mode(mode))?;
+    if let Some((uid, gid)) = owner {
+        std::os::unix::fs::chown(path, uid, gid)?;
+    }
+    Ok(listener)
+}
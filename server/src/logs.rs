@@ -0,0 +1,90 @@
+//! App log lines. An app sends the lines worth keeping as `Log` messages,
+//! `{"level": "warn", "message": "...", "fields": {...}}`, and they are
+//! stored in `logs`, apart from the message history: no snapshot, no
+//! state transition, no observer fan-out. `ts` is the message header's
+//! timestamp, when the app logged the line.
+//!
+//! The seq is the message's, so replays are told apart as for any
+//! message. Redaction applies as it does to messages. Sealed apps' `Log`
+//! messages can't be read, so they are stored as messages instead.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::db;
+use crate::error::TrailsError;
+use crate::state::AppState;
+
+pub const LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+/// A `Log` message's payload.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct LogLine {
+    pub level: String,
+    pub message: String,
+    #[serde(default)]
+    pub fields: Option<JsonValue>,
+}
+
+impl LogLine {
+    /// Read a log line from a payload; the level is lowercased.
+    pub fn parse(payload: &JsonValue) -> Result<Self, TrailsError> {
+        let mut line = LogLine::deserialize(payload)
+            .map_err(|e| TrailsError::Protocol(format!("log line: {e}")))?;
+        line.level.make_ascii_lowercase();
+        if !LEVELS.contains(&line.level.as_str()) {
+            return Err(TrailsError::Protocol(format!(
+                "log line: unknown level '{}'",
+                line.level
+            )));
+        }
+        Ok(line)
+    }
+}
+
+/// Store the log line that came as message `seq`, logged at
+/// `timestamp_ms`. False if it was already stored.
+pub async fn store(
+    state: &AppState,
+    app_id: Uuid,
+    seq: i64,
+    timestamp_ms: i64,
+    payload: &JsonValue,
+) -> Result<bool, TrailsError> {
+    let line = LogLine::parse(payload)?;
+    let ts = DateTime::from_timestamp_millis(timestamp_ms).unwrap_or_else(Utc::now);
+    db::store_log(&state.db, app_id, seq, &line, ts).await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parses_log_lines() {
+        let line = LogLine::parse(&json!({"level": "WARN", "message": "retrying"})).unwrap();
+        assert_eq!((line.level.as_str(), line.fields), ("warn", None));
+
+        let payload = json!({"level": "error", "message": "failed", "fields": {"table": "orders"}});
+        let line = LogLine::parse(&payload).unwrap();
+        assert_eq!(line.fields, Some(json!({"table": "orders"})));
+    }
+
+    #[test]
+    fn refuses_what_isnt_a_log_line() {
+        for payload in [
+            json!({"level": "loud", "message": "x"}),
+            json!({"level": "info"}),
+            json!("just text"),
+        ] {
+            assert!(matches!(
+                LogLine::parse(&payload),
+                Err(TrailsError::Protocol(_))
+            ));
+        }
+    }
+}
@@ -3,89 +3,252 @@
 //! Phase 1: WebSocket handler + lifecycle state machine + Postgres.
 //! See TRAILS-SPEC.md §21 for architecture overview.
 
+mod api;
+mod app_metrics;
+mod blob;
+mod child_watch;
 mod config;
+mod controls;
+mod crash_loop;
 mod db;
+mod dependencies;
+mod diff;
+mod drain;
+mod encoding;
 mod error;
+mod export;
+mod feed;
+mod ingest;
+mod latest;
 mod lifecycle;
+mod listener;
+mod logs;
+mod metrics;
+mod observe;
+mod outbox;
+mod purge;
+mod query;
+mod quota;
+mod redact;
+mod reload;
+mod request_id;
+mod retention;
+mod rollup;
+mod schema;
+mod sealed;
+mod signing;
+mod stall;
 mod state;
 mod types;
+mod version;
 mod ws;
 
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
 use axum::routing::get;
-use axum::Router;
+use axum::{Json, Router};
+use serde_json::{json, Value as JsonValue};
 use sqlx::postgres::PgPoolOptions;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{error, info, warn};
+
+use crate::state::AppState;
+
+/// Schema migrations, applied in order at startup.
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("001_init", include_str!("../migrations/001_init.sql")),
+    ("002_outbox", include_str!("../migrations/002_outbox.sql")),
+    ("003_schemas", include_str!("../migrations/003_schemas.sql")),
+    ("004_dead_letters", include_str!("../migrations/004_dead_letters.sql")),
+    ("005_message_blobs", include_str!("../migrations/005_message_blobs.sql")),
+    ("006_max_runtime", include_str!("../migrations/006_max_runtime.sql")),
+    ("007_paused", include_str!("../migrations/007_paused.sql")),
+    ("008_crash_loops", include_str!("../migrations/008_crash_loops.sql")),
+    ("009_priority", include_str!("../migrations/009_priority.sql")),
+    ("010_scheduled_at", include_str!("../migrations/010_scheduled_at.sql")),
+    ("011_sec_level", include_str!("../migrations/011_sec_level.sql")),
+    ("012_sealed", include_str!("../migrations/012_sealed.sql")),
+    ("013_purged_apps", include_str!("../migrations/013_purged_apps.sql")),
+    ("014_snapshot_seq", include_str!("../migrations/014_snapshot_seq.sql")),
+    ("015_message_query", include_str!("../migrations/015_message_query.sql")),
+    ("016_correlation", include_str!("../migrations/016_correlation.sql")),
+    ("017_app_name", include_str!("../migrations/017_app_name.sql")),
+    ("018_storage_quota", include_str!("../migrations/018_storage_quota.sql")),
+    ("019_server_version", include_str!("../migrations/019_server_version.sql")),
+    ("020_request_id", include_str!("../migrations/020_request_id.sql")),
+    ("021_stall", include_str!("../migrations/021_stall.sql")),
+    ("022_message_export", include_str!("../migrations/022_message_export.sql")),
+    ("023_dependencies", include_str!("../migrations/023_dependencies.sql")),
+    ("024_pending_controls", include_str!("../migrations/024_pending_controls.sql")),
+    ("025_redactions", include_str!("../migrations/025_redactions.sql")),
+    ("026_app_metrics", include_str!("../migrations/026_app_metrics.sql")),
+    ("027_http_ingest", include_str!("../migrations/027_http_ingest.sql")),
+    ("028_heartbeat", include_str!("../migrations/028_heartbeat.sql")),
+    ("029_logs", include_str!("../migrations/029_logs.sql")),
+    ("030_custom_msg_types", include_str!("../migrations/030_custom_msg_types.sql")),
+    ("031_message_seq_unique", include_str!("../migrations/031_message_seq_unique.sql")),
+    ("032_log_seq_unique", include_str!("../migrations/032_log_seq_unique.sql")),
+    ("033_outbox_lease", include_str!("../migrations/033_outbox_lease.sql")),
+    ("034_outbox_next_attempt", include_str!("../migrations/034_outbox_next_attempt.sql")),
+];
 
 #[tokio::main]
 async fn main() {
     // Load .env if present (local dev).
-    let _ = dotenvy::dotenv();
+    config::load_dotenv();
 
-    let config = config::Config::from_env();
+    let config::Loaded {
+        boot,
+        config,
+        unparsed,
+        invalid,
+    } = config::load();
 
     // Tracing.
     tracing_subscriber::fmt()
-        .with_env_filter(&config.log_level)
+        .with_env_filter(&boot.log_level)
         .with_target(true)
         .init();
 
-    info!("trailsd starting");
-    info!(listen = %config.listen_addr, instance = %config.server_instance);
+    info!(version = version::SERVER_VERSION, "trailsd starting");
+    info!(
+        listen = boot.listen_addr.as_deref().unwrap_or("off"),
+        uds = boot.listen_uds.as_deref().unwrap_or("off"),
+        instance = %boot.server_instance
+    );
+    for problem in &unparsed {
+        warn!("config: {problem}; using the default");
+    }
+    if !invalid.is_empty() {
+        for problem in &invalid {
+            error!("config: {problem}");
+        }
+        std::process::exit(1);
+    }
 
     // ── Postgres ────────────────────────────────────────────
     let pool = PgPoolOptions::new()
         .max_connections(20)
-        .connect(&config.database_url)
+        .connect(&boot.database_url)
         .await
         .expect("failed to connect to Postgres");
 
-    // Run migration.
+    // Run migrations. 001 is not idempotent and fails harmlessly on
+    // restart; later migrations use IF NOT EXISTS and re-run cleanly.
     info!("running migrations");
-    sqlx::query(include_str!("../migrations/001_init.sql"))
-        .execute(&pool)
-        .await
-        .unwrap_or_else(|e| {
-            // Migration may fail if tables exist — that's fine on restart.
-            info!("migration note (may already exist): {e}");
-            Default::default()
-        });
+    for (name, sql) in MIGRATIONS {
+        // raw_sql: migrations are multi-statement scripts, which the
+        // prepared-statement path rejects.
+        sqlx::raw_sql(sql)
+            .execute(&pool)
+            .await
+            .unwrap_or_else(|e| {
+                // Migration may fail if tables exist — that's fine on restart.
+                info!("migration note for {name} (may already exist): {e}");
+                Default::default()
+            });
+    }
+
+    // Data migration: stored names follow the registration rules.
+    if config.app_name_trim {
+        match db::trim_app_names(&pool).await {
+            Ok(0) => {}
+            Ok(n) => info!(count = n, "trimmed whitespace from stored app names"),
+            Err(e) => warn!("trimming stored app names failed: {e}"),
+        }
+    }
 
     info!("database ready");
 
     // ── Shared state ────────────────────────────────────────
-    let state = state::AppState::new(pool, config.clone());
+    let listen = boot.clone();
+    let state = state::AppState::new(pool, boot, config);
 
     // ── Background tasks ────────────────────────────────────
     // Reconnection window — mark old connections, wait, then mark lost.
     lifecycle::spawn_reconnection_window(Arc::clone(&state));
     // Start deadline checker — periodic scan.
     lifecycle::spawn_deadline_checker(Arc::clone(&state));
+    // Max-runtime checker — cancel, then force timed_out.
+    lifecycle::spawn_runtime_checker(Arc::clone(&state));
+    // Stall sweeper — flag running apps whose Status stopped changing.
+    stall::spawn_stall_checker(Arc::clone(&state));
+    // Outbox dispatcher — durable event delivery.
+    outbox::spawn_dispatcher(Arc::clone(&state));
+    // Schema validation counters — periodic flush.
+    schema::spawn_stats_flusher(Arc::clone(&state));
+    // Retention — prune aged dead letters, compact snapshots.
+    retention::spawn_retention_task(Arc::clone(&state));
+    // Dependencies — release or cancel blocked apps as siblings finish.
+    dependencies::spawn_dependency_resolver(Arc::clone(&state));
+    // HTTP ingestion — crash apps that stopped posting.
+    ingest::spawn_stale_sweeper(Arc::clone(&state));
+    // Queued controls — expire those never delivered.
+    controls::spawn_control_expirer(Arc::clone(&state));
+    // Crash-loop detection on CrashDetected.
+    crash_loop::spawn_crash_loop_detector(Arc::clone(&state));
+    // Parent rollups — cache invalidation, optional parent snapshots.
+    rollup::spawn_rollup_listener(Arc::clone(&state));
+    // Child watches — child events down the watching parent's connection.
+    child_watch::spawn_child_watcher(Arc::clone(&state));
+    // Observers — stored messages down the observing connections.
+    observe::spawn_observer_fanout(Arc::clone(&state));
+    // Storage quotas — reconcile stored_bytes counters.
+    quota::spawn_reconciler(Arc::clone(&state));
+    // Finish data purges interrupted by a restart.
+    purge::spawn_purge_resumer(Arc::clone(&state));
+    // Drain mode on SIGUSR1 (rolling upgrades).
+    drain::spawn_drain_signal(Arc::clone(&state));
+    // Configuration reload on SIGHUP.
+    reload::spawn_reload_signal(Arc::clone(&state));
 
     // ── Routes ──────────────────────────────────────────────
     let app = Router::new()
         // WebSocket endpoint.
         .route("/ws", get(ws::ws_handler))
-        // Health check (useful for K8s liveness probes).
+        .route("/ws/apps/{id}/feed", get(feed::feed_handler))
+        // REST API (spec §23).
+        .nest("/api/v1", api::router())
+        // HTTP ingestion for apps that can't hold a WebSocket.
+        .nest("/api/v1", ingest::router())
+        // Health check (useful for K8s liveness probes), with reload status.
         .route("/healthz", get(healthz))
+        // Readiness probe — 503 while draining.
+        .route("/readyz", get(readyz))
+        // Prometheus scrape endpoint.
+        .route("/metrics", get(metrics::metrics_handler))
         .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        // X-Request-Id in, out, and on the request's log lines.
+        .layer(middleware::from_fn(request_id::middleware))
+        .with_state(Arc::clone(&state));
 
     // ── Bind & serve ────────────────────────────────────────
-    let listener = tokio::net::TcpListener::bind(&config.listen_addr)
-        .await
-        .expect("failed to bind");
+    listener::serve(&listen, app, state).await;
 
-    info!(addr = %config.listen_addr, "trailsd listening");
+    info!("trailsd stopped");
+}
 
-    axum::serve(listener, app)
-        .await
-        .expect("server error");
+/// Liveness probe. Also reports configuration reloads, so an operator
+/// can confirm a SIGHUP took.
+async fn healthz(State(state): State<Arc<AppState>>) -> Json<JsonValue> {
+    Json(json!({
+        "status": "ok",
+        "configReloads": state.config_reloads.load(Ordering::Relaxed),
+        "configReloadFailures": state.config_reload_failures.load(Ordering::Relaxed),
+        "lastConfigReloadAt": state.config_reloaded_at(),
+    }))
 }
 
-/// Liveness probe.
-async fn healthz() -> &'static str {
-    "ok"
+/// Readiness probe. Fails while draining so the Service stops routing
+/// new connections here.
+async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, &'static str) {
+    if state.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "ok")
+    }
 }
@@ -0,0 +1,135 @@
+//! Prometheus metrics — a small in-process registry rendered in text format.
+//!
+//! Series are keyed by metric name + rendered label set. Counters only go up;
+//! gauges are overwritten by whoever owns them (usually a background task).
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use dashmap::DashMap;
+
+use crate::state::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Series {
+    kind: Kind,
+    value: f64,
+}
+
+/// Metric registry shared through `AppState`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series: DashMap<(&'static str, String), Series>,
+}
+
+impl Metrics {
+    /// Increment a counter by one.
+    pub fn inc(&self, name: &'static str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1.0);
+    }
+
+    /// Increment a counter by `delta`.
+    pub fn add(&self, name: &'static str, labels: &[(&str, &str)], delta: f64) {
+        let mut entry = self
+            .series
+            .entry((name, render_labels(labels)))
+            .or_insert(Series {
+                kind: Kind::Counter,
+                value: 0.0,
+            });
+        entry.value += delta;
+    }
+
+    /// Set a gauge to `value`.
+    pub fn set(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.series.insert(
+            (name, render_labels(labels)),
+            Series {
+                kind: Kind::Gauge,
+                value,
+            },
+        );
+    }
+
+    /// Drop every `name` series labelled `label="value"`.
+    pub fn remove_labelled(&self, name: &'static str, label: &str, value: &str) {
+        let pair = render_labels(&[(label, value)]);
+        let pair = &pair[1..pair.len() - 1];
+        self.series
+            .retain(|(n, labels), _| *n != name || !has_label(labels, pair));
+    }
+
+    /// Render all series in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut rows: Vec<(&'static str, String, Kind, f64)> = self
+            .series
+            .iter()
+            .map(|e| (e.key().0, e.key().1.clone(), e.value().kind, e.value().value))
+            .collect();
+        rows.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+        let mut out = String::new();
+        let mut last_name = "";
+        for (name, labels, kind, value) in rows {
+            if name != last_name {
+                let _ = writeln!(out, "# TYPE {name} {}", kind.as_str());
+                last_name = name;
+            }
+            let _ = writeln!(out, "{name}{labels} {value}");
+        }
+        out
+    }
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let parts: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| {
+            let escaped = v
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{k}=\"{escaped}\"")
+        })
+        .collect();
+    format!("{{{}}}", parts.join(","))
+}
+
+/// Whether a rendered label set contains the rendered `pair`.
+fn has_label(labels: &str, pair: &str) -> bool {
+    labels.match_indices(pair).any(|(i, _)| {
+        let before = &labels[..i];
+        let after = &labels[i + pair.len()..];
+        (before.ends_with('{') || before.ends_with(','))
+            && (after.starts_with('}') || after.starts_with(','))
+    })
+}
+
+/// GET /metrics — Prometheus scrape endpoint.
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
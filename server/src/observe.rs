@@ -0,0 +1,201 @@
+//! Observers: a read-only client, e.g. an orchestration UI backend, sends
+//! `observe` as its first frame on `/ws`, in place of a register, and is
+//! then sent an `observed` frame for each Status, Result and Error the app
+//! stores, until it disconnects. The `observing` answer carries the app's
+//! status and last stored seq; what came before is read over REST, or
+//! from the per-app feed, which backfills.
+//!
+//! Observers are kept in `AppState::observers` by the app they observe,
+//! and one task on the event bus fans stored messages out to them, read
+//! back from Postgres (and the blob store, if offloaded) once per message.
+//! A message racing the `observe` may be sent although its seq is in
+//! `last_seq`.
+//!
+//! When `OBSERVER_TOKEN` is set, the upgrade request must carry it as
+//! `Authorization: Bearer <token>`; otherwise observing is open, as the
+//! REST API is. `authorize` gets the app row for checks against its
+//! `role_refs` once observers carry an identity.
+
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket};
+use axum::http::{header, HeaderMap};
+use futures::stream::SplitStream;
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::blob;
+use crate::db::{self, AppRow};
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::{Event, MsgType, ObservedMsg, ObservingMsg, ServerMessage};
+use crate::ws::{self, Sender};
+
+/// What an observer presented on its upgrade request.
+#[derive(Debug, Default)]
+pub struct Credentials {
+    token: Option<String>,
+}
+
+impl Credentials {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|t| t.trim().to_string());
+        Self { token }
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+}
+
+/// Whether `credentials` may observe `app`.
+fn authorize(
+    state: &AppState,
+    credentials: &Credentials,
+    _app: &AppRow,
+) -> Result<(), TrailsError> {
+    let Some(expected) = state.config().observer_token.clone() else {
+        return Ok(());
+    };
+    match credentials.token.as_deref() {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        Some(_) => Err(TrailsError::Unauthorized("wrong observer token".into())),
+        None => Err(TrailsError::Unauthorized("observer token required".into())),
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Check an `observe` and answer it with `observing`; the connection is
+/// an observer of `app_id` from then on.
+pub async fn admit(
+    state: &AppState,
+    credentials: &Credentials,
+    app_id: Uuid,
+    sender: &Sender,
+) -> Result<(), TrailsError> {
+    let app = db::get_app(&state.db, app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+    authorize(state, credentials, &app)?;
+
+    // Observe first, so nothing stored while `last_seq` is read is lost.
+    state
+        .observers
+        .entry(app_id)
+        .or_default()
+        .push(Arc::clone(sender));
+    let observing = async {
+        let last_seq = db::max_message_seq(&state.db, app_id).await?;
+        let msg = ServerMessage::Observing(ObservingMsg {
+            app_id,
+            status: app.status,
+            last_seq,
+        });
+        ws::send_msg(sender, &msg).await
+    };
+    if let Err(e) = observing.await {
+        forget(state, app_id, sender);
+        return Err(e);
+    }
+    state.metrics.inc("trails_observer_connections_total", &[]);
+    debug!(app_id = %app_id, "observer connected");
+    Ok(())
+}
+
+/// Hold an admitted observer's connection until it closes. Observers only
+/// listen; anything they send is ignored.
+pub async fn serve(
+    receiver: &mut SplitStream<WebSocket>,
+    sender: &Sender,
+    state: &AppState,
+    app_id: Uuid,
+) {
+    while let Some(msg) = receiver.next().await {
+        match msg {
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+    forget(state, app_id, sender);
+    debug!(app_id = %app_id, "observer disconnected");
+}
+
+fn forget(state: &AppState, app_id: Uuid, sender: &Sender) {
+    state.observers.remove_if_mut(&app_id, |_, observers| {
+        observers.retain(|o| !Arc::ptr_eq(o, sender));
+        observers.is_empty()
+    });
+}
+
+/// Subscribe to the event bus and pass stored messages on to the apps'
+/// observers.
+pub fn spawn_observer_fanout(state: Arc<AppState>) {
+    let mut rx = state.event_tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!(skipped = n, "observer fan-out lagged, messages lost");
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let Event::MessageStored {
+                app_id,
+                msg_type: msg_type @ (MsgType::Status | MsgType::Result | MsgType::Error),
+                seq,
+                ..
+            } = event
+            else {
+                continue;
+            };
+            if let Err(e) = fan_out(&state, app_id, msg_type, seq).await {
+                debug!(app_id = %app_id, seq, "observed message not sent: {e}");
+            }
+        }
+    });
+}
+
+async fn fan_out(
+    state: &AppState,
+    app_id: Uuid,
+    msg_type: MsgType,
+    seq: i64,
+) -> Result<(), TrailsError> {
+    let observers = match state.observers.get(&app_id) {
+        Some(observers) => observers.clone(),
+        None => return Ok(()),
+    };
+    let rows =
+        db::list_messages(&state.db, app_id, Some(msg_type.as_str()), Some(seq - 1), 1).await?;
+    let Some(row) = rows.into_iter().next().filter(|row| row.seq == seq) else {
+        return Ok(());
+    };
+    let payload = match row.blob_key.as_deref() {
+        Some(key) => Some(blob::resolve(state, key).await?),
+        None => row.payload_json,
+    };
+    let msg = ServerMessage::Observed(ObservedMsg {
+        app_id,
+        seq,
+        msg_type: row.msg_type,
+        correlation_id: row.correlation_id,
+        payload,
+        created_at: row.created_at,
+    });
+    // A failed send is the observer going; its connection forgets it.
+    for observer in &observers {
+        let _ = ws::send_msg(observer, &msg).await;
+    }
+    Ok(())
+}
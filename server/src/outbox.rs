@@ -0,0 +1,238 @@
+//! Transactional outbox dispatcher.
+//!
+//! Lifecycle transitions in `db` insert an outbox row in the same
+//! transaction as the state change, so an event can't be lost to a crash
+//! between commit and publish. This task claims undelivered rows with a
+//! lease (`locked_until`, so several trailsd instances can share one
+//! database), hands each to every registered sink with no transaction
+//! open, and records per-sink attempts in a short transaction per row.
+//! A row with failed deliveries isn't claimed again until the earliest
+//! of its retries is due (`next_attempt_at`). After `outbox_max_attempts`
+//! failures a delivery is dead-lettered. With no sinks configured rows
+//! stay pending, for the sinks a reload adds.
+//!
+//! The in-memory bus (`AppState::publish`) stays the path for
+//! latency-sensitive consumers; durable sinks read from here.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::db::{self, OutboxRow};
+use crate::error::TrailsError;
+use crate::state::AppState;
+
+/// How long a sink may take over one delivery; the webhook client's
+/// timeout.
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+
+/// A durable event consumer.
+pub trait Sink: Send + Sync {
+    /// Stable name, used as the key for per-sink delivery state.
+    fn name(&self) -> &str;
+
+    /// Deliver one event. Errors are retried with backoff.
+    fn deliver<'a>(&'a self, entry: &'a OutboxRow) -> BoxFuture<'a, Result<(), String>>;
+}
+
+/// POSTs each event as JSON to a fixed URL. Any 2xx counts as delivered.
+pub struct WebhookSink {
+    name: String,
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, client: reqwest::Client) -> Self {
+        Self {
+            name: format!("webhook:{url}"),
+            url: url.into(),
+            client,
+        }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn deliver<'a>(&'a self, entry: &'a OutboxRow) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let body = json!({
+                "id": entry.id,
+                "event_type": entry.event_type,
+                "app_id": entry.app_id,
+                "created_at": entry.created_at,
+                "event": entry.payload_json,
+            });
+            let resp = self
+                .client
+                .post(&self.url)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            if resp.status().is_success() {
+                Ok(())
+            } else {
+                Err(format!("HTTP {}", resp.status()))
+            }
+        })
+    }
+}
+
+/// Build the sink set from the configured webhooks.
+fn build_sinks(client: &reqwest::Client, webhooks: &[String]) -> Vec<Arc<dyn Sink>> {
+    webhooks
+        .iter()
+        .map(|url| Arc::new(WebhookSink::new(url, client.clone())) as Arc<dyn Sink>)
+        .collect()
+}
+
+/// Spawn the outbox dispatcher. The sink set is rebuilt when a reload
+/// changes the webhook list.
+pub fn spawn_dispatcher(state: Arc<AppState>) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+        .build()
+        .expect("failed to build HTTP client");
+    tokio::spawn(async move {
+        let mut webhooks = Vec::new();
+        let mut sinks = Vec::new();
+        loop {
+            let config = state.config();
+            if config.outbox_webhooks != webhooks {
+                webhooks = config.outbox_webhooks.clone();
+                sinks = build_sinks(&client, &webhooks);
+                if let Err(e) = db::reset_outbox_backoff(&state.db).await {
+                    warn!("outbox backoff reset error: {e}");
+                }
+            }
+            if let Err(e) = dispatch_batch(&state, &sinks).await {
+                warn!("outbox dispatch error: {e}");
+            }
+            if let Err(e) = update_backlog_metrics(&state).await {
+                warn!("outbox metrics error: {e}");
+            }
+            if let Err(e) = db::prune_outbox(&state.db, config.outbox_retention).await {
+                warn!("outbox prune error: {e}");
+            }
+            tokio::time::sleep(Duration::from_millis(config.outbox_poll_ms)).await;
+        }
+    });
+}
+
+/// What one sink made of one row.
+enum Outcome {
+    Delivered,
+    Failed { error: String, attempts: i32 },
+}
+
+async fn dispatch_batch(state: &Arc<AppState>, sinks: &[Arc<dyn Sink>]) -> Result<(), TrailsError> {
+    // Nobody to deliver to yet; don't complete rows a later sink wants.
+    if sinks.is_empty() {
+        return Ok(());
+    }
+    let config = state.config();
+    let batch_size = config.outbox_batch_size;
+    // Long enough to reach the last row with every delivery timing out;
+    // each row's lease is released as soon as its outcome is recorded.
+    let per_row = DELIVERY_TIMEOUT_SECS as i64 * sinks.len() as i64;
+    let lease_secs = 60 + per_row * batch_size.max(1);
+    let rows = db::claim_pending_outbox(&state.db, batch_size, lease_secs).await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let ids: Vec<i64> = rows.iter().map(|r| r.id).collect();
+    let deliveries: HashMap<(i64, String), db::OutboxDeliveryRow> =
+        db::get_outbox_deliveries(&state.db, &ids)
+            .await?
+            .into_iter()
+            .map(|d| ((d.outbox_id, d.sink.clone()), d))
+            .collect();
+
+    let now = chrono::Utc::now();
+    for row in &rows {
+        // The earliest retry among the sinks still owed a delivery.
+        let mut retry_at: Option<chrono::DateTime<chrono::Utc>> = None;
+        let mut retry_at_earliest = |at| retry_at = Some(retry_at.map_or(at, |r| r.min(at)));
+        let mut outcomes = Vec::new();
+        for sink in sinks {
+            let prior = deliveries.get(&(row.id, sink.name().to_string()));
+            if let Some(d) = prior {
+                if d.delivered_at.is_some() || d.dead_lettered_at.is_some() {
+                    continue;
+                }
+                if d.next_attempt_at > now {
+                    retry_at_earliest(d.next_attempt_at);
+                    continue;
+                }
+            }
+            let outcome = match sink.deliver(row).await {
+                Ok(()) => Outcome::Delivered,
+                Err(error) => Outcome::Failed {
+                    error,
+                    attempts: prior.map(|d| d.attempts).unwrap_or(0) + 1,
+                },
+            };
+            outcomes.push((sink, outcome));
+        }
+
+        let mut tx = state.db.begin().await?;
+        for (sink, outcome) in outcomes {
+            match outcome {
+                Outcome::Delivered => {
+                    db::mark_outbox_delivered(&mut tx, row.id, sink.name()).await?;
+                    state.metrics.inc("trails_outbox_delivered_total", &[("sink", sink.name())]);
+                }
+                Outcome::Failed { error: e, attempts } => {
+                    let delay = retry_delay_secs(attempts);
+                    let dead = db::mark_outbox_failed(
+                        &mut tx,
+                        row.id,
+                        sink.name(),
+                        &e,
+                        delay,
+                        config.outbox_max_attempts,
+                    )
+                    .await?;
+                    state.metrics.inc("trails_outbox_failed_total", &[("sink", sink.name())]);
+                    if dead {
+                        warn!(outbox_id = row.id, sink = sink.name(), "outbox event dead-lettered: {e}");
+                        state
+                            .metrics
+                            .inc("trails_outbox_dead_lettered_total", &[("sink", sink.name())]);
+                    } else {
+                        debug!(outbox_id = row.id, sink = sink.name(), attempts, "outbox delivery failed: {e}");
+                        retry_at_earliest(chrono::Utc::now() + chrono::Duration::seconds(delay));
+                    }
+                }
+            }
+        }
+        db::release_outbox(&mut tx, row.id, retry_at).await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+/// Exponential retry delay: 2^attempts seconds, capped at 5 minutes.
+fn retry_delay_secs(attempts: i32) -> i64 {
+    1i64.checked_shl(attempts.clamp(0, 30) as u32)
+        .unwrap_or(i64::MAX)
+        .min(300)
+}
+
+async fn update_backlog_metrics(state: &Arc<AppState>) -> Result<(), TrailsError> {
+    let (depth, oldest) = db::outbox_backlog(&state.db).await?;
+    state.metrics.set("trails_outbox_depth", &[], depth as f64);
+    state
+        .metrics
+        .set("trails_outbox_oldest_age_seconds", &[], oldest.unwrap_or(0.0));
+    Ok(())
+}
@@ -0,0 +1,212 @@
+//! Right-to-erasure purges (`DELETE /api/v1/apps/{id}/data`).
+//!
+//! A purge removes everything stored for an app — messages and their
+//! offloaded blobs, snapshots, dead letters, crashes, control queue and
+//! grants — then the apps row itself, leaving a tombstone in
+//! `purged_apps` (app_id, sha256 of the name, who asked, when). The
+//! tombstone keeps the app_id from being registered again, and the purge
+//! is recorded in `audit_log`.
+//!
+//! Live apps (connected, running, reconnecting) are rejected with 409
+//! rather than force-terminated: the caller cancels the run and purges
+//! once it is terminal, so a purge never races a client still writing.
+//!
+//! Tombstones for the whole subtree are written first, in one
+//! transaction. Each batch delete then commits together with its row
+//! count on the tombstone, so an interrupted purge resumes where it
+//! stopped — on a repeat request for the same app, or at startup — and
+//! the final report is exact.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::db::{self, PurgedAppRow};
+use crate::error::TrailsError;
+use crate::request_id;
+use crate::state::AppState;
+use crate::types::Event;
+
+/// Outcome of a purge, returned by the API.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgeReport {
+    pub app_id: Uuid,
+    pub purged_by: String,
+    /// False if the purge stopped early; repeat the request to resume.
+    pub complete: bool,
+    /// Totals across all purged apps, per table (plus `blobs`).
+    pub removed: BTreeMap<String, i64>,
+    /// One tombstone per purged app, deepest first.
+    pub apps: Vec<PurgedAppRow>,
+}
+
+/// Purge `app_id` (and its descendants with `cascade`). Repeating the
+/// request resumes an unfinished purge or returns the finished report.
+pub async fn purge(
+    state: &Arc<AppState>,
+    app_id: Uuid,
+    cascade: bool,
+    purged_by: &str,
+) -> Result<PurgeReport, TrailsError> {
+    let tombstones = db::begin_purge(&state.db, app_id, cascade, purged_by).await?;
+    let root = tombstones
+        .first()
+        .map(|t| t.root_app_id)
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+
+    // Detached so a dropped HTTP request can't abandon the purge halfway
+    // with its advisory lock still held.
+    let task_state = Arc::clone(state);
+    let request_id = request_id::current();
+    let result = tokio::spawn(async move { run(&task_state, root, request_id.as_deref()).await })
+        .await
+        .expect("purge task panicked");
+    if let Err(e) = &result {
+        warn!(app_id = %root, "purge stopped early: {e}");
+    }
+
+    let report = report(root, db::list_purge(&state.db, root).await?);
+    match result {
+        // The report stands in for "already in progress" once finished.
+        Err(TrailsError::Conflict(_)) if report.complete => Ok(report),
+        Err(e) => Err(e),
+        Ok(()) => Ok(report),
+    }
+}
+
+/// Resume purges interrupted by a restart.
+pub fn spawn_purge_resumer(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let roots = match db::list_unfinished_purges(&state.db).await {
+            Ok(roots) => roots,
+            Err(e) => {
+                warn!("listing unfinished purges failed: {e}");
+                return;
+            }
+        };
+        for root in roots {
+            match run(&state, root, None).await {
+                Ok(()) => info!(app_id = %root, "resumed purge finished"),
+                Err(TrailsError::Conflict(_)) => {
+                    debug!(app_id = %root, "purge already running elsewhere");
+                }
+                Err(e) => warn!(app_id = %root, "resumed purge failed: {e}"),
+            }
+        }
+    });
+}
+
+/// Work off every unfinished tombstone of one purge, deepest first, under
+/// the purge's advisory lock. `request_id` is recorded on the audit rows.
+async fn run(state: &AppState, root: Uuid, request_id: Option<&str>) -> Result<(), TrailsError> {
+    let mut conn = state.db.acquire().await?;
+    if !db::try_lock_purge(&mut conn, root).await? {
+        return Err(TrailsError::Conflict(format!(
+            "purge of {root} is already in progress"
+        )));
+    }
+    let result = run_locked(state, root, request_id).await;
+    if let Err(e) = db::unlock_purge(&mut conn, root).await {
+        // Don't hand a connection holding the lock back to the pool.
+        warn!(app_id = %root, "purge unlock failed, dropping connection: {e}");
+        conn.detach();
+    }
+    result
+}
+
+async fn run_locked(
+    state: &AppState,
+    root: Uuid,
+    request_id: Option<&str>,
+) -> Result<(), TrailsError> {
+    for tombstone in db::list_purge(&state.db, root).await? {
+        if tombstone.purged_at.is_none() {
+            purge_app(state, tombstone.app_id, request_id).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn purge_app(
+    state: &AppState,
+    app_id: Uuid,
+    request_id: Option<&str>,
+) -> Result<(), TrailsError> {
+    let batch = state.config().purge_batch_size;
+
+    // Messages: blobs first, so a row is only gone once its blob is.
+    loop {
+        let rows = db::next_purge_messages(&state.db, app_id, batch).await?;
+        if rows.is_empty() {
+            break;
+        }
+        let keys: Vec<&str> = rows.iter().filter_map(|(_, key)| key.as_deref()).collect();
+        for key in &keys {
+            delete_blob(state, key).await?;
+        }
+        let ids: Vec<i64> = rows.iter().map(|(id, _)| *id).collect();
+        let blobs = keys.len() as u64;
+        let deleted = db::delete_purge_messages(&state.db, app_id, &ids, blobs).await?;
+        record(state, "messages", deleted);
+        record(state, "blobs", blobs);
+    }
+
+    for table in db::PURGE_TABLES {
+        loop {
+            let deleted = db::delete_purge_batch(&state.db, app_id, table, batch).await?;
+            record(state, table, deleted);
+            if deleted < batch as u64 {
+                break;
+            }
+        }
+    }
+
+    if let Some(tombstone) = db::finish_purge(&state.db, app_id, request_id).await? {
+        info!(app_id = %app_id, purged_by = %tombstone.purged_by, "app data purged");
+        state.metrics.inc("trails_purged_apps_total", &[]);
+        state.publish(Event::AppPurged {
+            app_id,
+            parent_id: tombstone.parent_id,
+        });
+    }
+    Ok(())
+}
+
+/// A blob left behind would defeat the purge, so a missing store is an
+/// error: the purge stays unfinished until it can be completed.
+async fn delete_blob(state: &AppState, key: &str) -> Result<(), TrailsError> {
+    let store = state.blobs.as_ref().ok_or_else(|| {
+        TrailsError::BlobStore(format!("blob store not configured; cannot delete {key}"))
+    })?;
+    store.delete(key).await.map_err(TrailsError::BlobStore)
+}
+
+fn record(state: &AppState, table: &str, count: u64) {
+    if count > 0 {
+        state
+            .metrics
+            .add("trails_purge_removed_total", &[("table", table)], count as f64);
+    }
+}
+
+fn report(app_id: Uuid, apps: Vec<PurgedAppRow>) -> PurgeReport {
+    let mut removed = BTreeMap::new();
+    for app in &apps {
+        if let Some(counts) = app.removed.as_object() {
+            for (table, count) in counts {
+                *removed.entry(table.clone()).or_default() += count.as_i64().unwrap_or(0);
+            }
+        }
+    }
+    PurgeReport {
+        app_id,
+        purged_by: apps.first().map(|a| a.purged_by.clone()).unwrap_or_default(),
+        complete: apps.iter().all(|a| a.purged_at.is_some()),
+        removed,
+        apps,
+    }
+}
@@ -0,0 +1,275 @@
+//! Ad-hoc message queries (`POST /api/v1/messages/query`).
+//!
+//! A deliberately small grammar: a scope (explicit app ids, or a parent
+//! and everything under it), a required time range, optional msg_type and
+//! correlation_id filters, and one flat list of predicates combined with
+//! `match: all | any`. Payload predicates compile to JSONB containment
+//! (`@>`) so the `jsonb_path_ops` GIN index on messages can serve them;
+//! every value is a bind parameter. Anything outside the grammar — nested
+//! groups, comparison operators, array indices — is rejected with a
+//! message saying what is supported instead of being guessed at.
+//!
+//! Results are ordered by message id; `nextAfter` is the keyset cursor
+//! for the next page and stays valid as new messages arrive.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::api::{present, MessageResponse};
+use crate::db::{self, MessageRow};
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::MsgType;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+/// Most app ids in an explicit scope.
+const MAX_APPS: usize = 100;
+/// Most predicates in `where`.
+const MAX_PREDICATES: usize = 16;
+/// Deepest payload path accepted.
+const MAX_PATH_DEPTH: usize = 8;
+
+/// Request body, as sent.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MessageQueryRequest {
+    #[serde(default)]
+    app_ids: Option<Vec<Uuid>>,
+    /// The parent and all of its descendants.
+    #[serde(default)]
+    parent_id: Option<Uuid>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    #[serde(default)]
+    msg_types: Vec<MsgType>,
+    #[serde(default)]
+    correlation_id: Option<String>,
+    #[serde(default, rename = "match")]
+    match_mode: Match,
+    #[serde(default, rename = "where")]
+    predicates: Vec<PredicateRequest>,
+    /// Return messages with id above this (pagination cursor).
+    #[serde(default)]
+    after: Option<i64>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Match {
+    #[default]
+    All,
+    Any,
+}
+
+/// One `where` entry: a subject (`path`, `msgType` or `correlationId`)
+/// and, for `path`, an operator (`eq` or `contains`).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct PredicateRequest {
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    msg_type: Option<MsgType>,
+    #[serde(default)]
+    correlation_id: Option<String>,
+    /// `null` is a valid operand, so absent and null are kept apart.
+    #[serde(default, deserialize_with = "present")]
+    eq: Option<Option<JsonValue>>,
+    #[serde(default)]
+    contains: Option<JsonValue>,
+}
+
+/// Which apps a query covers.
+#[derive(Debug)]
+pub enum Scope {
+    Apps(Vec<Uuid>),
+    Tree(Uuid),
+}
+
+/// A validated predicate.
+#[derive(Debug)]
+pub enum Predicate {
+    /// `payload_json @> doc`, plus `payload_json #> path = value` when
+    /// containment alone is looser than equality (objects and arrays).
+    Payload {
+        doc: JsonValue,
+        exact: Option<(Vec<String>, JsonValue)>,
+    },
+    MsgType(MsgType),
+    CorrelationId(String),
+}
+
+/// A validated query, ready for `db::query_messages`.
+#[derive(Debug)]
+pub struct MessageFilter {
+    pub scope: Scope,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub msg_types: Vec<MsgType>,
+    pub correlation_id: Option<String>,
+    /// Predicates are ORed rather than ANDed.
+    pub any: bool,
+    pub predicates: Vec<Predicate>,
+    pub after: Option<i64>,
+    pub limit: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePage {
+    messages: Vec<MessageResponse>,
+    /// Pass as `after` for the next page; null on the last page.
+    next_after: Option<i64>,
+}
+
+impl MessageQueryRequest {
+    /// Check the request against the grammar and limits.
+    pub fn validate(self, max_span_secs: i64) -> Result<MessageFilter, TrailsError> {
+        let scope = match (self.app_ids, self.parent_id) {
+            (Some(_), Some(_)) => return Err(invalid("give appIds or parentId, not both")),
+            (None, None) => return Err(invalid("a scope is required: appIds or parentId")),
+            (Some(ids), None) if ids.is_empty() => return Err(invalid("appIds is empty")),
+            (Some(ids), None) if ids.len() > MAX_APPS => {
+                return Err(invalid(format!("at most {MAX_APPS} appIds")))
+            }
+            (Some(ids), None) => Scope::Apps(ids),
+            (None, Some(parent)) => Scope::Tree(parent),
+        };
+        if self.from >= self.to {
+            return Err(invalid("from must be before to"));
+        }
+        if self.to - self.from > Duration::seconds(max_span_secs) {
+            return Err(invalid(format!(
+                "time range is limited to {max_span_secs} seconds; page through longer spans"
+            )));
+        }
+        if self.predicates.len() > MAX_PREDICATES {
+            return Err(invalid(format!("at most {MAX_PREDICATES} predicates in where")));
+        }
+        let predicates = self
+            .predicates
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| p.validate().map_err(|e| invalid(format!("where[{i}]: {e}"))))
+            .collect::<Result<_, _>>()?;
+        Ok(MessageFilter {
+            scope,
+            from: self.from,
+            to: self.to,
+            msg_types: self.msg_types,
+            correlation_id: self.correlation_id,
+            any: matches!(self.match_mode, Match::Any),
+            predicates,
+            after: self.after,
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        })
+    }
+}
+
+impl PredicateRequest {
+    fn validate(self) -> Result<Predicate, String> {
+        let operands = self.eq.is_some() as u8 + self.contains.is_some() as u8;
+        match (self.path, self.msg_type, self.correlation_id) {
+            (Some(path), None, None) => {
+                let tokens = path_tokens(&path)?;
+                match (self.eq, self.contains) {
+                    (Some(value), None) => {
+                        let value = value.unwrap_or(JsonValue::Null);
+                        let exact = (value.is_object() || value.is_array())
+                            .then(|| (tokens.clone(), value.clone()));
+                        Ok(Predicate::Payload {
+                            doc: nest(&tokens, value),
+                            exact,
+                        })
+                    }
+                    (None, Some(value)) => {
+                        // A scalar is looked for as an array member.
+                        let value = match value {
+                            JsonValue::Object(_) | JsonValue::Array(_) => value,
+                            scalar => JsonValue::Array(vec![scalar]),
+                        };
+                        Ok(Predicate::Payload {
+                            doc: nest(&tokens, value),
+                            exact: None,
+                        })
+                    }
+                    _ => Err(format!("path {path} needs exactly one of eq or contains")),
+                }
+            }
+            (None, Some(msg_type), None) if operands == 0 => Ok(Predicate::MsgType(msg_type)),
+            (None, None, Some(id)) if operands == 0 => Ok(Predicate::CorrelationId(id)),
+            (None, Some(_), None) | (None, None, Some(_)) => Err(
+                "msgType and correlationId take their value directly, e.g. {\"msgType\": \"Error\"}"
+                    .into(),
+            ),
+            _ => Err("each predicate needs exactly one of path, msgType or correlationId".into()),
+        }
+    }
+}
+
+/// Object keys of a payload path. Paths are JSON Pointers into objects;
+/// containment can't address array positions, so numeric tokens are
+/// refused rather than read as keys.
+fn path_tokens(path: &str) -> Result<Vec<String>, String> {
+    if !path.starts_with('/') || path.len() == 1 {
+        return Err(format!("path '{path}' is not a JSON Pointer to a member, e.g. /phase"));
+    }
+    let tokens: Vec<String> = path
+        .split('/')
+        .skip(1)
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect();
+    if tokens.len() > MAX_PATH_DEPTH {
+        return Err(format!("path '{path}' is deeper than {MAX_PATH_DEPTH} levels"));
+    }
+    let is_index = |t: &&String| !t.is_empty() && t.bytes().all(|b| b.is_ascii_digit());
+    if let Some(t) = tokens.iter().find(is_index) {
+        return Err(format!(
+            "path '{path}': array index '{t}' is not supported; use contains on the array"
+        ));
+    }
+    Ok(tokens)
+}
+
+/// `{"a": {"b": value}}` for tokens `[a, b]`.
+fn nest(tokens: &[String], value: JsonValue) -> JsonValue {
+    tokens.iter().rev().fold(value, |inner, key| {
+        let mut map = Map::new();
+        map.insert(key.clone(), inner);
+        JsonValue::Object(map)
+    })
+}
+
+fn invalid(msg: impl Into<String>) -> TrailsError {
+    TrailsError::InvalidRequest(msg.into())
+}
+
+impl MessagePage {
+    /// A page from up to `limit + 1` rows; the extra row only signals
+    /// that another page follows.
+    pub fn new(mut rows: Vec<MessageRow>, limit: i64) -> Self {
+        let next_after = if rows.len() as i64 > limit {
+            rows.truncate(limit as usize);
+            rows.last().map(|r| r.id)
+        } else {
+            None
+        };
+        Self {
+            messages: rows.into_iter().map(Into::into).collect(),
+            next_after,
+        }
+    }
+}
+
+/// Run a validated query and render one page.
+pub async fn run(state: &AppState, filter: &MessageFilter) -> Result<MessagePage, TrailsError> {
+    let rows =
+        db::query_messages(&state.db, filter, state.config().message_query_timeout_ms).await?;
+    state.metrics.inc("trails_message_queries_total", &[]);
+    Ok(MessagePage::new(rows, filter.limit))
+}
@@ -0,0 +1,171 @@
+//! Per-app storage quotas.
+//!
+//! Every message and snapshot write adds its JSON size to the app's
+//! `stored_bytes` in the same statement; a background pass recomputes the
+//! counters of live apps so compaction and other deletes are reflected.
+//! Limits are the app's admin override, else the first of its roles with
+//! a configured override, else its namespace's, else the global default.
+//!
+//! Past the soft limit the app gets one `QuotaWarning` and its Status
+//! storage is sampled: one Status per `sample_secs` is stored as usual,
+//! the rest only overwrite the latest snapshot. A non-terminal message
+//! that would take the app past the hard limit (what it has stored plus
+//! the message) is refused with `storage_quota_exceeded`; a final Result
+//! or Error is always stored.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::{Config, QuotaLimits};
+use crate::db::{self, AppRow};
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::{Event, MsgType};
+
+/// How often a disabled reconciler looks for a reload enabling it.
+const DISABLED_RECHECK: Duration = Duration::from_secs(60);
+
+/// Limits in force for an app.
+pub fn limits(config: &Config, app: &AppRow) -> QuotaLimits {
+    let configured = config
+        .storage_quota
+        .limits_for(app.namespace.as_deref(), app.role_refs.as_deref().unwrap_or_default());
+    QuotaLimits {
+        soft_bytes: app.quota_soft_bytes.or(configured.soft_bytes),
+        hard_bytes: app.quota_hard_bytes.or(configured.hard_bytes),
+    }
+}
+
+/// How an incoming data message is to be stored.
+pub struct Admission {
+    /// Store the Status only as the latest snapshot.
+    pub coalesce: bool,
+    limits: QuotaLimits,
+    warned: bool,
+}
+
+/// Check a data message against the app's quota before it is stored;
+/// `incoming` is the JSON size it would add to `stored_bytes`.
+pub async fn admit(
+    state: &AppState,
+    app_id: Uuid,
+    msg_type: &MsgType,
+    incoming: i64,
+) -> Result<Admission, TrailsError> {
+    let app = db::get_app(&state.db, app_id)
+        .await?
+        .ok_or(TrailsError::AppNotFound(app_id))?;
+    let limits = limits(&state.config(), &app);
+    let last_word = matches!(msg_type, MsgType::Result | MsgType::Error);
+    let after = app.stored_bytes.saturating_add(incoming);
+    if let Some(hard) = limits.hard_bytes.filter(|h| after > *h && !last_word) {
+        state
+            .metrics
+            .inc("trails_storage_quota_total", &[("action", "rejected")]);
+        return Err(TrailsError::StorageQuotaExceeded {
+            stored: app.stored_bytes,
+            limit: hard,
+        });
+    }
+    let over_soft = limits.soft_bytes.is_some_and(|soft| app.stored_bytes >= soft);
+    Ok(Admission {
+        coalesce: over_soft && *msg_type == MsgType::Status && !claim_sample(state, app_id),
+        limits,
+        warned: app.quota_warned_at.is_some(),
+    })
+}
+
+/// Whether a sampled Status is due for full storage; claims the slot if
+/// so. Sampling is per connection, so a reconnect stores the next one.
+fn claim_sample(state: &AppState, app_id: Uuid) -> bool {
+    let every = Duration::from_secs(state.config().storage_quota.sample_secs);
+    let Some(mut conn) = state.connections.get_mut(&app_id) else {
+        return true;
+    };
+    let due = conn.status_sampled_at.is_none_or(|at| at.elapsed() >= every);
+    if due {
+        conn.status_sampled_at = Some(Instant::now());
+    }
+    due
+}
+
+/// Account a stored message: the first crossing of the soft limit sends
+/// `QuotaWarning`.
+pub async fn record(
+    state: &AppState,
+    app_id: Uuid,
+    parent_id: Option<Uuid>,
+    admission: &Admission,
+    stored_bytes: i64,
+) -> Result<(), TrailsError> {
+    if admission.coalesce {
+        state
+            .metrics
+            .inc("trails_storage_quota_total", &[("action", "coalesced")]);
+    }
+    let Some(soft) = admission.limits.soft_bytes else {
+        return Ok(());
+    };
+    if admission.warned || stored_bytes < soft {
+        return Ok(());
+    }
+    let event = Event::QuotaWarning {
+        app_id,
+        parent_id,
+        stored_bytes,
+        soft_limit_bytes: soft,
+        hard_limit_bytes: admission.limits.hard_bytes,
+    };
+    if db::mark_quota_warned(&state.db, &event).await? {
+        warn!(app_id = %app_id, stored_bytes, soft_limit = soft, "storage quota warning");
+        state
+            .metrics
+            .inc("trails_storage_quota_total", &[("action", "warned")]);
+        state.publish(event);
+    }
+    Ok(())
+}
+
+/// Spawn the reconciler. Runs every `reconcile_interval` seconds, the
+/// first pass at startup; while the interval is 0 it only re-checks the
+/// setting.
+pub fn spawn_reconciler(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            let every = state.config().storage_quota.reconcile_interval;
+            if every == 0 {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            }
+            if let Err(e) = reconcile(&state).await {
+                warn!("storage reconciliation error: {e}");
+            }
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+/// One pass over all live apps, in batches.
+async fn reconcile(state: &AppState) -> Result<(), TrailsError> {
+    let batch = state.config().storage_quota.reconcile_batch;
+    let mut after = None;
+    let mut corrected = 0u64;
+    loop {
+        let (last, fixed) = db::reconcile_stored_bytes(&state.db, after, batch).await?;
+        corrected += fixed;
+        match last {
+            Some(id) => after = Some(id),
+            None => break,
+        }
+    }
+    if corrected > 0 {
+        info!(count = corrected, "reconciled storage counters");
+        state
+            .metrics
+            .add("trails_storage_reconciled_total", &[], corrected as f64);
+    }
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! Payload redaction at ingest.
+//!
+//! Rules come from `RedactionConfig`: the global ones, then the app's
+//! namespace's. Paths are applied first — the value at each matching JSON
+//! pointer is masked or dropped — then the patterns mask their matches
+//! inside string values. Each masked or dropped value and each pattern
+//! match counts as one redaction; the count is stored with the message.
+//!
+//! Cost is bounded: regexes are compiled when the config loads (and run
+//! in linear time), and at most `scan_max_bytes` of string content per
+//! payload is pattern-scanned. Without rules a payload isn't looked at.
+
+use regex::{Captures, Regex};
+use serde_json::Value as JsonValue;
+
+use crate::config::{RedactPath, RedactionConfig, RedactionRules};
+
+/// Replacement for masked values and pattern matches.
+pub const MASK: &str = "[REDACTED]";
+
+/// What redacting one payload did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Redacted {
+    /// Values masked or dropped, plus pattern matches masked.
+    pub applied: u32,
+    /// String bytes run through the patterns.
+    pub scanned_bytes: usize,
+    /// The scan budget ran out before every string was scanned.
+    pub truncated: bool,
+}
+
+/// Apply the rules for `namespace` to `payload` in place.
+pub fn redact(
+    config: &RedactionConfig,
+    namespace: Option<&str>,
+    payload: &mut JsonValue,
+) -> Redacted {
+    let extra = config.rules_for(namespace).filter(|r| !r.is_empty());
+    if config.default.is_empty() && extra.is_none() {
+        return Redacted::default();
+    }
+    let rules: Vec<&RedactionRules> = std::iter::once(&config.default).chain(extra).collect();
+
+    let mut redacted = Redacted::default();
+    for path in rules.iter().flat_map(|r| &r.paths) {
+        redacted.applied += apply_path(payload, path);
+    }
+    let patterns: Vec<&Regex> = rules.iter().filter_map(|r| r.pattern.as_ref()).collect();
+    if !patterns.is_empty() {
+        let mut budget = config.scan_max_bytes;
+        scan(payload, &patterns, &mut budget, &mut redacted);
+    }
+    redacted
+}
+
+/// Mask or drop every value `path` matches; returns how many.
+fn apply_path(value: &mut JsonValue, path: &RedactPath) -> u32 {
+    let Some((last, parents)) = path.segments.split_last() else {
+        return 0;
+    };
+    let mut targets = vec![value];
+    for segment in parents {
+        targets = targets
+            .into_iter()
+            .flat_map(|v| children(v, segment))
+            .collect();
+    }
+    targets.into_iter().map(|v| redact_child(v, last, path.drop)).sum()
+}
+
+/// The children of `value` that `segment` names: one key or index, or
+/// all of them for `*`.
+fn children<'a>(value: &'a mut JsonValue, segment: &str) -> Vec<&'a mut JsonValue> {
+    match value {
+        JsonValue::Object(map) => {
+            if segment == "*" {
+                map.values_mut().collect()
+            } else {
+                map.get_mut(segment).into_iter().collect()
+            }
+        }
+        JsonValue::Array(items) => {
+            if segment == "*" {
+                items.iter_mut().collect()
+            } else {
+                index(segment).and_then(|i| items.get_mut(i)).into_iter().collect()
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn redact_child(value: &mut JsonValue, segment: &str, drop: bool) -> u32 {
+    let mask = |v: &mut JsonValue| *v = JsonValue::String(MASK.into());
+    match value {
+        JsonValue::Object(map) if segment == "*" => {
+            let n = map.len() as u32;
+            if drop {
+                map.clear();
+            } else {
+                map.values_mut().for_each(mask);
+            }
+            n
+        }
+        JsonValue::Object(map) if drop => map.remove(segment).is_some() as u32,
+        JsonValue::Object(map) => map.get_mut(segment).map(mask).is_some() as u32,
+        JsonValue::Array(items) if segment == "*" => {
+            let n = items.len() as u32;
+            if drop {
+                items.clear();
+            } else {
+                items.iter_mut().for_each(mask);
+            }
+            n
+        }
+        JsonValue::Array(items) => match index(segment).filter(|i| *i < items.len()) {
+            Some(i) if drop => {
+                items.remove(i);
+                1
+            }
+            Some(i) => {
+                mask(&mut items[i]);
+                1
+            }
+            None => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// An array index segment: digits without a leading zero.
+fn index(segment: &str) -> Option<usize> {
+    if segment.len() > 1 && segment.starts_with('0') {
+        return None;
+    }
+    segment.parse().ok()
+}
+
+/// Mask pattern matches in every string value, depth first, until the
+/// budget runs out. Object keys aren't scanned.
+fn scan(value: &mut JsonValue, patterns: &[&Regex], budget: &mut usize, out: &mut Redacted) {
+    if out.truncated {
+        return;
+    }
+    match value {
+        JsonValue::String(s) => {
+            if s.len() > *budget {
+                out.truncated = true;
+                return;
+            }
+            *budget -= s.len();
+            out.scanned_bytes += s.len();
+            for pattern in patterns {
+                let mut matches = 0;
+                let replaced = pattern.replace_all(s, |_: &Captures| {
+                    matches += 1;
+                    MASK
+                });
+                if matches > 0 {
+                    *s = replaced.into_owned();
+                    out.applied += matches;
+                }
+            }
+        }
+        JsonValue::Array(items) => {
+            for item in items {
+                scan(item, patterns, budget, out);
+            }
+        }
+        JsonValue::Object(map) => {
+            for item in map.values_mut() {
+                scan(item, patterns, budget, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn path(pointer: &str, drop: bool) -> RedactPath {
+        RedactPath {
+            segments: pointer[1..].split('/').map(String::from).collect(),
+            drop,
+        }
+    }
+
+    fn config(paths: Vec<RedactPath>, pattern: Option<&str>) -> RedactionConfig {
+        RedactionConfig {
+            default: RedactionRules {
+                paths,
+                pattern: pattern.map(|p| Regex::new(p).unwrap()),
+            },
+            namespace_rules: HashMap::new(),
+            scan_max_bytes: 1024,
+        }
+    }
+
+    #[test]
+    fn test_nested_paths_and_arrays() {
+        let rules = config(
+            vec![
+                path("/user/email", false),
+                path("/auth/token", true),
+                path("/users/*/email", false),
+                path("/items/1", true),
+                path("/missing/deeper", false),
+            ],
+            None,
+        );
+        let mut payload = json!({
+            "user": {"email": "a@example.com", "name": "a"},
+            "auth": {"token": "secret", "scheme": "bearer"},
+            "users": [{"email": "b@example.com"}, {"name": "c"}, {"email": null}],
+            "items": ["keep", "drop", "keep too"],
+        });
+        let redacted = redact(&rules, None, &mut payload);
+        assert_eq!(redacted.applied, 5);
+        assert_eq!(
+            payload,
+            json!({
+                "user": {"email": MASK, "name": "a"},
+                "auth": {"scheme": "bearer"},
+                "users": [{"email": MASK}, {"name": "c"}, {"email": MASK}],
+                "items": ["keep", "keep too"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_patterns_mask_matches_in_strings() {
+        let rules = config(
+            vec![],
+            Some(r"(?i)bearer\s+\S+|[\w.+-]+@[\w-]+\.[\w.]+"),
+        );
+        let mut payload = json!({
+            "log": ["sent to a@example.com and b@example.org", "ok"],
+            "header": "Authorization: Bearer abc.def",
+            "count": 3,
+        });
+        let redacted = redact(&rules, None, &mut payload);
+        assert_eq!(redacted.applied, 3);
+        assert!(!redacted.truncated);
+        assert_eq!(payload["log"][0], format!("sent to {MASK} and {MASK}"));
+        assert_eq!(payload["header"], format!("Authorization: {MASK}"));
+        assert_eq!(payload["count"], 3);
+    }
+
+    #[test]
+    fn test_namespace_rules_add_to_global_ones() {
+        let mut rules = config(vec![path("/a", false)], None);
+        rules.namespace_rules.insert(
+            "billing".into(),
+            RedactionRules {
+                paths: vec![path("/b", true)],
+                pattern: None,
+            },
+        );
+        let mut payload = json!({"a": 1, "b": 2});
+        assert_eq!(redact(&rules, Some("other"), &mut payload).applied, 1);
+        assert_eq!(payload, json!({"a": MASK, "b": 2}));
+        assert_eq!(redact(&rules, Some("billing"), &mut payload).applied, 2);
+        assert_eq!(payload, json!({"a": MASK}));
+    }
+
+    #[test]
+    fn test_scan_stops_at_budget() {
+        let mut rules = config(vec![], Some("x"));
+        rules.scan_max_bytes = 10;
+        let mut payload = json!(["xxxx", "xxxx", "xxxx"]);
+        let redacted = redact(&rules, None, &mut payload);
+        assert!(redacted.truncated);
+        assert_eq!(redacted.scanned_bytes, 8);
+        assert_eq!(redacted.applied, 8);
+        assert_eq!(payload[2], "xxxx");
+    }
+
+    #[test]
+    fn test_no_rules_leaves_payload_unvisited() {
+        let rules = config(vec![], None);
+        let mut payload = json!({"email": "a@example.com", "nested": [{"token": "t"}]});
+        let before = payload.clone();
+        assert_eq!(redact(&rules, Some("any"), &mut payload), Redacted::default());
+        assert_eq!(payload, before);
+    }
+}
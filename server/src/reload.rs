@@ -0,0 +1,62 @@
+//! Configuration reload on SIGHUP.
+//!
+//! `.env` and `CONFIG_FILE` are read again and checked as a whole: a
+//! single value that doesn't parse or validate rejects the reload and the
+//! running settings stay. Boot-only settings are read too, but only
+//! compared — changes to them apply at the next start. The process
+//! environment is fixed once trailsd starts, so a server configured only
+//! through it has nothing to reload; SIGHUP says so.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::config;
+use crate::state::AppState;
+
+/// Reload the configuration on SIGHUP.
+pub fn spawn_reload_signal(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut hup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("cannot install SIGHUP handler: {e}");
+                return;
+            }
+        };
+        while hup.recv().await.is_some() {
+            reload(&state);
+        }
+    });
+}
+
+fn reload(state: &AppState) {
+    if !config::reloadable() {
+        warn!("SIGHUP: no .env or CONFIG_FILE, the environment can't change until a restart");
+    }
+    let loaded = config::load();
+    let problems: Vec<String> = loaded.unparsed.into_iter().chain(loaded.invalid).collect();
+    if !problems.is_empty() {
+        for problem in &problems {
+            warn!("config reload: {problem}");
+        }
+        warn!(problems = problems.len(), "SIGHUP: configuration rejected, keeping the current one");
+        state.config_reload_failures.fetch_add(1, Ordering::Relaxed);
+        state
+            .metrics
+            .inc("trails_config_reloads_total", &[("result", "rejected")]);
+        return;
+    }
+
+    let restart = loaded.boot.changed_keys(&state.boot);
+    if !restart.is_empty() {
+        warn!(keys = %restart.join(","), "changed settings only apply after a restart");
+    }
+    state.replace_config(loaded.config);
+    state
+        .metrics
+        .inc("trails_config_reloads_total", &[("result", "applied")]);
+    info!("SIGHUP: configuration reloaded");
+}
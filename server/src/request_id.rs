@@ -0,0 +1,175 @@
+//! Request ids (`X-Request-Id`).
+//!
+//! Every HTTP request gets an id: the caller's `X-Request-Id` when it is
+//! usable, else a fresh UUID. It is attached to the request's tracing
+//! span, returned in the response headers — error responses included —
+//! and recorded on audit and dead-letter rows written for the request. A
+//! WebSocket connection keeps the id of its upgrade request as its
+//! connection id for as long as it is open.
+//!
+//! The id is held in a task-local for the duration of the request, so
+//! `TrailsError::into_response` can reach it without an extractor.
+
+use std::future::Future;
+
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest inbound id kept; anything longer is replaced.
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Middleware: assign the id, run the request in its span, echo it back.
+pub async fn middleware(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| usable(v))
+        .map(str::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let span = info_span!("request", request_id = %id);
+    let mut response = scope(id.clone(), next.run(req)).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().entry(HEADER).or_insert(value);
+    }
+    response
+}
+
+/// Inbound ids are echoed into headers, logs and rows: keep them short
+/// and printable.
+fn usable(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Run `fut` with `id` as the current request id.
+pub async fn scope<F: Future>(id: String, fut: F) -> F::Output {
+    REQUEST_ID.scope(id, fut).await
+}
+
+/// Id of the request or WebSocket connection being handled, if any.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+    use tracing::info;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::error::TrailsError;
+
+    /// Log lines written while a test runs.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/ok",
+                get(|| async {
+                    info!("handled");
+                    "ok"
+                }),
+            )
+            .route(
+                "/missing",
+                get(|| async { Err::<(), _>(TrailsError::AppNotFound(Uuid::nil())) }),
+            )
+            .layer(axum::middleware::from_fn(middleware))
+    }
+
+    async fn call(path: &str, id: Option<&str>) -> Response {
+        let mut req = Request::builder().uri(path);
+        if let Some(id) = id {
+            req = req.header(&HEADER, id);
+        }
+        app().oneshot(req.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    fn response_id(resp: &Response) -> &str {
+        resp.headers()[&HEADER].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_inbound_id_round_trips() {
+        let resp = call("/ok", Some("ticket-4711")).await;
+        assert_eq!(response_id(&resp), "ticket-4711");
+    }
+
+    #[tokio::test]
+    async fn test_missing_or_unusable_id_is_generated() {
+        let resp = call("/ok", None).await;
+        assert!(Uuid::parse_str(response_id(&resp)).is_ok());
+
+        let long = "x".repeat(MAX_LEN + 1);
+        let resp = call("/ok", Some(&long)).await;
+        assert!(Uuid::parse_str(response_id(&resp)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_error_response_carries_id() {
+        let resp = call("/missing", Some("ticket-4712")).await;
+        assert_eq!(resp.status(), 404);
+        assert_eq!(response_id(&resp), "ticket-4712");
+    }
+
+    #[tokio::test]
+    async fn test_id_appears_in_log_lines() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        call("/ok", Some("ticket-4713")).await;
+        let line = captured
+            .text()
+            .lines()
+            .find(|l| l.contains("handled"))
+            .map(str::to_owned)
+            .expect("handler log line");
+        assert!(line.contains("request_id=ticket-4713"), "{line}");
+    }
+
+    #[test]
+    fn test_current_outside_request() {
+        assert_eq!(current(), None);
+    }
+}
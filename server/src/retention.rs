@@ -0,0 +1,132 @@
+//! Retention task — periodically prunes aged diagnostic data and
+//! compacts snapshot history of long-running apps.
+//!
+//! Deletes run in bounded batches so a large backlog is worked off over
+//! several ticks instead of one long-running transaction.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::db;
+use crate::error::TrailsError;
+use crate::state::AppState;
+
+/// Spawn the retention task. Runs every `retention_interval` seconds,
+/// the first pass at startup.
+pub fn spawn_retention_task(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = run_retention(&state).await {
+                warn!("retention task error: {e}");
+            }
+            if let Err(e) = compact_snapshots(&state).await {
+                warn!("snapshot compaction error: {e}");
+            }
+            let every = state.config().retention_interval;
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+async fn run_retention(state: &Arc<AppState>) -> Result<(), TrailsError> {
+    let retention = state.config().dead_letter_retention;
+    let mut total = 0u64;
+    loop {
+        let deleted = db::prune_dead_letters(&state.db, retention).await?;
+        total += deleted;
+        if deleted == 0 {
+            break;
+        }
+    }
+    if total > 0 {
+        info!(count = total, "pruned expired dead letters");
+        state.metrics.add(
+            "trails_retention_deleted_total",
+            &[("table", "dead_letters")],
+            total as f64,
+        );
+    }
+
+    let retention = state.config().app_metrics.retention;
+    let mut total = 0u64;
+    loop {
+        let deleted = db::prune_app_metrics(&state.db, retention).await?;
+        total += deleted;
+        if deleted == 0 {
+            break;
+        }
+    }
+    if total > 0 {
+        info!(count = total, "pruned expired app metrics");
+        state.metrics.add(
+            "trails_retention_deleted_total",
+            &[("table", "app_metrics")],
+            total as f64,
+        );
+    }
+    Ok(())
+}
+
+/// Thin snapshot history of non-terminal apps per the namespace's tiers.
+/// In dry-run mode only counts what would be deleted.
+async fn compact_snapshots(state: &Arc<AppState>) -> Result<(), TrailsError> {
+    let config = state.config();
+    let cfg = &config.compaction;
+    let apps = db::list_compactable_apps(&state.db, cfg.min_app_age).await?;
+    let mut per_namespace: HashMap<String, i64> = HashMap::new();
+
+    for (app_id, namespace) in apps {
+        let tiers = cfg.tiers_for(namespace.as_deref());
+        if tiers.is_empty() {
+            continue;
+        }
+        let ages: Vec<i64> = tiers.iter().map(|t| t.min_age).collect();
+        let buckets: Vec<i64> = tiers.iter().map(|t| t.bucket).collect();
+
+        let removed = if cfg.dry_run {
+            db::count_compactable_snapshots(&state.db, app_id, &ages, &buckets).await?
+        } else {
+            let mut total = 0u64;
+            loop {
+                let deleted = db::compact_snapshots(
+                    &state.db,
+                    app_id,
+                    &ages,
+                    &buckets,
+                    cfg.batch_size,
+                )
+                .await?;
+                total += deleted;
+                if deleted < cfg.batch_size as u64 {
+                    break;
+                }
+            }
+            total as i64
+        };
+        if removed > 0 && cfg.dry_run {
+            info!(app_id = %app_id, count = removed, "dry run: snapshots eligible for compaction");
+        } else if removed > 0 {
+            info!(app_id = %app_id, count = removed, "compacted snapshots");
+        }
+        *per_namespace
+            .entry(namespace.unwrap_or_default())
+            .or_default() += removed;
+    }
+
+    for (namespace, removed) in per_namespace {
+        let labels = [("namespace", namespace.as_str())];
+        if cfg.dry_run {
+            state
+                .metrics
+                .set("trails_snapshot_compaction_candidates", &labels, removed as f64);
+        } else if removed > 0 {
+            state
+                .metrics
+                .add("trails_snapshots_compacted_total", &labels, removed as f64);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! Parent progress rollup computed from children.
+//!
+//! `GET /api/v1/apps/{id}/rollup` aggregates a parent's direct children:
+//! counts by status, mean progress of the latest snapshot across
+//! non-terminal children (weighted by a numeric `weight` tag if present),
+//! earliest start, latest activity, and the failed children with their
+//! error messages.
+//!
+//! Results are cached for `rollup_cache_ms`; any bus event for a child
+//! drops its parent's entry. With `rollup_snapshots` on, a child reaching a
+//! terminal state also stores the rollup as a snapshot of the parent.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::db::{self, RollupChildRow};
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::{AppStatus, Event};
+
+/// Aggregate view of a parent's children.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rollup {
+    pub app_id: Uuid,
+    pub children: usize,
+    pub by_status: BTreeMap<String, usize>,
+    /// Mean progress (0.0–1.0) of non-terminal children reporting one.
+    pub progress: Option<f64>,
+    pub earliest_start: Option<DateTime<Utc>>,
+    pub latest_activity: Option<DateTime<Utc>>,
+    pub failed: Vec<FailedChild>,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedChild {
+    pub app_id: Uuid,
+    pub app_name: String,
+    pub status: String,
+    /// `message` of the child's last Error, if it sent one.
+    pub error: Option<String>,
+    /// Type of the child's last crash record, if any.
+    pub crash_type: Option<String>,
+}
+
+/// Statuses listed under `failed`.
+const FAILED_STATUSES: &[&str] = &["error", "crashed", "start_failed", "timed_out", "lost_contact"];
+
+impl Rollup {
+    fn compute(app_id: Uuid, rows: Vec<RollupChildRow>) -> Self {
+        let mut by_status = BTreeMap::new();
+        let (mut weighted, mut total_weight) = (0.0, 0.0);
+        let mut failed = Vec::new();
+
+        for row in &rows {
+            *by_status.entry(row.status.clone()).or_insert(0) += 1;
+
+            let terminal = row
+                .status
+                .parse::<AppStatus>()
+                .map(|s| s.is_terminal())
+                .unwrap_or(false);
+            let progress = row.progress.as_ref().and_then(|p| p.as_f64());
+            if let (false, Some(p)) = (terminal, progress) {
+                let w = row
+                    .weight
+                    .as_ref()
+                    .and_then(|w| w.as_f64())
+                    .filter(|w| *w >= 0.0)
+                    .unwrap_or(1.0);
+                weighted += p.clamp(0.0, 1.0) * w;
+                total_weight += w;
+            }
+
+            if FAILED_STATUSES.contains(&row.status.as_str()) {
+                failed.push(FailedChild {
+                    app_id: row.app_id,
+                    app_name: row.app_name.clone(),
+                    status: row.status.clone(),
+                    error: row.error_message.clone(),
+                    crash_type: row.crash_type.clone(),
+                });
+            }
+        }
+
+        Self {
+            app_id,
+            children: rows.len(),
+            by_status,
+            progress: (total_weight > 0.0).then(|| weighted / total_weight),
+            earliest_start: rows.iter().filter_map(|r| r.start_time).min(),
+            latest_activity: rows.iter().filter_map(|r| r.last_activity).max(),
+            failed,
+            computed_at: Utc::now(),
+        }
+    }
+}
+
+/// Short-lived rollup cache held in `AppState`.
+#[derive(Default)]
+pub struct RollupCache {
+    entries: DashMap<Uuid, (Instant, Arc<Rollup>)>,
+}
+
+impl RollupCache {
+    pub fn invalidate(&self, parent_id: Uuid) {
+        self.entries.remove(&parent_id);
+    }
+}
+
+/// Rollup for `app_id`, from cache if fresh.
+pub async fn get(state: &AppState, app_id: Uuid) -> Result<Arc<Rollup>, TrailsError> {
+    let ttl = Duration::from_millis(state.config().rollup_cache_ms);
+    if let Some(entry) = state.rollups.entries.get(&app_id) {
+        if entry.0.elapsed() < ttl {
+            return Ok(Arc::clone(&entry.1));
+        }
+    }
+    let rows = db::get_rollup_children(&state.db, app_id).await?;
+    let rollup = Arc::new(Rollup::compute(app_id, rows));
+    state
+        .rollups
+        .entries
+        .insert(app_id, (Instant::now(), Arc::clone(&rollup)));
+    Ok(rollup)
+}
+
+/// Subscribe to the event bus: invalidate the parent's cached rollup on
+/// any child event, and synthesize parent snapshots if configured.
+pub fn spawn_rollup_listener(state: Arc<AppState>) {
+    let mut rx = state.event_tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    debug!(skipped = n, "rollup listener lagged, clearing cache");
+                    state.rollups.entries.clear();
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let (parent_id, terminal) = match &event {
+                Event::AppTerminal { parent_id, .. } => (*parent_id, true),
+                Event::CrashDetected { parent_id, .. } => (*parent_id, true),
+                Event::AppConnected { parent_id, .. }
+                | Event::MessageStored { parent_id, .. }
+                | Event::CrashLoopDetected { parent_id, .. }
+                | Event::AppPurged { parent_id, .. }
+                | Event::QuotaWarning { parent_id, .. }
+                | Event::StallDetected { parent_id, .. }
+                | Event::DependenciesSatisfied { parent_id, .. } => (*parent_id, false),
+                Event::AppAdopted {
+                    parent_id,
+                    previous_parent_id,
+                    ..
+                } => {
+                    // The old parent lost a child as well.
+                    if let Some(previous) = previous_parent_id {
+                        state.rollups.invalidate(*previous);
+                    }
+                    (*parent_id, false)
+                }
+            };
+            let Some(parent_id) = parent_id else { continue };
+            state.rollups.invalidate(parent_id);
+
+            if terminal && state.config().rollup_snapshots {
+                if let Err(e) = store_parent_snapshot(&state, parent_id).await {
+                    warn!(parent_id = %parent_id, "rollup snapshot failed: {e}");
+                }
+            }
+        }
+    });
+}
+
+/// Store the current rollup as a snapshot of the parent.
+async fn store_parent_snapshot(state: &AppState, parent_id: Uuid) -> Result<(), TrailsError> {
+    let Some(parent) = db::get_app(&state.db, parent_id).await? else {
+        return Ok(());
+    };
+    let rollup = get(state, parent_id).await?;
+    let mut snapshot = serde_json::to_value(&*rollup).unwrap_or_default();
+    if let Some(obj) = snapshot.as_object_mut() {
+        obj.insert("phase".into(), "rollup".into());
+        obj.insert("synthesized".into(), true.into());
+    }
+    // seq 0: synthesized rows are not part of the parent's own sequence.
+    db::store_snapshot(&state.db, parent_id, parent.namespace.as_deref(), 0, &snapshot).await?;
+    Ok(())
+}
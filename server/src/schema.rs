@@ -0,0 +1,199 @@
+//! Payload schema registry — compiled schema cache and ingest validation.
+//!
+//! Schemas are registered per app_name (`PUT /api/v1/schemas/{app_name}`)
+//! and apply to every app with that name. The latest version is used at
+//! ingest. In `warn` mode violations are counted and logged but the
+//! message is stored; in `enforce` mode the message is rejected with
+//! `schema_violation`.
+//!
+//! Compiled validators are cached keyed by (app_name, version); registry
+//! writes invalidate the app_name's entries. Pass/warn/fail counters are
+//! accumulated in memory and flushed to `schema_validation_stats`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use jsonschema::Validator;
+use serde_json::Value as JsonValue;
+use tracing::warn;
+
+use crate::db::{self, SchemaRow};
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::MsgType;
+
+/// Max validation errors reported back in a `schema_violation`.
+const MAX_REPORTED_ERRORS: usize = 5;
+
+/// A schema version compiled for validation.
+pub struct CompiledSchema {
+    pub version: i32,
+    pub enforce: bool,
+    status: Option<Validator>,
+    result: Option<Validator>,
+}
+
+impl CompiledSchema {
+    fn compile(row: &SchemaRow) -> Result<Self, TrailsError> {
+        Ok(Self {
+            version: row.version,
+            enforce: row.mode == "enforce",
+            status: row.status_schema.as_ref().map(compile).transpose()?,
+            result: row.result_schema.as_ref().map(compile).transpose()?,
+        })
+    }
+
+    fn validator(&self, msg_type: &MsgType) -> Option<&Validator> {
+        match msg_type {
+            MsgType::Status => self.status.as_ref(),
+            MsgType::Result => self.result.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Compile a JSON Schema document, mapping errors to `InvalidRequest`.
+pub fn compile(schema: &JsonValue) -> Result<Validator, TrailsError> {
+    jsonschema::validator_for(schema)
+        .map_err(|e| TrailsError::InvalidRequest(format!("invalid JSON Schema: {e}")))
+}
+
+/// Schema cache held in `AppState`.
+#[derive(Default)]
+pub struct SchemaCache {
+    /// Latest registered version per app_name (None = no schema).
+    latest: DashMap<String, Option<i32>>,
+    /// Compiled validators keyed by (app_name, version).
+    compiled: DashMap<(String, i32), Arc<CompiledSchema>>,
+    /// Pending [pass, warn, fail] counters keyed by (app_name, version, msg_type).
+    stats: DashMap<(String, i32, String), [i64; 3]>,
+}
+
+impl SchemaCache {
+    /// Drop every cached entry for `app_name`. Called on registry writes.
+    pub fn invalidate(&self, app_name: &str) {
+        self.latest.remove(app_name);
+        self.compiled.retain(|(name, _), _| name != app_name);
+    }
+
+    fn record(&self, app_name: &str, version: i32, msg_type: &MsgType, outcome: usize) {
+        let key = (app_name.to_string(), version, msg_type.as_str().to_string());
+        self.stats.entry(key).or_insert([0; 3])[outcome] += 1;
+    }
+}
+
+/// Resolve the schema applied to `app_name` at ingest, compiling and
+/// caching it on first use. The first use also marks the version
+/// referenced, freezing its content.
+async fn resolve(
+    state: &AppState,
+    app_name: &str,
+) -> Result<Option<Arc<CompiledSchema>>, TrailsError> {
+    let cache = &state.schemas;
+    let cached_version = cache.latest.get(app_name).map(|v| *v);
+    if let Some(None) = cached_version {
+        return Ok(None);
+    }
+    if let Some(Some(version)) = cached_version {
+        if let Some(compiled) = cache.compiled.get(&(app_name.to_string(), version)) {
+            return Ok(Some(Arc::clone(&compiled)));
+        }
+    }
+
+    let Some(row) = db::get_latest_schema(&state.db, app_name).await? else {
+        cache.latest.insert(app_name.to_string(), None);
+        return Ok(None);
+    };
+    let compiled = match CompiledSchema::compile(&row) {
+        Ok(c) => Arc::new(c),
+        Err(e) => {
+            warn!(app_name, version = row.version, "stored schema does not compile: {e}");
+            cache.latest.insert(app_name.to_string(), None);
+            return Ok(None);
+        }
+    };
+    if !row.referenced {
+        db::mark_schema_referenced(&state.db, app_name, row.version).await?;
+    }
+    cache
+        .compiled
+        .insert((app_name.to_string(), row.version), Arc::clone(&compiled));
+    cache.latest.insert(app_name.to_string(), Some(row.version));
+    Ok(Some(compiled))
+}
+
+/// Validate an inbound payload against the registered schema for its
+/// app_name. Returns `SchemaViolation` only when the schema enforces.
+pub async fn check_payload(
+    state: &AppState,
+    app_name: &str,
+    msg_type: &MsgType,
+    payload: &JsonValue,
+) -> Result<(), TrailsError> {
+    if !matches!(msg_type, MsgType::Status | MsgType::Result) {
+        return Ok(());
+    }
+    let Some(schema) = resolve(state, app_name).await? else {
+        return Ok(());
+    };
+    let Some(validator) = schema.validator(msg_type) else {
+        return Ok(());
+    };
+
+    let errors: Vec<String> = validator
+        .iter_errors(payload)
+        .take(MAX_REPORTED_ERRORS)
+        .map(|e| format!("{} at '{}'", e, e.instance_path))
+        .collect();
+
+    let (outcome, label) = match (errors.is_empty(), schema.enforce) {
+        (true, _) => (0, "pass"),
+        (false, false) => (1, "warn"),
+        (false, true) => (2, "fail"),
+    };
+    state.schemas.record(app_name, schema.version, msg_type, outcome);
+    state.metrics.inc(
+        "trails_schema_validations_total",
+        &[("app_name", app_name), ("result", label)],
+    );
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let detail = format!(
+        "{app_name} v{} {}: {}",
+        schema.version,
+        msg_type.as_str(),
+        errors.join("; ")
+    );
+    if schema.enforce {
+        Err(TrailsError::SchemaViolation(detail))
+    } else {
+        warn!("schema warning: {detail}");
+        Ok(())
+    }
+}
+
+/// Spawn the task that flushes validation counters to Postgres every 5s.
+pub fn spawn_stats_flusher(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            let keys: Vec<_> = state.schemas.stats.iter().map(|e| e.key().clone()).collect();
+            for key in keys {
+                let Some((key, [pass, warned, fail])) = state.schemas.stats.remove(&key) else {
+                    continue;
+                };
+                let (app_name, version, ref msg_type) = key;
+                if let Err(e) =
+                    db::add_schema_stats(&state.db, &app_name, version, msg_type, pass, warned, fail)
+                        .await
+                {
+                    warn!(app_name, version, "schema stats flush error: {e}");
+                }
+            }
+        }
+    });
+}
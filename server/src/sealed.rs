@@ -0,0 +1,63 @@
+//! Sealed payloads — end-to-end encryption for `sec_level: "sealed"`.
+//!
+//! The client encrypts each data payload to a recipient X25519 key that
+//! trailsd never sees and sends this envelope as the payload:
+//!
+//! ```json
+//! { "v": 1, "alg": "x25519-hkdf-sha256-chacha20poly1305",
+//!   "kid": "team-a-2026", "epk": "<b64>", "nonce": "<b64>", "ct": "<b64>" }
+//! ```
+//!
+//! `epk` is the 32-byte ephemeral public key, `nonce` 12 bytes, `ct` the
+//! ciphertext with its 16-byte tag; `kid` is optional and only a hint for
+//! the recipient. Key derivation and AAD are in the client crate's
+//! `sealed` module and `conformance/vectors/sealed_envelope.json`.
+//!
+//! The server checks the shape — so a misconfigured client can't leak
+//! plaintext into a sealed app — and otherwise stores the envelope
+//! opaquely: no schema validation, and messages are flagged `sealed`.
+
+use base64::Engine;
+use serde_json::Value as JsonValue;
+
+use crate::error::TrailsError;
+
+pub const ENVELOPE_VERSION: u64 = 1;
+pub const ENVELOPE_ALG: &str = "x25519-hkdf-sha256-chacha20poly1305";
+
+/// Check that `payload` is a well-formed sealed envelope.
+pub fn check_envelope(payload: &JsonValue) -> Result<(), TrailsError> {
+    let err = |msg: &str| TrailsError::SealedEnvelope(msg.into());
+    let obj = payload.as_object().ok_or_else(|| err("payload is not an envelope object"))?;
+    if let Some(key) = obj
+        .keys()
+        .find(|k| !matches!(k.as_str(), "v" | "alg" | "kid" | "epk" | "nonce" | "ct"))
+    {
+        return Err(err(&format!("unexpected field '{key}'")));
+    }
+    if obj.get("v").and_then(JsonValue::as_u64) != Some(ENVELOPE_VERSION) {
+        return Err(err("unsupported envelope version"));
+    }
+    if obj.get("alg").and_then(JsonValue::as_str) != Some(ENVELOPE_ALG) {
+        return Err(err("unsupported alg"));
+    }
+    if obj.get("kid").is_some_and(|kid| !kid.is_string()) {
+        return Err(err("kid must be a string"));
+    }
+    let decoded_len = |field: &str| {
+        obj.get(field)
+            .and_then(JsonValue::as_str)
+            .and_then(|s| base64::engine::general_purpose::STANDARD.decode(s).ok())
+            .map(|b| b.len())
+    };
+    if decoded_len("epk") != Some(32) {
+        return Err(err("epk must be a base64 32-byte key"));
+    }
+    if decoded_len("nonce") != Some(12) {
+        return Err(err("nonce must be base64 12 bytes"));
+    }
+    if decoded_len("ct").is_none_or(|len| len < 16) {
+        return Err(err("ct must be base64 ciphertext with its tag"));
+    }
+    Ok(())
+}
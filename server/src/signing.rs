@@ -0,0 +1,212 @@
+//! Server signatures on outbound frames for signed-mode apps (spec §8),
+//! and the challenge a signed-mode client answers before it is registered.
+//!
+//! The signature covers the canonical JSON of the whole frame without its
+//! `sig` field: object keys sorted by code point, no insignificant
+//! whitespace, strings and numbers as serde_json writes them. It is added
+//! as `"sig": "ed25519:<base64>"`. Test vectors shared with the clients
+//! live in `conformance/vectors/server_signatures.json`.
+//!
+//! A registration challenge carries a random nonce; the client proves it
+//! holds its app key by signing `proof_message(app_id, nonce)`. A captured
+//! register or proof can't be replayed: every connection gets a fresh
+//! nonce, which expires after `CHALLENGE_TTL_MS` and is answerable once.
+//! Vectors: `conformance/vectors/register_proof.json`.
+//!
+//! HTTP ingest posts are signed the same way, with the app key over
+//! `ingest_message(app_id, timestamp, body)`; the timestamp bounds how
+//! long a captured post can be replayed. Vectors:
+//! `conformance/vectors/ingest_signature.json`.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use serde_json::Value as JsonValue;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::BootConfig;
+use crate::error::TrailsError;
+use crate::types::RegisterProofMsg;
+
+/// Valid `sec_level` values.
+pub const SEC_LEVELS: &[&str] = &["open", "signed", "full", "sealed"];
+
+/// Security levels whose frames are signed. Sealed apps are signed too.
+pub fn is_signed_level(sec_level: &str) -> bool {
+    matches!(sec_level, "signed" | "full" | "sealed")
+}
+
+/// Canonical JSON encoding of `value`.
+pub fn canonical_json(value: &JsonValue) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &JsonValue, out: &mut String) {
+    match value {
+        JsonValue::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&JsonValue::String(k.clone()).to_string());
+                out.push(':');
+                write_canonical(v, out);
+            }
+            out.push('}');
+        }
+        JsonValue::Array(items) => {
+            out.push('[');
+            for (i, v) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(v, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Sign `frame` in place, replacing any existing `sig`.
+pub fn sign_frame(key: &SigningKey, frame: &mut JsonValue) {
+    let Some(obj) = frame.as_object_mut() else {
+        return;
+    };
+    obj.remove("sig");
+    let signature = key.sign(canonical_json(frame).as_bytes());
+    let b64 = base64::engine::general_purpose::STANDARD.encode(signature.to_bytes());
+    if let Some(obj) = frame.as_object_mut() {
+        obj.insert("sig".into(), format!("ed25519:{b64}").into());
+    }
+}
+
+/// Load the server key: `SERVER_KEY` (base64 32-byte seed), else
+/// `SERVER_KEY_FILE`, else a fresh key. Signed-mode clients pin the public
+/// key, so a fresh key breaks them on every restart.
+pub fn load_server_key(config: &BootConfig) -> SigningKey {
+    let encoded = match (&config.server_key, &config.server_key_file) {
+        (Some(key), _) => Some(key.clone()),
+        (None, Some(path)) => Some(
+            fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("failed to read SERVER_KEY_FILE {path}: {e}")),
+        ),
+        (None, None) => None,
+    };
+    match encoded {
+        Some(encoded) => {
+            let seed: [u8; 32] = base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|b| b.try_into().ok())
+                .expect("server key must be a base64-encoded 32-byte Ed25519 seed");
+            info!("loaded persistent server key");
+            SigningKey::from_bytes(&seed)
+        }
+        None => {
+            warn!("no SERVER_KEY / SERVER_KEY_FILE; using an ephemeral server key");
+            SigningKey::generate(&mut rand::thread_rng())
+        }
+    }
+}
+
+/// Bytes a client signs to answer a registration challenge.
+pub fn proof_message(app_id: Uuid, nonce: &str) -> String {
+    format!("trails-register-proof:{app_id}:{nonce}")
+}
+
+/// A registration challenge issued on one connection.
+pub struct Challenge {
+    pub nonce: String,
+    issued: Instant,
+    ttl: Duration,
+}
+
+impl Challenge {
+    pub fn new(ttl: Duration) -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self {
+            nonce: base64::engine::general_purpose::STANDARD.encode(bytes),
+            issued: Instant::now(),
+            ttl,
+        }
+    }
+
+    /// Check `proof` against this challenge and the app's `pub_key`
+    /// ("ed25519:<base64>"). Consumes the challenge: one answer per nonce.
+    pub fn verify(
+        self,
+        proof: &RegisterProofMsg,
+        app_id: Uuid,
+        pub_key: &str,
+    ) -> Result<(), TrailsError> {
+        if self.issued.elapsed() > self.ttl {
+            return Err(TrailsError::ChallengeExpired);
+        }
+        if proof.app_id != app_id || proof.nonce != self.nonce {
+            return Err(TrailsError::InvalidProof(
+                "proof does not answer this connection's challenge".into(),
+            ));
+        }
+        let key = parse_key(pub_key).ok_or_else(|| {
+            TrailsError::InvalidProof(format!("unusable public key '{pub_key}'"))
+        })?;
+        let sig = parse_sig(&proof.sig)
+            .ok_or_else(|| TrailsError::InvalidProof("malformed signature".into()))?;
+        key.verify(proof_message(app_id, &self.nonce).as_bytes(), &sig)
+            .map_err(|_| TrailsError::InvalidProof("signature does not verify".into()))
+    }
+}
+
+/// Bytes a client signs for an HTTP ingest post: its app, the post's
+/// timestamp (unix ms) and the raw request body.
+pub fn ingest_message(app_id: Uuid, timestamp_ms: i64, body: &[u8]) -> Vec<u8> {
+    let mut message = format!("trails-ingest:{app_id}:{timestamp_ms}:").into_bytes();
+    message.extend_from_slice(body);
+    message
+}
+
+/// Check an ingest post's `sig` ("ed25519:<base64>") against the app's
+/// `pub_key`.
+pub fn verify_ingest(
+    pub_key: &str,
+    app_id: Uuid,
+    timestamp_ms: i64,
+    body: &[u8],
+    sig: &str,
+) -> Result<(), TrailsError> {
+    let key = parse_key(pub_key)
+        .ok_or_else(|| TrailsError::InvalidProof(format!("unusable public key '{pub_key}'")))?;
+    let sig =
+        parse_sig(sig).ok_or_else(|| TrailsError::InvalidProof("malformed signature".into()))?;
+    key.verify(&ingest_message(app_id, timestamp_ms, body), &sig)
+        .map_err(|_| TrailsError::InvalidProof("signature does not verify".into()))
+}
+
+/// Parse an "ed25519:<base64>" signature.
+fn parse_sig(s: &str) -> Option<Signature> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(s.strip_prefix("ed25519:")?)
+        .ok()?;
+    Signature::from_slice(&bytes).ok()
+}
+
+/// Parse an "ed25519:<base64>" public key.
+fn parse_key(s: &str) -> Option<VerifyingKey> {
+    let bytes: [u8; 32] = base64::engine::general_purpose::STANDARD
+        .decode(s.strip_prefix("ed25519:")?)
+        .ok()?
+        .try_into()
+        .ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
@@ -0,0 +1,123 @@
+//! Stall detection.
+//!
+//! Socket liveness says nothing about whether an app's work moves: a
+//! wedged worker can keep heartbeating. Progress is a Status whose content
+//! differs from the previous snapshot (`apps.progress_at`). A running app
+//! without progress for its threshold is flagged `stalled_at` and gets one
+//! `StallDetected`; its next changed Status clears the flag. The lifecycle
+//! status is never touched.
+//!
+//! The threshold is the app's `stall_after` tag — seconds, or a duration
+//! such as `"90s"`, `"30m"`, `"2h"`, `"1d"`; `"off"` opts out — else
+//! `STALL_AFTER_SECS`. Paused apps are not checked.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde_json::Value as JsonValue;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::db;
+use crate::error::TrailsError;
+use crate::state::AppState;
+use crate::types::Event;
+
+/// Spawn the stall sweeper. Runs every `stall_check_interval` seconds.
+pub fn spawn_stall_checker(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = check_stalls(&state).await {
+                warn!("stall checker error: {e}");
+            }
+            let every = state.config().stall_check_interval;
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+async fn check_stalls(state: &AppState) -> Result<(), TrailsError> {
+    let config = state.config();
+    let candidates = db::list_stall_candidates(&state.db, &state.boot.server_instance).await?;
+    let now = Utc::now();
+    for app in &candidates {
+        let Some(stall_after) = threshold(&config, app.stall_after.as_ref()) else {
+            continue;
+        };
+        let silent_secs = (now - app.progress_at).num_seconds();
+        if silent_secs < stall_after {
+            continue;
+        }
+        let event = Event::StallDetected {
+            app_id: app.app_id,
+            parent_id: app.parent_id,
+            silent_secs,
+            stall_after_secs: stall_after,
+        };
+        // Re-checked in the update: a Status may have arrived since the scan.
+        let cutoff = now - chrono::Duration::seconds(stall_after);
+        if !db::mark_stalled(&state.db, &event, cutoff).await? {
+            continue;
+        }
+        warn!(
+            app_id = %app.app_id,
+            app_name = %app.app_name,
+            silent_secs,
+            stall_after,
+            "no progress → stalled"
+        );
+        state.metrics.inc("trails_stalls_detected_total", &[]);
+        state.publish(event);
+    }
+    let stalled = db::count_stalled(&state.db).await?;
+    state.metrics.set("trails_apps_stalled", &[], stalled as f64);
+    Ok(())
+}
+
+/// Seconds without progress before an app counts as stalled; `None` when
+/// it isn't checked. A malformed tag falls back to the configured default.
+fn threshold(config: &Config, tag: Option<&JsonValue>) -> Option<i64> {
+    match tag {
+        Some(JsonValue::String(s)) if s.trim().eq_ignore_ascii_case("off") => None,
+        Some(JsonValue::String(s)) => parse_duration(s).or(config.stall_after),
+        Some(JsonValue::Number(n)) => n.as_i64().filter(|s| *s > 0).or(config.stall_after),
+        _ => config.stall_after,
+    }
+}
+
+/// `45`, `90s`, `30m`, `2h` or `1d`, in seconds; positive values only.
+fn parse_duration(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let scale = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return None,
+    };
+    let secs = number.parse::<i64>().ok()?.checked_mul(scale)?;
+    (secs > 0).then_some(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45"), Some(45));
+        assert_eq!(parse_duration("90s"), Some(90));
+        assert_eq!(parse_duration(" 30m "), Some(1800));
+        assert_eq!(parse_duration("2h"), Some(7200));
+        assert_eq!(parse_duration("1d"), Some(86_400));
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("10w"), None);
+        assert_eq!(parse_duration("1.5h"), None);
+    }
+}
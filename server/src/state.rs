@@ -1,24 +1,52 @@
 //! Shared server state — connection tracking and event bus.
 
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use ed25519_dalek::SigningKey;
 use sqlx::PgPool;
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
-use crate::config::Config;
+use crate::blob::BlobStore;
+use crate::config::{BootConfig, Config, MetricRule};
+use crate::latest::LatestCache;
+use crate::metrics::Metrics;
+use crate::rollup::RollupCache;
+use crate::schema::SchemaCache;
+use crate::signing;
 use crate::types::Event;
+use crate::ws::Sender;
 
 /// Per-connection info for a connected client.
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct ConnectedClient {
     pub app_id: Uuid,
     pub parent_id: Option<Uuid>,
+    pub app_name: String,
     pub namespace: Option<String>,
+    /// Payloads are sealed envelopes, stored opaquely.
+    pub sealed: bool,
     /// Current highest seq received from this client.
     pub last_seq: i64,
+    /// Last Status stored in full while over the soft storage quota.
+    pub status_sampled_at: Option<Instant>,
+    /// Last heartbeat received on this connection.
+    pub last_heartbeat: Option<Instant>,
+    /// Metric extraction rules from the app's `metrics` tag.
+    pub metric_rules: Arc<[MetricRule]>,
+    /// Children this app subscribed to, for `child_watch`.
+    pub watched_children: HashSet<Uuid>,
+    /// The app whose socket this subtask shares (`register_sub`); `None`
+    /// for the app that registered the socket.
+    pub subtask_of: Option<Uuid>,
+    /// Outbound half of the socket, for server-initiated messages.
+    pub sender: Sender,
 }
 
 /// Shared state accessible from all handlers.
@@ -26,30 +54,63 @@ pub struct AppState {
     pub db: PgPool,
     /// Active WebSocket connections keyed by app_id.
     pub connections: DashMap<Uuid, ConnectedClient>,
-    /// Internal event bus (spec §21). Today: parent notification.
-    /// Future: observer fan-out, Kafka/NATS publishing.
+    /// Read-only observers (`observe`), keyed by the app they observe.
+    pub observers: DashMap<Uuid, Vec<Sender>>,
+    /// Internal event bus (spec §21). Today: parent notification and
+    /// observer fan-out. Future: Kafka/NATS publishing.
     pub event_tx: broadcast::Sender<Event>,
     /// Server's Ed25519 signing key. Public key shared with clients.
     pub server_key: SigningKey,
-    pub config: Config,
+    /// Settings fixed at startup.
+    pub boot: BootConfig,
+    /// Reloadable settings; read through `config()`.
+    config: RwLock<Arc<Config>>,
+    /// Applied and rejected SIGHUP reloads.
+    pub config_reloads: AtomicU64,
+    pub config_reload_failures: AtomicU64,
+    config_reloaded_at: RwLock<Option<DateTime<Utc>>>,
+    /// Prometheus metrics, rendered at GET /metrics.
+    pub metrics: Metrics,
+    /// Compiled payload schemas keyed by (app_name, version).
+    pub schemas: SchemaCache,
+    /// Cached parent rollups, invalidated by child events.
+    pub rollups: RollupCache,
+    /// Rendered wallboard pages, keyed by query.
+    pub latest: LatestCache,
+    /// Object store for large payloads; `None` stores everything inline.
+    pub blobs: Option<BlobStore>,
+    /// Drain mode: new registrations are refused, existing connections
+    /// keep being served. Set by the admin API, SIGUSR1, or shutdown.
+    draining: AtomicBool,
 }
 
 impl AppState {
-    pub fn new(db: PgPool, config: Config) -> Arc<Self> {
+    pub fn new(db: PgPool, boot: BootConfig, config: Config) -> Arc<Self> {
         let (event_tx, _) = broadcast::channel(4096);
 
-        // Generate server Ed25519 keypair.
-        // In production, load from K8s Secret for persistence across restarts.
-        // Phase 1: fresh keypair per startup is fine.
-        let mut rng = rand::thread_rng();
-        let server_key = SigningKey::generate(&mut rng);
+        // Server Ed25519 keypair — persistent when configured (K8s Secret),
+        // so signed-mode clients can pin the public key.
+        let server_key = signing::load_server_key(&boot);
+
+        let blobs = BlobStore::from_config(&boot);
 
         Arc::new(Self {
             db,
             connections: DashMap::new(),
+            observers: DashMap::new(),
             event_tx,
             server_key,
-            config,
+            boot,
+            config: RwLock::new(Arc::new(config)),
+            config_reloads: AtomicU64::new(0),
+            config_reload_failures: AtomicU64::new(0),
+            config_reloaded_at: RwLock::new(None),
+            metrics: Metrics::default(),
+            schemas: SchemaCache::default(),
+            rollups: RollupCache::default(),
+            latest: LatestCache::default(),
+            blobs,
+            draining: AtomicBool::new(false),
         })
     }
 
@@ -61,6 +122,35 @@ impl AppState {
         format!("ed25519:{b64}")
     }
 
+    /// The current reloadable settings. Take one per operation so a
+    /// reload mid-way can't mix old and new values.
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Swap in reloaded settings.
+    pub fn replace_config(&self, config: Config) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+        *self.config_reloaded_at.write().unwrap_or_else(|e| e.into_inner()) = Some(Utc::now());
+        self.config_reloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// When settings were last reloaded; `None` if never.
+    pub fn config_reloaded_at(&self) -> Option<DateTime<Utc>> {
+        *self.config_reloaded_at.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Enter or leave drain mode. Returns the previous value.
+    pub fn set_draining(&self, draining: bool) -> bool {
+        self.metrics
+            .set("trails_draining", &[], if draining { 1.0 } else { 0.0 });
+        self.draining.swap(draining, Ordering::Relaxed)
+    }
+
     /// Publish an event to the internal bus. Failures (no receivers) are ignored.
     pub fn publish(&self, event: Event) {
         let _ = self.event_tx.send(event);
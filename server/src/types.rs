@@ -1,10 +1,10 @@
 //! Wire protocol types for TRAILS Phase 1.
 //!
-//! Covers: register, re_register, message (Status/Result/Error),
-//! disconnect, ack, registered, server_error.
+//! Covers: register, re_register, register_proof, message
+//! (Status/Result/Error), heartbeat, disconnect, subscribe_child, observe,
+//! challenge, ack, registered, server_error, child_*, observing, observed.
 //! Control path types are defined but not routed until Phase 3.
 
-use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -17,10 +17,16 @@ use uuid::Uuid;
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClientMessage {
-    Register(RegisterMsg),
+    Register(Box<RegisterMsg>),
     ReRegister(ReRegisterMsg),
+    /// A subtask of a registered app, over its connection (spec §8).
+    RegisterSub(Box<RegisterMsg>),
+    RegisterProof(RegisterProofMsg),
     Message(DataMsg),
+    Heartbeat(HeartbeatMsg),
     Disconnect(DisconnectMsg),
+    SubscribeChild(SubscribeChildMsg),
+    Observe(ObserveMsg),
 }
 
 /// First message after WebSocket connect (spec §8).
@@ -33,7 +39,22 @@ pub struct RegisterMsg {
     pub process_info: ProcessInfo,
     #[serde(default)]
     pub role_refs: Vec<String>,
+    /// Tags from TRAILS_INFO, kept as the auto-created row's metadata; a
+    /// pre-registered row keeps the ones its parent gave.
+    #[serde(default)]
+    pub tags: Option<serde_json::Value>,
+    /// Priority for an auto-created row; ignored when pre-registered.
+    #[serde(default)]
+    pub priority: Option<i16>,
+    /// `open` (default), `signed` or `full`. Signed levels get signed
+    /// server frames; a pre-registered signed level can't be lowered.
+    #[serde(default)]
+    pub sec_level: Option<String>,
+    /// A binary encoding the client can send frames in (`msgpack`).
+    #[serde(default)]
+    pub binary: Option<String>,
     /// Ed25519 signature — present but not verified in Phase 1 (secLevel: open).
+    #[allow(dead_code)]
     pub sig: Option<String>,
 }
 
@@ -67,15 +88,29 @@ pub struct ReRegisterMsg {
     pub app_id: Uuid,
     pub last_seq: i64,
     pub pub_key: String,
+    /// As in `register`.
+    #[serde(default)]
+    pub binary: Option<String>,
+    #[allow(dead_code)]
     pub sig: Option<String>,
 }
 
+/// Answer to a `challenge` in signed mode: `sig` is the app key's
+/// signature over `signing::proof_message(app_id, nonce)`.
+#[derive(Debug, Deserialize)]
+pub struct RegisterProofMsg {
+    pub app_id: Uuid,
+    pub nonce: String,
+    pub sig: String,
+}
+
 /// Data message carrying Status, Result, or Error (spec §8).
 #[derive(Debug, Deserialize)]
 pub struct DataMsg {
     pub app_id: Uuid,
     pub header: MsgHeader,
     pub payload: serde_json::Value,
+    #[allow(dead_code)]
     pub sig: Option<String>,
 }
 
@@ -85,27 +120,73 @@ pub struct MsgHeader {
     pub timestamp: i64,
     pub seq: i64,
     pub correlation_id: Option<String>,
+    /// `gzip` for a gzipped payload (see `encoding`); absent when plain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// A data message's type. Apps may send types of their own (`Metric`,
+/// `Checkpoint`, ...): they are stored and passed on like any message, but
+/// are neither progress nor terminal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum MsgType {
     Status,
     Result,
     Error,
     Control,
+    /// A log line, stored in `logs` (see `logs`).
+    Log,
+    /// Gauges and counters for the app's metric series (see
+    /// `app_metrics`).
+    Metric,
+    Other(String),
 }
 
 impl MsgType {
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             MsgType::Status => "Status",
             MsgType::Result => "Result",
             MsgType::Error => "Error",
             MsgType::Control => "Control",
+            MsgType::Log => "Log",
+            MsgType::Metric => "Metric",
+            MsgType::Other(name) => name,
+        }
+    }
+}
+
+impl From<String> for MsgType {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "Status" => MsgType::Status,
+            "Result" => MsgType::Result,
+            "Error" => MsgType::Error,
+            "Control" => MsgType::Control,
+            "Log" => MsgType::Log,
+            "Metric" => MsgType::Metric,
+            _ => MsgType::Other(name),
         }
     }
 }
 
+impl From<MsgType> for String {
+    fn from(msg_type: MsgType) -> Self {
+        match msg_type {
+            MsgType::Other(name) => name,
+            known => known.as_str().into(),
+        }
+    }
+}
+
+/// Liveness signal from an app with nothing to report (spec §8). Seq-less:
+/// not stored, not acked.
+#[derive(Debug, Deserialize)]
+pub struct HeartbeatMsg {
+    pub app_id: Uuid,
+}
+
 /// Graceful disconnect (spec §8).
 #[derive(Debug, Deserialize)]
 pub struct DisconnectMsg {
@@ -113,6 +194,34 @@ pub struct DisconnectMsg {
     pub reason: String,
 }
 
+impl DisconnectMsg {
+    /// The status the reason leaves the app in: `reconnecting` for a
+    /// process that will be back (`will_reconnect`), else terminal.
+    /// Reasons of the app's own end it `done`.
+    pub fn status(&self) -> AppStatus {
+        match self.reason.as_str() {
+            "error" | "failed" => AppStatus::Error,
+            "cancelled" | "superseded" => AppStatus::Cancelled,
+            "will_reconnect" => AppStatus::Reconnecting,
+            _ => AppStatus::Done,
+        }
+    }
+}
+
+/// A parent asking to be told, down its own connection, how one of its
+/// children gets on: the `child_*` messages. Seq-less, like a heartbeat.
+#[derive(Debug, Deserialize)]
+pub struct SubscribeChildMsg {
+    pub child_id: Uuid,
+}
+
+/// First message of a read-only observer, in place of a register: the
+/// connection is then sent the app's messages (see `observe`).
+#[derive(Debug, Deserialize)]
+pub struct ObserveMsg {
+    pub app_id: Uuid,
+}
+
 // ═══════════════════════════════════════════════════════════════
 // Server → Client messages
 // ═══════════════════════════════════════════════════════════════
@@ -121,10 +230,27 @@ pub struct DisconnectMsg {
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerMessage {
+    Challenge(ChallengeMsg),
     Registered(RegisteredMsg),
     Ack(AckMsg),
     Error(ServerErrorMsg),
-    // Control — Phase 3
+    Control(ControlMsg),
+    ChildStarted(ChildMsg),
+    ChildStatus(ChildStatusMsg),
+    ChildTerminal(ChildTerminalMsg),
+    ChildCrashed(ChildCrashedMsg),
+    /// Answer to `subscribe_child` for an app that isn't the parent's child.
+    ChildUnknown(ChildMsg),
+    Observing(ObservingMsg),
+    Observed(ObservedMsg),
+}
+
+/// Sent in signed mode between register/re_register and Registered.
+#[derive(Debug, Serialize)]
+pub struct ChallengeMsg {
+    pub app_id: Uuid,
+    pub nonce: String,
+    pub expires_in_ms: u64,
 }
 
 /// Sent after successful registration.
@@ -132,12 +258,57 @@ pub enum ServerMessage {
 pub struct RegisteredMsg {
     pub app_id: Uuid,
     pub server_pub_key: String,
+    /// The trailsd build, for client logs.
+    pub server_version: &'static str,
+    /// On re_register, the highest seq stored for the app; the client
+    /// resends only what comes after it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seq: Option<i64>,
+    /// The server's clock, ms since the epoch, for clients to estimate
+    /// their clock skew.
+    pub server_time: i64,
+    /// The binary encoding offered in the register, if taken.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<&'static str>,
 }
 
 /// Sent after each data message.
 #[derive(Debug, Serialize)]
 pub struct AckMsg {
     pub seq: i64,
+    /// As in `RegisteredMsg`.
+    pub server_time: i64,
+    /// The subtask the acked message came from; absent for the app that
+    /// registered the connection.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<Uuid>,
+}
+
+impl AckMsg {
+    pub fn new(seq: i64) -> Self {
+        Self {
+            seq,
+            server_time: server_time(),
+            app_id: None,
+        }
+    }
+}
+
+/// Now, as `server_time` reports it.
+pub fn server_time() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Server-initiated command (spec §8, §10). Sent today: `cancel` (max
+/// runtime) and `pause`/`resume`; general REST-routed control is Phase 3.
+#[derive(Debug, Serialize)]
+pub struct ControlMsg {
+    pub action: String,
+    pub correlation_id: Option<String>,
+    pub payload: serde_json::Value,
+    /// The subtask it is for, as in `AckMsg`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<Uuid>,
 }
 
 /// Sent on protocol errors.
@@ -145,6 +316,63 @@ pub struct AckMsg {
 pub struct ServerErrorMsg {
     pub code: String,
     pub message: String,
+    /// The subtask whose message failed, as in `AckMsg`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<Uuid>,
+}
+
+/// A watched child connected, or was already running when subscribed to.
+#[derive(Debug, Serialize)]
+pub struct ChildMsg {
+    pub child_id: Uuid,
+}
+
+/// A Status a watched child stored.
+#[derive(Debug, Serialize)]
+pub struct ChildStatusMsg {
+    pub child_id: Uuid,
+    pub seq: i64,
+    pub payload: Option<serde_json::Value>,
+}
+
+/// A watched child reached a terminal status; the watch ends.
+#[derive(Debug, Serialize)]
+pub struct ChildTerminalMsg {
+    pub child_id: Uuid,
+    pub status: String,
+    /// The child's Result payload, for `done`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+}
+
+/// A watched child crashed (e.g. `connection_drop`), or never started
+/// (`never_started`); the watch ends.
+#[derive(Debug, Serialize)]
+pub struct ChildCrashedMsg {
+    pub child_id: Uuid,
+    /// Its status now: `crashed`, or `start_failed`.
+    pub status: String,
+    pub crash_type: String,
+}
+
+/// Answer to `observe`: where the app stands as observing begins. What
+/// it stored up to `last_seq` is read over REST.
+#[derive(Debug, Serialize)]
+pub struct ObservingMsg {
+    pub app_id: Uuid,
+    pub status: String,
+    pub last_seq: Option<i64>,
+}
+
+/// A Status, Result or Error the observed app stored.
+#[derive(Debug, Serialize)]
+pub struct ObservedMsg {
+    pub app_id: Uuid,
+    pub seq: i64,
+    pub msg_type: String,
+    pub correlation_id: Option<String>,
+    pub payload: Option<serde_json::Value>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -152,8 +380,10 @@ pub struct ServerErrorMsg {
 // ═══════════════════════════════════════════════════════════════
 
 /// Events published to the internal broadcast channel.
-/// Phase 1: used for parent notification (future: observer fan-out).
-#[derive(Debug, Clone)]
+/// Phase 1: used for parent notification and observer fan-out.
+/// Lifecycle events are also written to the outbox for durable sinks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
 pub enum Event {
     /// A child registered / re-registered.
     AppConnected {
@@ -179,12 +409,118 @@ pub enum Event {
         parent_id: Option<Uuid>,
         crash_type: String,
     },
+    /// Repeated crashes of the same job identity (app_name + parent).
+    CrashLoopDetected {
+        app_id: Uuid,
+        parent_id: Option<Uuid>,
+        app_name: String,
+        crash_count: i32,
+        window_secs: i64,
+    },
+    /// App's data was erased and its row replaced by a tombstone.
+    AppPurged {
+        app_id: Uuid,
+        parent_id: Option<Uuid>,
+    },
+    /// App's stored data crossed its soft storage quota; further Status
+    /// messages are sampled.
+    QuotaWarning {
+        app_id: Uuid,
+        parent_id: Option<Uuid>,
+        stored_bytes: i64,
+        soft_limit_bytes: i64,
+        hard_limit_bytes: Option<i64>,
+    },
+    /// Running app sent no Status with changed content for
+    /// `stall_after_secs`. Advisory: the app stays running.
+    StallDetected {
+        app_id: Uuid,
+        parent_id: Option<Uuid>,
+        silent_secs: i64,
+        stall_after_secs: i64,
+    },
+    /// App was moved under a new parent by an admin.
+    AppAdopted {
+        app_id: Uuid,
+        parent_id: Option<Uuid>,
+        previous_parent_id: Option<Uuid>,
+    },
+    /// A blocked app's dependencies finished; its start deadline runs
+    /// from now. `failed_dependencies` is non-empty only when it was
+    /// released under the `release` failure policy.
+    DependenciesSatisfied {
+        app_id: Uuid,
+        parent_id: Option<Uuid>,
+        depends_on: Vec<Uuid>,
+        failed_dependencies: Vec<Uuid>,
+    },
+}
+
+impl Event {
+    /// Serialized `event` tag, used as the outbox `event_type`.
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Event::AppConnected { .. } => "app_connected",
+            Event::MessageStored { .. } => "message_stored",
+            Event::AppTerminal { .. } => "app_terminal",
+            Event::CrashDetected { .. } => "crash_detected",
+            Event::CrashLoopDetected { .. } => "crash_loop_detected",
+            Event::AppPurged { .. } => "app_purged",
+            Event::QuotaWarning { .. } => "quota_warning",
+            Event::StallDetected { .. } => "stall_detected",
+            Event::AppAdopted { .. } => "app_adopted",
+            Event::DependenciesSatisfied { .. } => "dependencies_satisfied",
+        }
+    }
+
+    pub fn app_id(&self) -> Uuid {
+        match self {
+            Event::AppConnected { app_id, .. }
+            | Event::MessageStored { app_id, .. }
+            | Event::AppTerminal { app_id, .. }
+            | Event::CrashDetected { app_id, .. }
+            | Event::CrashLoopDetected { app_id, .. }
+            | Event::AppPurged { app_id, .. }
+            | Event::QuotaWarning { app_id, .. }
+            | Event::StallDetected { app_id, .. }
+            | Event::AppAdopted { app_id, .. }
+            | Event::DependenciesSatisfied { app_id, .. } => *app_id,
+        }
+    }
+}
+
+/// Accepted app priorities; 0 is the default. Priority orders the
+/// background scans (deadline, runtime, compaction) and `sort=priority`
+/// listings. It doesn't change how an app's Status is stored, and any
+/// caller may set any value in range: there is no role capability map
+/// to check it against yet.
+pub const PRIORITY_RANGE: std::ops::RangeInclusive<i16> = -100..=100;
+
+/// Longest accepted app_name, in bytes.
+pub const APP_NAME_MAX: usize = 255;
+
+/// Apply the app_name rules: trimmed of surrounding whitespace when `trim`
+/// (`APP_NAME_TRIM`), then non-empty, at most `APP_NAME_MAX` bytes and
+/// free of control characters. Registration and by-name lookup share it,
+/// so a name always matches the way it was stored.
+pub fn normalize_app_name(name: &str, trim: bool) -> Result<&str, String> {
+    let name = if trim { name.trim() } else { name };
+    if name.is_empty() {
+        Err("app_name is empty".into())
+    } else if name.len() > APP_NAME_MAX {
+        Err(format!("app_name is longer than {APP_NAME_MAX} bytes"))
+    } else if name.chars().any(char::is_control) {
+        Err("app_name contains control characters".into())
+    } else {
+        Ok(name)
+    }
 }
 
 // ═══════════════════════════════════════════════════════════════
 // App status enum (matches Postgres CHECK constraint)
 // ═══════════════════════════════════════════════════════════════
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AppStatus {
     Scheduled,
@@ -197,8 +533,10 @@ pub enum AppStatus {
     StartFailed,
     Reconnecting,
     LostContact,
+    TimedOut,
 }
 
+#[allow(dead_code)]
 impl AppStatus {
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -212,13 +550,64 @@ impl AppStatus {
             Self::StartFailed => "start_failed",
             Self::Reconnecting => "reconnecting",
             Self::LostContact => "lost_contact",
+            Self::TimedOut => "timed_out",
         }
     }
 
     pub fn is_terminal(&self) -> bool {
         matches!(
             self,
-            Self::Done | Self::Error | Self::Crashed | Self::Cancelled | Self::StartFailed
+            Self::Done
+                | Self::Error
+                | Self::Crashed
+                | Self::Cancelled
+                | Self::StartFailed
+                | Self::TimedOut
         )
     }
 }
+
+impl std::str::FromStr for AppStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "scheduled" => Self::Scheduled,
+            "connected" => Self::Connected,
+            "running" => Self::Running,
+            "done" => Self::Done,
+            "error" => Self::Error,
+            "crashed" => Self::Crashed,
+            "cancelled" => Self::Cancelled,
+            "start_failed" => Self::StartFailed,
+            "reconnecting" => Self::Reconnecting,
+            "lost_contact" => Self::LostContact,
+            "timed_out" => Self::TimedOut,
+            other => return Err(format!("unknown app status '{other}'")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disconnect_reasons_set_the_status() {
+        let status = |reason: &str| {
+            let disc = DisconnectMsg {
+                app_id: Uuid::new_v4(),
+                reason: reason.into(),
+            };
+            disc.status()
+        };
+        assert_eq!(status("completed"), AppStatus::Done);
+        assert_eq!(status("error"), AppStatus::Error);
+        assert_eq!(status("failed"), AppStatus::Error);
+        assert_eq!(status("cancelled"), AppStatus::Cancelled);
+        assert_eq!(status("superseded"), AppStatus::Cancelled);
+        assert_eq!(status("will_reconnect"), AppStatus::Reconnecting);
+        assert_eq!(status("dropped"), AppStatus::Done);
+        assert_eq!(status("rotated logs"), AppStatus::Done);
+    }
+}
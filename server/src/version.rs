@@ -0,0 +1,53 @@
+//! Build identity, embedded by `build.rs`, for `GET /api/v1/version` and
+//! for attributing stored rows to the build that wrote them.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// `<crate version>+<git sha>`: sent in the Registered ack and stamped on
+/// crash records and audit entries.
+pub const SERVER_VERSION: &str =
+    concat!(env!("CARGO_PKG_VERSION"), "+", env!("TRAILS_GIT_SHA"));
+
+/// Protocol versions (`v` in TRAILS_INFO, spec §26) this build speaks.
+pub const PROTOCOL_MIN: u32 = 1;
+pub const PROTOCOL_MAX: u32 = 1;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    version: &'static str,
+    git_sha: &'static str,
+    build_timestamp: Option<DateTime<Utc>>,
+    server_version: &'static str,
+    protocol: ProtocolRange,
+    /// Cargo features compiled in.
+    features: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProtocolRange {
+    min: u32,
+    max: u32,
+}
+
+pub fn info() -> VersionInfo {
+    let features = [("blob-store", cfg!(feature = "blob-store"))];
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("TRAILS_GIT_SHA"),
+        build_timestamp: env!("TRAILS_BUILD_EPOCH")
+            .parse()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        server_version: SERVER_VERSION,
+        protocol: ProtocolRange {
+            min: PROTOCOL_MIN,
+            max: PROTOCOL_MAX,
+        },
+        features: features
+            .into_iter()
+            .filter_map(|(name, on)| on.then_some(name))
+            .collect(),
+    }
+}
@@ -4,46 +4,104 @@
 //! 1. Accept WS upgrade
 //! 2. Wait for register or re_register (first message)
 //! 3. Validate, store in Postgres, send Registered ack
+//!    (signed mode: challenge → register_proof first, see `signing`),
+//!    then any controls queued while the app wasn't connected
 //! 4. Enter message loop: receive data messages, send acks
 //! 5. On disconnect/drop: detect crash or graceful exit
+//!
+//! Once registered, the app may register subtasks over the same socket
+//! (`register_sub`); their frames name them by `app_id`, and they crash
+//! with the socket if still open when it drops.
+//!
+//! A first message of `observe` instead makes the connection a read-only
+//! observer of another app (see `observe`).
 
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
 
 use axum::extract::ws::{Message, WebSocket};
+use base64::Engine;
 use axum::extract::{State, WebSocketUpgrade};
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
+use ed25519_dalek::SigningKey;
 use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
 use tokio::sync::Mutex;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
+use crate::app_metrics;
+use crate::blob;
+use crate::child_watch;
+use crate::config::MetricRule;
+use crate::crash_loop;
 use crate::db;
+use crate::encoding;
+use crate::lifecycle;
+use crate::logs;
+use crate::observe::{self, Credentials};
+use crate::quota;
+use crate::redact;
+use crate::request_id;
 use crate::error::TrailsError;
+use crate::schema;
+use crate::sealed;
+use crate::signing;
 use crate::state::{AppState, ConnectedClient};
 use crate::types::*;
+use crate::version;
 
 /// Axum handler for GET /ws — upgrades to WebSocket.
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+    // The upgrade request's id names the connection from here on.
+    let conn_id = request_id::current().unwrap_or_else(|| Uuid::new_v4().to_string());
+    let credentials = Credentials::from_headers(&headers);
+    ws.on_upgrade(move |socket| {
+        let span = info_span!("ws", conn_id = %conn_id);
+        request_id::scope(conn_id, handle_socket(socket, state, credentials)).instrument(span)
+    })
 }
 
 /// Per-connection state machine.
-async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
+async fn handle_socket(socket: WebSocket, state: Arc<AppState>, credentials: Credentials) {
     let (sender, mut receiver) = socket.split();
-    let sender = Arc::new(Mutex::new(sender));
+    let sender = Arc::new(ClientSink::new(sender));
 
     // ── Phase 1: wait for registration ──────────────────────
-    let reg_result = wait_for_registration(&mut receiver, &sender, &state).await;
+    let reg_result = wait_for_registration(&mut receiver, &sender, &state, &credentials).await;
 
-    let (app_id, parent_id, namespace) = match reg_result {
-        Ok(info) => info,
+    let (app_id, parent_id) = match reg_result {
+        Ok(Joined::App(session)) => (session.app_id, session.parent_id),
+        Ok(Joined::Observer(app_id)) => {
+            observe::serve(&mut receiver, &sender, &state, app_id).await;
+            return;
+        }
         Err(e) => {
             warn!("registration failed: {e}");
-            let _ = send_error(&sender, "registration_failed", &e.to_string()).await;
+            // `draining` is passed through so clients know to retry
+            // elsewhere; `crash_loop`, `purged` and `already_*` so they
+            // stop retrying; challenge failures so signed clients can
+            // tell them apart; `app_not_found` and `unauthorized` for
+            // observers.
+            let code = match e {
+                TrailsError::Draining
+                | TrailsError::CrashLoop { .. }
+                | TrailsError::Purged(_)
+                | TrailsError::AlreadyConnected { .. }
+                | TrailsError::AlreadyTerminal { .. }
+                | TrailsError::ChallengeExpired
+                | TrailsError::InvalidProof(_)
+                | TrailsError::AppNotFound(_)
+                | TrailsError::Unauthorized(_) => e.code(),
+                _ => "registration_failed",
+            };
+            let _ = send_error(&sender, None, code, &e.to_string()).await;
             return;
         }
     };
@@ -51,52 +109,101 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     info!(app_id = %app_id, "client registered, entering message loop");
 
     // ── Phase 2: message loop ───────────────────────────────
+    // The apps the socket speaks for, with their parents: the one that
+    // registered it, and the subtasks registered over it since.
+    let mut identities = HashMap::from([(app_id, parent_id)]);
     let mut graceful = false;
     while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                match handle_client_message(&text, app_id, &state, &sender).await {
-                    Ok(terminal) => {
-                        if terminal {
-                            graceful = true;
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        warn!(app_id = %app_id, "message error: {e}");
-                        let _ = send_error(&sender, "message_error", &e.to_string()).await;
-                    }
+        let limit = state.config().max_message_bytes;
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            // MessagePack, from a client whose register offered it.
+            Ok(Message::Binary(bytes)) => match encoding::msgpack_frame(&bytes, limit) {
+                Ok(json) => json.into(),
+                Err(e) => {
+                    warn!(app_id = %app_id, "message error: {e}");
+                    let raw = base64::engine::general_purpose::STANDARD.encode(&bytes);
+                    dead_letter(&state, Some(app_id), &e, &raw).await;
+                    let _ = send_error(&sender, None, e.code(), &e.to_string()).await;
+                    continue;
                 }
-            }
+            },
             Ok(Message::Close(_)) => {
                 graceful = false; // Treat WS close frame without disconnect msg as crash
                 break;
             }
-            Ok(Message::Ping(_)) => { /* axum auto-pongs */ }
-            Ok(_) => { /* binary frames ignored */ }
+            Ok(_) => continue, // axum auto-pongs pings
             Err(e) => {
                 warn!(app_id = %app_id, "ws recv error: {e}");
                 break;
             }
+        };
+        if text.len() > limit {
+            let e = TrailsError::PayloadTooLarge {
+                size: text.len(),
+                limit,
+            };
+            warn!(app_id = %app_id, "message error: {e}");
+            dead_letter(&state, Some(app_id), &e, &text).await;
+            let _ = send_error(&sender, None, e.code(), &e.to_string()).await;
+            continue;
+        }
+        match handle_client_message(&text, app_id, &mut identities, &state, &sender).await {
+            Ok(Some(ended)) if ended == app_id => {
+                graceful = true;
+                break;
+            }
+            Ok(Some(subtask)) => {
+                // The socket carries on for the others.
+                identities.remove(&subtask);
+                state.connections.remove(&subtask);
+                app_metrics::forget(&state, subtask);
+                info!(app_id = %subtask, subtask_of = %app_id, "subtask ended");
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let subtask = subtask_of_frame(&text, app_id);
+                let from = subtask.filter(|id| identities.contains_key(id));
+                let from = from.unwrap_or(app_id);
+                warn!(app_id = %from, "message error: {e}");
+                dead_letter(&state, Some(from), &e, &text).await;
+                let _ = send_error(&sender, subtask, e.code(), &e.to_string()).await;
+            }
         }
     }
 
     // ── Phase 3: cleanup ────────────────────────────────────
-    state.connections.remove(&app_id);
-
-    if !graceful {
-        info!(app_id = %app_id, "connection dropped → crash");
-        if let Err(e) = db::set_crashed(&state.db, app_id).await {
-            error!(app_id = %app_id, "set_crashed error: {e}");
+    // Subtasks still open go down with the socket, even when the app that
+    // registered it ended gracefully.
+    for (id, parent_id) in identities {
+        state.connections.remove(&id);
+        app_metrics::forget(&state, id);
+        if id != app_id || !graceful {
+            crash_on_drop(&state, id, parent_id).await;
         }
-        if let Err(e) = db::record_crash(&state.db, app_id, "connection_drop", None, None).await {
-            error!(app_id = %app_id, "record_crash error: {e}");
+    }
+}
+
+/// Crash an app whose socket dropped. Only a live app crashes; one
+/// already forced terminal (e.g. timed_out) just has its socket closed,
+/// and one that moved to HTTP ingestion is watched by the stale sweeper
+/// instead.
+async fn crash_on_drop(state: &AppState, app_id: Uuid, parent_id: Option<Uuid>) {
+    match db::set_crashed(&state.db, app_id).await {
+        Ok(true) => {
+            info!(app_id = %app_id, "connection dropped → crash");
+            if let Err(e) = db::record_crash(&state.db, app_id, "connection_drop", None, None).await
+            {
+                error!(app_id = %app_id, "record_crash error: {e}");
+            }
+            state.publish(Event::CrashDetected {
+                app_id,
+                parent_id,
+                crash_type: "connection_drop".into(),
+            });
         }
-        state.publish(Event::CrashDetected {
-            app_id,
-            parent_id,
-            crash_type: "connection_drop".into(),
-        });
+        Ok(false) => info!(app_id = %app_id, "connection closed, app not live over ws"),
+        Err(e) => error!(app_id = %app_id, "set_crashed error: {e}"),
     }
 }
 
@@ -104,14 +211,51 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
 // Registration
 // ═══════════════════════════════════════════════════════════════
 
-type Sender = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+/// Outbound half of a client socket. Once the app registers in a signed
+/// mode, every frame sent through it carries a server signature.
+pub struct ClientSink {
+    sink: Mutex<SplitSink<WebSocket, Message>>,
+    signer: OnceLock<SigningKey>,
+}
+
+impl ClientSink {
+    fn new(sink: SplitSink<WebSocket, Message>) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+            signer: OnceLock::new(),
+        }
+    }
+
+    fn sign_with(&self, key: &SigningKey) {
+        let _ = self.signer.set(key.clone());
+    }
+}
+
+impl std::fmt::Debug for ClientSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientSink")
+            .field("signed", &self.signer.get().is_some())
+            .finish()
+    }
+}
+
+pub type Sender = Arc<ClientSink>;
 
-/// Wait for the first message — must be `register` or `re_register`.
+/// What the first message made of a connection.
+enum Joined {
+    App(Session),
+    /// A read-only observer of this app.
+    Observer(Uuid),
+}
+
+/// Wait for the first message — must be `register`, `re_register` or
+/// `observe`.
 async fn wait_for_registration(
     receiver: &mut futures::stream::SplitStream<WebSocket>,
     sender: &Sender,
     state: &Arc<AppState>,
-) -> Result<(Uuid, Option<Uuid>, Option<String>), TrailsError> {
+    credentials: &Credentials,
+) -> Result<Joined, TrailsError> {
     // Timeout: 30 seconds to send registration.
     let msg = tokio::time::timeout(std::time::Duration::from_secs(30), receiver.next())
         .await
@@ -124,62 +268,241 @@ async fn wait_for_registration(
         _ => return Err(TrailsError::Protocol("expected text frame for registration".into())),
     };
 
-    let client_msg: ClientMessage =
-        serde_json::from_str(&text).map_err(|e| TrailsError::Protocol(format!("invalid JSON: {e}")))?;
+    let client_msg: ClientMessage = match serde_json::from_str(&text) {
+        Ok(m) => m,
+        Err(e) => {
+            let e = TrailsError::InvalidJson(e.to_string());
+            dead_letter(state, None, &e, &text).await;
+            return Err(e);
+        }
+    };
 
-    match client_msg {
-        ClientMessage::Register(reg) => handle_register(reg, sender, state).await,
-        ClientMessage::ReRegister(rereg) => handle_re_register(rereg, sender, state).await,
-        _ => Err(TrailsError::Protocol(
-            "first message must be register or re_register".into(),
-        )),
+    let (app_id, result) = match client_msg {
+        // Draining refuses new apps only; re_register lets existing
+        // connections that blipped come back until shutdown.
+        ClientMessage::Register(_) if state.is_draining() => return Err(TrailsError::Draining),
+        ClientMessage::Register(reg) => (
+            Some(reg.app_id),
+            handle_register(*reg, receiver, sender, state).await.map(Joined::App),
+        ),
+        ClientMessage::ReRegister(rereg) => (
+            Some(rereg.app_id),
+            handle_re_register(rereg, receiver, sender, state)
+                .await
+                .map(Joined::App),
+        ),
+        ClientMessage::Observe(obs) => (
+            Some(obs.app_id),
+            observe::admit(state, credentials, obs.app_id, sender)
+                .await
+                .map(|()| Joined::Observer(obs.app_id)),
+        ),
+        _ => (
+            None,
+            Err(TrailsError::Protocol(
+                "first message must be register, re_register or observe".into(),
+            )),
+        ),
+    };
+    if let Err(e) = &result {
+        dead_letter(state, app_id, e, &text).await;
+    }
+    result
+}
+
+/// What message handling needs to know about the app a message came from.
+#[derive(Debug, Default)]
+pub(crate) struct Session {
+    pub app_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub app_name: String,
+    pub namespace: Option<String>,
+    /// Payloads are sealed envelopes, stored opaquely.
+    pub sealed: bool,
+    pub metric_rules: Arc<[MetricRule]>,
+}
+
+impl Session {
+    /// The session of an app connected here; a default one (no schema,
+    /// no metrics) if its connection has gone.
+    fn of_connection(state: &AppState, app_id: Uuid) -> Self {
+        let Some(c) = state.connections.get(&app_id) else {
+            return Self {
+                app_id,
+                ..Self::default()
+            };
+        };
+        Self {
+            app_id,
+            parent_id: c.parent_id,
+            app_name: c.app_name.clone(),
+            namespace: c.namespace.clone(),
+            sealed: c.sealed,
+            metric_rules: Arc::clone(&c.metric_rules),
+        }
+    }
+
+    /// The session of a registered app row.
+    pub(crate) async fn of_row(state: &AppState, row: &db::AppRow) -> Result<Self, TrailsError> {
+        Ok(Self {
+            app_id: row.app_id,
+            parent_id: row.parent_id,
+            app_name: row.app_name.clone(),
+            namespace: row.namespace.clone(),
+            sealed: row.sec_level == "sealed",
+            metric_rules: app_metrics::load_tag_rules(state, row.app_id).await?,
+        })
     }
 }
 
 /// Handle fresh registration.
 async fn handle_register(
-    reg: RegisterMsg,
+    mut reg: RegisterMsg,
+    receiver: &mut futures::stream::SplitStream<WebSocket>,
     sender: &Sender,
     state: &Arc<AppState>,
-) -> Result<(Uuid, Option<Uuid>, Option<String>), TrailsError> {
+) -> Result<Session, TrailsError> {
+    let app_id = reg.app_id;
+    let pub_key = reg.child_pub_key.clone();
+    let binary = encoding::binary_taken(reg.binary.as_deref());
+    let prove = async || {
+        sender.sign_with(&state.server_key);
+        challenge(receiver, sender, state, app_id, &pub_key).await
+    };
+    let session = register_app(&mut reg, state, "ws", prove).await?;
+
+    // Send Registered ack.
+    let ack = ServerMessage::Registered(RegisteredMsg {
+        app_id,
+        server_pub_key: state.server_pub_key_str(),
+        server_version: version::SERVER_VERSION,
+        last_seq: None,
+        server_time: server_time(),
+        binary,
+    });
+    send_msg(sender, &ack).await?;
+
+    // Track connection, after the ack: controls sent to it follow the ack.
+    state.connections.insert(
+        app_id,
+        ConnectedClient {
+            app_id,
+            parent_id: session.parent_id,
+            app_name: session.app_name.clone(),
+            namespace: session.namespace.clone(),
+            sealed: session.sealed,
+            last_seq: 0,
+            status_sampled_at: None,
+            last_heartbeat: None,
+            metric_rules: Arc::clone(&session.metric_rules),
+            watched_children: HashSet::new(),
+            subtask_of: None,
+            sender: Arc::clone(sender),
+        },
+    );
+
+    // Controls queued while it wasn't connected go before anything else.
+    // A failed send leaves them queued; the message loop sees the socket go.
+    if let Err(e) = deliver_pending_controls(state, app_id).await {
+        warn!(app_id = %app_id, "queued control delivery failed: {e}");
+    }
+
+    state.publish(Event::AppConnected {
+        app_id,
+        parent_id: session.parent_id,
+    });
+
+    Ok(session)
+}
+
+/// Validate a registration, create the app row unless a parent
+/// pre-registered it, and move it to connected over `transport`. Signed
+/// levels must `prove` possession of the key before anything is written.
+pub(crate) async fn register_app(
+    reg: &mut RegisterMsg,
+    state: &Arc<AppState>,
+    transport: &str,
+    prove: impl AsyncFnOnce() -> Result<(), TrailsError>,
+) -> Result<Session, TrailsError> {
     let app_id = reg.app_id;
     let parent_id = reg.parent_id;
+    reg.app_name = normalize_app_name(&reg.app_name, state.config().app_name_trim)
+        .map_err(TrailsError::RegistrationFailed)?
+        .to_string();
+
+    let requested_level = reg.sec_level.as_deref().unwrap_or("open");
+    if !signing::SEC_LEVELS.contains(&requested_level) {
+        return Err(TrailsError::RegistrationFailed(format!(
+            "unknown sec_level '{requested_level}'"
+        )));
+    }
+    let signed = signing::is_signed_level(requested_level);
 
     // Check if app already exists (Phase A pre-registration by parent).
     let existing = db::get_app(&state.db, app_id).await?;
 
+    // Only a fast path: `connect_app` settles concurrent registrations.
     if let Some(row) = &existing {
         if row.status != "scheduled" {
-            return Err(TrailsError::RegistrationFailed(format!(
-                "app {app_id} already in state '{}'",
-                row.status
-            )));
+            return Err(TrailsError::already_registered(app_id, row.status.clone()));
         }
-    } else {
+    }
+
+    // A purged id (or parent) stays dead; the tombstone outlives the row.
+    if existing.is_none() {
+        let ids: Vec<Uuid> = std::iter::once(app_id).chain(parent_id).collect();
+        if let Some(&purged) = db::purged_app_ids(&state.db, &ids).await?.first() {
+            return Err(TrailsError::Purged(purged));
+        }
+    }
+
+    // A parent's pre-registered signed level wins over an open register.
+    let sec_level = match &existing {
+        Some(row) if signing::is_signed_level(&row.sec_level) && !signed => row.sec_level.clone(),
+        _ => requested_level.to_string(),
+    };
+
+    // Signed mode: prove possession of the key before anything is written.
+    if signing::is_signed_level(&sec_level) {
+        prove().await?;
+    }
+
+    if existing.is_none() {
         // No Phase A pre-registration — auto-create scheduled row.
         // This supports the simple case: child connects directly without
         // parent calling POST /api/v1/children first.
-        db::create_scheduled_app(
-            &state.db,
+        crash_loop::check_registration(state, &reg.app_name, parent_id).await?;
+        let priority = reg.priority.unwrap_or(0);
+        if !PRIORITY_RANGE.contains(&priority) {
+            return Err(TrailsError::RegistrationFailed(format!(
+                "priority {priority} outside {PRIORITY_RANGE:?}"
+            )));
+        }
+        let app = db::NewApp {
             app_id,
             parent_id,
-            &reg.app_name,
-            state.config.default_start_deadline,
-            &reg.role_refs,
-            None,
-        )
-        .await?;
+            app_name: &reg.app_name,
+            start_deadline: state.config().default_start_deadline,
+            role_refs: &reg.role_refs,
+            metadata: reg.tags.as_ref(),
+            max_runtime_secs: None,
+            priority,
+            scheduled_at: None,
+            sec_level: requested_level,
+            correlation_id: None,
+            depends_on: &[],
+        };
+        db::create_scheduled_app(&state.db, &app).await?;
     }
 
     let pi = &reg.process_info;
-    let namespace = pi.namespace.clone();
 
     // Transition scheduled → connected.
     db::connect_app(
         &state.db,
         app_id,
         &reg.child_pub_key,
-        &state.config.server_instance,
+        &state.boot.server_instance,
         pi.pid,
         pi.ppid,
         pi.uid,
@@ -189,141 +512,448 @@ async fn handle_register(
         pi.pod_ip.as_deref(),
         pi.namespace.as_deref(),
         pi.executable.as_deref(),
+        &sec_level,
+        transport,
     )
     .await?;
-
-    // Track connection.
-    state.connections.insert(
-        app_id,
-        ConnectedClient {
-            app_id,
-            parent_id,
-            namespace: namespace.clone(),
-            last_seq: 0,
-        },
-    );
-
-    // Send Registered ack.
-    let ack = ServerMessage::Registered(RegisteredMsg {
-        app_id,
-        server_pub_key: state.server_pub_key_str(),
-    });
-    send_msg(sender, &ack).await?;
-
-    state.publish(Event::AppConnected { app_id, parent_id });
+    let metric_rules = app_metrics::load_tag_rules(state, app_id).await?;
 
     info!(
         app_id = %app_id,
         parent_id = ?parent_id,
         app_name = %reg.app_name,
         pid = pi.pid,
+        transport,
         "registration complete → connected"
     );
 
-    Ok((app_id, parent_id, namespace))
+    Ok(Session {
+        app_id,
+        parent_id,
+        app_name: reg.app_name.clone(),
+        namespace: pi.namespace.clone(),
+        sealed: sec_level == "sealed",
+        metric_rules,
+    })
+}
+
+/// Send a registration challenge and wait for the client's proof of
+/// holding the private half of `pub_key`. A proof that arrives after the
+/// TTL, answers another nonce, or doesn't verify fails the registration.
+async fn challenge(
+    receiver: &mut futures::stream::SplitStream<WebSocket>,
+    sender: &Sender,
+    state: &Arc<AppState>,
+    app_id: Uuid,
+    pub_key: &str,
+) -> Result<(), TrailsError> {
+    let ttl_ms = state.config().challenge_ttl_ms;
+    let ttl = std::time::Duration::from_millis(ttl_ms);
+    let challenge = signing::Challenge::new(ttl);
+    let msg = ServerMessage::Challenge(ChallengeMsg {
+        app_id,
+        nonce: challenge.nonce.clone(),
+        expires_in_ms: ttl_ms,
+    });
+    send_msg(sender, &msg).await?;
+
+    let result = match tokio::time::timeout(ttl, receiver.next()).await {
+        Err(_) => Err(TrailsError::ChallengeExpired),
+        Ok(None) => Err(TrailsError::Protocol("connection closed during challenge".into())),
+        Ok(Some(Err(e))) => Err(TrailsError::Protocol(format!("ws error: {e}"))),
+        Ok(Some(Ok(Message::Text(text)))) => match serde_json::from_str(&text) {
+            Ok(ClientMessage::RegisterProof(proof)) => challenge.verify(&proof, app_id, pub_key),
+            Ok(_) => Err(TrailsError::Protocol("expected register_proof".into())),
+            Err(e) => Err(TrailsError::InvalidJson(e.to_string())),
+        },
+        Ok(Some(Ok(_))) => Err(TrailsError::Protocol(
+            "expected text frame for register_proof".into(),
+        )),
+    };
+    let outcome = match &result {
+        Ok(()) => "ok",
+        Err(TrailsError::ChallengeExpired) => "expired",
+        Err(_) => "invalid",
+    };
+    state
+        .metrics
+        .inc("trails_registration_challenges_total", &[("result", outcome)]);
+    result
 }
 
 /// Handle re-registration after server restart (spec §19).
 async fn handle_re_register(
     rereg: ReRegisterMsg,
+    receiver: &mut futures::stream::SplitStream<WebSocket>,
     sender: &Sender,
     state: &Arc<AppState>,
-) -> Result<(Uuid, Option<Uuid>, Option<String>), TrailsError> {
+) -> Result<Session, TrailsError> {
     let app_id = rereg.app_id;
 
+    // Signed apps answer a challenge before the row is touched; the key
+    // itself is checked against the stored one by `reconnect_app`.
+    let signed = db::get_app(&state.db, app_id)
+        .await?
+        .is_some_and(|row| signing::is_signed_level(&row.sec_level));
+    if signed {
+        sender.sign_with(&state.server_key);
+        challenge(receiver, sender, state, app_id, &rereg.pub_key).await?;
+    }
+
     let row = db::reconnect_app(
         &state.db,
         app_id,
         &rereg.pub_key,
-        &state.config.server_instance,
+        &state.boot.server_instance,
+        "ws",
     )
-    .await?
-    .ok_or_else(|| {
-        TrailsError::RegistrationFailed(format!(
-            "re_register failed for {app_id}: not found or pub_key mismatch"
-        ))
-    })?;
+    .await?;
+    let Some(row) = row else {
+        // Lost a race with another re_register of the same app?
+        return Err(match db::get_app(&state.db, app_id).await? {
+            Some(app) if app.pub_key.as_deref() == Some(rereg.pub_key.as_str()) => {
+                TrailsError::already_registered(app_id, app.status)
+            }
+            _ => TrailsError::RegistrationFailed(format!(
+                "re_register failed for {app_id}: not found or pub_key mismatch"
+            )),
+        });
+    };
 
-    let parent_id = row.parent_id;
-    let namespace = row.namespace.clone();
+    let session = Session::of_row(state, &row).await?;
+    let parent_id = session.parent_id;
+
+    let stored_seq = db::max_message_seq(&state.db, app_id).await?;
+    let ack = ServerMessage::Registered(RegisteredMsg {
+        app_id,
+        server_pub_key: state.server_pub_key_str(),
+        server_version: version::SERVER_VERSION,
+        last_seq: stored_seq,
+        server_time: server_time(),
+        binary: encoding::binary_taken(rereg.binary.as_deref()),
+    });
+    send_msg(sender, &ack).await?;
 
     state.connections.insert(
         app_id,
         ConnectedClient {
             app_id,
             parent_id,
-            namespace: namespace.clone(),
-            last_seq: rereg.last_seq,
+            app_name: session.app_name.clone(),
+            namespace: session.namespace.clone(),
+            sealed: session.sealed,
+            last_seq: stored_seq.unwrap_or(0),
+            status_sampled_at: None,
+            last_heartbeat: None,
+            metric_rules: Arc::clone(&session.metric_rules),
+            watched_children: HashSet::new(),
+            subtask_of: None,
+            sender: Arc::clone(sender),
         },
     );
 
+    let delivered = deliver_pending_controls(state, app_id)
+        .await
+        .unwrap_or_else(|e| {
+            warn!(app_id = %app_id, "queued control delivery failed: {e}");
+            Vec::new()
+        });
+
+    // Re-deliver the pause so a reconnecting client doesn't resume by
+    // accident, unless a queued pause/resume just settled it.
+    let settled = delivered
+        .iter()
+        .any(|c| matches!(c.action.as_str(), "pause" | "resume"));
+    if row.paused && !settled {
+        send_control(state, app_id, "pause", serde_json::json!({ "redelivered": true })).await?;
+    }
+
+    state.publish(Event::AppConnected { app_id, parent_id });
+
+    info!(app_id = %app_id, last_seq = rereg.last_seq, paused = row.paused, "re-registered → running");
+
+    Ok(session)
+}
+
+/// Register a subtask over the socket `app_id` registered
+/// (`register_sub`). It must be a child of an app the socket speaks for
+/// and hold the socket's key, which a signed socket already proved, so
+/// there is no challenge. One that was connected before a server restart
+/// comes back as with `re_register`.
+async fn register_sub(
+    mut reg: RegisterMsg,
+    app_id: Uuid,
+    identities: &HashMap<Uuid, Option<Uuid>>,
+    state: &Arc<AppState>,
+    sender: &Sender,
+) -> Result<Uuid, TrailsError> {
+    let subtask = reg.app_id;
+    if identities.contains_key(&subtask) {
+        return Err(TrailsError::Protocol(format!(
+            "{subtask} is already registered on this connection"
+        )));
+    }
+    if !reg.parent_id.is_some_and(|p| identities.contains_key(&p)) {
+        return Err(TrailsError::RegistrationFailed(
+            "a subtask's parent must be registered on its connection".into(),
+        ));
+    }
+    let key = db::get_app(&state.db, app_id).await?.and_then(|row| row.pub_key);
+    if key.as_deref() != Some(reg.child_pub_key.as_str()) {
+        return Err(TrailsError::RegistrationFailed(
+            "a subtask must hold its connection's key".into(),
+        ));
+    }
+
+    let (session, last_seq) = match db::get_app(&state.db, subtask).await? {
+        Some(row) if row.status != "scheduled" => {
+            let server_instance = &state.boot.server_instance;
+            let pub_key = &reg.child_pub_key;
+            let Some(row) =
+                db::reconnect_app(&state.db, subtask, pub_key, server_instance, "ws").await?
+            else {
+                return Err(TrailsError::already_registered(subtask, row.status));
+            };
+            let last_seq = db::max_message_seq(&state.db, subtask).await?;
+            (Session::of_row(state, &row).await?, last_seq)
+        }
+        // Draining refuses new apps, as for `register`.
+        _ if state.is_draining() => return Err(TrailsError::Draining),
+        _ => (register_app(&mut reg, state, "ws", async || Ok(())).await?, None),
+    };
+
     let ack = ServerMessage::Registered(RegisteredMsg {
-        app_id,
+        app_id: subtask,
         server_pub_key: state.server_pub_key_str(),
+        server_version: version::SERVER_VERSION,
+        last_seq,
+        server_time: server_time(),
+        binary: None,
     });
     send_msg(sender, &ack).await?;
 
-    state.publish(Event::AppConnected { app_id, parent_id });
+    state.connections.insert(
+        subtask,
+        ConnectedClient {
+            app_id: subtask,
+            parent_id: session.parent_id,
+            app_name: session.app_name.clone(),
+            namespace: session.namespace.clone(),
+            sealed: session.sealed,
+            last_seq: last_seq.unwrap_or(0),
+            status_sampled_at: None,
+            last_heartbeat: None,
+            metric_rules: Arc::clone(&session.metric_rules),
+            watched_children: HashSet::new(),
+            subtask_of: Some(app_id),
+            sender: Arc::clone(sender),
+        },
+    );
 
-    info!(app_id = %app_id, last_seq = rereg.last_seq, "re-registered → running");
+    if let Err(e) = deliver_pending_controls(state, subtask).await {
+        warn!(app_id = %subtask, "queued control delivery failed: {e}");
+    }
 
-    Ok((app_id, parent_id, namespace))
+    state.publish(Event::AppConnected {
+        app_id: subtask,
+        parent_id: session.parent_id,
+    });
+
+    info!(app_id = %subtask, subtask_of = %app_id, "subtask registered → connected");
+
+    Ok(subtask)
 }
 
 // ═══════════════════════════════════════════════════════════════
 // Message handling
 // ═══════════════════════════════════════════════════════════════
 
-/// Handle a client message after registration.
-/// Returns Ok(true) if this was a terminal message (disconnect/done/error).
+/// Handle a client message after registration. `identities` are the
+/// apps the socket speaks for, with their parents: `app_id`, which
+/// registered it, and its subtasks. Returns the one this message ended
+/// (disconnect/done/error), if any.
 async fn handle_client_message(
     text: &str,
-    registered_app_id: Uuid,
+    app_id: Uuid,
+    identities: &mut HashMap<Uuid, Option<Uuid>>,
     state: &Arc<AppState>,
     sender: &Sender,
-) -> Result<bool, TrailsError> {
+) -> Result<Option<Uuid>, TrailsError> {
     let client_msg: ClientMessage =
-        serde_json::from_str(text).map_err(|e| TrailsError::Protocol(format!("invalid JSON: {e}")))?;
+        serde_json::from_str(text).map_err(|e| TrailsError::InvalidJson(e.to_string()))?;
 
     match client_msg {
         ClientMessage::Message(data) => {
-            // Verify app_id matches registration (or is a multiplexed identity).
-            // Phase 1: simple check — must match registered app_id.
-            if data.app_id != registered_app_id {
+            // The registered app, or one of its subtasks.
+            let from = data.app_id;
+            if !speaks_for(state, identities, app_id, from) {
                 return Err(TrailsError::Protocol(format!(
-                    "app_id mismatch: registered={registered_app_id}, message={}",
-                    data.app_id
+                    "app_id mismatch: registered={app_id}, message={from}"
                 )));
             }
 
-            handle_data_message(data, state, sender).await
+            let seq = data.header.seq;
+            let ack = || {
+                ServerMessage::Ack(AckMsg {
+                    app_id: (from != app_id).then_some(from),
+                    ..AckMsg::new(seq)
+                })
+            };
+            let session = Session::of_connection(state, from);
+            let handled = handle_data_message(data, state, &session).await?;
+
+            // Ack the message.
+            send_msg(sender, &ack()).await?;
+            Ok((handled == Handled::Terminal).then_some(from))
+        }
+        ClientMessage::Heartbeat(hb) => {
+            if hb.app_id != app_id {
+                return Err(TrailsError::Protocol(format!(
+                    "app_id mismatch: registered={app_id}, heartbeat={}",
+                    hb.app_id
+                )));
+            }
+            // The socket's heartbeat stands for its subtasks too.
+            for &id in identities.keys() {
+                if let Some(mut conn) = state.connections.get_mut(&id) {
+                    conn.last_heartbeat = Some(Instant::now());
+                }
+                db::record_heartbeat(&state.db, id).await?;
+            }
+            Ok(None) // not acked: it has no seq
         }
         ClientMessage::Disconnect(disc) => {
-            handle_disconnect(disc, state).await?;
-            Ok(true) // terminal
+            // A subtask's disconnect ends only the subtask.
+            let ended = match disc.app_id {
+                id if id != app_id && speaks_for(state, identities, app_id, id) => id,
+                _ => app_id,
+            };
+            let parent_id = identities.get(&ended).copied().flatten();
+            handle_disconnect(disc, state, parent_id).await?;
+            Ok(Some(ended)) // terminal
+        }
+        ClientMessage::RegisterSub(reg) => {
+            let parent_id = reg.parent_id;
+            let subtask = register_sub(*reg, app_id, identities, state, sender).await?;
+            identities.insert(subtask, parent_id);
+            Ok(None)
+        }
+        ClientMessage::SubscribeChild(sub) => {
+            child_watch::subscribe(state, app_id, sub.child_id, sender).await?;
+            Ok(None)
         }
         ClientMessage::Register(_) | ClientMessage::ReRegister(_) => {
             Err(TrailsError::Protocol("duplicate registration".into()))
         }
+        ClientMessage::Observe(_) => {
+            Err(TrailsError::Protocol("observe must be the first message".into()))
+        }
+        ClientMessage::RegisterProof(_) => {
+            Err(TrailsError::Protocol("register_proof without a pending challenge".into()))
+        }
+    }
+}
+
+/// Whether the socket `app_id` registered may send for `from`: itself, or
+/// a subtask of its still connected here (one forced terminal is dropped
+/// from `connections`, the socket being another app's).
+fn speaks_for(
+    state: &AppState,
+    identities: &HashMap<Uuid, Option<Uuid>>,
+    app_id: Uuid,
+    from: Uuid,
+) -> bool {
+    from == app_id || identities.contains_key(&from) && state.connections.contains_key(&from)
+}
+
+/// The app a frame names, when it isn't the one that registered the
+/// socket: the error for a subtask's frame names the subtask.
+fn subtask_of_frame(text: &str, app_id: Uuid) -> Option<Uuid> {
+    #[derive(serde::Deserialize)]
+    struct Named {
+        app_id: Option<Uuid>,
     }
+    let named = serde_json::from_str::<Named>(text).ok()?.app_id?;
+    (named != app_id).then_some(named)
 }
 
-/// Process a data message (Status, Result, Error).
-async fn handle_data_message(
-    data: DataMsg,
+/// What became of a data message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Handled {
+    Stored,
+    /// A Result or Error: the app is done.
+    Terminal,
+    /// Its seq was already stored, e.g. a replay racing a lost ack.
+    Duplicate,
+}
+
+/// Process a data message (Status, Result, Error); the caller acks it.
+pub(crate) async fn handle_data_message(
+    mut data: DataMsg,
     state: &Arc<AppState>,
-    sender: &Sender,
-) -> Result<bool, TrailsError> {
+    session: &Session,
+) -> Result<Handled, TrailsError> {
     let app_id = data.app_id;
-    let msg_type = data.header.msg_type;
+    let msg_type = data.header.msg_type.clone();
     let seq = data.header.seq;
 
-    // Get namespace for snapshot storage.
-    let namespace = state
-        .connections
-        .get(&app_id)
-        .map(|c| c.namespace.clone())
-        .unwrap_or(None);
+    // A resend of what is already stored skips the work (and the quota).
+    // Over a socket only a seq up to the connection's last can be one.
+    // This is a shortcut: the unique indexes on messages and logs are
+    // what keep a resend racing the first copy from being stored twice.
+    let resent = state.connections.get(&app_id).is_none_or(|c| seq <= c.last_seq);
+    if resent && db::message_seq_exists(&state.db, app_id, seq).await? {
+        debug!(app_id = %app_id, seq, "duplicate message, not stored");
+        return Ok(Handled::Duplicate);
+    }
+    encoding::decode(&mut data, state.config().max_message_bytes)?;
+
+    // Namespace for snapshot storage and app_name for schema lookup.
+    let Session {
+        namespace,
+        app_name,
+        sealed,
+        metric_rules,
+        ..
+    } = session;
+    let sealed = *sealed;
+
+    // Sealed payloads are opaque: check the envelope, skip the schema.
+    // Otherwise validate against the schema registered for this app_name.
+    if sealed {
+        sealed::check_envelope(&data.payload)?;
+    } else {
+        schema::check_payload(state, app_name, &msg_type, &data.payload).await?;
+    }
+
+    // Redaction rules apply to everything stored from here on; the schema
+    // saw the payload as sent.
+    let redactions = if sealed {
+        0
+    } else {
+        redact_payload(state, app_id, namespace.as_deref(), &mut data.payload)
+    };
+
+    // Past the hard storage quota only a final Result/Error gets through.
+    // Log lines go to their own table and don't count.
+    let incoming = if msg_type == MsgType::Log && !sealed {
+        0
+    } else {
+        data.payload.to_string().len() as i64
+    };
+    let admission = quota::admit(state, app_id, &msg_type, incoming).await?;
+
+    // A log line goes to the logs, not the message history.
+    if msg_type == MsgType::Log && !sealed {
+        if !logs::store(state, app_id, seq, data.header.timestamp, &data.payload).await? {
+            debug!(app_id = %app_id, seq, "duplicate log line, not stored");
+            return Ok(Handled::Duplicate);
+        }
+        if let Some(mut conn) = state.connections.get_mut(&app_id) {
+            conn.last_seq = conn.last_seq.max(seq);
+        }
+        return Ok(Handled::Stored);
+    }
 
     // On first Status message: transition connected → running.
     if msg_type == MsgType::Status {
@@ -331,43 +961,96 @@ async fn handle_data_message(
         let _ = db::set_running(&state.db, app_id).await;
     }
 
-    // Store the message.
-    db::store_message(
-        &state.db,
-        app_id,
-        "in",
-        msg_type.as_str(),
-        seq,
-        data.header.correlation_id.as_deref(),
-        &data.payload,
-    )
-    .await?;
-
-    // Status messages also stored as snapshots (spec §13).
-    if msg_type == MsgType::Status {
-        db::store_snapshot(&state.db, app_id, namespace.as_deref(), seq, &data.payload).await?;
+    // A changed Status is progress; it clears a stall flag.
+    if msg_type == MsgType::Status && db::record_progress(&state.db, app_id, &data.payload).await? {
+        info!(app_id = %app_id, "progress resumed, stall cleared");
     }
 
+    // A Status coalesced by the quota only replaces the latest snapshot.
+    let coalesced = if admission.coalesce {
+        db::coalesce_snapshot(&state.db, app_id, seq, &data.payload).await?
+    } else {
+        None
+    };
+    let stored_bytes = match coalesced {
+        Some(stored_bytes) => stored_bytes,
+        None => {
+            // Store the message; large payloads go to the blob store.
+            let blob = blob::offload(state, app_id, seq, &data.payload).await?;
+            let stored = db::store_message(
+                &state.db,
+                app_id,
+                "in",
+                msg_type.as_str(),
+                seq,
+                data.header.correlation_id.as_deref(),
+                &data.payload,
+                blob.as_ref(),
+                sealed,
+                redactions,
+            )
+            .await;
+            let stored = match stored {
+                Ok(stored) => stored,
+                Err(e) => {
+                    if let Some(blob) = &blob {
+                        blob::discard(state, app_id, seq, blob).await;
+                    }
+                    return Err(e);
+                }
+            };
+            // Another connection stored it first; it did the rest too.
+            let Some(stored_bytes) = stored else {
+                debug!(app_id = %app_id, seq, "duplicate message, not stored");
+                return Ok(Handled::Duplicate);
+            };
+
+            // A Metric's values join the app's metric series.
+            if msg_type == MsgType::Metric && !sealed {
+                let payload = &data.payload;
+                let recorded = app_metrics::record_reported(state, app_id, app_name, seq, payload);
+                if let Err(e) = recorded.await {
+                    warn!(app_id = %app_id, seq, "metric storage failed: {e}");
+                }
+            }
+
+            // Status messages also stored as snapshots (spec §13), and
+            // their metrics extracted.
+            if msg_type == MsgType::Status {
+                if !sealed {
+                    let payload = &data.payload;
+                    let recorded =
+                        app_metrics::record(state, app_id, app_name, metric_rules, seq, payload);
+                    if let Err(e) = recorded.await {
+                        warn!(app_id = %app_id, seq, "metric extraction failed: {e}");
+                    }
+                }
+                db::store_snapshot(&state.db, app_id, namespace.as_deref(), seq, &data.payload)
+                    .await?
+            } else {
+                stored_bytes
+            }
+        }
+    };
+
     // Update last_seq.
     if let Some(mut conn) = state.connections.get_mut(&app_id) {
-        conn.last_seq = seq;
+        conn.last_seq = conn.last_seq.max(seq);
     }
 
-    let parent_id = state
-        .connections
-        .get(&app_id)
-        .map(|c| c.parent_id)
-        .unwrap_or(None);
+    let parent_id = session.parent_id;
+
+    quota::record(state, app_id, parent_id, &admission, stored_bytes).await?;
 
     state.publish(Event::MessageStored {
         app_id,
         parent_id,
-        msg_type,
+        msg_type: msg_type.clone(),
         seq,
     });
 
     // Handle terminal message types.
-    let terminal = match msg_type {
+    let handled = match msg_type {
         MsgType::Result => {
             db::set_terminal(&state.db, app_id, "done").await?;
             state.publish(Event::AppTerminal {
@@ -375,7 +1058,7 @@ async fn handle_data_message(
                 parent_id,
                 status: "done".into(),
             });
-            true
+            Handled::Terminal
         }
         MsgType::Error => {
             db::set_terminal(&state.db, app_id, "error").await?;
@@ -384,47 +1067,64 @@ async fn handle_data_message(
                 parent_id,
                 status: "error".into(),
             });
-            true
+            Handled::Terminal
         }
-        _ => false,
+        _ => Handled::Stored,
     };
 
-    // Ack the message.
-    let ack = ServerMessage::Ack(AckMsg { seq });
-    send_msg(sender, &ack).await?;
+    Ok(handled)
+}
 
-    Ok(terminal)
+/// Apply the redaction rules to a payload; returns the redaction count.
+fn redact_payload(
+    state: &AppState,
+    app_id: Uuid,
+    namespace: Option<&str>,
+    payload: &mut serde_json::Value,
+) -> i32 {
+    let config = state.config();
+    let redacted = redact::redact(&config.redaction, namespace, payload);
+    if redacted.applied > 0 {
+        state
+            .metrics
+            .add("trails_redactions_total", &[], f64::from(redacted.applied));
+    }
+    if redacted.truncated {
+        warn!(
+            app_id = %app_id,
+            scanned_bytes = redacted.scanned_bytes,
+            "payload larger than REDACT_SCAN_MAX_BYTES, not fully scanned"
+        );
+        state.metrics.inc("trails_redaction_scans_truncated_total", &[]);
+    }
+    redacted.applied.try_into().unwrap_or(i32::MAX)
 }
 
 /// Handle graceful disconnect.
-async fn handle_disconnect(disc: DisconnectMsg, state: &Arc<AppState>) -> Result<(), TrailsError> {
+pub(crate) async fn handle_disconnect(
+    disc: DisconnectMsg,
+    state: &Arc<AppState>,
+    parent_id: Option<Uuid>,
+) -> Result<(), TrailsError> {
     let app_id = disc.app_id;
-    info!(app_id = %app_id, reason = %disc.reason, "graceful disconnect");
+    let status = disc.status();
+    info!(app_id = %app_id, reason = %disc.reason, status = status.as_str(), "graceful disconnect");
 
-    // If reason is "completed", transition to done (if not already terminal).
-    match disc.reason.as_str() {
-        "completed" | "done" => {
-            let _ = db::set_terminal(&state.db, app_id, "done").await;
-        }
-        "error" | "failed" => {
-            let _ = db::set_terminal(&state.db, app_id, "error").await;
-        }
-        _ => {
-            // Generic disconnect — mark as done.
-            let _ = db::set_terminal(&state.db, app_id, "done").await;
+    // A process that will be back is given the reconnect window, as after
+    // a server restart, instead of being ended.
+    if status == AppStatus::Reconnecting {
+        if db::mark_app_reconnecting(&state.db, app_id).await? {
+            lifecycle::spawn_reconnect_deadline(Arc::clone(state), app_id);
         }
+        return Ok(());
     }
 
-    let parent_id = state
-        .connections
-        .get(&app_id)
-        .map(|c| c.parent_id)
-        .unwrap_or(None);
-
+    // Not if already terminal: a Result or Error said how it ended.
+    let _ = db::set_terminal(&state.db, app_id, status.as_str()).await;
     state.publish(Event::AppTerminal {
         app_id,
         parent_id,
-        status: "done".into(),
+        status: status.as_str().into(),
     });
 
     Ok(())
@@ -434,10 +1134,148 @@ async fn handle_disconnect(disc: DisconnectMsg, state: &Arc<AppState>) -> Result
 // Helpers
 // ═══════════════════════════════════════════════════════════════
 
-async fn send_msg(sender: &Sender, msg: &ServerMessage) -> Result<(), TrailsError> {
-    let json = serde_json::to_string(msg)
-        .map_err(|e| TrailsError::Protocol(format!("serialize error: {e}")))?;
-    let mut guard = sender.lock().await;
+/// Record a rejected inbound frame in `dead_letters`. Database errors are
+/// server faults, not unprocessable input, so they are not dead-lettered;
+/// nor are frames refused by the storage quota, which storing would defeat.
+/// Failures to write the dead letter are logged and otherwise ignored.
+pub(crate) async fn dead_letter(state: &AppState, app_id: Option<Uuid>, err: &TrailsError, raw: &str) {
+    if matches!(err, TrailsError::Db(_) | TrailsError::StorageQuotaExceeded { .. }) {
+        return;
+    }
+    let reason = err.code();
+    let config = state.config();
+    let cap = config.dead_letter_max_bytes;
+    let truncated = raw.len() > cap;
+    let stored = if truncated {
+        let mut end = cap;
+        while !raw.is_char_boundary(end) {
+            end -= 1;
+        }
+        &raw[..end]
+    } else {
+        raw
+    };
+
+    match db::insert_dead_letter(
+        &state.db,
+        app_id,
+        reason,
+        Some(&err.to_string()),
+        stored,
+        raw.len().min(i32::MAX as usize) as i32,
+        truncated,
+        config.dead_letter_max_per_app,
+        request_id::current().as_deref(),
+    )
+    .await
+    {
+        Ok(true) => state.metrics.inc("trails_dead_letters_total", &[("reason", reason)]),
+        Ok(false) => state
+            .metrics
+            .inc("trails_dead_letters_capped_total", &[("reason", reason)]),
+        Err(e) => warn!(app_id = ?app_id, "dead letter write failed: {e}"),
+    }
+}
+
+/// Send a control command to a client connected to this instance.
+/// Returns false if the app has no live connection here.
+pub async fn send_control(
+    state: &AppState,
+    app_id: Uuid,
+    action: &str,
+    payload: serde_json::Value,
+) -> Result<bool, TrailsError> {
+    // Clone the sender out so the DashMap guard isn't held across await.
+    let Some((sender, subtask)) = connection_sender(state, app_id) else {
+        return Ok(false);
+    };
+    let msg = ServerMessage::Control(ControlMsg {
+        action: action.into(),
+        correlation_id: Some(format!("{action}-{}", Uuid::new_v4())),
+        payload,
+        app_id: subtask,
+    });
+    send_msg(&sender, &msg).await?;
+    Ok(true)
+}
+
+/// Deliver the app's queued controls down its connection here, oldest
+/// first. Returns those sent; controls that couldn't be sent stay queued.
+pub async fn deliver_pending_controls(
+    state: &AppState,
+    app_id: Uuid,
+) -> Result<Vec<db::PendingControl>, TrailsError> {
+    let Some((sender, subtask)) = connection_sender(state, app_id) else {
+        return Ok(Vec::new());
+    };
+    let mut pending = db::take_pending_controls(&state.db, app_id).await?;
+    for (i, control) in pending.iter().enumerate() {
+        if let Err(e) = send_msg(&sender, &queued_control_msg(control, subtask)).await {
+            let unsent: Vec<i64> = pending[i..].iter().map(|c| c.id).collect();
+            db::unclaim_controls(&state.db, &unsent).await?;
+            return Err(e);
+        }
+        info!(
+            app_id = %app_id,
+            action = %control.action,
+            control_id = control.id,
+            "queued control delivered"
+        );
+    }
+    state.metrics.add(
+        "trails_queued_controls_delivered_total",
+        &[],
+        pending.len() as f64,
+    );
+    pending.iter_mut().for_each(|c| c.state = "delivered".into());
+    Ok(pending)
+}
+
+/// The frame delivering a queued control, naming the `subtask` it is for.
+pub(crate) fn queued_control_msg(
+    control: &db::PendingControl,
+    subtask: Option<Uuid>,
+) -> ServerMessage {
+    ServerMessage::Control(ControlMsg {
+        action: control.action.clone(),
+        correlation_id: Some(format!("{}-{}", control.action, control.id)),
+        payload: control.payload_json.clone(),
+        app_id: subtask,
+    })
+}
+
+/// The socket of an app connected here, and the app's id if it is a
+/// subtask, for frames to it to name it.
+fn connection_sender(state: &AppState, app_id: Uuid) -> Option<(Sender, Option<Uuid>)> {
+    let conn = state.connections.get(&app_id)?;
+    Some((Arc::clone(&conn.sender), conn.subtask_of.map(|_| app_id)))
+}
+
+/// Close a client's socket from the server side, if connected here. A
+/// subtask's socket is another app's: the subtask is only cut off it.
+pub async fn close_connection(state: &AppState, app_id: Uuid) {
+    let Some((sender, subtask)) = connection_sender(state, app_id) else {
+        return;
+    };
+    if subtask.is_some() {
+        state.connections.remove(&app_id);
+        return;
+    }
+    let _ = sender.sink.lock().await.close().await;
+}
+
+pub(crate) async fn send_msg(sender: &Sender, msg: &ServerMessage) -> Result<(), TrailsError> {
+    let serialize_err =
+        |e: serde_json::Error| TrailsError::Protocol(format!("serialize error: {e}"));
+    let json = match sender.signer.get() {
+        Some(key) => {
+            let mut frame = serde_json::to_value(msg).map_err(serialize_err)?;
+            signing::sign_frame(key, &mut frame);
+            frame.to_string()
+        }
+        None => serde_json::to_string(msg).map_err(serialize_err)?,
+    };
+    let mut guard = sender.sink.lock().await;
     guard
         .send(Message::Text(json.into()))
         .await
@@ -445,10 +1283,16 @@ async fn send_msg(sender: &Sender, msg: &ServerMessage) -> Result<(), TrailsErro
     Ok(())
 }
 
-async fn send_error(sender: &Sender, code: &str, message: &str) -> Result<(), TrailsError> {
+async fn send_error(
+    sender: &Sender,
+    subtask: Option<Uuid>,
+    code: &str,
+    message: &str,
+) -> Result<(), TrailsError> {
     let msg = ServerMessage::Error(ServerErrorMsg {
         code: code.into(),
         message: message.into(),
+        app_id: subtask,
     });
     send_msg(sender, &msg).await
 }
@@ -0,0 +1,80 @@
+//! Admin routes (purge, storage quota, drain) refuse requests without the
+//! configured admin token, whatever `x-trails-actor` claims.
+//!
+//! Needs a running trailsd, so it is ignored by default. Set
+//! `TRAILS_TEST_ADMIN_TOKEN` to the server's `ADMIN_TOKEN` to also check
+//! that the token gets through:
+//!
+//!     TRAILS_TEST_SERVER=http://127.0.0.1:8443 cargo test -- --ignored
+
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+fn server() -> String {
+    std::env::var("TRAILS_TEST_SERVER").unwrap_or_else(|_| "http://127.0.0.1:8443".into())
+}
+
+fn admin_token() -> Option<String> {
+    std::env::var("TRAILS_TEST_ADMIN_TOKEN").ok().filter(|t| !t.is_empty())
+}
+
+/// The admin requests, each on an app that doesn't exist.
+fn admin_requests(http: &reqwest::Client) -> Vec<reqwest::RequestBuilder> {
+    let app_id = Uuid::new_v4();
+    let api = format!("{}/api/v1", server());
+    vec![
+        http.request(Method::DELETE, format!("{api}/apps/{app_id}/data")),
+        http.request(Method::PATCH, format!("{api}/apps/{app_id}/storage-quota"))
+            .json(&json!({"reset": true})),
+        http.request(Method::POST, format!("{api}/admin/drain")),
+        http.request(Method::DELETE, format!("{api}/admin/drain")),
+    ]
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_admin_routes_refuse_unauthenticated_requests() {
+    let http = reqwest::Client::new();
+    for req in admin_requests(&http) {
+        let resp = req.header("x-trails-actor", "mallory").send().await.unwrap();
+        let status = resp.status();
+        // 401 without the token; 403 when the server has none configured.
+        assert!(
+            matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN),
+            "{} {status}",
+            resp.url()
+        );
+    }
+    // Deploy tooling still polls the drain status.
+    let resp = http.get(format!("{}/api/v1/admin/drain", server())).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_admin_routes_take_the_admin_token() {
+    let Some(token) = admin_token() else {
+        return;
+    };
+    let http = reqwest::Client::new();
+    for req in admin_requests(&http) {
+        let resp = req
+            .header("x-trails-actor", "mallory")
+            .bearer_auth("not-the-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "{}", resp.url());
+    }
+
+    let [purge, quota, drain, undrain] = <[_; 4]>::try_from(admin_requests(&http)).unwrap();
+    for req in [purge, quota] {
+        let resp = req.header("x-trails-actor", "ops").bearer_auth(&token).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", resp.url());
+    }
+    for req in [drain, undrain] {
+        let resp = req.bearer_auth(&token).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK, "{}", resp.url());
+    }
+}
@@ -0,0 +1,130 @@
+//! Two simultaneous `register` frames for one app_id, over real sockets:
+//! exactly one wins, the other gets a structured `already_connected`.
+//!
+//! Needs a running trailsd and its Postgres, so it is ignored by default:
+//!
+//!     TRAILS_TEST_SERVER=http://127.0.0.1:8443 cargo test -- --ignored
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Rounds per test; the race only shows up some of the time when broken.
+const ROUNDS: usize = 25;
+
+const PUB_KEY: &str = "ed25519:A6EHv/POEL4dcN0Y50vAmWfk1jCbpQ1fHdyGZBJVMbg=";
+
+fn server() -> String {
+    std::env::var("TRAILS_TEST_SERVER").unwrap_or_else(|_| "http://127.0.0.1:8443".into())
+}
+
+fn register_frame(app_id: Uuid, parent_id: Option<Uuid>) -> String {
+    json!({
+        "type": "register",
+        "app_id": app_id,
+        "parent_id": parent_id,
+        "app_name": "concurrent-registration",
+        "child_pub_key": PUB_KEY,
+        "process_info": {
+            "pid": std::process::id(), "ppid": 1, "uid": 0, "gid": 0,
+            "hostname": "test", "node_name": null, "pod_ip": null,
+            "namespace": null, "start_time": 1, "executable": null
+        },
+        "role_refs": [],
+        "sig": null
+    })
+    .to_string()
+}
+
+async fn open() -> Socket {
+    let url = format!("{}/ws", server().replacen("http", "ws", 1));
+    connect_async(url).await.expect("connect to trailsd").0
+}
+
+/// Next JSON text frame.
+async fn recv(ws: &mut Socket) -> Value {
+    loop {
+        match ws.next().await.expect("socket open").expect("frame") {
+            Message::Text(text) => return serde_json::from_str(&text).expect("json frame"),
+            Message::Close(frame) => panic!("closed before a reply: {frame:?}"),
+            _ => {}
+        }
+    }
+}
+
+async fn register(ws: &mut Socket, frame: &str) -> Value {
+    ws.send(Message::Text(frame.into())).await.expect("send register");
+    recv(ws).await
+}
+
+/// Race two registrations of one app_id and check the outcome.
+async fn race(http: &reqwest::Client, app_id: Uuid, parent_id: Option<Uuid>) {
+    // Sockets are open before either frame goes out, so only the
+    // registration itself overlaps.
+    let (mut a, mut b) = tokio::join!(open(), open());
+    let frame = register_frame(app_id, parent_id);
+    let (ra, rb) = tokio::join!(register(&mut a, &frame), register(&mut b, &frame));
+
+    let (mut winner, loser) = match (ra["type"].as_str(), rb["type"].as_str()) {
+        (Some("registered"), Some("error")) => (a, rb),
+        (Some("error"), Some("registered")) => (b, ra),
+        _ => panic!("expected one registered and one error, got {ra} and {rb}"),
+    };
+    assert_eq!(loser["code"], "already_connected", "{loser}");
+    assert!(loser["message"].as_str().unwrap_or("").contains("connected"), "{loser}");
+
+    let base = format!("{}/api/v1/apps/{app_id}", server());
+    let app: Value = http.get(&base).send().await.unwrap().json().await.unwrap();
+    assert_eq!(app["status"], "connected", "{app}");
+
+    // The server's connection entry is the winner's: a control sent to
+    // the app arrives on the winning socket.
+    let resp = http.post(format!("{base}/pause")).send().await.unwrap();
+    assert!(resp.status().is_success(), "pause: {}", resp.status());
+    let control = recv(&mut winner).await;
+    assert_eq!(control["type"], "control", "{control}");
+    assert_eq!(control["action"], "pause", "{control}");
+
+    let _ = winner.close(None).await;
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_concurrent_registration_of_new_app() {
+    let http = reqwest::Client::new();
+    for _ in 0..ROUNDS {
+        race(&http, Uuid::new_v4(), None).await;
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_concurrent_registration_of_scheduled_app() {
+    let http = reqwest::Client::new();
+    let parent = Uuid::new_v4();
+    let mut ws = open().await;
+    let reply = register(&mut ws, &register_frame(parent, None)).await;
+    assert_eq!(reply["type"], "registered", "{reply}");
+
+    for _ in 0..ROUNDS {
+        let child_id = Uuid::new_v4();
+        let resp = http
+            .post(format!("{}/api/v1/children", server()))
+            .json(&json!({
+                "parentId": parent,
+                "appId": child_id,
+                "appName": "concurrent-registration",
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.status().is_success(), "register child: {}", resp.status());
+        race(&http, child_id, Some(parent)).await;
+    }
+    let _ = ws.close(None).await;
+}
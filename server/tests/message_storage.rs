@@ -0,0 +1,276 @@
+//! Data messages over real sockets, checked through the REST API: what
+//! the schema lets trailsd store.
+//!
+//! Needs a running trailsd and its Postgres, so it is ignored by default:
+//!
+//!     TRAILS_TEST_SERVER=http://127.0.0.1:8443 cargo test -- --ignored
+
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Seed of the key every app here registers with.
+const APP_KEY_SEED: [u8; 32] = [7; 32];
+
+fn app_key() -> SigningKey {
+    SigningKey::from_bytes(&APP_KEY_SEED)
+}
+
+fn pub_key() -> String {
+    let bytes = app_key().verifying_key().to_bytes();
+    format!("ed25519:{}", base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+fn server() -> String {
+    std::env::var("TRAILS_TEST_SERVER").unwrap_or_else(|_| "http://127.0.0.1:8443".into())
+}
+
+async fn open() -> Socket {
+    let url = format!("{}/ws", server().replacen("http", "ws", 1));
+    connect_async(url).await.expect("connect to trailsd").0
+}
+
+/// Next JSON text frame.
+async fn recv(ws: &mut Socket) -> Value {
+    loop {
+        match ws.next().await.expect("socket open").expect("frame") {
+            Message::Text(text) => return serde_json::from_str(&text).expect("json frame"),
+            Message::Close(frame) => panic!("closed before a reply: {frame:?}"),
+            _ => {}
+        }
+    }
+}
+
+async fn send(ws: &mut Socket, frame: Value) -> Value {
+    ws.send(Message::Text(frame.to_string().into())).await.expect("send frame");
+    recv(ws).await
+}
+
+/// A new app, registered over a socket of its own.
+async fn registered() -> (Uuid, Socket) {
+    let app_id = Uuid::new_v4();
+    let mut ws = open().await;
+    let reply = send(&mut ws, register_frame(app_id)).await;
+    assert_eq!(reply["type"], "registered", "{reply}");
+    (app_id, ws)
+}
+
+fn message(app_id: Uuid, msg_type: &str, seq: i64, payload: Value) -> Value {
+    json!({
+        "type": "message",
+        "app_id": app_id,
+        "header": {"msg_type": msg_type, "timestamp": 1, "seq": seq, "correlation_id": null},
+        "payload": payload,
+        "sig": null
+    })
+}
+
+fn register_frame(app_id: Uuid) -> Value {
+    json!({
+        "type": "register",
+        "app_id": app_id,
+        "parent_id": null,
+        "app_name": "message-storage",
+        "child_pub_key": pub_key(),
+        "process_info": {
+            "pid": std::process::id(), "ppid": 1, "uid": 0, "gid": 0,
+            "hostname": "test", "node_name": null, "pod_ip": null,
+            "namespace": null, "start_time": 1, "executable": null
+        },
+        "role_refs": [],
+        "sig": null
+    })
+}
+
+/// POST a batch to /ingest signed by `key` at `timestamp` (unix ms), as
+/// the app the headers name if `app_id` is set, else as the one the
+/// batch registers.
+async fn post_ingest(
+    http: &reqwest::Client,
+    app_id: Option<Uuid>,
+    batch: &[Value],
+    key: &SigningKey,
+    timestamp: i64,
+) -> reqwest::Response {
+    let body = serde_json::to_string(batch).unwrap();
+    let signer = app_id.unwrap_or_else(|| batch[0]["app_id"].as_str().unwrap().parse().unwrap());
+    let mut message = format!("trails-ingest:{signer}:{timestamp}:").into_bytes();
+    message.extend_from_slice(body.as_bytes());
+    let sig = base64::engine::general_purpose::STANDARD.encode(key.sign(&message).to_bytes());
+    let mut req = http
+        .post(format!("{}/api/v1/ingest", server()))
+        .header("content-type", "application/json")
+        .header("x-trails-timestamp", timestamp.to_string())
+        .header("x-trails-signature", format!("ed25519:{sig}"))
+        .body(body);
+    if let Some(app_id) = app_id {
+        req = req.header("x-trails-app-id", app_id.to_string());
+    }
+    req.send().await.unwrap()
+}
+
+/// POST a batch to /ingest signed with the app key, now.
+async fn ingest(http: &reqwest::Client, app_id: Option<Uuid>, batch: &[Value]) -> Value {
+    let now = chrono::Utc::now().timestamp_millis();
+    let resp = post_ingest(http, app_id, batch, &app_key(), now).await;
+    assert!(resp.status().is_success(), "POST /ingest: {}", resp.status());
+    resp.json().await.unwrap()
+}
+
+async fn get(http: &reqwest::Client, path: &str) -> Value {
+    let resp = http.get(format!("{}/api/v1{path}", server())).send().await.unwrap();
+    assert!(resp.status().is_success(), "GET {path}: {}", resp.status());
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_custom_msg_type_is_stored() {
+    let http = reqwest::Client::new();
+    let (app_id, mut ws) = registered().await;
+
+    let payload = json!({"offset": 81920});
+    let ack = send(&mut ws, message(app_id, "Checkpoint", 1, payload.clone())).await;
+    assert_eq!(ack["type"], "ack", "{ack}");
+    assert_eq!(ack["seq"], 1, "{ack}");
+
+    let stored = get(&http, &format!("/apps/{app_id}/messages?msg_type=Checkpoint")).await;
+    assert_eq!(stored.as_array().map(Vec::len), Some(1), "{stored}");
+    assert_eq!(stored[0]["msgType"], "Checkpoint", "{stored}");
+    assert_eq!(stored[0]["payload"], payload, "{stored}");
+    // Not progress: the app hasn't moved to running.
+    let app = get(&http, &format!("/apps/{app_id}")).await;
+    assert_eq!(app["status"], "connected", "{app}");
+    let _ = ws.close(None).await;
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_metric_message_is_stored_and_recorded() {
+    let http = reqwest::Client::new();
+    let (app_id, mut ws) = registered().await;
+
+    let payload = json!({
+        "gauges": {"rows_per_sec": 1234.5, "label": "fast"},
+        "counters": {"bytes_read": 4096}
+    });
+    let ack = send(&mut ws, message(app_id, "Metric", 1, payload.clone())).await;
+    assert_eq!(ack["type"], "ack", "{ack}");
+
+    let stored = get(&http, &format!("/apps/{app_id}/messages?msg_type=Metric")).await;
+    assert_eq!(stored.as_array().map(Vec::len), Some(1), "{stored}");
+    assert_eq!(stored[0]["payload"], payload, "{stored}");
+
+    let metrics = get(&http, &format!("/apps/{app_id}/metrics")).await;
+    let names: Vec<_> = metrics["series"]
+        .as_array()
+        .expect("series")
+        .iter()
+        .map(|s| s["metric"].as_str().unwrap_or_default().to_owned())
+        .collect();
+    assert_eq!(names, ["bytes_read", "rows_per_sec"], "{metrics}");
+    let _ = ws.close(None).await;
+}
+
+/// Post the same batch eight times at once, as a client retrying over
+/// connections it thinks dead; every frame is acked, once or as a
+/// duplicate.
+async fn resend_at_once(http: &reqwest::Client, app_id: Uuid, batch: &[Value]) {
+    let posts = (0..8).map(|_| ingest(http, Some(app_id), batch));
+    for resp in futures::future::join_all(posts).await {
+        for result in resp["results"].as_array().expect("results") {
+            assert!(
+                matches!(result["status"].as_str(), Some("ack" | "duplicate")),
+                "{resp}"
+            );
+        }
+    }
+}
+
+/// The seqs of a list of stored rows, in order.
+fn seqs(rows: &Value) -> Vec<i64> {
+    rows.as_array()
+        .expect("rows")
+        .iter()
+        .map(|row| row["seq"].as_i64().expect("seq"))
+        .collect()
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_ingest_needs_the_app_key() {
+    let http = reqwest::Client::new();
+    let app_id = Uuid::new_v4();
+    let now = chrono::Utc::now().timestamp_millis();
+    let other = SigningKey::from_bytes(&[8; 32]);
+
+    // Registering needs the key being registered...
+    let resp = post_ingest(&http, None, &[register_frame(app_id)], &other, now).await;
+    assert_eq!(resp.status(), 401);
+    ingest(&http, None, &[register_frame(app_id)]).await;
+
+    // ...and later posts the registered one, signed recently.
+    let batch = [message(app_id, "Status", 1, json!({"step": 1}))];
+    let resp = post_ingest(&http, Some(app_id), &batch, &other, now).await;
+    assert_eq!(resp.status(), 401);
+    let stale = now - 10 * 60 * 1000;
+    let resp = post_ingest(&http, Some(app_id), &batch, &app_key(), stale).await;
+    assert_eq!(resp.status(), 401);
+    let resp = http
+        .post(format!("{}/api/v1/ingest", server()))
+        .header("x-trails-app-id", app_id.to_string())
+        .json(&batch)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+
+    let stored = get(&http, &format!("/apps/{app_id}/messages")).await;
+    assert_eq!(stored.as_array().map(Vec::len), Some(0), "{stored}");
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_concurrent_resends_store_each_seq_once() {
+    let http = reqwest::Client::new();
+    let app_id = Uuid::new_v4();
+    let registered = ingest(&http, None, &[register_frame(app_id)]).await;
+    assert_eq!(registered["results"][0]["status"], "registered", "{registered}");
+
+    let batch: Vec<_> = (1..=20)
+        .map(|seq| message(app_id, "Status", seq, json!({"seq": seq})))
+        .collect();
+    resend_at_once(&http, app_id, &batch).await;
+
+    let stored = get(&http, &format!("/apps/{app_id}/messages?limit=100")).await;
+    assert_eq!(seqs(&stored), (1..=20).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+#[ignore = "needs a running trailsd"]
+async fn test_concurrent_resends_store_each_log_line_once() {
+    let http = reqwest::Client::new();
+    let app_id = Uuid::new_v4();
+    let registered = ingest(&http, None, &[register_frame(app_id)]).await;
+    assert_eq!(registered["results"][0]["status"], "registered", "{registered}");
+
+    let batch: Vec<_> = (1..=20)
+        .map(|seq| {
+            let line = json!({"level": "info", "message": format!("line {seq}")});
+            message(app_id, "Log", seq, line)
+        })
+        .collect();
+    resend_at_once(&http, app_id, &batch).await;
+
+    let logs = get(&http, &format!("/apps/{app_id}/logs?limit=100")).await;
+    let mut logged = seqs(&logs);
+    logged.sort_unstable();
+    assert_eq!(logged, (1..=20).collect::<Vec<_>>());
+}
@@ -0,0 +1,208 @@
+//! REST and feed access to trailsd, with the pagination loops.
+
+use std::time::Duration;
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use trails_client::api::{App, LatestPage, LatestSnapshot};
+use uuid::Uuid;
+
+use crate::error::CliError;
+use crate::settings::Settings;
+
+/// Largest page the server returns (`MAX_PAGE_LIMIT`).
+const MAX_PAGE: usize = 1000;
+
+const ACTOR_HEADER: &str = "x-trails-actor";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whole-transfer limit for streamed downloads; a stalled one fails on
+/// the read timeout long before.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(24 * 3600);
+
+pub struct Api {
+    http: reqwest::Client,
+    settings: Settings,
+}
+
+impl Api {
+    pub fn new(settings: Settings) -> Result<Self, CliError> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .read_timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("trailsctl/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| CliError::Other(e.to_string()))?;
+        Ok(Self { http, settings })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/api/v1{path}", self.settings.server);
+        let mut req = self.http.request(method, url);
+        if let Some(key) = &self.settings.api_key {
+            req = req.bearer_auth(key);
+        }
+        if let Some(actor) = &self.settings.actor {
+            req = req.header(ACTOR_HEADER, actor);
+        }
+        req
+    }
+
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, CliError> {
+        read(self.request(Method::GET, path).query(query).send().await).await
+    }
+
+    pub async fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, CliError> {
+        read(self.request(Method::POST, path).json(body).send().await).await
+    }
+
+    /// Start a streamed download; the body is read by the caller.
+    pub async fn stream(&self, path: &str, query: &[(&str, String)]) -> Result<Response, CliError> {
+        let req = self
+            .request(Method::GET, path)
+            .query(query)
+            .timeout(STREAM_TIMEOUT);
+        check(req.send().await).await
+    }
+
+    /// Every app of `path` (`/apps` or `/apps/{id}/children`) matching
+    /// `query`, up to `limit`, following the `after` cursor.
+    pub async fn list_apps(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        limit: Option<usize>,
+    ) -> Result<Vec<App>, CliError> {
+        let mut apps: Vec<App> = Vec::new();
+        loop {
+            let page = limit.map_or(MAX_PAGE, |l| (l - apps.len()).min(MAX_PAGE));
+            let mut q = query.to_vec();
+            q.push(("limit", page.to_string()));
+            if let Some(last) = apps.last() {
+                q.push(("after", last.app_id.to_string()));
+            }
+            let batch: Vec<App> = self.get(path, &q).await?;
+            let done = batch.len() < page;
+            apps.extend(batch);
+            if done || limit.is_some_and(|l| apps.len() >= l) {
+                return Ok(apps);
+            }
+        }
+    }
+
+    /// Latest snapshots matching `query`, up to `limit`, following
+    /// `nextAfter`.
+    pub async fn latest_snapshots(
+        &self,
+        query: &[(&str, String)],
+        limit: Option<usize>,
+    ) -> Result<Vec<LatestSnapshot>, CliError> {
+        let mut apps = Vec::new();
+        let mut after = None;
+        loop {
+            let page = limit.map_or(MAX_PAGE, |l| (l - apps.len()).min(MAX_PAGE));
+            let mut q = query.to_vec();
+            q.push(("limit", page.to_string()));
+            if let Some(after) = after {
+                q.push(("after", Uuid::to_string(&after)));
+            }
+            let batch: LatestPage = self.get("/snapshots/latest", &q).await?;
+            apps.extend(batch.apps);
+            after = batch.next_after;
+            if after.is_none() || limit.is_some_and(|l| apps.len() >= l) {
+                return Ok(apps);
+            }
+        }
+    }
+
+    /// Open the app's live feed (`/ws/apps/{id}/feed`).
+    pub async fn feed(
+        &self,
+        app_id: Uuid,
+        since_seq: Option<i64>,
+    ) -> Result<crate::tail::Socket, CliError> {
+        let base = self
+            .settings
+            .server
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+        let mut url = format!("{base}/ws/apps/{app_id}/feed");
+        if let Some(seq) = since_seq {
+            url.push_str(&format!("?since_seq={seq}"));
+        }
+        let mut req = url
+            .into_client_request()
+            .map_err(|e| CliError::Usage(format!("feed URL: {e}")))?;
+        if let Some(key) = &self.settings.api_key {
+            let value = HeaderValue::from_str(&format!("Bearer {key}"))
+                .map_err(|_| CliError::Usage("API key is not a valid header value".into()))?;
+            req.headers_mut().insert("authorization", value);
+        }
+        match tokio_tungstenite::connect_async(req).await {
+            Ok((socket, _)) => Ok(socket),
+            Err(tokio_tungstenite::tungstenite::Error::Http(resp)) => {
+                let body = resp
+                    .body()
+                    .as_deref()
+                    .map(|b| String::from_utf8_lossy(b).into_owned())
+                    .unwrap_or_default();
+                Err(status_error(resp.status().as_u16(), body))
+            }
+            Err(e) => Err(CliError::Server(format!("cannot open feed: {e}"))),
+        }
+    }
+}
+
+/// Decode a response, mapping failures to exit-code classes. The
+/// server's `x-request-id` is quoted so a failure can be found in its log.
+async fn read<T: DeserializeOwned>(sent: reqwest::Result<Response>) -> Result<T, CliError> {
+    check(sent)
+        .await?
+        .json()
+        .await
+        .map_err(|e| CliError::Other(format!("unreadable response: {e}")))
+}
+
+/// The response if it succeeded, else its error.
+async fn check(sent: reqwest::Result<Response>) -> Result<Response, CliError> {
+    let resp = sent.map_err(|e| CliError::Server(format!("cannot reach server: {e}")))?;
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let request_id = resp
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    let mut body = resp.text().await.unwrap_or_default();
+    if let Some(id) = request_id {
+        body.push_str(&format!(" (request id {id})"));
+    }
+    Err(status_error(status.as_u16(), body))
+}
+
+fn status_error(status: u16, body: String) -> CliError {
+    let message = match StatusCode::from_u16(status) {
+        Ok(code) => format!("{code}: {body}"),
+        Err(_) => format!("{status}: {body}"),
+    };
+    match status {
+        404 => CliError::NotFound(message),
+        409 => CliError::Conflict(message),
+        400..=499 => CliError::Rejected(message),
+        _ => CliError::Server(message),
+    }
+}
@@ -0,0 +1,478 @@
+//! Subcommand implementations.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use trails_client::api::{
+    AdoptChildrenRequest, AdoptChildrenResponse, AdoptRequest, App, BatchResponse, CancelRequest,
+    CancelResponse, ChildRequest, ExportTrailer, ForceRequest, PendingControl, StorageConsumer,
+    VersionInfo,
+};
+use uuid::Uuid;
+
+use crate::client::Api;
+use crate::error::CliError;
+use crate::output::{self, opt, Format, Table};
+
+/// `apps list` / `apps tree` filters, as query parameters.
+#[derive(Debug, Default)]
+pub struct AppFilter {
+    pub namespace: Option<String>,
+    pub status: Option<String>,
+    pub min_priority: Option<i16>,
+    pub stalled: bool,
+    pub sort: Option<String>,
+}
+
+impl AppFilter {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut q = Vec::new();
+        if let Some(ns) = &self.namespace {
+            q.push(("namespace", ns.clone()));
+        }
+        if let Some(status) = &self.status {
+            q.push(("status", status.clone()));
+        }
+        if let Some(p) = self.min_priority {
+            q.push(("min_priority", p.to_string()));
+        }
+        if self.stalled {
+            q.push(("stalled", "true".into()));
+        }
+        if let Some(sort) = &self.sort {
+            q.push(("sort", sort.clone()));
+        }
+        q
+    }
+}
+
+pub async fn apps_list(
+    api: &Api,
+    filter: &AppFilter,
+    limit: Option<usize>,
+    format: Format,
+) -> Result<(), CliError> {
+    let apps = api.list_apps("/apps", &filter.query(), limit).await?;
+    match format {
+        Format::Json => output::json(&apps),
+        Format::Table => apps_table(&apps),
+    }
+}
+
+fn apps_table(apps: &[App]) -> Result<(), CliError> {
+    let mut table = Table::new(&[
+        "APP_ID",
+        "NAME",
+        "STATUS",
+        "NAMESPACE",
+        "PRIORITY",
+        "CREATED",
+    ]);
+    for app in apps {
+        let status = if app.paused {
+            format!("{} (paused)", app.status)
+        } else if app.stalled_at.is_some() && app.status == "running" {
+            format!("{} (stalled)", app.status)
+        } else if app.blocked_at.is_some() && app.status == "scheduled" {
+            format!("{} (waiting)", app.status)
+        } else {
+            app.status.clone()
+        };
+        table.row(vec![
+            app.app_id.to_string(),
+            app.app_name.clone(),
+            status,
+            opt(app.namespace.as_deref()),
+            app.priority.to_string(),
+            app.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ]);
+    }
+    table.print()
+}
+
+pub async fn apps_get(api: &Api, app_id: Uuid, format: Format) -> Result<(), CliError> {
+    let app: App = api.get(&format!("/apps/{app_id}"), &[]).await?;
+    match format {
+        Format::Json => output::json(&app),
+        Format::Table => fields_table(&app),
+    }
+}
+
+/// `FIELD VALUE` rows of an object; nested values as compact JSON.
+fn fields_table<T: Serialize>(value: &T) -> Result<(), CliError> {
+    let JsonValue::Object(fields) = serde_json::to_value(value)? else {
+        return output::json(value);
+    };
+    let mut table = Table::new(&["FIELD", "VALUE"]);
+    for (key, value) in fields {
+        let value = match value {
+            JsonValue::Null => "-".into(),
+            JsonValue::String(s) => s,
+            other => other.to_string(),
+        };
+        table.row(vec![key, value]);
+    }
+    table.print()
+}
+
+/// An app with its descendants.
+#[derive(Debug, Serialize)]
+struct TreeNode {
+    #[serde(flatten)]
+    app: App,
+    children: Vec<TreeNode>,
+}
+
+pub async fn apps_tree(
+    api: &Api,
+    root: Uuid,
+    filter: &AppFilter,
+    depth: Option<usize>,
+    format: Format,
+) -> Result<(), CliError> {
+    let app: App = api.get(&format!("/apps/{root}"), &[]).await?;
+    // Breadth-first, one listing per parent; nodes are assembled after.
+    let mut nodes = vec![(app, Vec::new())];
+    let mut queue = VecDeque::from([(0usize, 0usize)]);
+    while let Some((index, level)) = queue.pop_front() {
+        if depth.is_some_and(|d| level >= d) {
+            continue;
+        }
+        let parent = nodes[index].0.app_id;
+        let children = api
+            .list_apps(&format!("/apps/{parent}/children"), &filter.query(), None)
+            .await?;
+        for child in children {
+            nodes.push((child, Vec::new()));
+            let child_index = nodes.len() - 1;
+            nodes[index].1.push(child_index);
+            queue.push_back((child_index, level + 1));
+        }
+    }
+    let tree = assemble(&mut nodes, 0);
+    match format {
+        Format::Json => output::json(&tree),
+        Format::Table => {
+            let mut text = format!("{}\n", label(&tree.app));
+            render_children(&tree, "", &mut text);
+            io::stdout().lock().write_all(text.as_bytes())?;
+            Ok(())
+        }
+    }
+}
+
+fn assemble(nodes: &mut [(App, Vec<usize>)], index: usize) -> TreeNode {
+    let children = std::mem::take(&mut nodes[index].1);
+    TreeNode {
+        app: nodes[index].0.clone(),
+        children: children.into_iter().map(|i| assemble(nodes, i)).collect(),
+    }
+}
+
+/// `name (short id) [status]`, as in the spec's tree view, then the
+/// short ids of the siblings it depends on.
+fn label(app: &App) -> String {
+    let id = app.app_id.to_string();
+    let flag = if app.paused {
+        " paused"
+    } else if app.blocked_at.is_some() && app.status == "scheduled" {
+        " waiting"
+    } else {
+        ""
+    };
+    let mut label = format!("{} ({}) [{}{flag}]", app.app_name, &id[..8], app.status);
+    if let Some(deps) = app.depends_on.as_deref().filter(|d| !d.is_empty()) {
+        let short: Vec<String> = deps
+            .iter()
+            .map(|d| d.to_string()[..8].to_string())
+            .collect();
+        label.push_str(&format!(" after {}", short.join(", ")));
+    }
+    label
+}
+
+fn render_children(node: &TreeNode, prefix: &str, text: &mut String) {
+    let last = node.children.len().saturating_sub(1);
+    for (i, child) in node.children.iter().enumerate() {
+        let (branch, indent) = if i == last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        text.push_str(&format!("{prefix}{branch}{}\n", label(&child.app)));
+        render_children(child, &format!("{prefix}{indent}"), text);
+    }
+}
+
+pub async fn apps_cancel(
+    api: &Api,
+    app_id: Uuid,
+    reason: Option<String>,
+    format: Format,
+) -> Result<(), CliError> {
+    let body = CancelRequest { reason };
+    let resp: CancelResponse = api.post(&format!("/apps/{app_id}/cancel"), &body).await?;
+    match format {
+        Format::Json => output::json(&resp),
+        Format::Table => {
+            match (resp.delivery.as_str(), &resp.control) {
+                ("queued", Some(control)) => println!(
+                    "cancel queued for {app_id} until it reconnects (expires {})",
+                    control.expires_at.format("%Y-%m-%d %H:%M:%S")
+                ),
+                ("expired", _) => {
+                    println!("cancel not delivered: {app_id} is not connected to this server")
+                }
+                _ => println!("cancel sent to {app_id}"),
+            }
+            Ok(())
+        }
+    }
+}
+
+pub async fn apps_controls(api: &Api, app_id: Uuid, format: Format) -> Result<(), CliError> {
+    let controls: Vec<PendingControl> = api.get(&format!("/apps/{app_id}/controls"), &[]).await?;
+    match format {
+        Format::Json => output::json(&controls),
+        Format::Table => {
+            let mut table = Table::new(&["ID", "ACTION", "STATE", "QUEUED", "EXPIRES", "BY"]);
+            for control in &controls {
+                table.row(vec![
+                    control.id.to_string(),
+                    control.action.clone(),
+                    control.state.clone(),
+                    control.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    control.expires_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    opt(control.requested_by.as_deref()),
+                ]);
+            }
+            table.print()
+        }
+    }
+}
+
+pub async fn apps_force(
+    api: &Api,
+    app_id: Uuid,
+    status: Option<String>,
+    reason: Option<String>,
+    format: Format,
+) -> Result<(), CliError> {
+    let body = ForceRequest { status, reason };
+    let app: App = api.post(&format!("/apps/{app_id}/force"), &body).await?;
+    match format {
+        Format::Json => output::json(&app),
+        Format::Table => {
+            println!("{app_id} is now {}", app.status);
+            Ok(())
+        }
+    }
+}
+
+pub async fn apps_adopt(
+    api: &Api,
+    app_id: Uuid,
+    new_parent_id: Uuid,
+    format: Format,
+) -> Result<(), CliError> {
+    let body = AdoptRequest { new_parent_id };
+    let app: App = api.post(&format!("/apps/{app_id}/adopt"), &body).await?;
+    match format {
+        Format::Json => output::json(&app),
+        Format::Table => {
+            println!("{app_id} is now a child of {new_parent_id}");
+            Ok(())
+        }
+    }
+}
+
+pub async fn apps_adopt_children(
+    api: &Api,
+    old_parent_id: Uuid,
+    new_parent_id: Uuid,
+    format: Format,
+) -> Result<(), CliError> {
+    let body = AdoptChildrenRequest {
+        old_parent_id,
+        new_parent_id,
+    };
+    let resp: AdoptChildrenResponse = api.post("/apps/adopt", &body).await?;
+    match format {
+        Format::Json => output::json(&resp.adopted),
+        Format::Table => apps_table(&resp.adopted),
+    }
+}
+
+pub async fn snapshots_latest(
+    api: &Api,
+    query: Vec<(&'static str, String)>,
+    limit: Option<usize>,
+    format: Format,
+) -> Result<(), CliError> {
+    let apps = api.latest_snapshots(&query, limit).await?;
+    match format {
+        Format::Json => output::json(&apps),
+        Format::Table => {
+            let mut table =
+                Table::new(&["APP_ID", "NAME", "STATUS", "SEQ", "LAST_SEEN", "SNAPSHOT"]);
+            for app in &apps {
+                let snapshot = match &app.snapshot {
+                    _ if app.sealed => "<sealed>".into(),
+                    Some(s) => s.to_string(),
+                    None => "-".into(),
+                };
+                table.row(vec![
+                    app.app_id.to_string(),
+                    app.app_name.clone(),
+                    app.status.clone(),
+                    opt(app.seq),
+                    opt(app.last_seen.map(|t| t.format("%Y-%m-%d %H:%M:%S"))),
+                    snapshot,
+                ]);
+            }
+            table.print()
+        }
+    }
+}
+
+/// Every stored message of an app as NDJSON, oldest first, from the
+/// server's streamed export. Returns the number written; fails if the
+/// export ended without a complete trailer.
+pub async fn export(
+    api: &Api,
+    app_id: Uuid,
+    query: Vec<(&'static str, String)>,
+    out: Option<&Path>,
+) -> Result<u64, CliError> {
+    let mut writer: Box<dyn Write> = match out {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut resp = api
+        .stream(&format!("/apps/{app_id}/messages/export"), &query)
+        .await?;
+    let cut_short = |e: reqwest::Error| CliError::Server(format!("export cut short: {e}"));
+    let mut pending = Vec::new();
+    let mut trailer = None;
+    while let Some(chunk) = resp.chunk().await.map_err(cut_short)? {
+        pending.extend_from_slice(&chunk);
+        let Some(end) = pending.iter().rposition(|b| *b == b'\n') else {
+            continue;
+        };
+        for line in pending[..end].split(|b| *b == b'\n') {
+            if line.starts_with(br#"{"trailer":"#) {
+                trailer = Some(serde_json::from_slice::<ExportTrailer>(line)?);
+            } else if !line.is_empty() {
+                writer.write_all(line)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        pending.drain(..=end);
+    }
+    writer.flush()?;
+    match trailer {
+        Some(t) if t.complete => Ok(t.count),
+        Some(t) => Err(CliError::Server(format!(
+            "export cut short after {} messages: {}",
+            t.count,
+            t.error.unwrap_or_default()
+        ))),
+        None => Err(CliError::Server("export ended without its trailer".into())),
+    }
+}
+
+pub async fn children_create(
+    api: &Api,
+    child: ChildRequest,
+    format: Format,
+) -> Result<(), CliError> {
+    let app: App = api.post("/children", &child).await?;
+    match format {
+        Format::Json => output::json(&app),
+        Format::Table => {
+            println!("{}", app.app_id);
+            Ok(())
+        }
+    }
+}
+
+/// Create many children from an NDJSON file of `ChildRequest`s in one
+/// `children:batch` request.
+pub async fn children_batch(
+    api: &Api,
+    children: Vec<ChildRequest>,
+    atomic: bool,
+    format: Format,
+) -> Result<BatchResponse, CliError> {
+    let body = serde_json::json!({ "children": children, "atomic": atomic });
+    let resp: BatchResponse = api.post("/children:batch", &body).await?;
+    match format {
+        Format::Json => output::json(&resp)?,
+        Format::Table => {
+            let mut table = Table::new(&["INDEX", "APP_ID", "STATUS", "ERROR"]);
+            for item in &resp.results {
+                table.row(vec![
+                    item.index.to_string(),
+                    item.app_id.to_string(),
+                    item.status.clone(),
+                    opt(item.error.as_deref()),
+                ]);
+            }
+            table.print()?;
+        }
+    }
+    Ok(resp)
+}
+
+pub async fn stats(
+    api: &Api,
+    namespace: Option<String>,
+    limit: usize,
+    format: Format,
+) -> Result<(), CliError> {
+    let mut query = vec![("limit", limit.to_string())];
+    if let Some(ns) = namespace {
+        query.push(("namespace", ns));
+    }
+    let consumers: Vec<StorageConsumer> = api.get("/stats/storage", &query).await?;
+    match format {
+        Format::Json => output::json(&consumers),
+        Format::Table => {
+            let mut table =
+                Table::new(&["APP_ID", "NAME", "STATUS", "STORED_BYTES", "SOFT", "HARD"]);
+            for c in &consumers {
+                table.row(vec![
+                    c.app_id.to_string(),
+                    c.app_name.clone(),
+                    c.status.clone(),
+                    c.stored_bytes.to_string(),
+                    opt(c.effective_storage_quota.soft_bytes),
+                    opt(c.effective_storage_quota.hard_bytes),
+                ]);
+            }
+            table.print()
+        }
+    }
+}
+
+pub async fn version(api: &Api, format: Format) -> Result<(), CliError> {
+    let server: VersionInfo = api.get("/version", &[]).await?;
+    match format {
+        Format::Json => output::json(&serde_json::json!({
+            "trailsctl": env!("CARGO_PKG_VERSION"),
+            "server": server,
+        })),
+        Format::Table => {
+            println!("trailsctl {}", env!("CARGO_PKG_VERSION"));
+            println!(
+                "trailsd   {} (protocol {}-{})",
+                server.server_version, server.protocol.min, server.protocol.max
+            );
+            Ok(())
+        }
+    }
+}
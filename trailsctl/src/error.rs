@@ -0,0 +1,72 @@
+//! Errors and the exit codes they map to.
+
+use std::fmt;
+
+/// A failed command. The variant decides the exit code, so scripts can
+/// tell "no such app" from "server down" without parsing messages.
+#[derive(Debug)]
+pub enum CliError {
+    /// Bad arguments or settings.
+    Usage(String),
+    /// 404: the app (or other target) doesn't exist.
+    NotFound(String),
+    /// 409: the target's state doesn't allow the request.
+    Conflict(String),
+    /// Any other 4xx.
+    Rejected(String),
+    /// 5xx, or the server could not be reached.
+    Server(String),
+    /// Anything else: unreadable responses, local I/O.
+    Other(String),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Other(_) => 1,
+            Self::Usage(_) => 2,
+            Self::NotFound(_) => 3,
+            Self::Conflict(_) => 4,
+            Self::Rejected(_) => 5,
+            Self::Server(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage(e)
+            | Self::NotFound(e)
+            | Self::Conflict(e)
+            | Self::Rejected(e)
+            | Self::Server(e)
+            | Self::Other(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<std::io::Error> for CliError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Other(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Other(format!("serialize error: {e}"))
+    }
+}
+
+/// Exit codes, for `--help`.
+pub const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  1  unexpected error (unreadable response, local I/O)
+  2  usage error (bad arguments, missing settings)
+  3  not found
+  4  conflict: the app's state doesn't allow it
+  5  request rejected by the server (other 4xx)
+  6  server error or server unreachable";
@@ -0,0 +1,456 @@
+//! trailsctl — admin CLI for a TRAILS server.
+//!
+//! Wraps the REST API (`/api/v1`) and the per-app live feed: listings
+//! page through the server's cursors on their own, admin requests carry
+//! the `x-trails-actor` header, and failures exit with a code per error
+//! class (see `--help`).
+
+mod client;
+mod commands;
+mod error;
+mod output;
+mod settings;
+mod tail;
+
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use serde_json::Value as JsonValue;
+use trails_client::api::ChildRequest;
+use uuid::Uuid;
+
+use crate::client::Api;
+use crate::commands::AppFilter;
+use crate::error::{CliError, EXIT_CODES};
+use crate::output::Format;
+use crate::settings::{Given, Settings};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "trailsctl",
+    version,
+    about = "Query and control a TRAILS server"
+)]
+#[command(after_help = EXIT_CODES)]
+struct Cli {
+    /// Server base URL [default: http://127.0.0.1:8443].
+    #[arg(long, env = "TRAILS_SERVER", global = true)]
+    server: Option<String>,
+    /// Bearer token for deployments behind an authenticating proxy.
+    #[arg(long, env = "TRAILS_API_KEY", global = true, hide_env_values = true)]
+    api_key: Option<String>,
+    /// Name recorded in the audit log for admin requests [default: $USER].
+    #[arg(long, env = "TRAILS_ACTOR", global = true)]
+    actor: Option<String>,
+    /// Settings file in env format [default: ~/.config/trailsctl/config].
+    #[arg(long, env = "TRAILSCTL_CONFIG", global = true)]
+    config: Option<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = Format::Table, global = true)]
+    output: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List, inspect and control apps.
+    #[command(subcommand)]
+    Apps(AppsCommand),
+    /// Stored messages.
+    #[command(subcommand)]
+    Messages(MessagesCommand),
+    /// Latest snapshots.
+    #[command(subcommand)]
+    Snapshots(SnapshotsCommand),
+    /// Write every stored message of an app as NDJSON, oldest first.
+    Export {
+        app_id: Uuid,
+        /// Only this message type (e.g. Status, Result).
+        #[arg(long)]
+        msg_type: Option<String>,
+        /// Only messages stored at or after this time (RFC 3339).
+        #[arg(long)]
+        from: Option<DateTime<Utc>>,
+        /// Only messages stored before this time (RFC 3339).
+        #[arg(long)]
+        to: Option<DateTime<Utc>>,
+        /// Inline offloaded payloads from the blob store.
+        #[arg(long)]
+        resolve: bool,
+        /// Write to this file instead of stdout.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Pre-register children.
+    #[command(subcommand)]
+    Children(ChildrenCommand),
+    /// Apps storing the most data.
+    Stats {
+        #[arg(long)]
+        namespace: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Client and server versions.
+    Version,
+}
+
+#[derive(Debug, Args)]
+struct FilterArgs {
+    #[arg(long)]
+    namespace: Option<String>,
+    #[arg(long)]
+    status: Option<String>,
+    #[arg(long)]
+    min_priority: Option<i16>,
+    /// Only running apps flagged stalled (no Status progress).
+    #[arg(long)]
+    stalled: bool,
+}
+
+impl FilterArgs {
+    fn into_filter(self, sort: Option<String>) -> AppFilter {
+        AppFilter {
+            namespace: self.namespace,
+            status: self.status,
+            min_priority: self.min_priority,
+            stalled: self.stalled,
+            sort,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum AppsCommand {
+    /// Apps, newest first.
+    List {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// `created` (default) or `priority`.
+        #[arg(long)]
+        sort: Option<String>,
+        /// Most apps to list.
+        #[arg(long, default_value_t = 100, conflicts_with = "all")]
+        limit: usize,
+        /// List every matching app.
+        #[arg(long)]
+        all: bool,
+    },
+    /// One app in full.
+    Get { app_id: Uuid },
+    /// An app and its descendants.
+    Tree {
+        app_id: Uuid,
+        /// Filters apply to descendants, not the root.
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Levels below the root to show.
+        #[arg(long)]
+        depth: Option<usize>,
+    },
+    /// Ask an app to stop; queued until it reconnects if it isn't connected.
+    Cancel {
+        app_id: Uuid,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Controls queued for an app while it wasn't connected.
+    Controls { app_id: Uuid },
+    /// Move a stuck app straight to a terminal status.
+    Force {
+        app_id: Uuid,
+        /// Terminal status to set [default: cancelled].
+        #[arg(long)]
+        status: Option<String>,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Move an app, or every live child of `--from`, under a new parent.
+    Adopt {
+        #[arg(required_unless_present = "from")]
+        app_id: Option<Uuid>,
+        /// Current parent whose non-terminal children move.
+        #[arg(long, conflicts_with = "app_id")]
+        from: Option<Uuid>,
+        /// New parent.
+        #[arg(long)]
+        to: Uuid,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum MessagesCommand {
+    /// Print an app's messages as they arrive, until it ends.
+    Tail {
+        app_id: Uuid,
+        /// Start after this seq [default: only new messages].
+        #[arg(long)]
+        since_seq: Option<i64>,
+        /// Stop after the stored messages instead of following.
+        #[arg(long)]
+        no_follow: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SnapshotsCommand {
+    /// Latest snapshot of every matching app.
+    Latest {
+        /// Comma-separated statuses [server default: connected,running].
+        #[arg(long)]
+        status: Option<String>,
+        #[arg(long)]
+        namespace: Option<String>,
+        /// Comma-separated JSON Pointers to keep, e.g. /progress,/phase.
+        #[arg(long)]
+        fields: Option<String>,
+        /// Most apps to list [default: all].
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ChildrenCommand {
+    /// Pre-register one child, or a batch from an NDJSON file; prints
+    /// the new app ids.
+    Create {
+        /// App name of the child.
+        #[arg(long, required_unless_present = "from_file")]
+        name: Option<String>,
+        #[arg(long)]
+        parent: Option<Uuid>,
+        /// App id to use [default: a new random id].
+        #[arg(long)]
+        app_id: Option<Uuid>,
+        /// Seconds after launch the child must connect within.
+        #[arg(long)]
+        start_deadline: Option<i32>,
+        #[arg(long)]
+        max_runtime_secs: Option<i32>,
+        #[arg(long)]
+        priority: Option<i16>,
+        /// Intended launch time (RFC 3339).
+        #[arg(long)]
+        scheduled_at: Option<DateTime<Utc>>,
+        /// `open`, `signed`, `full` or `sealed`.
+        #[arg(long)]
+        sec_level: Option<String>,
+        #[arg(long)]
+        correlation_id: Option<String>,
+        /// Role reference; repeatable.
+        #[arg(long = "role")]
+        roles: Vec<String>,
+        /// Sibling that must be done first; repeatable. The child waits
+        /// without a start deadline until then.
+        #[arg(long = "depends-on")]
+        depends_on: Vec<Uuid>,
+        /// Tag as KEY=VALUE; repeatable.
+        #[arg(long = "tag", value_parser = parse_tag)]
+        tags: Vec<(String, String)>,
+        /// NDJSON file of children (`POST /children` bodies), `-` for
+        /// stdin; created in one batch.
+        #[arg(long, conflicts_with = "name")]
+        from_file: Option<PathBuf>,
+        /// With --from-file: create all or none.
+        #[arg(long, requires = "from_file")]
+        atomic: bool,
+    },
+}
+
+fn parse_tag(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{s}'"))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("trailsctl: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), CliError> {
+    let settings = Settings::resolve(Given {
+        server: cli.server,
+        api_key: cli.api_key,
+        actor: cli.actor,
+        config: cli.config,
+    })?;
+    let api = Api::new(settings)?;
+    let format = cli.output;
+
+    match cli.command {
+        Command::Apps(cmd) => match cmd {
+            AppsCommand::List {
+                filter,
+                sort,
+                limit,
+                all,
+            } => {
+                let limit = (!all).then_some(limit);
+                commands::apps_list(&api, &filter.into_filter(sort), limit, format).await
+            }
+            AppsCommand::Get { app_id } => commands::apps_get(&api, app_id, format).await,
+            AppsCommand::Tree {
+                app_id,
+                filter,
+                depth,
+            } => commands::apps_tree(&api, app_id, &filter.into_filter(None), depth, format).await,
+            AppsCommand::Cancel { app_id, reason } => {
+                commands::apps_cancel(&api, app_id, reason, format).await
+            }
+            AppsCommand::Controls { app_id } => commands::apps_controls(&api, app_id, format).await,
+            AppsCommand::Force {
+                app_id,
+                status,
+                reason,
+            } => commands::apps_force(&api, app_id, status, reason, format).await,
+            AppsCommand::Adopt { app_id, from, to } => match (app_id, from) {
+                (Some(app_id), _) => commands::apps_adopt(&api, app_id, to, format).await,
+                (None, Some(from)) => commands::apps_adopt_children(&api, from, to, format).await,
+                (None, None) => unreachable!("clap requires an app id or --from"),
+            },
+        },
+        Command::Messages(MessagesCommand::Tail {
+            app_id,
+            since_seq,
+            no_follow,
+        }) => tail::run(&api, app_id, since_seq, !no_follow, format).await,
+        Command::Snapshots(SnapshotsCommand::Latest {
+            status,
+            namespace,
+            fields,
+            limit,
+        }) => {
+            let query = [
+                ("status", status),
+                ("namespace", namespace),
+                ("fields", fields),
+            ]
+            .into_iter()
+            .filter_map(|(k, v)| v.map(|v| (k, v)))
+            .collect();
+            commands::snapshots_latest(&api, query, limit, format).await
+        }
+        Command::Export {
+            app_id,
+            msg_type,
+            from,
+            to,
+            resolve,
+            out,
+        } => {
+            let mut query = Vec::new();
+            if let Some(t) = msg_type {
+                query.push(("msg_type", t));
+            }
+            if let Some(from) = from {
+                query.push(("from", from.to_rfc3339()));
+            }
+            if let Some(to) = to {
+                query.push(("to", to.to_rfc3339()));
+            }
+            if resolve {
+                query.push(("resolve", "true".into()));
+            }
+            let count = commands::export(&api, app_id, query, out.as_deref()).await?;
+            eprintln!("exported {count} messages");
+            Ok(())
+        }
+        Command::Children(ChildrenCommand::Create {
+            name,
+            parent,
+            app_id,
+            start_deadline,
+            max_runtime_secs,
+            priority,
+            scheduled_at,
+            sec_level,
+            correlation_id,
+            roles,
+            depends_on,
+            tags,
+            from_file,
+            atomic,
+        }) => {
+            if let Some(path) = from_file {
+                let children = read_children(&path)?;
+                let resp = commands::children_batch(&api, children, atomic, format).await?;
+                if !resp.committed || resp.failed > 0 {
+                    return Err(CliError::Rejected(format!(
+                        "{} of {} children failed{}",
+                        resp.failed,
+                        resp.results.len(),
+                        if resp.committed {
+                            ""
+                        } else {
+                            "; nothing was created"
+                        }
+                    )));
+                }
+                return Ok(());
+            }
+            let tags = (!tags.is_empty()).then(|| {
+                JsonValue::Object(
+                    tags.into_iter()
+                        .map(|(k, v)| (k, JsonValue::String(v)))
+                        .collect(),
+                )
+            });
+            let child = ChildRequest {
+                app_id: app_id.unwrap_or_else(Uuid::new_v4),
+                parent_id: parent,
+                app_name: name.unwrap_or_default(),
+                start_deadline,
+                role_refs: roles,
+                tags,
+                max_runtime_secs,
+                priority,
+                scheduled_at,
+                sec_level,
+                correlation_id,
+                depends_on,
+            };
+            commands::children_create(&api, child, format).await
+        }
+        Command::Stats { namespace, limit } => {
+            commands::stats(&api, namespace, limit, format).await
+        }
+        Command::Version => commands::version(&api, format).await,
+    }
+}
+
+/// `ChildRequest`s, one JSON object per line; blank lines are skipped.
+fn read_children(path: &std::path::Path) -> Result<Vec<ChildRequest>, CliError> {
+    let reader: Box<dyn BufRead> = if path.as_os_str() == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = std::fs::File::open(path)
+            .map_err(|e| CliError::Usage(format!("{}: {e}", path.display())))?;
+        Box::new(std::io::BufReader::new(file))
+    };
+    let mut children = Vec::new();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let child = serde_json::from_str(&line)
+            .map_err(|e| CliError::Usage(format!("{} line {}: {e}", path.display(), n + 1)))?;
+        children.push(child);
+    }
+    if children.is_empty() {
+        return Err(CliError::Usage(format!("{}: no children", path.display())));
+    }
+    Ok(children)
+}
@@ -0,0 +1,95 @@
+//! Connection settings: flags, then the environment, then a config file.
+//!
+//! The config file uses the env-file format of the server's `CONFIG_FILE`
+//! and the same variable names as the environment:
+//!
+//! ```text
+//! TRAILS_SERVER=https://trails.internal:8443
+//! TRAILS_API_KEY=...
+//! TRAILS_ACTOR=alice
+//! ```
+//!
+//! It is `--config`/`TRAILSCTL_CONFIG`, else `trailsctl/config` under
+//! `$XDG_CONFIG_HOME` (default `~/.config`); only an explicitly named file
+//! has to exist.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use crate::error::CliError;
+
+pub const DEFAULT_SERVER: &str = "http://127.0.0.1:8443";
+
+/// Resolved settings for one run.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Base URL, without `/api/v1`.
+    pub server: String,
+    /// Sent as `Authorization: Bearer`, for deployments behind an
+    /// authenticating proxy.
+    pub api_key: Option<String>,
+    /// Sent as `x-trails-actor` on audited admin requests.
+    pub actor: Option<String>,
+}
+
+/// Values given as flags or environment variables (clap reads both).
+#[derive(Debug, Default)]
+pub struct Given {
+    pub server: Option<String>,
+    pub api_key: Option<String>,
+    pub actor: Option<String>,
+    pub config: Option<PathBuf>,
+}
+
+impl Settings {
+    pub fn resolve(given: Given) -> Result<Self, CliError> {
+        let file = read_file(given.config)?;
+        let from_file = |key: &str| file.get(key).filter(|v| !v.is_empty()).cloned();
+        let server = given
+            .server
+            .or_else(|| from_file("TRAILS_SERVER"))
+            .unwrap_or_else(|| DEFAULT_SERVER.into());
+        if !server.starts_with("http://") && !server.starts_with("https://") {
+            return Err(CliError::Usage(format!(
+                "server URL must be http(s)://, got '{server}'"
+            )));
+        }
+        Ok(Self {
+            server: server.trim_end_matches('/').to_string(),
+            api_key: given.api_key.or_else(|| from_file("TRAILS_API_KEY")),
+            actor: given
+                .actor
+                .or_else(|| from_file("TRAILS_ACTOR"))
+                .or_else(|| env::var("USER").ok())
+                .or_else(|| env::var("LOGNAME").ok()),
+        })
+    }
+}
+
+fn read_file(explicit: Option<PathBuf>) -> Result<HashMap<String, String>, CliError> {
+    let (path, required) = match explicit {
+        Some(path) => (path, true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(HashMap::new()),
+        },
+    };
+    if !required && !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let iter = dotenvy::from_path_iter(&path)
+        .map_err(|e| CliError::Usage(format!("config file {}: {e}", path.display())))?;
+    iter.map(|item| {
+        item.map_err(|e| CliError::Usage(format!("config file {}: {e}", path.display())))
+    })
+    .collect()
+}
+
+fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("trailsctl").join("config"))
+}
@@ -0,0 +1,106 @@
+//! `messages tail`: print an app's messages from its live feed.
+//!
+//! The feed backfills from `--since-seq` (from the current end when
+//! omitted), then streams. Lifecycle events are noted on stderr; the tail
+//! ends once the app is terminal, or at the end of the backfill with
+//! `--no-follow`.
+
+use futures::StreamExt;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use trails_client::api::{App, FeedFrame, Message};
+use uuid::Uuid;
+
+use crate::client::Api;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+pub type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const TERMINAL: [&str; 6] = [
+    "done",
+    "error",
+    "crashed",
+    "cancelled",
+    "start_failed",
+    "timed_out",
+];
+
+pub async fn run(
+    api: &Api,
+    app_id: Uuid,
+    since_seq: Option<i64>,
+    follow: bool,
+    format: Format,
+) -> Result<(), CliError> {
+    let mut socket = api.feed(app_id, since_seq).await?;
+    let mut terminal = false;
+    while let Some(frame) = socket.next().await {
+        let text = match frame.map_err(|e| CliError::Server(format!("feed: {e}")))? {
+            Frame::Text(text) => text,
+            Frame::Close(_) => break,
+            _ => continue,
+        };
+        let frame: FeedFrame = serde_json::from_str(&text)
+            .map_err(|e| CliError::Other(format!("unreadable feed frame: {e}")))?;
+        match frame {
+            FeedFrame::Hello { app, .. } => {
+                note(&app);
+                terminal = is_terminal(&app);
+            }
+            FeedFrame::Message { message } => print(&message, format)?,
+            FeedFrame::Live { .. } if !follow || terminal => return Ok(()),
+            FeedFrame::Live { .. } => {}
+            FeedFrame::Event { event, app } => {
+                let name = event
+                    .get("event")
+                    .and_then(|e| e.as_str())
+                    .unwrap_or("event");
+                eprintln!("-- {name}");
+                match app {
+                    Some(app) if is_terminal(&app) => {
+                        note(&app);
+                        return Ok(());
+                    }
+                    Some(_) => {}
+                    // Purged: the feed ends here.
+                    None => return Ok(()),
+                }
+            }
+            FeedFrame::App { app } => terminal = is_terminal(&app),
+            FeedFrame::Error { code, message } => {
+                return Err(CliError::Server(format!("feed error {code}: {message}")))
+            }
+        }
+    }
+    Err(CliError::Server("feed closed by the server".into()))
+}
+
+fn is_terminal(app: &App) -> bool {
+    TERMINAL.contains(&app.status.as_str())
+}
+
+fn note(app: &App) {
+    eprintln!("-- {} ({}) [{}]", app.app_name, app.app_id, app.status);
+}
+
+/// One message: a line of text, or an NDJSON object.
+pub fn print(message: &Message, format: Format) -> Result<(), CliError> {
+    if format == Format::Json {
+        return output::json_line(message);
+    }
+    let payload = match (&message.payload, &message.blob) {
+        _ if message.sealed => "<sealed>".to_string(),
+        (Some(payload), _) => payload.to_string(),
+        (None, Some(blob)) => format!("<blob {} bytes> {}", blob.size, blob.preview),
+        (None, None) => "-".into(),
+    };
+    println!(
+        "{}  #{:<6} {:<8} {payload}",
+        message.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+        message.seq,
+        message.msg_type,
+    );
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! Runs the trailsctl binary. The live tests need a running trailsd and
+//! its Postgres, so they are ignored by default:
+//!
+//!     TRAILS_TEST_SERVER=http://127.0.0.1:8443 cargo test -- --ignored
+
+use std::process::{Command, Output};
+
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Nothing listens here; connections are refused at once.
+const DEAD_SERVER: &str = "http://127.0.0.1:1";
+
+fn server() -> String {
+    std::env::var("TRAILS_TEST_SERVER").unwrap_or_else(|_| "http://127.0.0.1:8443".into())
+}
+
+/// Run trailsctl with a clean settings environment.
+fn trailsctl(server: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_trailsctl"))
+        .args(args)
+        .env("TRAILS_SERVER", server)
+        .env("TRAILS_ACTOR", "trailsctl-test")
+        .env_remove("TRAILS_API_KEY")
+        .env("TRAILSCTL_CONFIG", "/dev/null")
+        .output()
+        .expect("run trailsctl")
+}
+
+fn code(out: &Output) -> i32 {
+    out.status.code().expect("exit code")
+}
+
+fn json(out: &Output) -> Value {
+    assert_eq!(
+        code(out),
+        0,
+        "stderr: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    serde_json::from_slice(&out.stdout).expect("json output")
+}
+
+#[test]
+fn test_usage_errors_exit_2() {
+    assert_eq!(code(&trailsctl(DEAD_SERVER, &["apps", "frobnicate"])), 2);
+    assert_eq!(
+        code(&trailsctl(DEAD_SERVER, &["apps", "get", "not-a-uuid"])),
+        2
+    );
+    assert_eq!(code(&trailsctl("ftp://example", &["apps", "list"])), 2);
+}
+
+#[test]
+fn test_unreachable_server_exits_6() {
+    let out = trailsctl(DEAD_SERVER, &["apps", "list"]);
+    assert_eq!(code(&out), 6);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cannot reach server"));
+}
+
+#[test]
+fn test_config_file_supplies_server() {
+    let path = std::env::temp_dir().join(format!("trailsctl-{}.conf", Uuid::new_v4()));
+    std::fs::write(&path, format!("TRAILS_SERVER={DEAD_SERVER}\n")).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_trailsctl"))
+        .args(["--config", path.to_str().unwrap(), "version"])
+        .env_remove("TRAILS_SERVER")
+        .output()
+        .expect("run trailsctl");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(code(&out), 6);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("127.0.0.1:1"));
+}
+
+#[test]
+#[ignore]
+fn test_children_tree_and_force() {
+    let server = server();
+    let root = json(&trailsctl(
+        &server,
+        &[
+            "-o",
+            "json",
+            "children",
+            "create",
+            "--name",
+            "trailsctl-root",
+        ],
+    ));
+    let root_id = root["appId"].as_str().unwrap().to_string();
+    for name in ["step-1", "step-2"] {
+        let out = trailsctl(
+            &server,
+            &["children", "create", "--name", name, "--parent", &root_id],
+        );
+        assert_eq!(code(&out), 0);
+    }
+
+    let tree = json(&trailsctl(
+        &server,
+        &["-o", "json", "apps", "tree", &root_id],
+    ));
+    let mut names: Vec<&str> = tree["children"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["appName"].as_str().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["step-1", "step-2"]);
+
+    let forced = json(&trailsctl(
+        &server,
+        &["-o", "json", "apps", "force", &root_id],
+    ));
+    assert_eq!(forced["status"], "cancelled");
+    // Already terminal: a conflict.
+    assert_eq!(code(&trailsctl(&server, &["apps", "force", &root_id])), 4);
+}
+
+#[test]
+#[ignore]
+fn test_list_pages_past_one_request() {
+    let server = server();
+    let parent = Uuid::new_v4().to_string();
+    let out = trailsctl(
+        &server,
+        &[
+            "children",
+            "create",
+            "--name",
+            "trailsctl-pages",
+            "--app-id",
+            &parent,
+        ],
+    );
+    assert_eq!(code(&out), 0);
+    let children: String = (0..1200)
+        .map(|i| {
+            format!(
+                "{{\"appId\":\"{}\",\"parentId\":\"{parent}\",\"appName\":\"page-{i}\"}}\n",
+                Uuid::new_v4()
+            )
+        })
+        .collect();
+    let file = std::env::temp_dir().join(format!("trailsctl-{parent}.ndjson"));
+    std::fs::write(&file, children).unwrap();
+    let out = trailsctl(
+        &server,
+        &["children", "create", "--from-file", file.to_str().unwrap()],
+    );
+    std::fs::remove_file(&file).unwrap();
+    assert_eq!(code(&out), 0);
+
+    // Batch children share one created_at; the cursor must not skip any.
+    let tree = json(&trailsctl(
+        &server,
+        &["-o", "json", "apps", "tree", &parent],
+    ));
+    assert_eq!(tree["children"].as_array().unwrap().len(), 1200);
+}
+
+#[test]
+#[ignore]
+fn test_unknown_app_exits_3() {
+    let missing = Uuid::new_v4().to_string();
+    let server = server();
+    assert_eq!(code(&trailsctl(&server, &["apps", "get", &missing])), 3);
+    assert_eq!(code(&trailsctl(&server, &["export", &missing])), 3);
+}
+
+#[test]
+#[ignore]
+fn test_adopt_moves_children() {
+    let server = server();
+    let create = |name: &str, parent: Option<&str>| {
+        let mut args = vec!["-o", "json", "children", "create", "--name", name];
+        if let Some(parent) = parent {
+            args.extend(["--parent", parent]);
+        }
+        json(&trailsctl(&server, &args))["appId"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    let old = create("adopt-old", None);
+    let new = create("adopt-new", None);
+    let child = create("adopt-child", Some(&old));
+    create("adopt-sibling", Some(&old));
+
+    let adopted = json(&trailsctl(
+        &server,
+        &["-o", "json", "apps", "adopt", "--from", &old, "--to", &new],
+    ));
+    assert_eq!(adopted.as_array().unwrap().len(), 2);
+    let app = json(&trailsctl(&server, &["-o", "json", "apps", "get", &child]));
+    assert_eq!(app["parentId"], new.as_str());
+    assert_eq!(app["adoptedFrom"][0]["parentId"], old.as_str());
+
+    // The new parent under its own child: a cycle.
+    let out = trailsctl(&server, &["apps", "adopt", &new, "--to", &child]);
+    assert_eq!(code(&out), 4);
+}
+
+#[test]
+#[ignore]
+fn test_dependent_waits_and_follows_failure() {
+    let server = server();
+    let create = |name: &str, parent: Option<&str>, depends_on: Option<&str>| {
+        let mut args = vec!["-o", "json", "children", "create", "--name", name];
+        if let Some(parent) = parent {
+            args.extend(["--parent", parent]);
+        }
+        if let Some(dep) = depends_on {
+            args.extend(["--depends-on", dep]);
+        }
+        json(&trailsctl(&server, &args))
+    };
+    let root = create("deps-root", None, None)["appId"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let first = create("deps-first", Some(&root), None)["appId"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let second = create("deps-second", Some(&root), Some(&first));
+    assert!(second["blockedAt"].is_string());
+    assert_eq!(second["dependsOn"][0], first.as_str());
+    let second = second["appId"].as_str().unwrap().to_string();
+
+    // Only siblings qualify.
+    let out = trailsctl(
+        &server,
+        &[
+            "children",
+            "create",
+            "--name",
+            "x",
+            "--parent",
+            &root,
+            "--depends-on",
+            &root,
+        ],
+    );
+    assert_eq!(code(&out), 5);
+
+    // The dependency is forced to cancelled: under the default policy the
+    // dependent is cancelled too.
+    assert_eq!(code(&trailsctl(&server, &["apps", "force", &first])), 0);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let app = json(&trailsctl(&server, &["-o", "json", "apps", "get", &second]));
+    assert_eq!(app["status"], "cancelled");
+    assert_eq!(app["failedDependencies"][0], first.as_str());
+}
+
+#[test]
+#[ignore]
+fn test_cancel_before_connect_is_queued() {
+    let server = server();
+    let app = json(&trailsctl(
+        &server,
+        &[
+            "-o",
+            "json",
+            "children",
+            "create",
+            "--name",
+            "queued-cancel",
+        ],
+    ));
+    let app_id = app["appId"].as_str().unwrap().to_string();
+
+    let cancel = json(&trailsctl(
+        &server,
+        &["-o", "json", "apps", "cancel", &app_id, "--reason", "test"],
+    ));
+    assert_eq!(cancel["delivered"], false);
+    assert_eq!(cancel["delivery"], "queued");
+
+    let controls = json(&trailsctl(
+        &server,
+        &["-o", "json", "apps", "controls", &app_id],
+    ));
+    assert_eq!(controls[0]["action"], "cancel");
+    assert_eq!(controls[0]["state"], "pending");
+    assert_eq!(controls[0]["requestedBy"], "trailsctl-test");
+
+    // Ended before connecting: nothing left to cancel.
+    assert_eq!(code(&trailsctl(&server, &["apps", "force", &app_id])), 0);
+    assert_eq!(code(&trailsctl(&server, &["apps", "cancel", &app_id])), 4);
+}